@@ -0,0 +1,174 @@
+use crate::components::abilities::Abilities;
+use crate::components::action_budget::ActionBudget;
+use crate::components::attack_profile::{AttackProfile, AttackShape};
+use crate::components::economy::Upkeep;
+use crate::components::experience::Experience;
+use crate::components::health::Health;
+use crate::components::hexagon::Hexagon;
+use crate::components::mobility::Mobility;
+use crate::components::morale::{Leader, Morale};
+use crate::components::node_template::NodeTemplate;
+use crate::components::player::Player;
+use crate::components::regeneration::Regeneration;
+use crate::components::status_effects::StatusEffects;
+use crate::components::supply::Supply;
+use crate::components::transport::{CargoFate, Transport};
+use crate::components::unit::Unit;
+use crate::components::vision::Vision;
+use crate::components::weapon::{DamageType, Weapon, Weapons};
+use crate::economy::Currency;
+use crate::game_state::Rules;
+use legion::{Entity, World};
+
+/// The units that can be spawned on the map and recruited by buildings
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UnitKind {
+    Tank,
+    Artillery,
+}
+
+impl UnitKind {
+    pub fn iter() -> impl Iterator<Item = UnitKind> {
+        [UnitKind::Tank, UnitKind::Artillery].iter().copied()
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            UnitKind::Tank => "Tank",
+            UnitKind::Artillery => "Artillery",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<UnitKind> {
+        UnitKind::iter().find(|kind| kind.get_name() == name)
+    }
+
+    /// Resources paid when recruiting the unit
+    pub fn get_cost(&self) -> Vec<(Currency, i32)> {
+        match self {
+            UnitKind::Tank => vec![(Currency::Credits, 8), (Currency::Fuel, 2)],
+            UnitKind::Artillery => vec![(Currency::Credits, 6)],
+        }
+    }
+
+    /// Turns until a recruited unit is ready
+    pub fn get_build_turns(&self) -> u32 {
+        match self {
+            UnitKind::Tank => 2,
+            UnitKind::Artillery => 1,
+        }
+    }
+
+    /// Spawns a unit of the kind, which keeps the kind as a component
+    pub fn spawn(
+        &self,
+        world: &mut World,
+        player: usize,
+        hexagon: Hexagon,
+        rules: &Rules,
+    ) -> Entity {
+        let entity = world.push((
+            *self,
+            Player(player),
+            hexagon,
+            NodeTemplate {
+                scene_file: "res://DummyUnit.tscn".to_owned(),
+                scale_x: 1.0,
+                scale_y: 1.0,
+                z_index: 1,
+            },
+            Unit,
+            StatusEffects::new(),
+            Experience::default(),
+            Morale::new(10),
+        ));
+        let mut entry = match world.entry(entity) {
+            None => return entity,
+            Some(entry) => entry,
+        };
+        if let Some(points) = rules.action_points {
+            entry.add_component(points);
+        }
+        match self {
+            UnitKind::Tank => {
+                entry.add_component(Health::new(20, 3));
+                entry.add_component(Weapons::new(vec![
+                    Weapon::new("Cannon", 5, 2, 1),
+                    Weapon {
+                        damage_type: DamageType::Piercing,
+                        ammo: Some(3),
+                        max_ammo: Some(3),
+                        ..Weapon::new("Rail gun", 7, 3, 2)
+                    },
+                ]));
+                entry.add_component(Mobility::new(5, 5));
+                entry.add_component(ActionBudget::new(1, 1));
+                entry.add_component(Regeneration::new(2, true));
+                entry.add_component(Leader::new(2, 1));
+                entry.add_component(Transport::new(1, CargoFate::Ejected));
+                entry.add_component(Vision::new(4));
+                entry.add_component(Upkeep::new(Currency::Fuel, 1));
+                entry.add_component(Abilities::from_names(&["Dig in", "Overwatch", "Repair"]));
+            }
+            UnitKind::Artillery => {
+                entry.add_component(Health::new(10, 1));
+                entry.add_component(Weapons::new(vec![Weapon {
+                    damage_type: DamageType::Explosive,
+                    ..Weapon::new("Artillery", 10, 4, 2)
+                }]));
+                entry.add_component(Mobility::new(2, 2));
+                entry.add_component(ActionBudget::new(1, 1));
+                entry.add_component(Abilities::from_names(&["Smoke screen", "Heal"]));
+                entry.add_component(AttackProfile::new(AttackShape::Burst(1), false));
+                entry.add_component(Supply::new(2, 1, 1));
+                entry.add_component(Vision::new(2));
+                entry.add_component(Upkeep::new(Currency::Credits, 1));
+            }
+        }
+        entity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::action_points::{ActionCosts, ActionPoints};
+    use legion::{EntityStore, WorldOptions};
+
+    #[test]
+    fn spawn_creates_units_of_the_player() {
+        let mut world = World::new(WorldOptions::default());
+        for kind in UnitKind::iter() {
+            let entity = kind.spawn(&mut world, 1, Hexagon::zero(), &Rules::default());
+            let entry = world.entry_ref(entity).unwrap();
+            assert_eq!(*entry.get_component::<UnitKind>().unwrap(), kind);
+            assert_eq!(UnitKind::from_name(kind.get_name()), Some(kind));
+            assert_eq!(entry.get_component::<Player>().unwrap().0, 1);
+            assert_eq!(*entry.get_component::<Hexagon>().unwrap(), Hexagon::zero());
+            assert!(entry.get_component::<Unit>().is_ok());
+            assert!(entry.get_component::<Health>().is_ok());
+            assert!(entry.get_component::<NodeTemplate>().is_ok());
+            assert!(entry.get_component::<ActionPoints>().is_err());
+        }
+    }
+
+    #[test]
+    fn spawn_adds_action_points_from_rules() {
+        let mut world = World::new(WorldOptions::default());
+        let rules = Rules {
+            action_points: Some(ActionPoints::new(
+                4,
+                ActionCosts {
+                    move_hexagon: 1,
+                    attack: 2,
+                    ability: 1,
+                },
+            )),
+            ..Rules::default()
+        };
+        let entity = UnitKind::Artillery.spawn(&mut world, 0, Hexagon::zero(), &rules);
+
+        let entry = world.entry_ref(entity).unwrap();
+        assert!(entry.get_component::<ActionPoints>().is_ok());
+    }
+}
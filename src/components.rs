@@ -1,6 +1,24 @@
+pub mod abilities;
+pub mod action_budget;
+pub mod action_points;
+pub mod attack_profile;
+pub mod economy;
+pub mod experience;
 pub mod field;
+pub mod health;
 pub mod hexagon;
+pub mod mobility;
+pub mod morale;
 pub mod node_component;
 pub mod node_template;
+pub mod objective;
 pub mod player;
+pub mod production;
+pub mod regeneration;
+pub mod status_effects;
+pub mod structure;
+pub mod supply;
+pub mod transport;
 pub mod unit;
+pub mod vision;
+pub mod weapon;
@@ -0,0 +1,327 @@
+use crate::components::status_effects::StatusEffectKind;
+use std::fmt;
+use std::str::FromStr;
+
+/// The abilities units can have, one per line as parsed by `parse_abilities`
+pub const DEFAULT_ABILITIES: &str = "\
+Heal: actions=1 cooldown=2 target=ally range=0:1 restore=5
+Repair: actions=1 cooldown=3 target=own range=0:0 restore=3
+Smoke screen: actions=1 cooldown=3 target=area:1 range=1:4 status=Suppressed:2
+Dig in: actions=1 cooldown=0 target=own range=0:0 status=Entrenched:2
+Overwatch: actions=1 cooldown=0 target=own range=0:0 status=Overwatch:2";
+
+/// What an ability can be used on
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AbilityTarget {
+    /// Only the unit using the ability
+    Own,
+    /// A unit of the same player, including the unit using the ability
+    Ally,
+    /// A unit of another player
+    Enemy,
+    /// Any hexagon, regardless of what is on it
+    Hexagon,
+    /// All units up to the radius around the targeted hexagon
+    Area(i32),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AbilityEffect {
+    RestoreIntegrity(i32),
+    ApplyStatus(StatusEffectKind, i32),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ability {
+    pub name: String,
+    /// Attacks used up by the ability
+    pub actions: i32,
+    /// Turns until the ability can be used again
+    pub cooldown: i32,
+    pub target: AbilityTarget,
+    pub min_range: i32,
+    pub max_range: i32,
+    pub effect: AbilityEffect,
+}
+
+impl Ability {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        name: &str,
+        actions: i32,
+        cooldown: i32,
+        target: AbilityTarget,
+        min_range: i32,
+        max_range: i32,
+        effect: AbilityEffect,
+    ) -> Self {
+        Ability {
+            name: name.to_owned(),
+            actions,
+            cooldown,
+            target,
+            min_range,
+            max_range,
+            effect,
+        }
+    }
+
+    /// Returns the ability with the name from the default abilities
+    pub fn from_name(name: &str) -> Option<Ability> {
+        parse_abilities(DEFAULT_ABILITIES)
+            .ok()?
+            .into_iter()
+            .find(|ability| ability.name == name)
+    }
+
+    pub fn is_in_range(&self, distance: i32) -> bool {
+        distance >= self.min_range && distance <= self.max_range
+    }
+}
+
+/// Parses an ability from its name followed by a colon and "key=value" fields, e.g.
+/// "Heal: actions=1 cooldown=2 target=ally range=0:1 restore=5". The target is own, ally,
+/// enemy, hexagon or area:radius, and the effect either restore=integrity or
+/// status=kind:turns.
+impl FromStr for Ability {
+    type Err = ParseAbilityError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut parts = value.splitn(2, ':');
+        let name = parts.next().unwrap_or_default().trim();
+        let fields = parts.next().ok_or(ParseAbilityError::MissingField)?;
+        let mut actions = None;
+        let mut cooldown = None;
+        let mut target = None;
+        let mut range = None;
+        let mut effect = None;
+        for field in fields.split_whitespace() {
+            let invalid = || ParseAbilityError::InvalidValue(field.to_owned());
+            let number = |value: &str| value.parse::<i32>().map_err(|_| invalid());
+            let mut parts = field.splitn(2, '=');
+            let key = parts.next().unwrap_or_default();
+            let value = parts.next().ok_or_else(invalid)?;
+            let values: Vec<&str> = value.split(':').collect();
+            match (key, values.as_slice()) {
+                ("actions", [value]) => actions = Some(number(value)?),
+                ("cooldown", [value]) => cooldown = Some(number(value)?),
+                ("target", ["own"]) => target = Some(AbilityTarget::Own),
+                ("target", ["ally"]) => target = Some(AbilityTarget::Ally),
+                ("target", ["enemy"]) => target = Some(AbilityTarget::Enemy),
+                ("target", ["hexagon"]) => target = Some(AbilityTarget::Hexagon),
+                ("target", ["area", radius]) => target = Some(AbilityTarget::Area(number(radius)?)),
+                ("range", [min, max]) => range = Some((number(min)?, number(max)?)),
+                ("restore", [value]) => {
+                    effect = Some(AbilityEffect::RestoreIntegrity(number(value)?))
+                }
+                ("status", [kind, turns]) => {
+                    let kind = StatusEffectKind::from_name(kind).ok_or_else(invalid)?;
+                    effect = Some(AbilityEffect::ApplyStatus(kind, number(turns)?));
+                }
+                ("actions", _)
+                | ("cooldown", _)
+                | ("target", _)
+                | ("range", _)
+                | ("restore", _)
+                | ("status", _) => return Err(invalid()),
+                (key, _) => return Err(ParseAbilityError::UnknownKey(key.to_owned())),
+            }
+        }
+        match (actions, cooldown, target, range, effect) {
+            (Some(actions), Some(cooldown), Some(target), Some((min, max)), Some(effect))
+                if !name.is_empty() =>
+            {
+                Ok(Ability::new(
+                    name, actions, cooldown, target, min, max, effect,
+                ))
+            }
+            _ => Err(ParseAbilityError::MissingField),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseAbilityError {
+    MissingField,
+    UnknownKey(String),
+    InvalidValue(String),
+}
+
+impl fmt::Display for ParseAbilityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseAbilityError::MissingField => write!(f, "missing ability field"),
+            ParseAbilityError::UnknownKey(key) => write!(f, "unknown ability key {}", key),
+            ParseAbilityError::InvalidValue(field) => {
+                write!(f, "invalid ability field {}", field)
+            }
+        }
+    }
+}
+
+/// Parses the abilities of a table with one ability per line, skipping empty lines
+pub fn parse_abilities(table: &str) -> Result<Vec<Ability>, ParseAbilityError> {
+    table
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.parse())
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AbilitySlot {
+    pub ability: Ability,
+    pub remaining_cooldown: i32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Abilities {
+    pub slots: Vec<AbilitySlot>,
+}
+
+impl Abilities {
+    pub fn new(abilities: Vec<Ability>) -> Self {
+        Abilities {
+            slots: abilities
+                .into_iter()
+                .map(|ability| AbilitySlot {
+                    ability,
+                    remaining_cooldown: 0,
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the abilities with the names from the default abilities, skipping unknown
+    /// names
+    pub fn from_names(names: &[&str]) -> Self {
+        Abilities::new(
+            names
+                .iter()
+                .filter_map(|name| Ability::from_name(name))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Ability> {
+        self.slots.get(index).map(|slot| &slot.ability)
+    }
+
+    pub fn is_ready(&self, index: usize) -> bool {
+        matches!(self.slots.get(index), Some(slot) if slot.remaining_cooldown <= 0)
+    }
+
+    pub fn start_cooldown(&mut self, index: usize) {
+        if let Some(slot) = self.slots.get_mut(index) {
+            slot.remaining_cooldown = slot.ability.cooldown;
+        }
+    }
+
+    pub fn tick(&mut self) {
+        for slot in self.slots.iter_mut() {
+            slot.remaining_cooldown = (slot.remaining_cooldown - 1).max(0);
+        }
+    }
+}
+
+pub enum AbilityError {
+    NotFound,
+    OnCooldown,
+    NoActionsLeft,
+    /// The unit is prevented from acting, e.g. by being stunned
+    Disabled,
+    InvalidTarget,
+}
+
+impl fmt::Display for AbilityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AbilityError::NotFound => write!(f, "Unit does not have this ability"),
+            AbilityError::OnCooldown => write!(f, "Ability is on cooldown"),
+            AbilityError::NoActionsLeft => write!(f, "Unit has no actions left"),
+            AbilityError::Disabled => write!(f, "Unit can not act"),
+            AbilityError::InvalidTarget => write!(f, "Ability can not be used on this target"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_abilities_are_parsed() {
+        let abilities = parse_abilities(DEFAULT_ABILITIES).unwrap();
+        assert_eq!(abilities.len(), 5);
+        assert_eq!(
+            Ability::from_name("Smoke screen"),
+            Some(Ability::new(
+                "Smoke screen",
+                1,
+                3,
+                AbilityTarget::Area(1),
+                1,
+                4,
+                AbilityEffect::ApplyStatus(StatusEffectKind::Suppressed, 2),
+            ))
+        );
+        assert_eq!(Ability::from_name("Fly"), None);
+        let abilities = Abilities::from_names(&["Heal", "Fly", "Repair"]);
+        assert_eq!(abilities.get(1).unwrap().name, "Repair");
+    }
+
+    #[test]
+    fn invalid_abilities_are_rejected() {
+        assert_eq!(
+            "Heal actions=1".parse::<Ability>(),
+            Err(ParseAbilityError::MissingField)
+        );
+        assert_eq!(
+            "Heal: actions=1 cooldown=2 target=ally range=0:1".parse::<Ability>(),
+            Err(ParseAbilityError::MissingField)
+        );
+        assert_eq!(
+            "Heal: speed=1".parse::<Ability>(),
+            Err(ParseAbilityError::UnknownKey("speed".to_owned()))
+        );
+        assert_eq!(
+            "Heal: target=friend".parse::<Ability>(),
+            Err(ParseAbilityError::InvalidValue("target=friend".to_owned()))
+        );
+        assert_eq!(
+            "Heal: status=Burning:2".parse::<Ability>(),
+            Err(ParseAbilityError::InvalidValue(
+                "status=Burning:2".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn is_in_range_respects_min_and_max_range() {
+        let ability = Ability::from_name("Smoke screen").unwrap();
+        assert!(!ability.is_in_range(0));
+        assert!(ability.is_in_range(1));
+        assert!(ability.is_in_range(4));
+        assert!(!ability.is_in_range(5));
+    }
+
+    #[test]
+    fn start_cooldown_makes_ability_unavailable_until_ticked() {
+        let mut abilities = Abilities::from_names(&["Heal", "Dig in"]);
+        abilities.start_cooldown(0);
+        abilities.start_cooldown(1);
+        assert!(!abilities.is_ready(0));
+        assert!(abilities.is_ready(1));
+        abilities.tick();
+        assert!(!abilities.is_ready(0));
+        abilities.tick();
+        assert!(abilities.is_ready(0));
+    }
+
+    #[test]
+    fn is_ready_returns_false_for_missing_ability() {
+        let abilities = Abilities::from_names(&["Heal"]);
+        assert!(!abilities.is_ready(1));
+        assert!(abilities.get(1).is_none());
+    }
+}
@@ -0,0 +1,53 @@
+use crate::components::status_effects::StatModifiers;
+
+/// The actions a unit can still take this round
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ActionBudget {
+    pub attacks: i32,
+    pub remaining_attacks: i32,
+}
+
+impl ActionBudget {
+    pub fn new(attacks: i32, remaining_attacks: i32) -> Self {
+        ActionBudget {
+            attacks,
+            remaining_attacks,
+        }
+    }
+
+    pub fn can_attack(&self, modifiers: &StatModifiers) -> bool {
+        modifiers.can_attack && self.remaining_attacks > 0
+    }
+
+    pub fn reset(&mut self) {
+        self.remaining_attacks = self.attacks;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_attack_requires_remaining_attacks() {
+        let modifiers = StatModifiers::default();
+        assert!(ActionBudget::new(1, 1).can_attack(&modifiers));
+        assert!(!ActionBudget::new(1, 0).can_attack(&modifiers));
+    }
+
+    #[test]
+    fn can_attack_respects_modifiers() {
+        let modifiers = StatModifiers {
+            can_attack: false,
+            ..StatModifiers::default()
+        };
+        assert!(!ActionBudget::new(1, 1).can_attack(&modifiers));
+    }
+
+    #[test]
+    fn reset_restores_attacks() {
+        let mut budget = ActionBudget::new(2, 0);
+        budget.reset();
+        assert_eq!(budget.remaining_attacks, 2);
+    }
+}
@@ -0,0 +1,143 @@
+use crate::components::action_budget::ActionBudget;
+use crate::components::mobility::Mobility;
+use crate::components::status_effects::StatModifiers;
+
+/// Action points needed for the actions of a unit
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ActionCosts {
+    pub move_hexagon: i32,
+    pub attack: i32,
+    pub ability: i32,
+}
+
+/// A shared budget for moving, attacking and using abilities. Units without it use their
+/// movement range and attacks independently.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ActionPoints {
+    pub points: i32,
+    pub remaining_points: i32,
+    pub costs: ActionCosts,
+}
+
+impl ActionPoints {
+    pub fn new(points: i32, costs: ActionCosts) -> Self {
+        ActionPoints {
+            points,
+            remaining_points: points,
+            costs,
+        }
+    }
+
+    /// Hexagons the unit can still move with the remaining points
+    pub fn get_movement_range(&self) -> i32 {
+        (self.remaining_points / self.costs.move_hexagon.max(1)).max(0)
+    }
+
+    /// Attacks the unit can still make with the remaining points
+    pub fn get_attacks(&self) -> i32 {
+        (self.remaining_points / self.costs.attack.max(1)).max(0)
+    }
+
+    pub fn can_afford_ability(&self, actions: i32) -> bool {
+        self.remaining_points >= self.costs.ability * actions
+    }
+
+    pub fn spend_movement(&mut self, distance: i32) {
+        self.remaining_points -= self.costs.move_hexagon * distance;
+    }
+
+    pub fn spend_attacks(&mut self, attacks: i32) {
+        self.remaining_points -= self.costs.attack * attacks;
+    }
+
+    pub fn spend_ability(&mut self, actions: i32) {
+        self.remaining_points -= self.costs.ability * actions;
+    }
+
+    /// Restores the points and gives the unit the movement range and attacks they allow
+    pub fn reset(
+        &mut self,
+        mobility: &mut Mobility,
+        budget: &mut ActionBudget,
+        modifiers: &StatModifiers,
+    ) {
+        self.remaining_points = self.points;
+        mobility.remaining_range = (self.get_movement_range() + modifiers.mobility).max(0);
+        budget.remaining_attacks = self.get_attacks();
+    }
+
+    /// Lowers the movement range to what the remaining points still allow
+    pub fn limit_movement(&self, mobility: &mut Mobility) {
+        mobility.remaining_range = mobility.remaining_range.min(self.get_movement_range());
+    }
+
+    /// Lowers the attacks to what the remaining points still allow
+    pub fn limit_attacks(&self, budget: &mut ActionBudget) {
+        budget.remaining_attacks = budget.remaining_attacks.min(self.get_attacks());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action_points(points: i32) -> ActionPoints {
+        ActionPoints::new(
+            points,
+            ActionCosts {
+                move_hexagon: 1,
+                attack: 2,
+                ability: 3,
+            },
+        )
+    }
+
+    #[test]
+    fn reset_gives_range_and_attacks_of_all_points() {
+        let mut points = action_points(4);
+        points.remaining_points = 0;
+        let mut mobility = Mobility::new(2, 0);
+        let mut budget = ActionBudget::new(1, 0);
+
+        points.reset(&mut mobility, &mut budget, &StatModifiers::default());
+
+        assert_eq!(points.remaining_points, 4);
+        assert_eq!(mobility.remaining_range, 4);
+        assert_eq!(budget.remaining_attacks, 2);
+    }
+
+    #[test]
+    fn spending_points_limits_range_and_attacks() {
+        let mut points = action_points(4);
+        let mut mobility = Mobility::new(2, 4);
+        let mut budget = ActionBudget::new(1, 2);
+
+        points.spend_attacks(1);
+        points.limit_movement(&mut mobility);
+        points.limit_attacks(&mut budget);
+        assert_eq!(mobility.remaining_range, 2);
+        assert_eq!(budget.remaining_attacks, 1);
+
+        points.spend_movement(1);
+        points.limit_movement(&mut mobility);
+        points.limit_attacks(&mut budget);
+        assert_eq!(mobility.remaining_range, 1);
+        assert_eq!(budget.remaining_attacks, 0);
+    }
+
+    #[test]
+    fn limit_movement_does_not_raise_range() {
+        let points = action_points(4);
+        let mut mobility = Mobility::new(2, 0);
+
+        points.limit_movement(&mut mobility);
+        assert_eq!(mobility.remaining_range, 0);
+    }
+
+    #[test]
+    fn can_afford_ability_uses_ability_cost() {
+        let points = action_points(4);
+        assert!(points.can_afford_ability(1));
+        assert!(!points.can_afford_ability(2));
+    }
+}
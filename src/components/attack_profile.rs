@@ -0,0 +1,183 @@
+use crate::components::hexagon::{Direction, Hexagon};
+
+/// The area an attack hits, relative to the attacker and the targeted hexagon.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AttackShape {
+    /// Only the targeted hexagon
+    Single,
+    /// The targeted hexagon and everything up to the radius around it
+    Burst(i32),
+    /// The targeted hexagon and the hexagons behind it, pointing away from the attacker
+    Line(i32),
+    /// The targeted hexagon and a widening cone from the attacker towards it, up to the
+    /// given length
+    Cone(i32),
+    /// The targeted hexagon and the hexagons at exactly the radius around it
+    Ring(i32),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AttackProfile {
+    pub shape: AttackShape,
+    /// Whether units of the attacking player are hit, too
+    pub friendly_fire: bool,
+}
+
+impl Default for AttackProfile {
+    fn default() -> Self {
+        AttackProfile {
+            shape: AttackShape::Single,
+            friendly_fire: false,
+        }
+    }
+}
+
+impl AttackProfile {
+    pub fn new(shape: AttackShape, friendly_fire: bool) -> Self {
+        AttackProfile {
+            shape,
+            friendly_fire,
+        }
+    }
+
+    /// Returns the hexagons hit by an attack on the target, which is always one of them
+    pub fn get_affected_hexagons(&self, origin: &Hexagon, target: &Hexagon) -> Vec<Hexagon> {
+        let mut hexagons = match self.shape {
+            AttackShape::Single => vec![*target],
+            AttackShape::Burst(radius) => get_hexagons_in_range(target, radius),
+            AttackShape::Ring(radius) => get_hexagons_in_range(target, radius)
+                .into_iter()
+                .filter(|hexagon| hexagon.distance_to(target) == radius)
+                .collect(),
+            AttackShape::Line(length) => match origin.get_direction_to(target) {
+                None => vec![*target],
+                Some(direction) => (0..length.max(1))
+                    .map(|step| *target + direction.get_offset() * step)
+                    .collect(),
+            },
+            AttackShape::Cone(length) => match origin.get_direction_to(target) {
+                None => vec![*target],
+                Some(direction) => get_cone(origin, direction, length),
+            },
+        };
+        if !hexagons.contains(target) {
+            hexagons.insert(0, *target);
+        }
+        hexagons
+    }
+}
+
+fn get_hexagons_in_range(center: &Hexagon, radius: i32) -> Vec<Hexagon> {
+    // https://www.redblobgames.com/grids/hexagons/#range-coordinate
+    let mut hexagons = Vec::new();
+    for q in -radius..=radius {
+        for r in (-radius).max(-q - radius)..=radius.min(-q + radius) {
+            hexagons.push(*center + Hexagon::new_axial(q, r));
+        }
+    }
+    hexagons
+}
+
+/// Returns the hexagons in the 60° wedge centered on the direction
+fn get_cone(origin: &Hexagon, direction: Direction, length: i32) -> Vec<Hexagon> {
+    // The wedge is calculated facing East and then rotated into the direction
+    get_hexagons_in_range(&Hexagon::zero(), length)
+        .into_iter()
+        .filter(|hexagon| {
+            *hexagon != Hexagon::zero()
+                && hexagon.get_q() >= hexagon.get_r()
+                && hexagon.get_q() >= -2 * hexagon.get_r()
+        })
+        .map(|hexagon| hexagon.rotate_around(&Hexagon::zero(), -(direction as i32)) + *origin)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn affected(shape: AttackShape, origin: Hexagon, target: Hexagon) -> Vec<Hexagon> {
+        AttackProfile::new(shape, false).get_affected_hexagons(&origin, &target)
+    }
+
+    #[test]
+    fn single_affects_only_target() {
+        let target = Hexagon::new_axial(3, -1);
+        assert_eq!(
+            affected(AttackShape::Single, Hexagon::zero(), target),
+            vec![target]
+        );
+    }
+
+    #[test]
+    fn burst_affects_all_hexagons_in_radius() {
+        let target = Hexagon::new_axial(3, -1);
+        let result = affected(AttackShape::Burst(2), Hexagon::zero(), target);
+        assert_eq!(result.len(), 19);
+        assert!(result.contains(&target));
+        assert!(result
+            .iter()
+            .all(|hexagon| hexagon.distance_to(&target) <= 2));
+    }
+
+    #[test]
+    fn ring_affects_target_and_hexagons_at_radius() {
+        let target = Hexagon::new_axial(-2, 4);
+        let result = affected(AttackShape::Ring(2), Hexagon::zero(), target);
+        assert_eq!(result.len(), 13);
+        assert!(result.contains(&target));
+        assert!(result
+            .iter()
+            .filter(|hexagon| **hexagon != target)
+            .all(|hexagon| hexagon.distance_to(&target) == 2));
+    }
+
+    #[test]
+    fn line_extends_away_from_attacker() {
+        let result = affected(
+            AttackShape::Line(3),
+            Hexagon::zero(),
+            Hexagon::new_axial(0, 2),
+        );
+        assert_eq!(
+            result,
+            vec![
+                Hexagon::new_axial(0, 2),
+                Hexagon::new_axial(0, 3),
+                Hexagon::new_axial(0, 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn cone_widens_along_direction() {
+        let origin = Hexagon::new_axial(1, 1);
+        let result = affected(AttackShape::Cone(3), origin, Hexagon::new_axial(-1, 1));
+        assert_eq!(result.len(), 7);
+        assert!(result.contains(&Hexagon::new_axial(0, 1)));
+        assert!(result.contains(&Hexagon::new_axial(-2, 1)));
+        assert!(!result.contains(&origin));
+        assert_eq!(
+            result
+                .iter()
+                .filter(|hexagon| hexagon.distance_to(&origin) == 2)
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn cone_points_in_every_direction() {
+        for direction in Direction::iter() {
+            let result = affected(
+                AttackShape::Cone(1),
+                Hexagon::zero(),
+                direction.get_offset() * 4,
+            );
+            assert_eq!(
+                result,
+                vec![direction.get_offset() * 4, direction.get_offset()]
+            );
+        }
+    }
+}
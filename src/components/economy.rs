@@ -0,0 +1,27 @@
+use crate::economy::Currency;
+
+/// Resources the owner of the entity receives at the start of their turn
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Income {
+    pub currency: Currency,
+    pub amount: i32,
+}
+
+impl Income {
+    pub fn new(currency: Currency, amount: i32) -> Self {
+        Income { currency, amount }
+    }
+}
+
+/// Resources the owner of the unit pays at the start of their turn
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Upkeep {
+    pub currency: Currency,
+    pub amount: i32,
+}
+
+impl Upkeep {
+    pub fn new(currency: Currency, amount: i32) -> Self {
+        Upkeep { currency, amount }
+    }
+}
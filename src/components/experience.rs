@@ -0,0 +1,317 @@
+use crate::components::status_effects::StatModifiers;
+use std::fmt;
+use std::str::FromStr;
+
+/// Experience gained for destroying a unit, on top of the damage dealt
+pub const KILL_EXPERIENCE: i32 = 5;
+
+/// The ranks units start with, one per line as parsed by `parse_ranks`
+pub const DEFAULT_RANKS: &str = "\
+Recruit - 0
+Veteran * 10 damage=1
+Elite ** 25 damage=1 armor=1
+Hero *** 50 damage=2 armor=1 mobility=1";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rank {
+    pub name: String,
+    /// Short text shown next to the integrity of the unit node
+    pub badge: String,
+    /// Experience needed to reach the rank
+    pub experience: i32,
+    pub bonus: StatModifiers,
+}
+
+impl Rank {
+    pub fn new(name: &str, badge: &str, experience: i32, bonus: StatModifiers) -> Self {
+        Rank {
+            name: name.to_owned(),
+            badge: badge.to_owned(),
+            experience,
+            bonus,
+        }
+    }
+}
+
+/// The experience of a unit and the ranks it can reach with it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Experience {
+    pub points: i32,
+    pub ranks: Vec<Rank>,
+}
+
+/// Parses a rank from its name, its badge or "-" for none, the experience needed and the
+/// bonus as "stat=value" fields, e.g. "Veteran * 10 damage=1 armor=1"
+impl FromStr for Rank {
+    type Err = ParseRankError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut fields = value.split_whitespace();
+        let name = fields.next().ok_or(ParseRankError::MissingField)?;
+        let badge = match fields.next().ok_or(ParseRankError::MissingField)? {
+            "-" => "",
+            badge => badge,
+        };
+        let experience = fields.next().ok_or(ParseRankError::MissingField)?;
+        let experience = experience
+            .parse()
+            .map_err(|_| ParseRankError::InvalidValue(experience.to_owned()))?;
+        let mut bonus = StatModifiers::default();
+        for field in fields {
+            let invalid = || ParseRankError::InvalidValue(field.to_owned());
+            let mut parts = field.splitn(2, '=');
+            let stat = parts.next().unwrap_or_default();
+            let value: i32 = parts
+                .next()
+                .ok_or_else(invalid)?
+                .parse()
+                .map_err(|_| invalid())?;
+            match stat {
+                "damage" => bonus.damage = value,
+                "armor" => bonus.armor = value,
+                "mobility" => bonus.mobility = value,
+                "attack_range" => bonus.attack_range = value,
+                _ => return Err(ParseRankError::UnknownStat(stat.to_owned())),
+            }
+        }
+        Ok(Rank::new(name, badge, experience, bonus))
+    }
+}
+
+/// Writes the rank the way it is parsed, leaving out the stats without bonus
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let badge = if self.badge.is_empty() {
+            "-"
+        } else {
+            &self.badge
+        };
+        write!(f, "{} {} {}", self.name, badge, self.experience)?;
+        let stats = [
+            ("damage", self.bonus.damage),
+            ("armor", self.bonus.armor),
+            ("mobility", self.bonus.mobility),
+            ("attack_range", self.bonus.attack_range),
+        ];
+        for (stat, value) in stats.iter().filter(|(_, value)| *value != 0) {
+            write!(f, " {}={}", stat, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the points and the ranks on one line separated by ";", e.g.
+/// "12;Recruit - 0;Veteran * 10 damage=1", for saves and resyncs
+impl fmt::Display for Experience {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.points)?;
+        for rank in &self.ranks {
+            write!(f, ";{}", rank)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Experience {
+    type Err = ParseRankError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut fields = value.split(';');
+        let points = fields.next().unwrap_or_default().trim();
+        let points = points
+            .parse()
+            .map_err(|_| ParseRankError::InvalidValue(points.to_owned()))?;
+        let ranks = fields
+            .map(|rank| rank.parse())
+            .collect::<Result<Vec<Rank>, ParseRankError>>()?;
+        Ok(Experience { points, ranks })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseRankError {
+    MissingField,
+    InvalidValue(String),
+    UnknownStat(String),
+}
+
+impl fmt::Display for ParseRankError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseRankError::MissingField => write!(f, "missing rank field"),
+            ParseRankError::InvalidValue(field) => write!(f, "invalid rank field {}", field),
+            ParseRankError::UnknownStat(stat) => write!(f, "unknown stat {}", stat),
+        }
+    }
+}
+
+/// Parses the ranks of a table with one rank per line, skipping empty lines
+pub fn parse_ranks(table: &str) -> Result<Vec<Rank>, ParseRankError> {
+    table
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.parse())
+        .collect()
+}
+
+impl Default for Experience {
+    fn default() -> Self {
+        Experience::new(parse_ranks(DEFAULT_RANKS).unwrap_or_default())
+    }
+}
+
+impl Experience {
+    pub fn new(ranks: Vec<Rank>) -> Self {
+        Experience { points: 0, ranks }
+    }
+
+    /// Adds the experience for an attack and returns whether the unit reached a new rank
+    pub fn gain(&mut self, damage: i32, killed: bool) -> bool {
+        let previous = self.get_rank_index();
+        self.points += damage.max(0);
+        if killed {
+            self.points += KILL_EXPERIENCE;
+        }
+        self.get_rank_index() != previous
+    }
+
+    /// Index of the highest rank the experience is enough for
+    pub fn get_rank_index(&self) -> Option<usize> {
+        self.ranks
+            .iter()
+            .enumerate()
+            .filter(|(_, rank)| rank.experience <= self.points)
+            .max_by_key(|(_, rank)| rank.experience)
+            .map(|(index, _)| index)
+    }
+
+    pub fn get_rank(&self) -> Option<&Rank> {
+        self.get_rank_index()
+            .and_then(|index| self.ranks.get(index))
+    }
+
+    pub fn get_modifiers(&self) -> StatModifiers {
+        self.get_rank().map(|rank| rank.bonus).unwrap_or_default()
+    }
+
+    pub fn get_badge(&self) -> &str {
+        self.get_rank().map_or("", |rank| rank.badge.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gain_adds_damage_and_kill_experience() {
+        let mut experience = Experience::default();
+        experience.gain(3, false);
+        assert_eq!(experience.points, 3);
+        experience.gain(2, true);
+        assert_eq!(experience.points, 5 + KILL_EXPERIENCE);
+    }
+
+    #[test]
+    fn gain_ignores_negative_damage() {
+        let mut experience = Experience::default();
+        experience.gain(-3, false);
+        assert_eq!(experience.points, 0);
+    }
+
+    #[test]
+    fn gain_returns_true_on_rank_up() {
+        let mut experience = Experience::default();
+        assert!(!experience.gain(9, false));
+        assert!(experience.gain(1, false));
+        assert_eq!(experience.get_rank().unwrap().name, "Veteran");
+        assert_eq!(experience.get_badge(), "*");
+    }
+
+    #[test]
+    fn get_modifiers_uses_bonus_of_highest_rank() {
+        let experience = Experience {
+            points: 30,
+            ..Experience::default()
+        };
+        let modifiers = experience.get_modifiers();
+        assert_eq!(modifiers.damage, 1);
+        assert_eq!(modifiers.armor, 1);
+    }
+
+    #[test]
+    fn default_ranks_are_parsed() {
+        let ranks = parse_ranks(DEFAULT_RANKS).unwrap();
+        assert_eq!(ranks.len(), 4);
+        assert_eq!(
+            ranks[0],
+            Rank::new("Recruit", "", 0, StatModifiers::default())
+        );
+        assert_eq!(
+            ranks[3],
+            Rank::new(
+                "Hero",
+                "***",
+                50,
+                StatModifiers {
+                    damage: 2,
+                    armor: 1,
+                    mobility: 1,
+                    ..StatModifiers::default()
+                },
+            )
+        );
+    }
+
+    #[test]
+    fn invalid_ranks_are_rejected() {
+        assert_eq!(parse_ranks("Veteran *"), Err(ParseRankError::MissingField));
+        assert_eq!(
+            parse_ranks("Veteran * ten"),
+            Err(ParseRankError::InvalidValue("ten".to_owned()))
+        );
+        assert_eq!(
+            parse_ranks("Veteran * 10 speed=1"),
+            Err(ParseRankError::UnknownStat("speed".to_owned()))
+        );
+        assert_eq!(
+            parse_ranks("Veteran * 10 damage"),
+            Err(ParseRankError::InvalidValue("damage".to_owned()))
+        );
+    }
+
+    #[test]
+    fn experience_round_trips_through_text() {
+        let experience = Experience {
+            points: 27,
+            ..Experience::default()
+        };
+        let text = experience.to_string();
+        assert_eq!(
+            text,
+            "27;Recruit - 0;Veteran * 10 damage=1;Elite ** 25 damage=1 armor=1;\
+             Hero *** 50 damage=2 armor=1 mobility=1"
+        );
+        assert_eq!(text.parse(), Ok(experience));
+        assert_eq!(
+            "3".parse(),
+            Ok(Experience {
+                points: 3,
+                ranks: Vec::new()
+            })
+        );
+        assert_eq!(
+            "many".parse::<Experience>(),
+            Err(ParseRankError::InvalidValue("many".to_owned()))
+        );
+    }
+
+    #[test]
+    fn units_without_ranks_have_no_bonus() {
+        let mut experience = Experience::new(Vec::new());
+        experience.gain(100, true);
+        assert!(experience.get_rank().is_none());
+        assert_eq!(experience.get_modifiers(), StatModifiers::default());
+        assert_eq!(experience.get_badge(), "");
+    }
+}
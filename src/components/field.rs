@@ -1,18 +1,64 @@
 use crate::components::hexagon::Hexagon;
+use gdnative::core_types::Color;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Terrain {
+    Water,
+    River,
+    Plains,
+    Forest,
+    Hills,
+    Mountains,
+}
+
+impl Terrain {
+    pub fn is_passable(&self) -> bool {
+        match self {
+            Terrain::Water => false,
+            Terrain::River => true,
+            Terrain::Plains => true,
+            Terrain::Forest => true,
+            Terrain::Hills => true,
+            Terrain::Mountains => false,
+        }
+    }
+
+    pub fn get_colour(&self) -> Color {
+        match self {
+            Terrain::Water => Color::rgb(0.15, 0.3, 0.6),
+            Terrain::River => Color::rgb(0.3, 0.5, 0.8),
+            Terrain::Plains => Color::rgb(0.5, 0.5, 0.5),
+            Terrain::Forest => Color::rgb(0.25, 0.45, 0.25),
+            Terrain::Hills => Color::rgb(0.55, 0.5, 0.35),
+            Terrain::Mountains => Color::rgb(0.35, 0.3, 0.3),
+        }
+    }
+}
 
 #[derive(Copy, Clone)]
 pub struct Field {
     pub location: Hexagon,
+    pub terrain: Terrain,
     pub moveable: bool,
     pub attackable: bool,
+    /// Whether the field is hit by the attack on the hovered target
+    pub splash: bool,
+    /// Whether the ability being targeted can be used on the field
+    pub targetable: bool,
+    /// Whether a hovered or pinned enemy unit can attack the field next turn
+    pub danger: bool,
 }
 
 impl Field {
-    pub fn new(location: Hexagon) -> Field {
+    pub fn new(location: Hexagon, terrain: Terrain) -> Field {
         Field {
             location,
+            terrain,
             moveable: false,
             attackable: false,
+            splash: false,
+            targetable: false,
+            danger: false,
         }
     }
 }
@@ -0,0 +1,80 @@
+use crate::components::status_effects::StatModifiers;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Health {
+    pub integrity: i32,
+    /// Integrity can not be restored above this value
+    pub max_integrity: i32,
+    pub armor: i32,
+}
+
+impl Health {
+    /// Creates a health at its maximum integrity
+    pub fn new(integrity: i32, armor: i32) -> Self {
+        Health {
+            integrity,
+            max_integrity: integrity,
+            armor,
+        }
+    }
+
+    /// Restores integrity up to the maximum and returns the restored amount
+    pub fn heal(&mut self, amount: i32) -> i32 {
+        let healed = (self.integrity + amount.max(0))
+            .min(self.max_integrity)
+            .max(self.integrity);
+        let restored = healed - self.integrity;
+        self.integrity = healed;
+        restored
+    }
+
+    pub fn get_armor(&self, modifiers: &StatModifiers) -> i32 {
+        (self.armor + modifiers.armor).max(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_armor_adds_modifier() {
+        let health = Health::new(5, 1);
+        let modifiers = StatModifiers {
+            armor: 2,
+            ..StatModifiers::default()
+        };
+        assert_eq!(health.get_armor(&modifiers), 3);
+    }
+
+    #[test]
+    fn heal_does_not_exceed_max_integrity() {
+        let mut health = Health {
+            integrity: 3,
+            ..Health::new(5, 0)
+        };
+        assert_eq!(health.heal(4), 2);
+        assert_eq!(health.integrity, 5);
+        assert_eq!(health.heal(1), 0);
+    }
+
+    #[test]
+    fn heal_ignores_negative_amounts() {
+        let mut health = Health {
+            integrity: 3,
+            ..Health::new(5, 0)
+        };
+        assert_eq!(health.heal(-1), 0);
+        assert_eq!(health.integrity, 3);
+    }
+
+    #[test]
+    fn get_armor_is_never_negative() {
+        let health = Health::new(5, 1);
+        let modifiers = StatModifiers {
+            armor: -3,
+            ..StatModifiers::default()
+        };
+        assert_eq!(health.get_armor(&modifiers), 0);
+    }
+}
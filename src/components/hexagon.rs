@@ -1,5 +1,10 @@
+use crate::layout::Layout;
 use gdnative::core_types::Vector2;
+use std::fmt;
 use std::hash::Hash;
+use std::num::ParseIntError;
+use std::ops::{Add, Mul, Sub};
+use std::str::FromStr;
 
 /// Hexagonal map cube position as describe here: https://www.redblobgames.com/grids/hexagons/#coordinates-cube
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
@@ -24,9 +29,8 @@ impl Hexagon {
         }
     }
 
-    pub fn from_vector2(pos: Vector2, hexfield_size: f32) -> Hexagon {
-        let q = (3_f32.sqrt() / 3_f32 * pos.x - 1_f32 / 3_f32 * pos.y) / (hexfield_size);
-        let r = (2_f32 / 3_f32 * pos.y) / (hexfield_size);
+    pub fn from_vector2(pos: Vector2, layout: &Layout) -> Hexagon {
+        let (q, r) = layout.pixel_to_axial(pos);
         let s = -q - r;
 
         cube_round(q, r, s)
@@ -70,21 +74,214 @@ impl Hexagon {
         self.distance_to(&other) == 1
     }
 
+    /// Returns the direction that points closest to the other position,
+    /// or None if both positions are the same
+    pub fn get_direction_to(&self, other: &Hexagon) -> Option<Direction> {
+        let distance = self.distance_to(other);
+        if distance == 0 {
+            return None;
+        }
+        let relative = *other - *self;
+        Direction::iter()
+            .min_by_key(|direction| relative.distance_to(&(direction.get_offset() * distance)))
+    }
+
     pub fn get_neighbour(&self, direction: Direction) -> Hexagon {
-        let cube_directions = [
-            Hexagon::new_cube(1, 0, -1),
-            Hexagon::new_cube(1, -1, 0),
-            Hexagon::new_cube(0, -1, 1),
-            Hexagon::new_cube(-1, 0, 1),
-            Hexagon::new_cube(-1, 1, 0),
-            Hexagon::new_cube(0, 1, -1),
-        ];
-        let direction = cube_directions[direction as usize];
-        Hexagon::new_cube(
-            self.q + direction.q,
-            self.r + direction.r,
-            self.s + direction.s,
-        )
+        *self + direction.get_offset()
+    }
+
+    /// Rotates the position by 60° steps around the given center.
+    /// Positive steps rotate right (clockwise on screen), negative steps rotate left.
+    pub fn rotate_around(&self, center: &Hexagon, steps: i32) -> Hexagon {
+        // https://www.redblobgames.com/grids/hexagons/#rotation
+        let mut relative = *self - *center;
+        for _ in 0..steps.rem_euclid(6) {
+            relative = Hexagon::new_cube(-relative.r, -relative.s, -relative.q);
+        }
+        relative + *center
+    }
+
+    /// Mirrors the position across the q axis, swapping r and s
+    pub fn reflect_q(&self) -> Hexagon {
+        // https://www.redblobgames.com/grids/hexagons/#reflection
+        Hexagon::new_cube(self.q, self.s, self.r)
+    }
+
+    /// Mirrors the position across the r axis, swapping q and s
+    pub fn reflect_r(&self) -> Hexagon {
+        Hexagon::new_cube(self.s, self.r, self.q)
+    }
+
+    /// Mirrors the position across the s axis, swapping q and r
+    pub fn reflect_s(&self) -> Hexagon {
+        Hexagon::new_cube(self.r, self.q, self.s)
+    }
+
+    /// Returns the positions on the straight line to the other position, including both ends
+    pub fn line_to(&self, other: &Hexagon) -> Vec<Hexagon> {
+        // https://www.redblobgames.com/grids/hexagons/#line-drawing
+        let distance = self.distance_to(other);
+        if distance == 0 {
+            return vec![*self];
+        }
+        // Nudges the points off the edges between two positions, so that they round the same way
+        let lerp = |a: i32, b: i32, epsilon: f32, t: f32| a as f32 + epsilon + (b - a) as f32 * t;
+        (0..=distance)
+            .map(|step| {
+                let t = step as f32 / distance as f32;
+                cube_round(
+                    lerp(self.q, other.q, 1e-3, t),
+                    lerp(self.r, other.r, 2e-3, t),
+                    lerp(self.s, other.s, -3e-3, t),
+                )
+            })
+            .collect()
+    }
+}
+
+impl Add for Hexagon {
+    type Output = Hexagon;
+
+    fn add(self, other: Hexagon) -> Hexagon {
+        Hexagon::new_cube(self.q + other.q, self.r + other.r, self.s + other.s)
+    }
+}
+
+impl Sub for Hexagon {
+    type Output = Hexagon;
+
+    fn sub(self, other: Hexagon) -> Hexagon {
+        Hexagon::new_cube(self.q - other.q, self.r - other.r, self.s - other.s)
+    }
+}
+
+impl Mul<i32> for Hexagon {
+    type Output = Hexagon;
+
+    fn mul(self, factor: i32) -> Hexagon {
+        Hexagon::new_cube(self.q * factor, self.r * factor, self.s * factor)
+    }
+}
+
+/// Offset coordinate systems as described here: https://www.redblobgames.com/grids/hexagons/#coordinates-offset
+/// The "r" variants are used for pointy topped layouts, the "q" variants for flat topped ones.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OffsetType {
+    /// Shoves odd rows right
+    OddR,
+    /// Shoves even rows right
+    EvenR,
+    /// Shoves odd columns down
+    OddQ,
+    /// Shoves even columns down
+    EvenQ,
+}
+
+/// Doubled coordinate systems as described here: https://www.redblobgames.com/grids/hexagons/#coordinates-doubled
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DoubledType {
+    /// Doubles the column values, used for pointy topped layouts
+    Width,
+    /// Doubles the row values, used for flat topped layouts
+    Height,
+}
+
+impl Hexagon {
+    /// Creates a position from offset coordinates
+    pub fn from_offset(col: i32, row: i32, offset_type: OffsetType) -> Hexagon {
+        // https://www.redblobgames.com/grids/hexagons/#conversions-offset
+        match offset_type {
+            OffsetType::OddR => Hexagon::new_axial(col - (row - (row & 1)) / 2, row),
+            OffsetType::EvenR => Hexagon::new_axial(col - (row + (row & 1)) / 2, row),
+            OffsetType::OddQ => Hexagon::new_axial(col, row - (col - (col & 1)) / 2),
+            OffsetType::EvenQ => Hexagon::new_axial(col, row - (col + (col & 1)) / 2),
+        }
+    }
+
+    /// Returns the offset coordinates as (col, row)
+    pub fn to_offset(self, offset_type: OffsetType) -> (i32, i32) {
+        match offset_type {
+            OffsetType::OddR => (self.q + (self.r - (self.r & 1)) / 2, self.r),
+            OffsetType::EvenR => (self.q + (self.r + (self.r & 1)) / 2, self.r),
+            OffsetType::OddQ => (self.q, self.r + (self.q - (self.q & 1)) / 2),
+            OffsetType::EvenQ => (self.q, self.r + (self.q + (self.q & 1)) / 2),
+        }
+    }
+
+    /// Creates a position from doubled coordinates. Returns None if col and row
+    /// do not describe a hexagon, as only every second value is used.
+    pub fn from_doubled(col: i32, row: i32, doubled_type: DoubledType) -> Option<Hexagon> {
+        // https://www.redblobgames.com/grids/hexagons/#conversions-doubled
+        if (col + row) % 2 != 0 {
+            return None;
+        }
+        match doubled_type {
+            DoubledType::Width => Some(Hexagon::new_axial((col - row) / 2, row)),
+            DoubledType::Height => Some(Hexagon::new_axial(col, (row - col) / 2)),
+        }
+    }
+
+    /// Returns the doubled coordinates as (col, row)
+    pub fn to_doubled(self, doubled_type: DoubledType) -> (i32, i32) {
+        match doubled_type {
+            DoubledType::Width => (2 * self.q + self.r, self.r),
+            DoubledType::Height => (self.q, 2 * self.r + self.q),
+        }
+    }
+}
+
+/// Formats the position as cube coordinates: "q,r,s"
+impl fmt::Display for Hexagon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}", self.q, self.r, self.s)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseHexagonError {
+    WrongNumberOfCoordinates(usize),
+    InvalidCoordinate(ParseIntError),
+    CoordinatesDoNotSumToZero,
+}
+
+impl fmt::Display for ParseHexagonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseHexagonError::WrongNumberOfCoordinates(count) => {
+                write!(f, "expected 3 coordinates, found {}", count)
+            }
+            ParseHexagonError::InvalidCoordinate(error) => {
+                write!(f, "invalid coordinate: {}", error)
+            }
+            ParseHexagonError::CoordinatesDoNotSumToZero => {
+                write!(f, "coordinates do not sum to 0")
+            }
+        }
+    }
+}
+
+/// Parses cube coordinates in the format written by `Display`: "q,r,s"
+impl FromStr for Hexagon {
+    type Err = ParseHexagonError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let coordinates = value
+            .split(',')
+            .map(|coordinate| coordinate.trim().parse::<i32>())
+            .collect::<Result<Vec<i32>, ParseIntError>>()
+            .map_err(ParseHexagonError::InvalidCoordinate)?;
+        match coordinates.as_slice() {
+            [q, r, s] => {
+                if q + r + s != 0 {
+                    Err(ParseHexagonError::CoordinatesDoNotSumToZero)
+                } else {
+                    Ok(Hexagon::new_cube(*q, *r, *s))
+                }
+            }
+            _ => Err(ParseHexagonError::WrongNumberOfCoordinates(
+                coordinates.len(),
+            )),
+        }
     }
 }
 
@@ -111,6 +308,7 @@ fn cube_round(x: f32, y: f32, z: f32) -> Hexagon {
     Hexagon::new_cube(rx as i32, ry as i32, rz as i32)
 }
 
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum Direction {
     East = 0,
     NorthEast = 1,
@@ -120,12 +318,51 @@ pub enum Direction {
     SouthEast = 5,
 }
 
+impl Direction {
+    /// All directions, counter clockwise starting at East
+    pub const ALL: [Direction; 6] = [
+        Direction::East,
+        Direction::NorthEast,
+        Direction::NorthWest,
+        Direction::West,
+        Direction::SouthWest,
+        Direction::SouthEast,
+    ];
+
+    pub fn iter() -> impl Iterator<Item = Direction> {
+        Self::ALL.iter().copied()
+    }
+
+    /// Returns the position of the neighbour in this direction relative to 0, 0, 0
+    pub fn get_offset(&self) -> Hexagon {
+        match self {
+            Direction::East => Hexagon::new_cube(1, 0, -1),
+            Direction::NorthEast => Hexagon::new_cube(1, -1, 0),
+            Direction::NorthWest => Hexagon::new_cube(0, -1, 1),
+            Direction::West => Hexagon::new_cube(-1, 0, 1),
+            Direction::SouthWest => Hexagon::new_cube(-1, 1, 0),
+            Direction::SouthEast => Hexagon::new_cube(0, 1, -1),
+        }
+    }
+
+    pub fn opposite(&self) -> Direction {
+        self.rotate(3)
+    }
+
+    /// Rotates the direction by 60° steps. Positive steps rotate right (clockwise on screen).
+    pub fn rotate(&self, steps: i32) -> Direction {
+        let index = (*self as i32 - steps).rem_euclid(6);
+        Self::ALL[index as usize]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::components::hexagon::Direction::{
         East, NorthEast, NorthWest, SouthEast, SouthWest, West,
     };
+    use proptest::prelude::*;
 
     macro_rules! new_axial_calculates_s_correctly {
         ($($name:ident: $value:expr,)*) => {
@@ -328,4 +565,291 @@ mod tests {
         neighbour_sw_2: (Hexagon::new_axial(-20, 13), SouthWest, Hexagon::new_cube(-21, 14, 7)),
         neighbour_se_2: (Hexagon::new_axial(-3, -8), SouthEast, Hexagon::new_cube(-3, -7, 10)),
     }
+
+    macro_rules! to_offset_returns_correct_values {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (hexagon, offset_type, expected) = $value;
+                assert_eq!(hexagon.to_offset(offset_type), expected);
+                assert_eq!(Hexagon::from_offset(expected.0, expected.1, offset_type), hexagon);
+            }
+        )*
+        }
+    }
+
+    to_offset_returns_correct_values! {
+        offset_odd_r_0: (Hexagon::new_axial(0, 0), OffsetType::OddR, (0, 0)),
+        offset_odd_r_1: (Hexagon::new_axial(0, 1), OffsetType::OddR, (0, 1)),
+        offset_odd_r_2: (Hexagon::new_axial(-1, 2), OffsetType::OddR, (0, 2)),
+        offset_odd_r_3: (Hexagon::new_axial(1, -1), OffsetType::OddR, (0, -1)),
+        offset_even_r_0: (Hexagon::new_axial(0, 1), OffsetType::EvenR, (1, 1)),
+        offset_even_r_1: (Hexagon::new_axial(-1, 2), OffsetType::EvenR, (0, 2)),
+        offset_even_r_2: (Hexagon::new_axial(1, -1), OffsetType::EvenR, (1, -1)),
+        offset_odd_q_0: (Hexagon::new_axial(1, 0), OffsetType::OddQ, (1, 0)),
+        offset_odd_q_1: (Hexagon::new_axial(2, -1), OffsetType::OddQ, (2, 0)),
+        offset_odd_q_2: (Hexagon::new_axial(-1, 1), OffsetType::OddQ, (-1, 0)),
+        offset_even_q_0: (Hexagon::new_axial(1, 0), OffsetType::EvenQ, (1, 1)),
+        offset_even_q_1: (Hexagon::new_axial(2, -1), OffsetType::EvenQ, (2, 0)),
+        offset_even_q_2: (Hexagon::new_axial(-1, 0), OffsetType::EvenQ, (-1, 0)),
+    }
+
+    macro_rules! to_doubled_returns_correct_values {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (hexagon, doubled_type, expected) = $value;
+                assert_eq!(hexagon.to_doubled(doubled_type), expected);
+                assert_eq!(Hexagon::from_doubled(expected.0, expected.1, doubled_type), Some(hexagon));
+            }
+        )*
+        }
+    }
+
+    to_doubled_returns_correct_values! {
+        doubled_width_0: (Hexagon::new_axial(0, 0), DoubledType::Width, (0, 0)),
+        doubled_width_1: (Hexagon::new_axial(1, 0), DoubledType::Width, (2, 0)),
+        doubled_width_2: (Hexagon::new_axial(0, 1), DoubledType::Width, (1, 1)),
+        doubled_width_3: (Hexagon::new_axial(-3, 2), DoubledType::Width, (-4, 2)),
+        doubled_height_0: (Hexagon::new_axial(1, 0), DoubledType::Height, (1, 1)),
+        doubled_height_1: (Hexagon::new_axial(0, 1), DoubledType::Height, (0, 2)),
+        doubled_height_2: (Hexagon::new_axial(2, -3), DoubledType::Height, (2, -4)),
+    }
+
+    #[test]
+    fn from_doubled_returns_none_for_unused_coordinates() {
+        assert_eq!(Hexagon::from_doubled(1, 0, DoubledType::Width), None);
+        assert_eq!(Hexagon::from_doubled(0, -3, DoubledType::Height), None);
+    }
+
+    #[test]
+    fn add_and_sub_combine_each_coordinate() {
+        let first = Hexagon::new_axial(3, -5);
+        let second = Hexagon::new_axial(-1, 2);
+        assert_eq!(first + second, Hexagon::new_cube(2, -3, 1));
+        assert_eq!(first - second, Hexagon::new_cube(4, -7, 3));
+    }
+
+    #[test]
+    #[allow(clippy::erasing_op)]
+    fn mul_scales_each_coordinate() {
+        assert_eq!(Hexagon::new_axial(2, -1) * 3, Hexagon::new_cube(6, -3, -3));
+        assert_eq!(Hexagon::new_axial(2, -1) * -1, Hexagon::new_cube(-2, 1, 1));
+        assert_eq!(Hexagon::new_axial(2, -1) * 0, Hexagon::zero());
+    }
+
+    macro_rules! rotate_around_returns_correct_values {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (hexagon, center, steps, expected) = $value;
+                assert_eq!(hexagon.rotate_around(&center, steps), expected);
+            }
+        )*
+        }
+    }
+
+    rotate_around_returns_correct_values! {
+        rotate_0: (Hexagon::new_axial(1, 0), Hexagon::zero(), 0, Hexagon::new_axial(1, 0)),
+        rotate_right: (Hexagon::new_axial(1, 0), Hexagon::zero(), 1, Hexagon::new_axial(0, 1)),
+        rotate_left: (Hexagon::new_axial(1, 0), Hexagon::zero(), -1, Hexagon::new_axial(1, -1)),
+        rotate_half: (Hexagon::new_axial(2, -3), Hexagon::zero(), 3, Hexagon::new_axial(-2, 3)),
+        rotate_full: (Hexagon::new_axial(2, -3), Hexagon::zero(), 6, Hexagon::new_axial(2, -3)),
+        rotate_center: (Hexagon::new_axial(6, 3), Hexagon::new_axial(5, 3), 1, Hexagon::new_axial(5, 4)),
+        rotate_self: (Hexagon::new_axial(5, 3), Hexagon::new_axial(5, 3), 2, Hexagon::new_axial(5, 3)),
+    }
+
+    #[test]
+    fn reflect_keeps_the_mirror_axis() {
+        let hexagon = Hexagon::new_cube(1, 2, -3);
+        assert_eq!(hexagon.reflect_q(), Hexagon::new_cube(1, -3, 2));
+        assert_eq!(hexagon.reflect_r(), Hexagon::new_cube(-3, 2, 1));
+        assert_eq!(hexagon.reflect_s(), Hexagon::new_cube(2, 1, -3));
+    }
+
+    #[test]
+    fn line_to_returns_straight_line() {
+        assert_eq!(
+            Hexagon::zero().line_to(&Hexagon::new_axial(3, 0)),
+            vec![
+                Hexagon::zero(),
+                Hexagon::new_axial(1, 0),
+                Hexagon::new_axial(2, 0),
+                Hexagon::new_axial(3, 0),
+            ]
+        );
+        assert_eq!(
+            Hexagon::zero().line_to(&Hexagon::zero()),
+            vec![Hexagon::zero()]
+        );
+    }
+
+    #[test]
+    fn direction_iter_returns_all_neighbours() {
+        let neighbours: Vec<Hexagon> = Direction::iter()
+            .map(|direction| Hexagon::zero().get_neighbour(direction))
+            .collect();
+        assert_eq!(neighbours.len(), 6);
+        assert!(neighbours
+            .iter()
+            .all(|neighbour| neighbour.is_neighbour(&Hexagon::zero())));
+    }
+
+    macro_rules! opposite_returns_correct_direction {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (direction, expected) = $value;
+                assert_eq!(direction.opposite(), expected);
+                assert_eq!(direction.get_offset() * -1, expected.get_offset());
+            }
+        )*
+        }
+    }
+
+    opposite_returns_correct_direction! {
+        opposite_e: (East, West),
+        opposite_ne: (NorthEast, SouthWest),
+        opposite_nw: (NorthWest, SouthEast),
+        opposite_w: (West, East),
+        opposite_sw: (SouthWest, NorthEast),
+        opposite_se: (SouthEast, NorthWest),
+    }
+
+    macro_rules! get_direction_to_returns_closest_direction {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (first, second, expected) = $value;
+                assert_eq!(first.get_direction_to(&second), expected);
+            }
+        )*
+        }
+    }
+
+    get_direction_to_returns_closest_direction! {
+        direction_to_self: (Hexagon::new_axial(2, 2), Hexagon::new_axial(2, 2), None),
+        direction_to_e: (Hexagon::zero(), Hexagon::new_axial(3, 0), Some(East)),
+        direction_to_w: (Hexagon::new_axial(1, 1), Hexagon::new_axial(-4, 1), Some(West)),
+        direction_to_se: (Hexagon::zero(), Hexagon::new_axial(0, 5), Some(SouthEast)),
+        direction_to_mostly_ne: (Hexagon::zero(), Hexagon::new_axial(3, -4), Some(NorthEast)),
+        direction_to_mostly_nw: (Hexagon::zero(), Hexagon::new_axial(-1, -4), Some(NorthWest)),
+    }
+
+    #[test]
+    fn direction_rotate_matches_hexagon_rotation() {
+        for direction in Direction::iter() {
+            for steps in -7..8 {
+                assert_eq!(
+                    direction.rotate(steps).get_offset(),
+                    direction
+                        .get_offset()
+                        .rotate_around(&Hexagon::zero(), steps)
+                );
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn rotate_around_keeps_distance_to_center(hexagon in any_hexagon(), center in any_hexagon(), steps in -12..12) {
+            let rotated = hexagon.rotate_around(&center, steps);
+            prop_assert_eq!(rotated.distance_to(&center), hexagon.distance_to(&center));
+            prop_assert_eq!(rotated.rotate_around(&center, -steps), hexagon);
+        }
+
+        #[test]
+        fn line_to_connects_neighbours(start in map_hexagon(), end in map_hexagon()) {
+            let line = start.line_to(&end);
+            prop_assert_eq!(line.len() as i32, start.distance_to(&end) + 1);
+            prop_assert_eq!(line.first(), Some(&start));
+            prop_assert_eq!(line.last(), Some(&end));
+            prop_assert!(line.windows(2).all(|pair| pair[0].is_neighbour(&pair[1])));
+        }
+
+        #[test]
+        fn reflect_twice_returns_same_hexagon(hexagon in any_hexagon()) {
+            prop_assert_eq!(hexagon.reflect_q().reflect_q(), hexagon);
+            prop_assert_eq!(hexagon.reflect_r().reflect_r(), hexagon);
+            prop_assert_eq!(hexagon.reflect_s().reflect_s(), hexagon);
+        }
+    }
+
+    #[test]
+    fn display_writes_cube_coordinates() {
+        assert_eq!(Hexagon::new_axial(3, -5).to_string(), "3,-5,2");
+    }
+
+    macro_rules! from_str_returns_error_for_invalid_input {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (input, expected) = $value;
+                match input.parse::<Hexagon>() {
+                    Ok(_) => panic!("Expected a result with Error value"),
+                    Err(error) => assert!(expected(error)),
+                }
+            }
+        )*
+        }
+    }
+
+    from_str_returns_error_for_invalid_input! {
+        parse_too_few: ("1,2", |e| e == ParseHexagonError::WrongNumberOfCoordinates(2)),
+        parse_too_many: ("1,2,-3,0", |e| e == ParseHexagonError::WrongNumberOfCoordinates(4)),
+        parse_not_a_number: ("1,a,-1", |e| matches!(e, ParseHexagonError::InvalidCoordinate(_))),
+        parse_empty: ("", |e| matches!(e, ParseHexagonError::InvalidCoordinate(_))),
+        parse_bad_sum: ("1,1,1", |e| e == ParseHexagonError::CoordinatesDoNotSumToZero),
+    }
+
+    #[test]
+    fn from_str_accepts_whitespace() {
+        assert_eq!(" 4, -1 , -3".parse(), Ok(Hexagon::new_axial(4, -1)));
+    }
+
+    fn any_hexagon() -> impl Strategy<Value = Hexagon> {
+        (-10_000..10_000, -10_000..10_000).prop_map(|(q, r)| Hexagon::new_axial(q, r))
+    }
+
+    fn map_hexagon() -> impl Strategy<Value = Hexagon> {
+        (-128..128, -128..128).prop_map(|(q, r)| Hexagon::new_axial(q, r))
+    }
+
+    fn any_offset_type() -> impl Strategy<Value = OffsetType> {
+        prop_oneof![
+            Just(OffsetType::OddR),
+            Just(OffsetType::EvenR),
+            Just(OffsetType::OddQ),
+            Just(OffsetType::EvenQ),
+        ]
+    }
+
+    fn any_doubled_type() -> impl Strategy<Value = DoubledType> {
+        prop_oneof![Just(DoubledType::Width), Just(DoubledType::Height)]
+    }
+
+    proptest! {
+        #[test]
+        fn offset_round_trip(hexagon in any_hexagon(), offset_type in any_offset_type()) {
+            let (col, row) = hexagon.to_offset(offset_type);
+            prop_assert_eq!(Hexagon::from_offset(col, row, offset_type), hexagon);
+        }
+
+        #[test]
+        fn doubled_round_trip(hexagon in any_hexagon(), doubled_type in any_doubled_type()) {
+            let (col, row) = hexagon.to_doubled(doubled_type);
+            prop_assert_eq!(Hexagon::from_doubled(col, row, doubled_type), Some(hexagon));
+        }
+
+        #[test]
+        fn string_round_trip(hexagon in any_hexagon()) {
+            prop_assert_eq!(hexagon.to_string().parse::<Hexagon>(), Ok(hexagon));
+        }
+    }
 }
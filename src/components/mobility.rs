@@ -0,0 +1,99 @@
+use crate::components::status_effects::StatModifiers;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Mobility {
+    pub mobility: i32,
+    pub remaining_range: i32,
+}
+
+impl Mobility {
+    pub fn new(mobility: i32, remaining_range: i32) -> Self {
+        Mobility {
+            mobility,
+            remaining_range,
+        }
+    }
+
+    /// The range the unit gets at the start of a round
+    pub fn get_mobility(&self, modifiers: &StatModifiers) -> i32 {
+        (self.mobility + modifiers.mobility).max(0)
+    }
+
+    pub fn is_in_movement_range(&self, distance: i32, modifiers: &StatModifiers) -> CanMove {
+        if modifiers.can_move && distance > 0 && self.remaining_range >= distance {
+            CanMove::Yes(self.remaining_range - distance)
+        } else {
+            CanMove::No
+        }
+    }
+}
+
+pub enum CanMove {
+    Yes(i32),
+    No,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn is_in_movement_range_returns_ok_with_remaining_distance_if_distance_is_below_or_equal_to_remaining_range(
+    ) {
+        let mobility = Mobility::new(0, 5);
+        let result = mobility.is_in_movement_range(4, &StatModifiers::default());
+        match result {
+            CanMove::Yes(remaining_range) => assert_eq!(remaining_range, 1),
+            _ => panic!("Expected result of Yes"),
+        }
+
+        let result = mobility.is_in_movement_range(5, &StatModifiers::default());
+        match result {
+            CanMove::Yes(remaining_range) => assert_eq!(remaining_range, 0),
+            _ => panic!("Expected result of Yes"),
+        }
+    }
+
+    #[test]
+    pub fn is_in_movement_range_returns_no_if_distance_is_higher_than_remaining_range() {
+        let mobility = Mobility::new(0, 4);
+        let result = mobility.is_in_movement_range(5, &StatModifiers::default());
+        match result {
+            CanMove::No => {}
+            _ => panic!("Expected result of No"),
+        };
+    }
+
+    #[test]
+    pub fn is_in_movement_range_returns_no_if_distance_is_0() {
+        let mobility = Mobility::new(0, 4);
+        let result = mobility.is_in_movement_range(0, &StatModifiers::default());
+        match result {
+            CanMove::No => {}
+            _ => panic!("Expected result of No"),
+        };
+    }
+
+    #[test]
+    pub fn is_in_movement_range_returns_no_if_unit_cannot_move() {
+        let mobility = Mobility::new(0, 4);
+        let modifiers = StatModifiers {
+            can_move: false,
+            ..StatModifiers::default()
+        };
+        match mobility.is_in_movement_range(1, &modifiers) {
+            CanMove::No => {}
+            _ => panic!("Expected result of No"),
+        };
+    }
+
+    #[test]
+    pub fn get_mobility_adds_modifier() {
+        let mobility = Mobility::new(3, 0);
+        let modifiers = StatModifiers {
+            mobility: -1,
+            ..StatModifiers::default()
+        };
+        assert_eq!(mobility.get_mobility(&modifiers), 2);
+    }
+}
@@ -0,0 +1,166 @@
+use crate::components::status_effects::StatModifiers;
+
+/// Morale lost when losing all integrity in one attack
+pub const DAMAGE_MORALE: i32 = 10;
+/// Additional morale lost when attacked while at least two enemies are adjacent
+pub const FLANKING_MORALE: i32 = 2;
+/// Morale lost when a friendly unit is destroyed nearby
+pub const FRIENDLY_DEATH_MORALE: i32 = 3;
+pub const FRIENDLY_DEATH_RANGE: i32 = 2;
+/// Morale recovered at the start of the turn when no enemy is adjacent
+pub const RALLY_MORALE: i32 = 1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MoraleState {
+    Steady,
+    /// Deals less damage
+    Shaken,
+    /// Can not be controlled and flees from enemies at the start of the turn
+    Routing,
+}
+
+impl MoraleState {
+    /// Short text shown in the icon list of the unit node
+    pub fn get_icon(&self) -> &'static str {
+        match self {
+            MoraleState::Steady => "",
+            MoraleState::Shaken => "SHK",
+            MoraleState::Routing => "RTE",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Morale {
+    pub morale: i32,
+    pub max_morale: i32,
+    /// The unit is shaken at or below this morale
+    pub shaken_at: i32,
+    /// The unit routs at or below this morale
+    pub routing_at: i32,
+}
+
+impl Morale {
+    pub fn new(max_morale: i32) -> Self {
+        Morale {
+            morale: max_morale,
+            max_morale,
+            shaken_at: max_morale / 2,
+            routing_at: max_morale / 5,
+        }
+    }
+
+    pub fn get_state(&self) -> MoraleState {
+        if self.morale <= self.routing_at {
+            MoraleState::Routing
+        } else if self.morale <= self.shaken_at {
+            MoraleState::Shaken
+        } else {
+            MoraleState::Steady
+        }
+    }
+
+    pub fn lose(&mut self, amount: i32) {
+        self.morale = (self.morale - amount.max(0)).max(0);
+    }
+
+    pub fn recover(&mut self, amount: i32) {
+        self.morale = (self.morale + amount.max(0)).min(self.max_morale);
+    }
+
+    pub fn get_modifiers(&self) -> StatModifiers {
+        match self.get_state() {
+            MoraleState::Steady => StatModifiers::default(),
+            MoraleState::Shaken => StatModifiers {
+                damage: -2,
+                ..StatModifiers::default()
+            },
+            MoraleState::Routing => StatModifiers {
+                can_move: false,
+                can_attack: false,
+                ..StatModifiers::default()
+            },
+        }
+    }
+}
+
+/// Returns the morale a unit loses by taking damage
+pub fn get_damage_morale_loss(damage: i32, max_integrity: i32) -> i32 {
+    if damage <= 0 {
+        0
+    } else {
+        (damage * DAMAGE_MORALE / max_integrity.max(1)).max(1)
+    }
+}
+
+/// Lets friendly units around the entity rally faster
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Leader {
+    pub range: i32,
+    /// Morale recovered at the start of the turn in addition to `RALLY_MORALE`
+    pub rally: i32,
+}
+
+impl Leader {
+    pub fn new(range: i32, rally: i32) -> Self {
+        Leader { range, rally }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! get_state_returns_correct_state {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (morale, expected) = $value;
+                let morale = Morale {
+                    morale,
+                    ..Morale::new(10)
+                };
+                assert_eq!(morale.get_state(), expected);
+            }
+        )*
+        }
+    }
+
+    get_state_returns_correct_state! {
+        get_state_full: (10, MoraleState::Steady),
+        get_state_above_shaken: (6, MoraleState::Steady),
+        get_state_shaken: (5, MoraleState::Shaken),
+        get_state_above_routing: (3, MoraleState::Shaken),
+        get_state_routing: (2, MoraleState::Routing),
+        get_state_empty: (0, MoraleState::Routing),
+    }
+
+    #[test]
+    fn lose_and_recover_stay_in_bounds() {
+        let mut morale = Morale::new(10);
+        morale.lose(15);
+        assert_eq!(morale.morale, 0);
+        morale.recover(15);
+        assert_eq!(morale.morale, 10);
+    }
+
+    #[test]
+    fn routing_units_can_not_act() {
+        let morale = Morale {
+            morale: 0,
+            ..Morale::new(10)
+        };
+        let modifiers = morale.get_modifiers();
+        assert!(!modifiers.can_move);
+        assert!(!modifiers.can_attack);
+    }
+
+    #[test]
+    fn get_damage_morale_loss_scales_with_max_integrity() {
+        assert_eq!(get_damage_morale_loss(0, 10), 0);
+        assert_eq!(get_damage_morale_loss(5, 10), 5);
+        assert_eq!(get_damage_morale_loss(1, 20), 1);
+        assert_eq!(get_damage_morale_loss(20, 20), DAMAGE_MORALE);
+    }
+}
@@ -0,0 +1,55 @@
+use crate::components::economy::Income;
+use crate::economy::Currency;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ObjectiveKind {
+    Village,
+    Depot,
+    Flag,
+}
+
+impl ObjectiveKind {
+    pub fn iter() -> impl Iterator<Item = ObjectiveKind> {
+        [
+            ObjectiveKind::Village,
+            ObjectiveKind::Depot,
+            ObjectiveKind::Flag,
+        ]
+        .iter()
+        .copied()
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ObjectiveKind::Village => "Village",
+            ObjectiveKind::Depot => "Depot",
+            ObjectiveKind::Flag => "Flag",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ObjectiveKind> {
+        ObjectiveKind::iter().find(|kind| kind.get_name() == name)
+    }
+
+    /// What the objective earns its owner every turn
+    pub fn get_income(&self) -> Income {
+        match self {
+            ObjectiveKind::Village => Income::new(Currency::Credits, 2),
+            ObjectiveKind::Depot => Income::new(Currency::Fuel, 2),
+            ObjectiveKind::Flag => Income::new(Currency::Credits, 3),
+        }
+    }
+}
+
+/// A point on the map that a player captures by ending the turn with a unit on it.
+/// The owner is the `Player` of the entity, objectives without one are neutral.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Objective {
+    pub kind: ObjectiveKind,
+}
+
+impl Objective {
+    pub fn new(kind: ObjectiveKind) -> Self {
+        Objective { kind }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::catalogue::UnitKind;
+use std::collections::VecDeque;
+use std::fmt;
+
+/// A unit that is being built
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Order {
+    pub kind: UnitKind,
+    pub remaining_turns: u32,
+}
+
+/// Lets the owner of the entity recruit units from its list. Recruited units are built
+/// one after another and appear on or next to the entity when they are ready.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Production {
+    pub recruits: Vec<UnitKind>,
+    pub queue: VecDeque<Order>,
+}
+
+impl Production {
+    pub fn new(recruits: Vec<UnitKind>) -> Self {
+        Production {
+            recruits,
+            queue: VecDeque::new(),
+        }
+    }
+
+    pub fn get_recruit(&self, index: usize) -> Option<UnitKind> {
+        self.recruits.get(index).copied()
+    }
+
+    pub fn order(&mut self, kind: UnitKind) {
+        self.queue.push_back(Order {
+            kind,
+            remaining_turns: kind.get_build_turns(),
+        });
+    }
+
+    /// Advances the order at the front of the queue by one turn
+    pub fn tick(&mut self) {
+        if let Some(order) = self.queue.front_mut() {
+            order.remaining_turns = order.remaining_turns.saturating_sub(1);
+        }
+    }
+
+    /// Returns the unit at the front of the queue if it is ready
+    pub fn get_finished(&self) -> Option<UnitKind> {
+        match self.queue.front() {
+            Some(order) if order.remaining_turns == 0 => Some(order.kind),
+            _ => None,
+        }
+    }
+
+    pub fn remove_finished(&mut self) {
+        if self.get_finished().is_some() {
+            self.queue.pop_front();
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RecruitError {
+    NotFound,
+    CanNotAfford,
+}
+
+impl fmt::Display for RecruitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecruitError::NotFound => write!(f, "Building can not recruit this unit"),
+            RecruitError::CanNotAfford => write!(f, "Not enough resources to recruit this unit"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_front_order_is_built() {
+        let mut production = Production::new(vec![UnitKind::Artillery]);
+        production.order(UnitKind::Artillery);
+        production.order(UnitKind::Artillery);
+        assert_eq!(production.get_finished(), None);
+
+        production.tick();
+        assert_eq!(production.get_finished(), Some(UnitKind::Artillery));
+        assert_eq!(production.queue[1].remaining_turns, 1);
+
+        production.remove_finished();
+        assert_eq!(production.queue.len(), 1);
+        assert_eq!(production.get_finished(), None);
+    }
+
+    #[test]
+    fn remove_finished_keeps_unfinished_orders() {
+        let mut production = Production::new(vec![UnitKind::Tank]);
+        production.order(UnitKind::Tank);
+        production.tick();
+        production.remove_finished();
+        assert_eq!(production.queue.len(), 1);
+    }
+}
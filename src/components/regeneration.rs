@@ -0,0 +1,16 @@
+/// Integrity a unit restores by itself at the end of its turn
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Regeneration {
+    pub amount: i32,
+    /// Only regenerate if the unit did not move, attack or use an ability during the turn
+    pub requires_rest: bool,
+}
+
+impl Regeneration {
+    pub fn new(amount: i32, requires_rest: bool) -> Self {
+        Regeneration {
+            amount,
+            requires_rest,
+        }
+    }
+}
@@ -0,0 +1,299 @@
+/// Temporary changes to the stats of a unit
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StatModifiers {
+    pub damage: i32,
+    pub armor: i32,
+    pub mobility: i32,
+    pub attack_range: i32,
+    pub can_move: bool,
+    pub can_attack: bool,
+}
+
+impl Default for StatModifiers {
+    fn default() -> Self {
+        StatModifiers {
+            damage: 0,
+            armor: 0,
+            mobility: 0,
+            attack_range: 0,
+            can_move: true,
+            can_attack: true,
+        }
+    }
+}
+
+impl StatModifiers {
+    /// Returns the sum of both modifiers. An action is only allowed if both allow it.
+    pub fn combine(&self, other: &StatModifiers) -> StatModifiers {
+        StatModifiers {
+            damage: self.damage + other.damage,
+            armor: self.armor + other.armor,
+            mobility: self.mobility + other.mobility,
+            attack_range: self.attack_range + other.attack_range,
+            can_move: self.can_move && other.can_move,
+            can_attack: self.can_attack && other.can_attack,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StatusEffectKind {
+    /// Can neither move nor attack
+    Stunned,
+    /// Less mobility per stack
+    Slowed,
+    /// Loses integrity per stack at every turn boundary
+    Poisoned,
+    /// More armor, but can not move
+    Entrenched,
+    /// Less damage and attack range
+    Suppressed,
+    /// Attacks the first enemy that moves into attack range
+    Overwatch,
+}
+
+/// What happens when an effect is applied to a unit that already has it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stacking {
+    /// The longer of both durations is kept
+    Refresh,
+    /// The durations are added
+    Extend,
+    /// A stack is added and the longer of both durations is kept
+    Intensify { max_stacks: i32 },
+}
+
+impl StatusEffectKind {
+    pub fn iter() -> impl Iterator<Item = StatusEffectKind> {
+        [
+            StatusEffectKind::Stunned,
+            StatusEffectKind::Slowed,
+            StatusEffectKind::Poisoned,
+            StatusEffectKind::Entrenched,
+            StatusEffectKind::Suppressed,
+            StatusEffectKind::Overwatch,
+        ]
+        .iter()
+        .copied()
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            StatusEffectKind::Stunned => "Stunned",
+            StatusEffectKind::Slowed => "Slowed",
+            StatusEffectKind::Poisoned => "Poisoned",
+            StatusEffectKind::Entrenched => "Entrenched",
+            StatusEffectKind::Suppressed => "Suppressed",
+            StatusEffectKind::Overwatch => "Overwatch",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<StatusEffectKind> {
+        StatusEffectKind::iter().find(|kind| kind.get_name() == name)
+    }
+
+    pub fn get_stacking(&self) -> Stacking {
+        match self {
+            StatusEffectKind::Stunned => Stacking::Refresh,
+            StatusEffectKind::Slowed => Stacking::Intensify { max_stacks: 3 },
+            StatusEffectKind::Poisoned => Stacking::Intensify { max_stacks: 5 },
+            StatusEffectKind::Entrenched => Stacking::Refresh,
+            StatusEffectKind::Suppressed => Stacking::Extend,
+            StatusEffectKind::Overwatch => Stacking::Refresh,
+        }
+    }
+
+    /// Short text shown in the icon list of the unit node
+    pub fn get_icon(&self) -> &'static str {
+        match self {
+            StatusEffectKind::Stunned => "STN",
+            StatusEffectKind::Slowed => "SLW",
+            StatusEffectKind::Poisoned => "PSN",
+            StatusEffectKind::Entrenched => "ENT",
+            StatusEffectKind::Suppressed => "SUP",
+            StatusEffectKind::Overwatch => "OVW",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StatusEffect {
+    pub kind: StatusEffectKind,
+    pub remaining_turns: i32,
+    pub stacks: i32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatusEffects {
+    pub effects: Vec<StatusEffect>,
+}
+
+impl StatusEffects {
+    pub fn new() -> Self {
+        StatusEffects {
+            effects: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, kind: StatusEffectKind, turns: i32) {
+        if turns <= 0 {
+            return;
+        }
+        match self.effects.iter_mut().find(|effect| effect.kind == kind) {
+            None => self.effects.push(StatusEffect {
+                kind,
+                remaining_turns: turns,
+                stacks: 1,
+            }),
+            Some(effect) => match kind.get_stacking() {
+                Stacking::Refresh => effect.remaining_turns = effect.remaining_turns.max(turns),
+                Stacking::Extend => effect.remaining_turns += turns,
+                Stacking::Intensify { max_stacks } => {
+                    effect.stacks = (effect.stacks + 1).min(max_stacks);
+                    effect.remaining_turns = effect.remaining_turns.max(turns);
+                }
+            },
+        }
+    }
+
+    pub fn has(&self, kind: StatusEffectKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    pub fn remove(&mut self, kind: StatusEffectKind) {
+        self.effects.retain(|effect| effect.kind != kind);
+    }
+
+    /// Advances all effects by one turn, removing expired ones.
+    /// Returns the damage dealt by the effects this turn.
+    pub fn tick(&mut self) -> i32 {
+        let damage = self
+            .effects
+            .iter()
+            .filter(|effect| effect.kind == StatusEffectKind::Poisoned)
+            .map(|effect| effect.stacks)
+            .sum();
+        for effect in self.effects.iter_mut() {
+            effect.remaining_turns -= 1;
+        }
+        self.effects.retain(|effect| effect.remaining_turns > 0);
+        damage
+    }
+
+    pub fn get_modifiers(&self) -> StatModifiers {
+        let mut modifiers = StatModifiers::default();
+        for effect in &self.effects {
+            match effect.kind {
+                StatusEffectKind::Stunned => {
+                    modifiers.can_move = false;
+                    modifiers.can_attack = false;
+                }
+                StatusEffectKind::Slowed => modifiers.mobility -= effect.stacks,
+                StatusEffectKind::Poisoned | StatusEffectKind::Overwatch => {}
+                StatusEffectKind::Entrenched => {
+                    modifiers.armor += 2;
+                    modifiers.can_move = false;
+                }
+                StatusEffectKind::Suppressed => {
+                    modifiers.damage -= 2;
+                    modifiers.attack_range -= 1;
+                }
+            }
+        }
+        modifiers
+    }
+
+    /// Text for the icon list of the unit node, e.g. "STN1 PSN2"
+    pub fn get_icon_text(&self) -> String {
+        self.effects
+            .iter()
+            .map(|effect| format!("{}{}", effect.kind.get_icon(), effect.remaining_turns))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_refresh_keeps_longer_duration() {
+        let mut effects = StatusEffects::new();
+        effects.add(StatusEffectKind::Stunned, 3);
+        effects.add(StatusEffectKind::Stunned, 1);
+        assert_eq!(effects.effects.len(), 1);
+        assert_eq!(effects.effects[0].remaining_turns, 3);
+        assert_eq!(effects.effects[0].stacks, 1);
+    }
+
+    #[test]
+    fn add_extend_adds_durations() {
+        let mut effects = StatusEffects::new();
+        effects.add(StatusEffectKind::Suppressed, 2);
+        effects.add(StatusEffectKind::Suppressed, 2);
+        assert_eq!(effects.effects[0].remaining_turns, 4);
+    }
+
+    #[test]
+    fn add_intensify_adds_stacks_up_to_maximum() {
+        let mut effects = StatusEffects::new();
+        for _ in 0..5 {
+            effects.add(StatusEffectKind::Slowed, 2);
+        }
+        assert_eq!(effects.effects[0].stacks, 3);
+        assert_eq!(effects.get_modifiers().mobility, -3);
+    }
+
+    #[test]
+    fn tick_removes_expired_effects() {
+        let mut effects = StatusEffects::new();
+        effects.add(StatusEffectKind::Stunned, 1);
+        effects.add(StatusEffectKind::Entrenched, 2);
+        effects.tick();
+        assert!(!effects.has(StatusEffectKind::Stunned));
+        assert!(effects.has(StatusEffectKind::Entrenched));
+        effects.tick();
+        assert!(effects.effects.is_empty());
+    }
+
+    #[test]
+    fn tick_returns_poison_damage_per_stack() {
+        let mut effects = StatusEffects::new();
+        effects.add(StatusEffectKind::Poisoned, 3);
+        effects.add(StatusEffectKind::Poisoned, 3);
+        assert_eq!(effects.tick(), 2);
+    }
+
+    #[test]
+    fn combine_adds_values_and_restrictions() {
+        let first = StatModifiers {
+            damage: 1,
+            can_move: false,
+            ..StatModifiers::default()
+        };
+        let second = StatModifiers {
+            damage: 2,
+            armor: -1,
+            ..StatModifiers::default()
+        };
+        let combined = first.combine(&second);
+        assert_eq!(combined.damage, 3);
+        assert_eq!(combined.armor, -1);
+        assert!(!combined.can_move);
+        assert!(combined.can_attack);
+    }
+
+    #[test]
+    fn get_modifiers_combines_effects() {
+        let mut effects = StatusEffects::new();
+        effects.add(StatusEffectKind::Entrenched, 1);
+        effects.add(StatusEffectKind::Suppressed, 1);
+        let modifiers = effects.get_modifiers();
+        assert_eq!(modifiers.armor, 2);
+        assert_eq!(modifiers.damage, -2);
+        assert_eq!(modifiers.attack_range, -1);
+        assert!(!modifiers.can_move);
+        assert!(modifiers.can_attack);
+    }
+}
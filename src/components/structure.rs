@@ -0,0 +1,92 @@
+use crate::components::health::Health;
+use gdnative::core_types::Color;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StructureKind {
+    Wall,
+    Bunker,
+    Bridge,
+    Building,
+}
+
+impl StructureKind {
+    pub fn iter() -> impl Iterator<Item = StructureKind> {
+        [
+            StructureKind::Wall,
+            StructureKind::Bunker,
+            StructureKind::Bridge,
+            StructureKind::Building,
+        ]
+        .iter()
+        .copied()
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            StructureKind::Wall => "Wall",
+            StructureKind::Bunker => "Bunker",
+            StructureKind::Bridge => "Bridge",
+            StructureKind::Building => "Building",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<StructureKind> {
+        StructureKind::iter().find(|kind| kind.get_name() == name)
+    }
+
+    /// The health a new structure of the kind starts with
+    pub fn get_health(&self) -> Health {
+        match self {
+            StructureKind::Wall => Health::new(15, 3),
+            StructureKind::Bunker => Health::new(20, 3),
+            StructureKind::Bridge => Health::new(10, 1),
+            StructureKind::Building => Health::new(25, 2),
+        }
+    }
+
+    pub fn get_colour(&self) -> Color {
+        match self {
+            StructureKind::Wall => Color::rgb(0.3, 0.3, 0.3),
+            StructureKind::Bunker => Color::rgb(0.45, 0.45, 0.4),
+            StructureKind::Bridge => Color::rgb(0.55, 0.4, 0.25),
+            StructureKind::Building => Color::rgb(0.6, 0.55, 0.5),
+        }
+    }
+
+    /// Whether units can not enter the hexagon of the structure
+    pub fn blocks_movement(&self) -> bool {
+        matches!(self, StructureKind::Wall)
+    }
+
+    /// Whether attacks can not be made through the hexagon of the structure
+    pub fn blocks_sight(&self) -> bool {
+        matches!(self, StructureKind::Wall | StructureKind::Bunker)
+    }
+
+    /// Whether units can cross impassable terrain on the hexagon of the structure
+    pub fn is_crossing(&self) -> bool {
+        matches!(self, StructureKind::Bridge)
+    }
+
+    /// Armor granted to units on the hexagon of the structure
+    pub fn get_defense(&self) -> i32 {
+        match self {
+            StructureKind::Bunker => 2,
+            StructureKind::Building => 1,
+            StructureKind::Wall | StructureKind::Bridge => 0,
+        }
+    }
+}
+
+/// A static entity that occupies a hexagon. Structures with `Health` can be attacked
+/// and are removed when it drops to 0.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Structure {
+    pub kind: StructureKind,
+}
+
+impl Structure {
+    pub fn new(kind: StructureKind) -> Self {
+        Structure { kind }
+    }
+}
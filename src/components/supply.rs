@@ -0,0 +1,22 @@
+/// Restores integrity and ammo of the units around the entity at the start of their turn.
+/// An entity with a player only supplies the units of that player.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Supply {
+    pub integrity: i32,
+    pub ammo: i32,
+    pub range: i32,
+}
+
+impl Supply {
+    pub fn new(integrity: i32, ammo: i32, range: i32) -> Self {
+        Supply {
+            integrity,
+            ammo,
+            range,
+        }
+    }
+
+    pub fn is_in_range(&self, distance: i32) -> bool {
+        distance <= self.range
+    }
+}
@@ -0,0 +1,96 @@
+use legion::Entity;
+
+/// What happens to the cargo when its transport is destroyed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CargoFate {
+    Destroyed,
+    /// Placed on the free hexagons around the transport, destroyed if there is no room
+    Ejected,
+}
+
+/// Lets friendly units embark by moving onto the entity
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transport {
+    pub capacity: usize,
+    pub cargo: Vec<Entity>,
+    pub on_destroyed: CargoFate,
+}
+
+impl Transport {
+    pub fn new(capacity: usize, on_destroyed: CargoFate) -> Self {
+        Transport {
+            capacity,
+            cargo: Vec::new(),
+            on_destroyed,
+        }
+    }
+
+    pub fn has_space(&self) -> bool {
+        self.cargo.len() < self.capacity
+    }
+
+    /// Adds the entity to the cargo and returns whether there was space for it
+    pub fn embark(&mut self, entity: Entity) -> bool {
+        if !self.has_space() || self.cargo.contains(&entity) {
+            return false;
+        }
+        self.cargo.push(entity);
+        true
+    }
+
+    /// Removes the entity from the cargo and returns whether it was aboard
+    pub fn disembark(&mut self, entity: Entity) -> bool {
+        let length = self.cargo.len();
+        self.cargo.retain(|cargo| *cargo != entity);
+        self.cargo.len() != length
+    }
+}
+
+/// Marks a unit that is aboard a transport. Carried units have no `Hexagon`,
+/// so they are not found on the map until they disembark.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Carried {
+    pub transport: Entity,
+}
+
+impl Carried {
+    pub fn new(transport: Entity) -> Self {
+        Carried { transport }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use legion::{World, WorldOptions};
+
+    fn create_entities(count: usize) -> Vec<Entity> {
+        let mut world = World::new(WorldOptions::default());
+        world.extend(vec![(0,); count]).to_vec()
+    }
+
+    #[test]
+    fn embark_respects_capacity() {
+        let entities = create_entities(3);
+        let mut transport = Transport::new(2, CargoFate::Destroyed);
+
+        assert!(transport.embark(entities[0]));
+        assert!(!transport.embark(entities[0]));
+        assert!(transport.embark(entities[1]));
+        assert!(!transport.has_space());
+        assert!(!transport.embark(entities[2]));
+        assert_eq!(transport.cargo, vec![entities[0], entities[1]]);
+    }
+
+    #[test]
+    fn disembark_removes_cargo() {
+        let entities = create_entities(2);
+        let mut transport = Transport::new(1, CargoFate::Ejected);
+        transport.embark(entities[0]);
+
+        assert!(!transport.disembark(entities[1]));
+        assert!(transport.disembark(entities[0]));
+        assert!(transport.cargo.is_empty());
+        assert!(transport.has_space());
+    }
+}
@@ -1,122 +1,150 @@
+use crate::components::action_budget::ActionBudget;
+use crate::components::action_points::ActionPoints;
+use crate::components::health::Health;
+use crate::components::status_effects::StatModifiers;
+use crate::components::weapon::Weapon;
+
+/// Marks an entity as a unit. The stats are in the `Health`, `Mobility`, `Weapons` and
+/// `ActionBudget` components, which a unit may or may not have.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Unit;
+
+/// The components of the attacking unit that take part in an attack
+#[derive(Clone)]
+pub struct Attacker {
+    /// The weapon used for the attack and its index in the `Weapons` of the unit
+    pub weapon: Weapon,
+    pub weapon_index: usize,
+    pub budget: ActionBudget,
+    pub points: Option<ActionPoints>,
+    pub modifiers: StatModifiers,
+}
+
+/// The components of the defending unit that take part in an attack
 #[derive(Copy, Clone)]
-pub struct Unit {
-    pub integrity: i32,
-    pub damage: i32,
-    pub max_attack_range: i32,
-    pub min_attack_range: i32,
-    pub armor: i32,
-    pub mobility: i32,
-    pub remaining_range: i32,
-    pub remaining_attacks: i32,
+pub struct Defender {
+    pub health: Health,
+    pub modifiers: StatModifiers,
 }
 
-impl Unit {
-    //TODO: Split this into separate components (combat, move)?
-    #[allow(clippy::too_many_arguments)]
+impl Attacker {
     pub fn new(
-        integrity: i32,
-        damage: i32,
-        max_attack_range: i32,
-        min_attack_range: i32,
-        armor: i32,
-        mobility: i32,
-        remaining_range: i32,
-        remaining_attacks: i32,
-    ) -> Unit {
-        Unit {
-            integrity,
-            damage,
-            max_attack_range,
-            min_attack_range,
-            armor,
-            mobility,
-            remaining_range,
-            remaining_attacks,
+        weapon: Weapon,
+        weapon_index: usize,
+        budget: ActionBudget,
+        modifiers: StatModifiers,
+    ) -> Self {
+        Attacker {
+            weapon,
+            weapon_index,
+            budget,
+            points: None,
+            modifiers,
         }
     }
 
-    pub fn attack(&self, defender: &Unit) -> Result<AttackResult, AttackError> {
-        if self.remaining_attacks <= 0 {
+    /// Returns the damage the attack would deal to the defender
+    pub fn forecast(&self, defender: &Defender) -> i32 {
+        self.weapon.damage_type.apply_armor(
+            self.weapon.get_damage(&self.modifiers),
+            defender.health.get_armor(&defender.modifiers),
+        )
+    }
+
+    pub fn attack(&self, defender: &Defender) -> Result<AttackResult, AttackError> {
+        if !self.modifiers.can_attack {
+            Err(AttackError::Disabled)
+        } else if self.budget.remaining_attacks <= 0
+            || self.budget.remaining_attacks < self.weapon.attack_cost
+        {
             Err(AttackError::NoAttacksLeft)
+        } else if !self.weapon.has_ammo() {
+            Err(AttackError::NoAmmo)
         } else {
-            let actual_damage = self.damage - defender.armor;
+            let actual_damage = self.forecast(defender);
 
-            let mut attacker = *self;
-            let mut defender = *defender;
+            let mut attacker = self.budget;
+            let mut points = self.points;
+            let mut weapon = self.weapon.clone();
+            let mut defender = defender.health;
             defender.integrity -= actual_damage;
-            attacker.remaining_range = 0;
-            attacker.remaining_attacks -= 1;
+            attacker.remaining_attacks -= self.weapon.attack_cost;
+            if let Some(points) = points.as_mut() {
+                points.spend_attacks(self.weapon.attack_cost);
+            }
+            weapon.ammo = weapon.ammo.map(|ammo| ammo - 1);
             Ok(AttackResult {
                 actual_damage,
                 attacker,
+                points,
+                weapon_index: self.weapon_index,
+                weapon,
                 defender,
             })
         }
     }
+}
 
-    pub fn is_in_movement_range(&self, distance: i32) -> CanMove {
-        if distance > 0 && self.remaining_range >= distance {
-            CanMove::Yes(self.remaining_range - distance)
-        } else {
-            CanMove::No
-        }
-    }
-
-    pub fn is_in_attack_range(&self, distance: i32) -> bool {
-        distance <= self.max_attack_range && distance >= self.min_attack_range
+impl Defender {
+    pub fn new(health: Health, modifiers: StatModifiers) -> Self {
+        Defender { health, modifiers }
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct AttackResult {
     pub actual_damage: i32,
-    pub attacker: Unit,
-    pub defender: Unit,
+    pub attacker: ActionBudget,
+    pub points: Option<ActionPoints>,
+    /// The used weapon with its remaining ammo
+    pub weapon_index: usize,
+    pub weapon: Weapon,
+    pub defender: Health,
 }
 
 pub enum AttackError {
     NoAttacksLeft,
-}
-
-pub enum CanMove {
-    Yes(i32),
-    No,
+    NoAmmo,
+    /// The unit is prevented from attacking, e.g. by being stunned
+    Disabled,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::components::action_points::ActionCosts;
+    use crate::components::weapon::DamageType;
 
-    #[test]
-    pub fn attack_reduces_integrity() {
-        let defender = Unit::new(5, 0, 0, 0, 0, 0, 0, 0);
-        let attacker = Unit::new(0, 4, 0, 0, 0, 0, 0, 1);
+    fn attacker(damage: i32, remaining_attacks: i32) -> Attacker {
+        Attacker::new(
+            Weapon::new("Test", damage, 0, 0),
+            0,
+            ActionBudget::new(1, remaining_attacks),
+            StatModifiers::default(),
+        )
+    }
 
-        let result = attacker.attack(&defender);
-        let result = match result {
-            Ok(x) => x,
-            Err(_) => panic!("Expected a result with Ok value"),
-        };
-        assert_eq!(result.defender.integrity, 1);
+    fn defender(integrity: i32, armor: i32) -> Defender {
+        Defender::new(Health::new(integrity, armor), StatModifiers::default())
     }
 
     #[test]
-    pub fn attack_sets_attacker_remaining_range_to_0() {
-        let defender = Unit::new(5, 0, 0, 0, 0, 0, 0, 0);
-        let attacker = Unit::new(0, 4, 0, 0, 0, 0, 5, 1);
+    pub fn attack_reduces_integrity() {
+        let defender = defender(5, 0);
+        let attacker = attacker(4, 1);
 
         let result = attacker.attack(&defender);
         let result = match result {
             Ok(x) => x,
             Err(_) => panic!("Expected a result with Ok value"),
         };
-        assert_eq!(result.attacker.remaining_range, 0);
+        assert_eq!(result.defender.integrity, 1);
     }
 
     #[test]
     pub fn attack_reduces_attacker_attacks_by_1() {
-        let defender = Unit::new(5, 0, 0, 0, 0, 0, 0, 0);
-        let attacker = Unit::new(0, 4, 0, 0, 0, 5, 0, 2);
+        let defender = defender(5, 0);
+        let mut attacker = attacker(4, 2);
 
         let result = attacker.attack(&defender);
         let result = match result {
@@ -124,7 +152,7 @@ mod tests {
             Err(_) => panic!("Expected a result with Ok value"),
         };
         assert_eq!(result.attacker.remaining_attacks, 1);
-        let attacker = result.attacker;
+        attacker.budget = result.attacker;
         let result = attacker.attack(&defender);
         let result = match result {
             Ok(x) => x,
@@ -135,8 +163,8 @@ mod tests {
 
     #[test]
     pub fn attack_takes_armor_into_account() {
-        let defender = Unit::new(5, 0, 0, 0, 1, 0, 0, 0);
-        let attacker = Unit::new(0, 4, 0, 0, 0, 0, 0, 1);
+        let defender = defender(5, 1);
+        let attacker = attacker(4, 1);
 
         let result = attacker.attack(&defender);
         let result = match result {
@@ -146,10 +174,24 @@ mod tests {
         assert_eq!(result.defender.integrity, 2);
     }
 
+    #[test]
+    pub fn attack_does_not_heal_when_armor_exceeds_damage() {
+        let defender = defender(5, 6);
+        let attacker = attacker(4, 1);
+
+        let result = attacker.attack(&defender);
+        let result = match result {
+            Ok(x) => x,
+            Err(_) => panic!("Expected a result with Ok value"),
+        };
+        assert_eq!(result.actual_damage, 0);
+        assert_eq!(result.defender.integrity, 5);
+    }
+
     #[test]
     pub fn attack_returns_correct_damage() {
-        let defender = Unit::new(5, 0, 0, 0, 1, 0, 0, 0);
-        let attacker = Unit::new(0, 4, 0, 0, 0, 0, 0, 1);
+        let defender = defender(5, 1);
+        let attacker = attacker(4, 1);
 
         let result = attacker.attack(&defender);
         let result = match result {
@@ -161,8 +203,8 @@ mod tests {
 
     #[test]
     pub fn attack_returns_error_when_attacker_has_no_attack_left() {
-        let defender = Unit::new(5, 0, 0, 0, 1, 0, 0, 0);
-        let attacker = Unit::new(0, 4, 0, 0, 0, 0, 0, 0);
+        let defender = defender(5, 1);
+        let attacker = attacker(4, 0);
 
         let result = attacker.attack(&defender);
         if result.is_ok() {
@@ -171,53 +213,79 @@ mod tests {
     }
 
     #[test]
-    pub fn is_in_movement_range_returns_ok_with_remaining_distance_if_distance_is_below_or_equal_to_remaining_range(
-    ) {
-        let unit = Unit::new(0, 0, 0, 0, 0, 0, 5, 0);
-        let result = unit.is_in_movement_range(4);
-        match result {
-            CanMove::Yes(remaining_range) => assert_eq!(remaining_range, 1),
-            _ => panic!("Expected result of Yes"),
-        }
+    pub fn attack_takes_modifiers_into_account() {
+        let mut defender = defender(5, 1);
+        defender.modifiers.armor = 1;
+        let mut attacker = attacker(4, 1);
+        attacker.modifiers.damage = -1;
 
-        let result = unit.is_in_movement_range(5);
-        match result {
-            CanMove::Yes(remaining_range) => assert_eq!(remaining_range, 0),
-            _ => panic!("Expected result of Yes"),
-        }
+        let result = match attacker.attack(&defender) {
+            Ok(x) => x,
+            Err(_) => panic!("Expected a result with Ok value"),
+        };
+        assert_eq!(result.actual_damage, 1);
     }
 
     #[test]
-    pub fn is_in_movement_range_returns_no_if_distance_is_higher_than_remaining_range() {
-        let unit = Unit::new(0, 0, 0, 0, 0, 0, 4, 0);
-        let result = unit.is_in_movement_range(5);
-        match result {
-            CanMove::No => {}
-            _ => panic!("Expected result of No"),
-        };
+    pub fn attack_returns_error_when_attacker_cannot_attack() {
+        let defender = defender(5, 0);
+        let mut attacker = attacker(4, 1);
+        attacker.modifiers.can_attack = false;
+
+        match attacker.attack(&defender) {
+            Err(AttackError::Disabled) => {}
+            _ => panic!("Expected a result with Disabled error"),
+        }
     }
 
     #[test]
-    pub fn is_in_movement_range_returns_no_if_distance_is_0() {
-        let unit = Unit::new(0, 0, 0, 0, 0, 0, 4, 0);
-        let result = unit.is_in_movement_range(0);
-        match result {
-            CanMove::No => {}
-            _ => panic!("Expected result of No"),
+    pub fn attack_uses_attack_cost_and_ammo() {
+        let defender = defender(5, 0);
+        let mut attacker = attacker(4, 2);
+        attacker.weapon.attack_cost = 2;
+        attacker.weapon.ammo = Some(1);
+
+        let result = match attacker.attack(&defender) {
+            Ok(x) => x,
+            Err(_) => panic!("Expected a result with Ok value"),
         };
+        assert_eq!(result.attacker.remaining_attacks, 0);
+        assert_eq!(result.weapon.ammo, Some(0));
+
+        attacker.budget.remaining_attacks = 2;
+        attacker.weapon = result.weapon;
+        match attacker.attack(&defender) {
+            Err(AttackError::NoAmmo) => {}
+            _ => panic!("Expected a result with NoAmmo error"),
+        }
     }
 
     #[test]
-    pub fn is_in_attack_range_returns_true_if_distance_is_inside_range() {
-        let unit = Unit::new(0, 0, 2, 1, 0, 0, 0, 0);
-        assert!(unit.is_in_attack_range(1));
-        assert!(unit.is_in_attack_range(2));
+    pub fn attack_spends_action_points() {
+        let defender = defender(5, 0);
+        let mut attacker = attacker(4, 1);
+        attacker.points = Some(ActionPoints::new(
+            4,
+            ActionCosts {
+                move_hexagon: 1,
+                attack: 3,
+                ability: 1,
+            },
+        ));
+
+        let result = match attacker.attack(&defender) {
+            Ok(x) => x,
+            Err(_) => panic!("Expected a result with Ok value"),
+        };
+        assert_eq!(result.points.unwrap().remaining_points, 1);
     }
 
     #[test]
-    pub fn is_in_attack_range_returns_false_if_distance_is_outside_range() {
-        let unit = Unit::new(0, 0, 2, 2, 0, 0, 0, 0);
-        assert!(!unit.is_in_attack_range(3));
-        assert!(!unit.is_in_attack_range(1));
+    pub fn forecast_uses_damage_type() {
+        let defender = defender(5, 4);
+        let mut attacker = attacker(4, 1);
+        assert_eq!(attacker.forecast(&defender), 0);
+        attacker.weapon.damage_type = DamageType::Piercing;
+        assert_eq!(attacker.forecast(&defender), 2);
     }
 }
@@ -0,0 +1,12 @@
+/// Reveals the hexagons within range of the entity to its player and their allies,
+/// when the rules hide the rest of the map
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Vision {
+    pub range: i32,
+}
+
+impl Vision {
+    pub fn new(range: i32) -> Self {
+        Vision { range }
+    }
+}
@@ -0,0 +1,279 @@
+use crate::components::action_budget::ActionBudget;
+use crate::components::status_effects::StatModifiers;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DamageType {
+    /// Armor is subtracted from the damage, down to no damage
+    Kinetic,
+    /// Only half of the armor is subtracted from the damage, down to no damage
+    Piercing,
+    /// Armor is subtracted from the damage, but at least 1 damage is dealt
+    Explosive,
+}
+
+impl DamageType {
+    pub fn apply_armor(&self, damage: i32, armor: i32) -> i32 {
+        match self {
+            DamageType::Kinetic => (damage - armor).max(0),
+            DamageType::Piercing => (damage - armor / 2).max(0),
+            DamageType::Explosive => (damage - armor).max(1),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Weapon {
+    pub name: String,
+    pub damage: i32,
+    pub max_attack_range: i32,
+    pub min_attack_range: i32,
+    pub damage_type: DamageType,
+    /// Remaining shots, unlimited if None
+    pub ammo: Option<i32>,
+    /// Ammo can not be resupplied above this value
+    pub max_ammo: Option<i32>,
+    /// Attacks used up by firing the weapon
+    pub attack_cost: i32,
+}
+
+impl Weapon {
+    pub fn new(name: &str, damage: i32, max_attack_range: i32, min_attack_range: i32) -> Self {
+        Weapon {
+            name: name.to_owned(),
+            damage,
+            max_attack_range,
+            min_attack_range,
+            damage_type: DamageType::Kinetic,
+            ammo: None,
+            max_ammo: None,
+            attack_cost: 1,
+        }
+    }
+
+    pub fn get_damage(&self, modifiers: &StatModifiers) -> i32 {
+        (self.damage + modifiers.damage).max(0)
+    }
+
+    pub fn get_max_attack_range(&self, modifiers: &StatModifiers) -> i32 {
+        self.max_attack_range + modifiers.attack_range
+    }
+
+    pub fn is_in_attack_range(&self, distance: i32, modifiers: &StatModifiers) -> bool {
+        modifiers.can_attack
+            && distance <= self.get_max_attack_range(modifiers)
+            && distance >= self.min_attack_range
+    }
+
+    pub fn has_ammo(&self) -> bool {
+        !matches!(self.ammo, Some(ammo) if ammo <= 0)
+    }
+
+    /// Adds ammo up to the maximum
+    pub fn resupply(&mut self, amount: i32) {
+        if let Some(ammo) = self.ammo {
+            let resupplied = ammo + amount.max(0);
+            self.ammo = Some(match self.max_ammo {
+                None => resupplied,
+                Some(max_ammo) => resupplied.min(max_ammo).max(ammo),
+            });
+        }
+    }
+
+    pub fn can_fire(&self, budget: &ActionBudget, modifiers: &StatModifiers) -> bool {
+        modifiers.can_attack && budget.remaining_attacks >= self.attack_cost && self.has_ammo()
+    }
+}
+
+/// The weapons of a unit and the one the player chose to attack with
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Weapons {
+    pub weapons: Vec<Weapon>,
+    /// The best weapon is picked for every attack if None
+    pub selected: Option<usize>,
+}
+
+impl Weapons {
+    pub fn new(weapons: Vec<Weapon>) -> Self {
+        Weapons {
+            weapons,
+            selected: None,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Weapon> {
+        self.weapons.get(index)
+    }
+
+    /// Whether the selected weapon or, without a selection, any of the weapons can reach
+    /// the distance
+    pub fn is_in_attack_range(&self, distance: i32, modifiers: &StatModifiers) -> bool {
+        match self.selected {
+            Some(index) => matches!(self.get(index), Some(weapon)
+                if weapon.is_in_attack_range(distance, modifiers)),
+            None => self
+                .weapons
+                .iter()
+                .any(|weapon| weapon.is_in_attack_range(distance, modifiers)),
+        }
+    }
+
+    /// Returns the indices of the weapons that can fire at the distance
+    pub fn get_usable(
+        &self,
+        distance: i32,
+        budget: &ActionBudget,
+        modifiers: &StatModifiers,
+    ) -> Vec<usize> {
+        self.weapons
+            .iter()
+            .enumerate()
+            .filter(|(_, weapon)| {
+                weapon.is_in_attack_range(distance, modifiers) && weapon.can_fire(budget, modifiers)
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    pub fn resupply(&mut self, amount: i32) {
+        for weapon in self.weapons.iter_mut() {
+            weapon.resupply(amount);
+        }
+    }
+
+    /// Cycles through automatic selection and every weapon
+    pub fn select_next(&mut self) {
+        self.selected = match self.selected {
+            None if !self.weapons.is_empty() => Some(0),
+            Some(index) if index + 1 < self.weapons.len() => Some(index + 1),
+            _ => None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weapon(max_attack_range: i32, min_attack_range: i32) -> Weapon {
+        Weapon::new("Test", 0, max_attack_range, min_attack_range)
+    }
+
+    #[test]
+    pub fn is_in_attack_range_returns_true_if_distance_is_inside_range() {
+        let weapon = weapon(2, 1);
+        assert!(weapon.is_in_attack_range(1, &StatModifiers::default()));
+        assert!(weapon.is_in_attack_range(2, &StatModifiers::default()));
+    }
+
+    #[test]
+    pub fn is_in_attack_range_returns_false_if_distance_is_outside_range() {
+        let weapon = weapon(2, 2);
+        assert!(!weapon.is_in_attack_range(3, &StatModifiers::default()));
+        assert!(!weapon.is_in_attack_range(1, &StatModifiers::default()));
+    }
+
+    #[test]
+    pub fn is_in_attack_range_uses_modified_max_range() {
+        let weapon = weapon(3, 1);
+        let modifiers = StatModifiers {
+            attack_range: -1,
+            ..StatModifiers::default()
+        };
+        assert!(weapon.is_in_attack_range(2, &modifiers));
+        assert!(!weapon.is_in_attack_range(3, &modifiers));
+    }
+
+    #[test]
+    pub fn is_in_attack_range_returns_false_if_unit_cannot_attack() {
+        let weapon = weapon(3, 0);
+        let modifiers = StatModifiers {
+            can_attack: false,
+            ..StatModifiers::default()
+        };
+        assert!(!weapon.is_in_attack_range(0, &modifiers));
+    }
+
+    #[test]
+    pub fn weapons_range_is_union_of_weapon_ranges() {
+        let weapons = Weapons::new(vec![weapon(1, 1), weapon(4, 3)]);
+        let modifiers = StatModifiers::default();
+        assert!(weapons.is_in_attack_range(1, &modifiers));
+        assert!(!weapons.is_in_attack_range(2, &modifiers));
+        assert!(weapons.is_in_attack_range(4, &modifiers));
+    }
+
+    #[test]
+    pub fn weapons_range_is_range_of_selected_weapon() {
+        let mut weapons = Weapons::new(vec![weapon(1, 1), weapon(4, 3)]);
+        weapons.selected = Some(1);
+        let modifiers = StatModifiers::default();
+        assert!(!weapons.is_in_attack_range(1, &modifiers));
+        assert!(weapons.is_in_attack_range(4, &modifiers));
+    }
+
+    #[test]
+    pub fn get_usable_respects_ammo_and_attack_cost() {
+        let weapons = Weapons::new(vec![
+            Weapon {
+                ammo: Some(0),
+                ..weapon(2, 1)
+            },
+            Weapon {
+                attack_cost: 2,
+                ..weapon(2, 1)
+            },
+            weapon(2, 1),
+        ]);
+        let usable = weapons.get_usable(1, &ActionBudget::new(1, 1), &StatModifiers::default());
+        assert_eq!(usable, vec![2]);
+    }
+
+    #[test]
+    pub fn resupply_restores_ammo_up_to_maximum() {
+        let mut weapons = Weapons::new(vec![
+            Weapon {
+                ammo: Some(0),
+                max_ammo: Some(2),
+                ..weapon(2, 1)
+            },
+            weapon(2, 1),
+        ]);
+        weapons.resupply(1);
+        assert_eq!(weapons.weapons[0].ammo, Some(1));
+        weapons.resupply(3);
+        assert_eq!(weapons.weapons[0].ammo, Some(2));
+        assert_eq!(weapons.weapons[1].ammo, None);
+    }
+
+    #[test]
+    pub fn select_next_cycles_through_automatic_and_weapons() {
+        let mut weapons = Weapons::new(vec![weapon(1, 1), weapon(2, 1)]);
+        weapons.select_next();
+        assert_eq!(weapons.selected, Some(0));
+        weapons.select_next();
+        assert_eq!(weapons.selected, Some(1));
+        weapons.select_next();
+        assert_eq!(weapons.selected, None);
+    }
+
+    macro_rules! apply_armor_returns_correct_damage {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (damage_type, damage, armor, expected) = $value;
+                assert_eq!(DamageType::apply_armor(&damage_type, damage, armor), expected);
+            }
+        )*
+        }
+    }
+
+    apply_armor_returns_correct_damage! {
+        apply_armor_kinetic: (DamageType::Kinetic, 5, 3, 2),
+        apply_armor_kinetic_minimum: (DamageType::Kinetic, 2, 3, 0),
+        apply_armor_piercing: (DamageType::Piercing, 5, 3, 4),
+        apply_armor_piercing_minimum: (DamageType::Piercing, 1, 6, 0),
+        apply_armor_explosive: (DamageType::Explosive, 5, 3, 2),
+        apply_armor_explosive_minimum: (DamageType::Explosive, 2, 3, 1),
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Relation {
+    Allied,
+    Neutral,
+    Hostile,
+}
+
+impl Relation {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Relation::Allied => "Allied",
+            Relation::Neutral => "Neutral",
+            Relation::Hostile => "Hostile",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Relation> {
+        [Relation::Allied, Relation::Neutral, Relation::Hostile]
+            .iter()
+            .copied()
+            .find(|relation| relation.get_name() == name)
+    }
+}
+
+/// The teams of the players and the relations between them. Players of the same team are
+/// always allied, players of different teams are hostile unless another relation is set.
+/// Players without a team form a team of their own, with the player index as its id.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Diplomacy {
+    teams: HashMap<usize, usize>,
+    relations: HashMap<(usize, usize), Relation>,
+}
+
+impl Diplomacy {
+    pub fn new() -> Self {
+        Diplomacy::default()
+    }
+
+    pub fn get_team(&self, player: usize) -> usize {
+        self.teams.get(&player).copied().unwrap_or(player)
+    }
+
+    pub fn set_team(&mut self, player: usize, team: usize) {
+        self.teams.insert(player, team);
+    }
+
+    /// Removes the player from their team, leaving them without allies
+    pub fn clear_team(&mut self, player: usize) {
+        self.teams.remove(&player);
+    }
+
+    pub fn get_relation(&self, player: usize, other: usize) -> Relation {
+        if self.get_team(player) == self.get_team(other) {
+            return Relation::Allied;
+        }
+        self.relations
+            .get(&Self::get_key(player, other))
+            .copied()
+            .unwrap_or(Relation::Hostile)
+    }
+
+    /// Sets the relation between two players of different teams, in both directions
+    pub fn set_relation(&mut self, player: usize, other: usize, relation: Relation) {
+        self.relations
+            .insert(Self::get_key(player, other), relation);
+    }
+
+    /// The players that have a team, with their teams, ordered by player
+    pub fn get_teams(&self) -> Vec<(usize, usize)> {
+        let mut teams: Vec<(usize, usize)> = self
+            .teams
+            .iter()
+            .map(|(player, team)| (*player, *team))
+            .collect();
+        teams.sort_unstable();
+        teams
+    }
+
+    /// The relations that were set, ordered by the players
+    pub fn get_relations(&self) -> Vec<(usize, usize, Relation)> {
+        let mut relations: Vec<(usize, usize, Relation)> = self
+            .relations
+            .iter()
+            .map(|((player, other), relation)| (*player, *other, *relation))
+            .collect();
+        relations.sort_unstable_by_key(|(player, other, _)| (*player, *other));
+        relations
+    }
+
+    pub fn is_allied(&self, player: usize, other: usize) -> bool {
+        self.get_relation(player, other) == Relation::Allied
+    }
+
+    pub fn is_hostile(&self, player: usize, other: usize) -> bool {
+        self.get_relation(player, other) == Relation::Hostile
+    }
+
+    fn get_key(player: usize, other: usize) -> (usize, usize) {
+        (player.min(other), player.max(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn players_without_teams_are_hostile() {
+        let diplomacy = Diplomacy::new();
+        assert!(diplomacy.is_allied(1, 1));
+        assert!(diplomacy.is_hostile(0, 1));
+    }
+
+    #[test]
+    fn players_of_a_team_are_allied() {
+        let mut diplomacy = Diplomacy::new();
+        diplomacy.set_team(0, 0);
+        diplomacy.set_team(1, 1);
+        diplomacy.set_team(2, 0);
+        diplomacy.set_team(3, 1);
+        diplomacy.set_relation(0, 1, Relation::Hostile);
+
+        assert!(diplomacy.is_allied(2, 0));
+        assert!(diplomacy.is_allied(1, 3));
+        assert!(diplomacy.is_hostile(2, 3));
+        assert_eq!(diplomacy.get_team(2), 0);
+
+        diplomacy.clear_team(2);
+        assert!(diplomacy.is_hostile(2, 0));
+        assert_eq!(diplomacy.get_team(2), 2);
+    }
+
+    #[test]
+    fn set_relation_applies_in_both_directions() {
+        let mut diplomacy = Diplomacy::new();
+        diplomacy.set_relation(2, 0, Relation::Neutral);
+        assert_eq!(diplomacy.get_relation(0, 2), Relation::Neutral);
+        assert_eq!(diplomacy.get_relation(2, 0), Relation::Neutral);
+
+        diplomacy.set_relation(0, 2, Relation::Allied);
+        assert!(diplomacy.is_allied(2, 0));
+        assert!(!diplomacy.is_hostile(0, 2));
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Currency {
+    Credits,
+    Fuel,
+}
+
+impl Currency {
+    pub fn iter() -> impl Iterator<Item = Currency> {
+        [Currency::Credits, Currency::Fuel].iter().copied()
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Currency::Credits => "Credits",
+            Currency::Fuel => "Fuel",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Currency> {
+        Currency::iter().find(|currency| currency.get_name() == name)
+    }
+}
+
+/// A single change of the balance of a player
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transaction {
+    pub currency: Currency,
+    /// Positive for income, negative for expenses
+    pub amount: i32,
+    pub reason: String,
+}
+
+impl Transaction {
+    pub fn new(currency: Currency, amount: i32, reason: &str) -> Self {
+        Transaction {
+            currency,
+            amount,
+            reason: reason.to_owned(),
+        }
+    }
+}
+
+/// The balances of a player and the transactions that led to them.
+/// Balances can become negative when the upkeep is higher than the income.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Ledger {
+    balances: HashMap<Currency, i32>,
+    transactions: Vec<Transaction>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Ledger::default()
+    }
+
+    pub fn get_balance(&self, currency: Currency) -> i32 {
+        self.balances.get(&currency).copied().unwrap_or(0)
+    }
+
+    pub fn get_transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    pub fn record(&mut self, transaction: Transaction) {
+        *self.balances.entry(transaction.currency).or_insert(0) += transaction.amount;
+        self.transactions.push(transaction);
+    }
+
+    pub fn can_afford(&self, costs: &[(Currency, i32)]) -> bool {
+        costs
+            .iter()
+            .all(|(currency, amount)| self.get_balance(*currency) >= *amount)
+    }
+
+    /// Pays the costs if all of them can be afforded and returns whether they were paid
+    pub fn spend(&mut self, costs: &[(Currency, i32)], reason: &str) -> bool {
+        if !self.can_afford(costs) {
+            return false;
+        }
+        for (currency, amount) in costs {
+            self.record(Transaction::new(*currency, -amount, reason));
+        }
+        true
+    }
+
+    /// Text with the balance of every currency, as shown in the UI
+    pub fn get_summary(&self) -> String {
+        Currency::iter()
+            .map(|currency| format!("{}: {}", currency.get_name(), self.get_balance(currency)))
+            .collect::<Vec<String>>()
+            .join("  ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_updates_balance() {
+        let mut ledger = Ledger::new();
+        ledger.record(Transaction::new(Currency::Credits, 5, "Income"));
+        ledger.record(Transaction::new(Currency::Credits, -7, "Upkeep"));
+
+        assert_eq!(ledger.get_balance(Currency::Credits), -2);
+        assert_eq!(ledger.get_balance(Currency::Fuel), 0);
+        assert_eq!(ledger.get_transactions().len(), 2);
+    }
+
+    #[test]
+    fn spend_requires_all_costs_to_be_affordable() {
+        let mut ledger = Ledger::new();
+        ledger.record(Transaction::new(Currency::Credits, 5, "Income"));
+
+        assert!(!ledger.spend(&[(Currency::Credits, 3), (Currency::Fuel, 1)], "Unit"));
+        assert_eq!(ledger.get_balance(Currency::Credits), 5);
+        assert!(ledger.spend(&[(Currency::Credits, 3)], "Unit"));
+        assert_eq!(ledger.get_balance(Currency::Credits), 2);
+    }
+
+    #[test]
+    fn currency_names_round_trip() {
+        for currency in Currency::iter() {
+            assert_eq!(Currency::from_name(currency.get_name()), Some(currency));
+        }
+        assert_eq!(Currency::from_name("Gold"), None);
+    }
+
+    #[test]
+    fn get_summary_lists_all_currencies() {
+        let mut ledger = Ledger::new();
+        ledger.record(Transaction::new(Currency::Fuel, 3, "Income"));
+        assert_eq!(ledger.get_summary(), "Credits: 0  Fuel: 3");
+    }
+}
@@ -1,7 +1,10 @@
+use crate::components::action_points::ActionPoints;
 use crate::components::hexagon::Hexagon;
-use crate::player::Player;
+use crate::diplomacy::Diplomacy;
+use crate::player::{Controller, Player};
 use legion::Entity;
 use std::collections::vec_deque::VecDeque;
+use std::collections::{HashMap, HashSet};
 
 pub struct GameState {
     pub state: State,
@@ -12,8 +15,31 @@ pub struct GameState {
     pub red_layer: bool,
     pub green_layer: bool,
     pub blue_layer: bool,
+    /// Whether the damage the visible enemies can deal next turn is drawn
+    pub threat_layer: bool,
+    /// The damage the visible enemies of the current player can deal to each hexagon next
+    /// turn, kept up to date while the threat layer is shown
+    pub threat_map: HashMap<Hexagon, i32>,
+    /// Whether the threat map and the danger zone have to be computed again, because the
+    /// world, the hovered hexagon, the pinned units or the shown layers changed
+    pub update_threats: bool,
+    /// The enemy units whose danger zone stays shown while they are not hovered
+    pub pinned_units: Vec<Entity>,
+    /// The hexagons the hovered and the pinned enemy units can attack next turn, kept up
+    /// to date while the green layer is shown
+    pub danger_zone: HashSet<Hexagon>,
     pub update_fields: bool,
     pub hovered_hexagon: Option<Hexagon>,
+    pub splash_area: Vec<Hexagon>,
+    /// The seed the map is generated with
+    pub seed: u64,
+    pub rules: Rules,
+    pub diplomacy: Diplomacy,
+    /// The hexagons the current player and their allies can see, or None if the whole map
+    /// is visible
+    pub visible_hexagons: Option<HashSet<Hexagon>>,
+    /// The players that won the game, once it is over
+    pub winners: Option<Vec<usize>>,
 }
 
 impl GameState {
@@ -27,18 +53,118 @@ impl GameState {
             red_layer: true,
             green_layer: true,
             blue_layer: true,
+            threat_layer: false,
+            threat_map: HashMap::new(),
+            update_threats: true,
+            pinned_units: Vec::new(),
+            danger_zone: HashSet::new(),
             update_fields: false,
             hovered_hexagon: None,
+            splash_area: Vec::new(),
+            seed: 0,
+            rules: Rules::default(),
+            diplomacy: Diplomacy::new(),
+            visible_hexagons: None,
+            winners: None,
         }
     }
+
+    pub fn is_hexagon_visible(&self, hexagon: &Hexagon) -> bool {
+        match &self.visible_hexagons {
+            None => true,
+            Some(visible_hexagons) => visible_hexagons.contains(hexagon),
+        }
+    }
+
+    pub fn set_controller(&mut self, player: usize, controller: Controller) {
+        if let Some(player) = self.players.get_mut(player) {
+            player.set_controller(controller);
+        }
+    }
+
+    /// Returns the controller of the current player
+    pub fn get_controller(&self) -> Option<Controller> {
+        Some(self.players[self.current_player?].get_controller())
+    }
+
+    /// Whether the current player is played by the computer
+    pub fn is_ai_turn(&self) -> bool {
+        !matches!(self.get_controller(), None | Some(Controller::Human))
+    }
+
+    /// The state the turn of the current player starts in. When several humans share the
+    /// screen, the board is handed over first.
+    pub fn get_turn_start(&self) -> State {
+        let humans = self
+            .players
+            .iter()
+            .filter(|player| player.is_human())
+            .count();
+        let is_human = match self.current_player {
+            None => false,
+            Some(player) => self.players[player].is_human(),
+        };
+        if is_human && humans > 1 {
+            State::Handover
+        } else {
+            State::Waiting
+        }
+    }
+}
+
+/// Options that change how units act during their turn
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rules {
+    /// Spawned units share these action points between moving, attacking and abilities
+    pub action_points: Option<ActionPoints>,
+    /// Whether attacking uses up the remaining movement range of the unit
+    pub attacking_ends_movement: bool,
+    /// Whether using an ability uses up the remaining movement range of the unit
+    pub ability_ends_movement: bool,
+    /// Whether players only see the hexagons within vision range of their and their
+    /// allies' entities
+    pub fog_of_war: bool,
 }
 
-#[derive(Clone)]
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            action_points: None,
+            attacking_ends_movement: true,
+            ability_ends_movement: false,
+            fog_of_war: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum State {
     Startup,
     NewRound,
+    /// The board is hidden until the current player confirms they took over
+    Handover,
     Waiting,
     Selected(Entity),
     Attacking(Entity, Entity),
     Moving(Entity, VecDeque<Hexagon>, f64),
+    /// Choosing the target for the ability with the index
+    Targeting(Entity, usize),
+    UsingAbility(Entity, usize, Hexagon),
+    /// Choosing the hexagon the next cargo of the transport disembarks to
+    Unloading(Entity),
+    Disembarking(Entity, Hexagon),
+    /// Recruiting the unit with the index in the building
+    Recruiting(Entity, usize),
+    /// Selecting the weapon with the index of the unit, or automatic selection if None
+    SelectingWeapon(Entity, Option<usize>),
+}
+
+impl State {
+    /// Whether the state waits for input of the current player, without changing the game
+    pub fn is_idle(&self) -> bool {
+        matches!(
+            self,
+            State::Waiting | State::Selected(_) | State::Targeting(_, _) | State::Unloading(_)
+        )
+    }
 }
@@ -0,0 +1,166 @@
+use crate::components::hexagon::Hexagon;
+use gdnative::core_types::Vector2;
+use std::f32::consts::PI;
+
+/// Whether the hexagons have a corner or an edge at the top.
+/// See https://www.redblobgames.com/grids/hexagons/#basics
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    Pointy,
+    Flat,
+}
+
+struct OrientationMatrix {
+    forward: [f32; 4],
+    backward: [f32; 4],
+    start_angle: f32,
+}
+
+impl Orientation {
+    fn get_matrix(&self) -> OrientationMatrix {
+        let sqrt_3 = 3.0_f32.sqrt();
+        match self {
+            Orientation::Pointy => OrientationMatrix {
+                forward: [sqrt_3, sqrt_3 / 2.0, 0.0, 3.0 / 2.0],
+                backward: [sqrt_3 / 3.0, -1.0 / 3.0, 0.0, 2.0 / 3.0],
+                start_angle: 0.5,
+            },
+            Orientation::Flat => OrientationMatrix {
+                forward: [3.0 / 2.0, 0.0, sqrt_3 / 2.0, sqrt_3],
+                backward: [2.0 / 3.0, 0.0, -1.0 / 3.0, sqrt_3 / 3.0],
+                start_angle: 0.0,
+            },
+        }
+    }
+}
+
+/// Maps hexagons to 2d positions and back.
+/// See https://www.redblobgames.com/grids/hexagons/implementation.html#layout
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Layout {
+    pub orientation: Orientation,
+    /// Distance from the center to a corner, separately for each axis.
+    pub size: Vector2,
+    /// 2d position of the hexagon at 0, 0, 0
+    pub origin: Vector2,
+}
+
+impl Layout {
+    pub fn new(orientation: Orientation, size: Vector2, origin: Vector2) -> Self {
+        Layout {
+            orientation,
+            size,
+            origin,
+        }
+    }
+
+    pub fn hex_to_pixel(&self, hexagon: &Hexagon) -> Vector2 {
+        let matrix = self.orientation.get_matrix().forward;
+        let q = hexagon.get_q() as f32;
+        let r = hexagon.get_r() as f32;
+        let x = (matrix[0] * q + matrix[1] * r) * self.size.x;
+        let y = (matrix[2] * q + matrix[3] * r) * self.size.y;
+        Vector2::new(x + self.origin.x, y + self.origin.y)
+    }
+
+    /// Returns the fractional axial coordinates (q, r) of a 2d position
+    pub fn pixel_to_axial(&self, position: Vector2) -> (f32, f32) {
+        let matrix = self.orientation.get_matrix().backward;
+        let x = (position.x - self.origin.x) / self.size.x;
+        let y = (position.y - self.origin.y) / self.size.y;
+        let q = matrix[0] * x + matrix[1] * y;
+        let r = matrix[2] * x + matrix[3] * y;
+        (q, r)
+    }
+
+    /// Returns the corners of a hexagon centered at 0, 0
+    pub fn get_corner_offsets(&self) -> Vec<Vector2> {
+        let start_angle = self.orientation.get_matrix().start_angle;
+        (0..6)
+            .map(|corner| {
+                let angle = 2.0 * PI * (start_angle + corner as f32) / 6.0;
+                Vector2::new(self.size.x * angle.cos(), self.size.y * angle.sin())
+            })
+            .collect()
+    }
+
+    /// Returns the width and height of the rectangle that encloses a hexagon
+    pub fn get_hexagon_extents(&self) -> Vector2 {
+        let sqrt_3 = 3.0_f32.sqrt();
+        match self.orientation {
+            Orientation::Pointy => Vector2::new(sqrt_3 * self.size.x, 2.0 * self.size.y),
+            Orientation::Flat => Vector2::new(2.0 * self.size.x, sqrt_3 * self.size.y),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::hexgrid::create_grid;
+
+    macro_rules! pixel_round_trip_returns_same_hexagon {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let layout = $value;
+                for hexagon in create_grid(5) {
+                    let position = layout.hex_to_pixel(&hexagon);
+                    assert_eq!(Hexagon::from_vector2(position, &layout), hexagon);
+                }
+            }
+        )*
+        }
+    }
+
+    pixel_round_trip_returns_same_hexagon! {
+        round_trip_pointy: Layout::new(Orientation::Pointy, Vector2::new(40.0, 40.0), Vector2::zero()),
+        round_trip_flat: Layout::new(Orientation::Flat, Vector2::new(40.0, 40.0), Vector2::zero()),
+        round_trip_squashed: Layout::new(Orientation::Flat, Vector2::new(40.0, 20.0), Vector2::zero()),
+        round_trip_origin: Layout::new(Orientation::Pointy, Vector2::new(10.0, 15.0), Vector2::new(-7.0, 30.0)),
+    }
+
+    #[test]
+    fn hex_to_pixel_pointy_matches_previous_calculation() {
+        let layout = Layout::new(
+            Orientation::Pointy,
+            Vector2::new(40.0, 40.0),
+            Vector2::zero(),
+        );
+        let position = layout.hex_to_pixel(&Hexagon::new_axial(1, 2));
+        assert!((position.x - 40.0 * 3.0_f32.sqrt() * 2.0).abs() < 0.001);
+        assert!((position.y - 120.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn hex_to_pixel_flat_places_east_neighbour_on_the_diagonal() {
+        let layout = Layout::new(Orientation::Flat, Vector2::new(10.0, 10.0), Vector2::zero());
+        let position = layout.hex_to_pixel(&Hexagon::new_axial(1, 0));
+        assert!((position.x - 15.0).abs() < 0.001);
+        assert!((position.y - 5.0 * 3.0_f32.sqrt()).abs() < 0.001);
+    }
+
+    #[test]
+    fn get_corner_offsets_pointy_has_a_corner_at_the_top() {
+        let layout = Layout::new(
+            Orientation::Pointy,
+            Vector2::new(10.0, 20.0),
+            Vector2::zero(),
+        );
+        let corners = layout.get_corner_offsets();
+        assert_eq!(corners.len(), 6);
+        assert!(corners
+            .iter()
+            .any(|corner| corner.x.abs() < 0.001 && (corner.y + 20.0).abs() < 0.001));
+    }
+
+    #[test]
+    fn get_corner_offsets_flat_has_a_corner_at_the_right() {
+        let layout = Layout::new(Orientation::Flat, Vector2::new(10.0, 20.0), Vector2::zero());
+        let corners = layout.get_corner_offsets();
+        assert!(corners
+            .iter()
+            .any(|corner| (corner.x - 10.0).abs() < 0.001 && corner.y.abs() < 0.001));
+    }
+}
@@ -10,3 +10,22 @@ pub fn entity_has_component<T: Component, S: EntityStore>(world: &S, entity: &En
     };
     entry.get_component::<T>().is_ok()
 }
+
+/// Adds a unit of the player on the hexagon with the other components, for tests
+#[cfg(test)]
+pub fn add_unit<T>(
+    world: &mut legion::World,
+    player: usize,
+    hexagon: crate::components::hexagon::Hexagon,
+    components: T,
+) -> Entity
+where
+    Option<T>: legion::storage::IntoComponentSource,
+{
+    let unit = world.push(components);
+    let mut entry = world.entry(unit).unwrap();
+    entry.add_component(crate::components::unit::Unit);
+    entry.add_component(crate::components::player::Player(player));
+    entry.add_component(hexagon);
+    unit
+}
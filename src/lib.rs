@@ -20,11 +20,21 @@ use gdnative::prelude::*;
 use nodes::gameworld;
 use nodes::units::dummy_unit;
 
+mod catalogue;
 mod components;
+mod diplomacy;
+mod economy;
 mod game_state;
+mod layout;
 mod legion;
+mod map_generator;
+mod network;
 mod nodes;
 mod player;
+mod scenario;
+mod search;
+mod setup;
+mod snapshot;
 mod systems;
 
 // Function that registers all exposed classes to Godot
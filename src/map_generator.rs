@@ -0,0 +1,541 @@
+use crate::components::field::{Field, Terrain};
+use crate::components::hexagon::Hexagon;
+use crate::components::objective::ObjectiveKind;
+use crate::components::structure::{Structure, StructureKind};
+use crate::layout::{Layout, Orientation};
+use crate::scenario::Scenario;
+use crate::systems::hexgrid::{
+    create_grid, find_path, get_2d_position_from_hex, get_neighbours, TerrainMap,
+};
+use gdnative::core_types::Vector2;
+use legion::World;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::iter::once;
+
+/// Settings used by `generate_map`. The same settings always produce the same map.
+#[derive(Clone, Debug)]
+pub struct MapSettings {
+    pub seed: u64,
+    pub radius: u32,
+    pub player_count: usize,
+    /// Distance of the start zone centers from the center of the map.
+    pub start_distance: i32,
+    /// At least 1, since every player starts with two units on different hexagons.
+    pub start_zone_radius: u32,
+    /// Rotates the terrain around the center of the map onto every start zone, so that
+    /// all players get the same surroundings. The grid only turns in steps of 60 degrees,
+    /// so this only works for 2, 3 or 6 players and is ignored for other player counts.
+    pub symmetric: bool,
+    pub water_level: f32,
+    pub hills_level: f32,
+    pub mountains_level: f32,
+    pub forest_moisture: f32,
+    pub river_count: usize,
+    /// Size of the noise features, in hexagons.
+    pub feature_size: f32,
+}
+
+impl Default for MapSettings {
+    fn default() -> Self {
+        MapSettings {
+            seed: 0,
+            radius: 128,
+            player_count: 2,
+            start_distance: 4,
+            start_zone_radius: 1,
+            symmetric: true,
+            water_level: 0.3,
+            hills_level: 0.65,
+            mountains_level: 0.75,
+            forest_moisture: 0.6,
+            river_count: 32,
+            feature_size: 12.0,
+        }
+    }
+}
+
+/// Deterministic pseudo random numbers (SplitMix64)
+struct Random {
+    state: u64,
+}
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        Random { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        mix(self.state)
+    }
+
+    fn next_below(&mut self, max: usize) -> usize {
+        (self.next_u64() % max as u64) as usize
+    }
+}
+
+fn mix(value: u64) -> u64 {
+    let mut z = value;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Fractal value noise in the range 0..1
+struct Noise {
+    seed: u64,
+}
+
+impl Noise {
+    const OCTAVES: u32 = 4;
+
+    fn new(seed: u64) -> Self {
+        Noise { seed }
+    }
+
+    fn lattice(&self, x: i64, y: i64) -> f32 {
+        let hash = mix(self.seed ^ mix((x as u64) ^ mix(y as u64)));
+        (hash >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fade = |t: f32| t * t * (3.0 - 2.0 * t);
+        let tx = fade(x - x0);
+        let ty = fade(y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.lattice(x0, y0) * (1.0 - tx) + self.lattice(x0 + 1, y0) * tx;
+        let bottom = self.lattice(x0, y0 + 1) * (1.0 - tx) + self.lattice(x0 + 1, y0 + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+
+    fn fractal(&self, position: Vector2) -> f32 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut max = 0.0;
+        for _ in 0..Self::OCTAVES {
+            total += self.value(position.x * frequency, position.y * frequency) * amplitude;
+            max += amplitude;
+            amplitude /= 2.0;
+            frequency *= 2.0;
+        }
+        total / max
+    }
+}
+
+/// Number of rotations the terrain is repeated in, or 1 if it is not symmetric
+fn get_symmetry(settings: &MapSettings) -> usize {
+    if settings.symmetric && settings.player_count > 1 && 6 % settings.player_count == 0 {
+        settings.player_count
+    } else {
+        1
+    }
+}
+
+/// Returns the hexagons the hexagon is rotated onto by the symmetry, without itself
+fn get_images(hexagon: &Hexagon, symmetry: usize) -> Vec<Hexagon> {
+    let step = 6 / symmetry as i32;
+    (1..symmetry as i32)
+        .map(|turn| hexagon.rotate_around(&Hexagon::zero(), turn * step))
+        .collect()
+}
+
+/// Calculates the centers of the start zones, evenly spaced around the center of the map.
+pub fn get_start_zone_centers(player_count: usize, distance: i32) -> Vec<Hexagon> {
+    let layout = Layout::new(Orientation::Pointy, Vector2::new(1.0, 1.0), Vector2::zero());
+    let radius = 3.0_f32.sqrt() * distance as f32;
+    (0..player_count)
+        .map(|player| {
+            let angle = 2.0 * PI * player as f32 / player_count as f32;
+            Hexagon::from_vector2(
+                Vector2::new(radius * angle.cos(), radius * angle.sin()),
+                &layout,
+            )
+        })
+        .collect()
+}
+
+/// Generates a map from elevation and moisture noise, with rivers flowing downhill and
+/// passable start zones that are connected to each other.
+pub fn generate_map(settings: &MapSettings) -> Scenario {
+    let mut random = Random::new(settings.seed);
+    let elevation_noise = Noise::new(random.next_u64());
+    let moisture_noise = Noise::new(random.next_u64());
+
+    let hexagons = create_grid(settings.radius);
+    let feature_size = 1.0 / settings.feature_size;
+    let noise_layout = Layout::new(
+        Orientation::Pointy,
+        Vector2::new(feature_size, feature_size),
+        Vector2::zero(),
+    );
+    let sample = |noise: &Noise, hexagon: &Hexagon| {
+        noise.fractal(get_2d_position_from_hex(hexagon, &noise_layout))
+    };
+    let symmetry = get_symmetry(settings);
+    let sample_symmetric = |noise: &Noise, hexagon: &Hexagon| {
+        let images: f32 = get_images(hexagon, symmetry)
+            .iter()
+            .map(|image| sample(noise, image))
+            .sum();
+        (sample(noise, hexagon) + images) / symmetry as f32
+    };
+
+    let elevation: HashMap<Hexagon, f32> = hexagons
+        .iter()
+        .map(|hexagon| (*hexagon, sample_symmetric(&elevation_noise, hexagon)))
+        .collect();
+
+    let mut terrain: HashMap<Hexagon, Terrain> = hexagons
+        .iter()
+        .map(|hexagon| {
+            let height = elevation[hexagon];
+            let moisture = sample_symmetric(&moisture_noise, hexagon);
+            let terrain = if height < settings.water_level {
+                Terrain::Water
+            } else if height > settings.mountains_level {
+                Terrain::Mountains
+            } else if height > settings.hills_level {
+                Terrain::Hills
+            } else if moisture > settings.forest_moisture {
+                Terrain::Forest
+            } else {
+                Terrain::Plains
+            };
+            (*hexagon, terrain)
+        })
+        .collect();
+
+    let sources: Vec<Hexagon> = hexagons
+        .iter()
+        .filter(|hexagon| elevation[hexagon] > settings.hills_level)
+        .copied()
+        .collect();
+    if !sources.is_empty() {
+        for _ in 0..settings.river_count {
+            let source = sources[random.next_below(sources.len())];
+            for hexagon in trace_river(&source, &elevation) {
+                set_terrain(&mut terrain, &hexagon, Terrain::River, symmetry);
+            }
+        }
+    }
+
+    let centers = get_start_zone_centers(settings.player_count, settings.start_distance);
+    let start_zones: Vec<Vec<Hexagon>> = centers
+        .iter()
+        .map(|center| {
+            create_grid(settings.start_zone_radius.max(1))
+                .iter()
+                .map(|hexagon| *hexagon + *center)
+                .collect()
+        })
+        .collect();
+    let objectives = get_objectives(&centers, settings, &start_zones);
+    for hexagon in start_zones
+        .iter()
+        .flatten()
+        .chain(objectives.iter().map(|(hexagon, _)| hexagon))
+    {
+        set_terrain(&mut terrain, hexagon, Terrain::Plains, symmetry);
+    }
+
+    let connected: Vec<Hexagon> = centers
+        .iter()
+        .copied()
+        .chain(objectives.iter().map(|(hexagon, _)| *hexagon))
+        .collect();
+    let bridges = connect_start_zones(&connected, &mut terrain, symmetry);
+
+    let fields = hexagons
+        .iter()
+        .map(|hexagon| Field::new(*hexagon, terrain[hexagon]))
+        .collect();
+
+    Scenario {
+        objectives,
+        structures: bridges
+            .into_iter()
+            .map(|hexagon| (hexagon, StructureKind::Bridge))
+            .collect(),
+        ..Scenario::new(fields, start_zones)
+    }
+}
+
+/// Places a flag in the center of the map and a village between every start zone and
+/// the center, outside of the start zones.
+fn get_objectives(
+    centers: &[Hexagon],
+    settings: &MapSettings,
+    start_zones: &[Vec<Hexagon>],
+) -> Vec<(Hexagon, ObjectiveKind)> {
+    let mut objectives = vec![(Hexagon::zero(), ObjectiveKind::Flag)];
+    if centers.is_empty() {
+        return objectives;
+    }
+    for village in get_start_zone_centers(centers.len(), settings.start_distance / 2) {
+        if objectives.iter().all(|(hexagon, _)| *hexagon != village)
+            && !start_zones
+                .iter()
+                .flatten()
+                .any(|hexagon| *hexagon == village)
+        {
+            objectives.push((village, ObjectiveKind::Village));
+        }
+    }
+    objectives
+        .into_iter()
+        .filter(|(hexagon, _)| hexagon.distance_to(&Hexagon::zero()) <= settings.radius as i32)
+        .collect()
+}
+
+fn set_terrain(
+    terrain: &mut HashMap<Hexagon, Terrain>,
+    hexagon: &Hexagon,
+    value: Terrain,
+    symmetry: usize,
+) {
+    for hexagon in once(*hexagon).chain(get_images(hexagon, symmetry)) {
+        if let Some(current) = terrain.get_mut(&hexagon) {
+            *current = value;
+        }
+    }
+}
+
+/// Follows the steepest descent from the source until water or a local minimum is reached.
+fn trace_river(source: &Hexagon, elevation: &HashMap<Hexagon, f32>) -> Vec<Hexagon> {
+    let mut river = Vec::new();
+    let mut current = *source;
+    loop {
+        river.push(current);
+        let lowest = get_neighbours(&current)
+            .into_iter()
+            .filter_map(|hexagon| elevation.get(&hexagon).map(|height| (hexagon, *height)))
+            .fold(None, |lowest: Option<(Hexagon, f32)>, next| match lowest {
+                Some(lowest) if lowest.1 <= next.1 => Some(lowest),
+                _ => Some(next),
+            });
+        match lowest {
+            Some((next, height)) if height < elevation[&current] => current = next,
+            _ => break,
+        }
+    }
+    river
+}
+
+/// Makes sure every hexagon can be reached from the first one, carving a passable
+/// line through the terrain where `find_path` finds no way. Water on the line is crossed
+/// by bridges, whose hexagons are returned.
+fn connect_start_zones(
+    centers: &[Hexagon],
+    terrain: &mut HashMap<Hexagon, Terrain>,
+    symmetry: usize,
+) -> Vec<Hexagon> {
+    let mut world = World::default();
+    let mut bridges = Vec::new();
+    let first = match centers.first() {
+        None => return bridges,
+        Some(first) => first,
+    };
+    for center in centers.iter().skip(1) {
+        let terrain_map = TerrainMap(terrain.clone());
+        if !find_path(first, center, &world, &terrain_map).is_empty() {
+            continue;
+        }
+        let mut current = *first;
+        while current != *center {
+            current = get_neighbours(&current)
+                .into_iter()
+                .min_by_key(|hexagon| hexagon.distance_to(center))
+                .unwrap();
+            match terrain.get(&current) {
+                Some(Terrain::Water) => {
+                    let images = get_images(&current, symmetry)
+                        .into_iter()
+                        .filter(|image| terrain.get(image) == Some(&Terrain::Water));
+                    for hexagon in once(current).chain(images) {
+                        if !bridges.contains(&hexagon) {
+                            world.push((hexagon, Structure::new(StructureKind::Bridge)));
+                            bridges.push(hexagon);
+                        }
+                    }
+                }
+                Some(terrain_at_current) if !terrain_at_current.is_passable() => {
+                    set_terrain(terrain, &current, Terrain::Plains, symmetry);
+                }
+                _ => {}
+            }
+        }
+    }
+    bridges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_settings(seed: u64) -> MapSettings {
+        MapSettings {
+            seed,
+            radius: 16,
+            river_count: 4,
+            ..MapSettings::default()
+        }
+    }
+
+    #[test]
+    fn generate_map_is_deterministic_for_a_seed() {
+        let first = generate_map(&test_settings(42));
+        let second = generate_map(&test_settings(42));
+
+        assert_eq!(first.fields.len(), second.fields.len());
+        assert!(first
+            .fields
+            .iter()
+            .zip(second.fields.iter())
+            .all(|(a, b)| a.location == b.location && a.terrain == b.terrain));
+        assert_eq!(first.start_zones, second.start_zones);
+    }
+
+    #[test]
+    fn generate_map_differs_between_seeds() {
+        let first = generate_map(&test_settings(1));
+        let second = generate_map(&test_settings(2));
+
+        assert!(first
+            .fields
+            .iter()
+            .zip(second.fields.iter())
+            .any(|(a, b)| a.terrain != b.terrain));
+    }
+
+    #[test]
+    fn generate_map_creates_a_field_for_every_hexagon() {
+        let scenario = generate_map(&test_settings(3));
+        assert_eq!(scenario.fields.len(), create_grid(16).len());
+    }
+
+    #[test]
+    fn generate_map_start_zones_are_passable_and_connected() {
+        for seed in 0..8 {
+            let scenario = generate_map(&MapSettings {
+                player_count: 3,
+                start_distance: 8,
+                symmetric: false,
+                ..test_settings(seed)
+            });
+            let terrain_map = scenario.get_terrain_map();
+            let mut world = World::default();
+            scenario.spawn_structures(&mut world);
+
+            assert_eq!(scenario.start_zones.len(), 3);
+            assert!(scenario
+                .start_zones
+                .iter()
+                .flatten()
+                .all(|hexagon| terrain_map.is_passable(hexagon)));
+            let first = scenario.start_zones[0][0];
+            for zone in scenario.start_zones.iter().skip(1) {
+                assert!(!find_path(&first, &zone[0], &world, &terrain_map).is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn generate_map_start_zones_hold_the_starting_units() {
+        let scenario = generate_map(&MapSettings {
+            start_zone_radius: 0,
+            ..test_settings(4)
+        });
+
+        assert!(scenario.start_zones.iter().all(|zone| zone.len() >= 2));
+    }
+
+    #[test]
+    fn connect_start_zones_bridges_water() {
+        let mut terrain: HashMap<Hexagon, Terrain> = create_grid(3)
+            .into_iter()
+            .map(|hexagon| (hexagon, Terrain::Plains))
+            .collect();
+        for r in -3..4 {
+            terrain.insert(Hexagon::new_axial(0, r), Terrain::Water);
+        }
+
+        let bridges = connect_start_zones(
+            &[Hexagon::new_axial(-2, 0), Hexagon::new_axial(2, 0)],
+            &mut terrain,
+            1,
+        );
+
+        assert_eq!(bridges, vec![Hexagon::zero()]);
+        assert_eq!(terrain[&Hexagon::zero()], Terrain::Water);
+    }
+
+    #[test]
+    fn generate_map_symmetric_mirrors_terrain() {
+        let scenario = generate_map(&test_settings(7));
+        let terrain_map = scenario.get_terrain_map();
+
+        assert!(scenario.fields.iter().all(|field| {
+            let mirrored = field.location.rotate_around(&Hexagon::zero(), 3);
+            terrain_map.get_terrain(&mirrored) == Some(field.terrain)
+        }));
+    }
+
+    #[test]
+    fn generate_map_symmetric_rotates_terrain_for_three_players() {
+        let settings = MapSettings {
+            player_count: 3,
+            start_distance: 8,
+            ..test_settings(7)
+        };
+        let scenario = generate_map(&settings);
+        let terrain_map = scenario.get_terrain_map();
+
+        assert!(scenario.fields.iter().all(|field| {
+            get_images(&field.location, 3)
+                .iter()
+                .all(|image| terrain_map.get_terrain(image) == Some(field.terrain))
+        }));
+        assert_eq!(
+            get_symmetry(&MapSettings {
+                player_count: 4,
+                ..settings
+            }),
+            1
+        );
+    }
+
+    #[test]
+    fn get_start_zone_centers_places_two_players_opposite() {
+        let centers = get_start_zone_centers(2, 5);
+        assert_eq!(centers[0], Hexagon::new_axial(5, 0));
+        assert_eq!(centers[1], Hexagon::new_axial(-5, 0));
+    }
+
+    #[test]
+    fn generate_map_places_reachable_objectives_outside_start_zones() {
+        let scenario = generate_map(&test_settings(5));
+        let terrain_map = scenario.get_terrain_map();
+        let mut world = World::default();
+        scenario.spawn_structures(&mut world);
+
+        assert_eq!(
+            scenario.objectives.first(),
+            Some(&(Hexagon::zero(), ObjectiveKind::Flag))
+        );
+        assert_eq!(scenario.objectives.len(), 3);
+        let first = scenario.start_zones[0][0];
+        for (hexagon, _) in &scenario.objectives {
+            assert!(!scenario
+                .start_zones
+                .iter()
+                .flatten()
+                .any(|zone| zone == hexagon));
+            assert!(!find_path(&first, hexagon, &world, &terrain_map).is_empty());
+        }
+    }
+}
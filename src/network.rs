@@ -0,0 +1,1044 @@
+use crate::components::hexagon::{Hexagon, ParseHexagonError};
+use crate::setup::{GameSetup, ParseSetupError};
+use crate::snapshot::{ParseSnapshotError, Snapshot};
+use crossbeam::channel::{Receiver, TryRecvError};
+use crossbeam::crossbeam_channel;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::num::ParseIntError;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+/// A command of a player that every peer applies in the same order. Units are identified
+/// by their hexagon, since the entities differ between the worlds of the peers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Moves the unit on the hexagon along the path
+    Move(Hexagon, Vec<Hexagon>),
+    /// Attacks the second hexagon with the unit on the first one
+    Attack(Hexagon, Hexagon),
+    /// Uses the ability with the index of the unit on the first hexagon on the second one
+    UseAbility(Hexagon, usize, Hexagon),
+    /// Selects the weapon with the index of the unit on the hexagon, or automatic selection
+    SelectWeapon(Hexagon, Option<usize>),
+    /// Disembarks the next cargo of the transport on the first hexagon to the second one
+    Disembark(Hexagon, Hexagon),
+    /// Recruits the unit with the index in the building on the hexagon
+    Recruit(Hexagon, usize),
+    EndTurn,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub player: usize,
+    pub command: Command,
+}
+
+impl Entry {
+    pub fn new(player: usize, command: Command) -> Self {
+        Entry { player, command }
+    }
+}
+
+/// Formats the entry as the player followed by the command, e.g. "0 attack 0,0,0 1,0,-1"
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.player)?;
+        match &self.command {
+            Command::Move(from, path) => {
+                write!(f, "move {}", from)?;
+                for hexagon in path {
+                    write!(f, " {}", hexagon)?;
+                }
+                Ok(())
+            }
+            Command::Attack(from, target) => write!(f, "attack {} {}", from, target),
+            Command::UseAbility(from, ability, target) => {
+                write!(f, "ability {} {} {}", from, ability, target)
+            }
+            Command::SelectWeapon(from, None) => write!(f, "weapon {} auto", from),
+            Command::SelectWeapon(from, Some(weapon)) => write!(f, "weapon {} {}", from, weapon),
+            Command::Disembark(from, target) => write!(f, "disembark {} {}", from, target),
+            Command::Recruit(from, index) => write!(f, "recruit {} {}", from, index),
+            Command::EndTurn => write!(f, "end"),
+        }
+    }
+}
+
+impl FromStr for Entry {
+    type Err = ParseMessageError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        parse_entry(value.split_whitespace())
+    }
+}
+
+/// The messages the peers exchange, one per line
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    /// Sent by a peer that joins or reconnects, with the players it plays
+    Hello(Vec<usize>),
+    /// The setup of the host, sent before its log
+    Setup(GameSetup),
+    /// The entry at the index of the log
+    Command(usize, Entry),
+    /// The hash of the game state once the entries up to the index are applied
+    Hash(usize, u64),
+    /// The latest snapshot of the host, taken once the entries up to the index were applied.
+    /// It is sent before the log on a resync.
+    Snapshot(usize, Snapshot),
+    /// The whole log of the host
+    Resync(Vec<Entry>),
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Message::Hello(players) => {
+                write!(f, "hello")?;
+                for player in players {
+                    write!(f, " {}", player)?;
+                }
+                Ok(())
+            }
+            Message::Setup(setup) => write!(f, "setup {}", setup),
+            Message::Command(sequence, entry) => write!(f, "command {} {}", sequence, entry),
+            Message::Hash(sequence, hash) => write!(f, "hash {} {}", sequence, hash),
+            Message::Snapshot(sequence, snapshot) => {
+                write!(f, "snapshot {} {}", sequence, snapshot)
+            }
+            Message::Resync(entries) => {
+                let entries: Vec<String> = entries.iter().map(|entry| entry.to_string()).collect();
+                write!(f, "resync {}", entries.join(";"))
+            }
+        }
+    }
+}
+
+impl FromStr for Message {
+    type Err = ParseMessageError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut fields = value.split_whitespace();
+        match next_field(&mut fields)? {
+            "hello" => Ok(Message::Hello(
+                fields
+                    .map(|player| player.parse())
+                    .collect::<Result<Vec<usize>, ParseIntError>>()?,
+            )),
+            "setup" => Ok(Message::Setup(
+                value.trim_start().trim_start_matches("setup").parse()?,
+            )),
+            "command" => {
+                let sequence = next_field(&mut fields)?.parse()?;
+                Ok(Message::Command(sequence, parse_entry(fields)?))
+            }
+            "hash" => {
+                let sequence = next_field(&mut fields)?.parse()?;
+                Ok(Message::Hash(sequence, next_field(&mut fields)?.parse()?))
+            }
+            "snapshot" => {
+                let sequence = next_field(&mut fields)?.parse()?;
+                let snapshot: Vec<&str> = fields.collect();
+                Ok(Message::Snapshot(sequence, snapshot.join(" ").parse()?))
+            }
+            "resync" => Ok(Message::Resync(
+                value
+                    .trim_start()
+                    .trim_start_matches("resync")
+                    .split(';')
+                    .filter(|entry| !entry.trim().is_empty())
+                    .map(|entry| entry.parse())
+                    .collect::<Result<Vec<Entry>, ParseMessageError>>()?,
+            )),
+            kind => Err(ParseMessageError::UnknownKind(kind.to_owned())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseMessageError {
+    UnknownKind(String),
+    MissingField,
+    InvalidNumber(ParseIntError),
+    InvalidHexagon(ParseHexagonError),
+    InvalidSetup(ParseSetupError),
+    InvalidSnapshot(ParseSnapshotError),
+}
+
+impl fmt::Display for ParseMessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseMessageError::UnknownKind(kind) => write!(f, "unknown message {}", kind),
+            ParseMessageError::MissingField => write!(f, "missing field"),
+            ParseMessageError::InvalidNumber(error) => write!(f, "invalid number: {}", error),
+            ParseMessageError::InvalidHexagon(error) => write!(f, "invalid hexagon: {}", error),
+            ParseMessageError::InvalidSetup(error) => write!(f, "invalid setup: {}", error),
+            ParseMessageError::InvalidSnapshot(error) => write!(f, "invalid snapshot: {}", error),
+        }
+    }
+}
+
+impl From<ParseIntError> for ParseMessageError {
+    fn from(error: ParseIntError) -> Self {
+        ParseMessageError::InvalidNumber(error)
+    }
+}
+
+impl From<ParseHexagonError> for ParseMessageError {
+    fn from(error: ParseHexagonError) -> Self {
+        ParseMessageError::InvalidHexagon(error)
+    }
+}
+
+impl From<ParseSetupError> for ParseMessageError {
+    fn from(error: ParseSetupError) -> Self {
+        ParseMessageError::InvalidSetup(error)
+    }
+}
+
+impl From<ParseSnapshotError> for ParseMessageError {
+    fn from(error: ParseSnapshotError) -> Self {
+        ParseMessageError::InvalidSnapshot(error)
+    }
+}
+
+fn next_field<'a, I: Iterator<Item = &'a str>>(
+    fields: &mut I,
+) -> Result<&'a str, ParseMessageError> {
+    fields.next().ok_or(ParseMessageError::MissingField)
+}
+
+fn parse_entry<'a, I: Iterator<Item = &'a str>>(mut fields: I) -> Result<Entry, ParseMessageError> {
+    let player = next_field(&mut fields)?.parse()?;
+    let command = match next_field(&mut fields)? {
+        "move" => {
+            let from = next_field(&mut fields)?.parse()?;
+            let path = fields
+                .map(|hexagon| hexagon.parse())
+                .collect::<Result<Vec<Hexagon>, ParseHexagonError>>()?;
+            Command::Move(from, path)
+        }
+        "attack" => Command::Attack(
+            next_field(&mut fields)?.parse()?,
+            next_field(&mut fields)?.parse()?,
+        ),
+        "ability" => Command::UseAbility(
+            next_field(&mut fields)?.parse()?,
+            next_field(&mut fields)?.parse()?,
+            next_field(&mut fields)?.parse()?,
+        ),
+        "weapon" => {
+            let from = next_field(&mut fields)?.parse()?;
+            let weapon = match next_field(&mut fields)? {
+                "auto" => None,
+                weapon => Some(weapon.parse()?),
+            };
+            Command::SelectWeapon(from, weapon)
+        }
+        "disembark" => Command::Disembark(
+            next_field(&mut fields)?.parse()?,
+            next_field(&mut fields)?.parse()?,
+        ),
+        "recruit" => Command::Recruit(
+            next_field(&mut fields)?.parse()?,
+            next_field(&mut fields)?.parse()?,
+        ),
+        "end" => Command::EndTurn,
+        kind => return Err(ParseMessageError::UnknownKind(kind.to_owned())),
+    };
+    Ok(Entry::new(player, command))
+}
+
+/// How long sending a message may block before the peer counts as disconnected, so that a
+/// peer that stops reading does not stall the game
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A TCP connection to a peer. A thread reads the messages of the peer, invalid messages
+/// close the connection.
+struct Connection {
+    id: usize,
+    stream: TcpStream,
+    receiver: Receiver<Message>,
+    /// The players of the peer, once it said hello
+    players: Vec<usize>,
+    /// The players the peer asked for, while they are played on another connection
+    requested: Option<Vec<usize>>,
+    /// Hashes of the peer for entries the local game has not applied yet
+    hashes: HashMap<usize, u64>,
+    is_open: bool,
+}
+
+impl Connection {
+    fn new(id: usize, stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let message = match line.map(|line| line.parse::<Message>()) {
+                    Ok(Ok(message)) => message,
+                    _ => break,
+                };
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Connection {
+            id,
+            stream,
+            receiver,
+            players: Vec::new(),
+            requested: None,
+            hashes: HashMap::new(),
+            is_open: true,
+        })
+    }
+
+    /// Sends the message, or closes the connection if that fails or times out
+    fn send(&mut self, message: &Message) {
+        if !self.is_open {
+            return;
+        }
+        if writeln!(self.stream, "{}", message).is_err() {
+            self.is_open = false;
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // Also ends the reading thread, which holds a clone of the stream
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetworkEvent {
+    /// A peer that plays the player joined or reconnected
+    Joined(usize),
+    /// The setup of the host arrived. The game has to start over with it, until the resync
+    /// of the host that follows.
+    Setup(GameSetup),
+    Disconnected(usize),
+    /// The state hashes of a peer and the local game differ once the entries up to the
+    /// sequence are applied
+    Desync {
+        sequence: usize,
+        local: u64,
+        remote: u64,
+    },
+    /// The game has to start over. The snapshot of the host is applied if there is one, the
+    /// commands of the host after it are replayed.
+    Restart(Option<Snapshot>),
+}
+
+/// A lockstep session. Every peer applies the same commands in the same order. The host
+/// orders the commands of all peers and relays them, and resyncs peers whose state hashes
+/// differ from its own by sending them its latest snapshot and its log.
+pub struct Lockstep {
+    /// The players played on this peer
+    players: Vec<usize>,
+    /// The setup of the game, which peers receive from the host
+    setup: Option<GameSetup>,
+    listener: Option<TcpListener>,
+    connections: Vec<Connection>,
+    next_connection: usize,
+    /// The applied commands
+    log: Vec<Entry>,
+    /// Received commands that are not applied yet, with the connections they came from
+    incoming: VecDeque<(Option<usize>, Entry)>,
+    /// The local state hashes by the number of applied commands
+    hashes: HashMap<usize, u64>,
+    /// Whether the peer waits for a resync from the host
+    is_desynced: bool,
+    /// The latest snapshot of the host with the number of entries applied before it. Peers
+    /// keep the one they received until the resync that follows it.
+    snapshot: Option<(usize, Snapshot)>,
+    events: Vec<NetworkEvent>,
+}
+
+impl Lockstep {
+    fn new(players: Vec<usize>, setup: Option<GameSetup>, listener: Option<TcpListener>) -> Self {
+        Lockstep {
+            players,
+            setup,
+            listener,
+            connections: Vec::new(),
+            next_connection: 0,
+            log: Vec::new(),
+            incoming: VecDeque::new(),
+            hashes: HashMap::new(),
+            is_desynced: false,
+            snapshot: None,
+            events: Vec::new(),
+        }
+    }
+
+    /// Hosts a game with the setup on the address for peers to join. The game has to be at
+    /// its start.
+    pub fn host<A: ToSocketAddrs>(
+        address: A,
+        players: Vec<usize>,
+        setup: GameSetup,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(Lockstep::new(players, Some(setup), Some(listener)))
+    }
+
+    /// Joins the game hosted on the address. The host sends its setup and the commands
+    /// applied so far.
+    pub fn join<A: ToSocketAddrs>(address: A, players: Vec<usize>) -> io::Result<Self> {
+        let mut lockstep = Lockstep::new(players, None, None);
+        lockstep.reconnect(address)?;
+        Ok(lockstep)
+    }
+
+    /// Connects to the host again. The host only sends the commands that were missed,
+    /// unless the game went out of sync.
+    pub fn reconnect<A: ToSocketAddrs>(&mut self, address: A) -> io::Result<()> {
+        let mut connection = Connection::new(self.next_connection, TcpStream::connect(address)?)?;
+        self.next_connection += 1;
+        connection.send(&Message::Hello(self.players.clone()));
+        self.connections = vec![connection];
+        self.incoming.clear();
+        Ok(())
+    }
+
+    /// The address the host listens on
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.as_ref()?.local_addr().ok()
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.listener.is_some()
+    }
+
+    /// Whether the setup of the game is known, which peers have to wait for
+    pub fn is_ready(&self) -> bool {
+        self.setup.is_some()
+    }
+
+    /// Whether the player is played on this peer
+    pub fn is_local(&self, player: usize) -> bool {
+        self.players.contains(&player)
+    }
+
+    pub fn get_log(&self) -> &[Entry] {
+        &self.log
+    }
+
+    /// Whether received commands wait to be applied
+    pub fn has_pending(&self) -> bool {
+        !self.incoming.is_empty()
+    }
+
+    /// Whether a turn just ended and the state hash for it has not been recorded yet
+    pub fn needs_hash(&self) -> bool {
+        matches!(self.log.last(), Some(entry) if entry.command == Command::EndTurn)
+            && !self.hashes.contains_key(&self.log.len())
+    }
+
+    /// Accepts new peers and receives the messages of the peers
+    pub fn poll(&mut self) -> Vec<NetworkEvent> {
+        if let Some(listener) = &self.listener {
+            while let Ok((stream, _)) = listener.accept() {
+                if let Ok(connection) = Connection::new(self.next_connection, stream) {
+                    self.connections.push(connection);
+                    self.next_connection += 1;
+                }
+            }
+        }
+        for index in 0..self.connections.len() {
+            loop {
+                match self.connections[index].receiver.try_recv() {
+                    Ok(message) => self.handle_message(index, message),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.connections[index].is_open = false;
+                        break;
+                    }
+                }
+            }
+        }
+        self.accept_requests();
+        let events = &mut self.events;
+        self.connections.retain(|connection| {
+            if !connection.is_open {
+                events.extend(
+                    connection
+                        .players
+                        .iter()
+                        .copied()
+                        .map(NetworkEvent::Disconnected),
+                );
+            }
+            connection.is_open
+        });
+        std::mem::take(&mut self.events)
+    }
+
+    /// Applies the command of a local player and sends it to the peers. Returns false if
+    /// the player is not played on this peer, or if the game has to catch up first.
+    pub fn submit(&mut self, entry: Entry) -> bool {
+        if !self.is_local(entry.player)
+            || !self.is_ready()
+            || self.is_desynced
+            || self.has_pending()
+            || self.needs_hash()
+        {
+            return false;
+        }
+        let message = Message::Command(self.log.len(), entry.clone());
+        self.log.push(entry);
+        for connection in &mut self.connections {
+            connection.send(&message);
+        }
+        true
+    }
+
+    /// Returns the next received command once it is applied, or the command as error if it
+    /// is not valid. The host resyncs the peer that sent an invalid command, other peers ask
+    /// the host for a resync.
+    pub fn next_command<F: FnOnce(&Entry) -> bool>(
+        &mut self,
+        is_valid: F,
+    ) -> Option<Result<Entry, Entry>> {
+        let (origin, entry) = self.incoming.pop_front()?;
+        if !is_valid(&entry) {
+            match origin {
+                Some(origin) if self.is_host() => {
+                    self.incoming.retain(|(other, _)| *other != Some(origin));
+                    self.resync(origin);
+                }
+                _ => self.request_resync(),
+            }
+            return Some(Err(entry));
+        }
+        let message = Message::Command(self.log.len(), entry.clone());
+        self.log.push(entry.clone());
+        if self.is_host() {
+            for connection in &mut self.connections {
+                if Some(connection.id) != origin {
+                    connection.send(&message);
+                }
+            }
+        }
+        Some(Ok(entry))
+    }
+
+    /// Records the hash of the game state with all commands applied and sends it to the
+    /// peers
+    pub fn record_hash(&mut self, hash: u64) {
+        let sequence = self.log.len();
+        self.hashes.insert(sequence, hash);
+        let message = Message::Hash(sequence, hash);
+        for index in 0..self.connections.len() {
+            self.connections[index].send(&message);
+            if let Some(remote) = self.connections[index].hashes.remove(&sequence) {
+                self.compare_hash(index, sequence, remote);
+            }
+        }
+    }
+
+    /// Keeps the snapshot of the game state with all commands applied, which the host sends
+    /// to peers it resyncs
+    pub fn record_snapshot(&mut self, snapshot: Snapshot) {
+        self.snapshot = Some((self.log.len(), snapshot));
+    }
+
+    fn handle_message(&mut self, index: usize, message: Message) {
+        let is_host = self.is_host();
+        match message {
+            Message::Hello(players) if is_host => {
+                self.connections[index].requested = Some(players);
+            }
+            Message::Command(sequence, entry) if !self.is_desynced => {
+                let expected = self.log.len() + self.incoming.len();
+                let connection = &self.connections[index];
+                let is_sender_valid = if is_host {
+                    connection.players.contains(&entry.player)
+                } else {
+                    !self.is_local(entry.player)
+                };
+                if sequence < expected && is_sender_valid {
+                    return;
+                }
+                if sequence > expected || !is_sender_valid {
+                    if is_host {
+                        let id = connection.id;
+                        self.resync(id);
+                    } else {
+                        self.request_resync();
+                    }
+                    return;
+                }
+                self.incoming.push_back((Some(connection.id), entry));
+            }
+            Message::Hash(sequence, hash) if !self.is_desynced => {
+                if self.hashes.contains_key(&sequence) {
+                    self.compare_hash(index, sequence, hash);
+                } else {
+                    self.connections[index].hashes.insert(sequence, hash);
+                }
+            }
+            Message::Setup(setup) if !is_host => {
+                if self.setup.as_ref() == Some(&setup) {
+                    return;
+                }
+                // The resync that follows starts from the snapshot or the start of the log
+                self.log.clear();
+                self.hashes.clear();
+                self.incoming.clear();
+                for connection in &mut self.connections {
+                    connection.hashes.clear();
+                }
+                self.is_desynced = false;
+                self.snapshot = None;
+                self.setup = Some(setup.clone());
+                self.events.push(NetworkEvent::Setup(setup));
+            }
+            Message::Snapshot(sequence, snapshot) if !is_host => {
+                self.snapshot = Some((sequence, snapshot));
+            }
+            Message::Resync(entries) if !is_host => {
+                let applied = self.log.len();
+                let is_prefix = !self.is_desynced
+                    && entries.len() >= applied
+                    && entries[..applied] == self.log[..];
+                // The snapshot is only worth a restart if it is ahead of the local game
+                let snapshot = self.snapshot.take().filter(|(sequence, _)| {
+                    *sequence <= entries.len() && (!is_prefix || *sequence > applied)
+                });
+                let origin = Some(self.connections[index].id);
+                let missing = if is_prefix && snapshot.is_none() {
+                    entries[applied..].to_vec()
+                } else {
+                    self.hashes.clear();
+                    for connection in &mut self.connections {
+                        connection.hashes.clear();
+                    }
+                    self.is_desynced = false;
+                    let (sequence, snapshot) = match snapshot {
+                        None => (0, None),
+                        Some((sequence, snapshot)) => (sequence, Some(snapshot)),
+                    };
+                    self.log = entries[..sequence].to_vec();
+                    self.events.push(NetworkEvent::Restart(snapshot));
+                    entries[sequence..].to_vec()
+                };
+                self.incoming = missing.into_iter().map(|entry| (origin, entry)).collect();
+            }
+            _ => {}
+        }
+    }
+
+    /// Lets the peers play the players they asked for. Peers that ask for players of the host
+    /// or players that do not exist are disconnected. Peers that ask for players of another
+    /// peer wait until it is gone, like peers that reconnect before the host noticed.
+    fn accept_requests(&mut self) {
+        let player_count = self.setup.as_ref().map_or(0, |setup| setup.profiles.len());
+        for index in 0..self.connections.len() {
+            let players = match self.connections[index].requested.take() {
+                None => continue,
+                Some(players) => players,
+            };
+            let id = self.connections[index].id;
+            if players.is_empty()
+                || players
+                    .iter()
+                    .any(|player| *player >= player_count || self.is_local(*player))
+            {
+                self.connections[index].is_open = false;
+                continue;
+            }
+            let is_taken = self.connections.iter().any(|connection| {
+                connection.id != id
+                    && connection.is_open
+                    && players
+                        .iter()
+                        .any(|player| connection.players.contains(player))
+            });
+            if is_taken {
+                self.connections[index].requested = Some(players);
+                continue;
+            }
+            self.events
+                .extend(players.iter().copied().map(NetworkEvent::Joined));
+            let setup = self.setup.clone();
+            let connection = &mut self.connections[index];
+            connection.players = players;
+            connection.hashes.clear();
+            if let Some(setup) = setup {
+                connection.send(&Message::Setup(setup));
+            }
+            self.resync(id);
+        }
+    }
+
+    fn compare_hash(&mut self, index: usize, sequence: usize, remote: u64) {
+        let local = self.hashes[&sequence];
+        if local == remote {
+            return;
+        }
+        self.events.push(NetworkEvent::Desync {
+            sequence,
+            local,
+            remote,
+        });
+        if self.is_host() {
+            let id = self.connections[index].id;
+            self.resync(id);
+        } else {
+            self.is_desynced = true;
+        }
+    }
+
+    /// Sends the latest snapshot and the whole log to the peer on the connection
+    fn resync(&mut self, id: usize) {
+        let message = Message::Resync(self.log.clone());
+        if let Some(connection) = self
+            .connections
+            .iter_mut()
+            .find(|connection| connection.id == id)
+        {
+            connection.hashes.clear();
+            if let Some((sequence, snapshot)) = &self.snapshot {
+                connection.send(&Message::Snapshot(*sequence, snapshot.clone()));
+            }
+            connection.send(&message);
+        }
+    }
+
+    /// Drops the received commands and asks the host for a resync
+    fn request_resync(&mut self) {
+        self.is_desynced = true;
+        self.incoming.clear();
+        let message = Message::Hello(self.players.clone());
+        for connection in &mut self.connections {
+            connection.send(&message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diplomacy::Diplomacy;
+    use crate::game_state::Rules;
+    use crate::player::PlayerProfile;
+    use std::time::Instant;
+
+    struct Peers {
+        host: Lockstep,
+        peer: Lockstep,
+        host_events: Vec<NetworkEvent>,
+        peer_events: Vec<NetworkEvent>,
+    }
+
+    impl Peers {
+        /// Hosts a game for player 0 on localhost and joins it with player 1
+        fn connect() -> Self {
+            let host = Lockstep::host("127.0.0.1:0", vec![0], game_setup()).unwrap();
+            let peer = Lockstep::join(host.local_addr().unwrap(), vec![1]).unwrap();
+            let mut peers = Peers {
+                host,
+                peer,
+                host_events: Vec::new(),
+                peer_events: Vec::new(),
+            };
+            peers.poll_until(|peers| {
+                peers.host_events.contains(&NetworkEvent::Joined(1)) && peers.peer.is_ready()
+            });
+            peers
+        }
+
+        fn poll_until<F: Fn(&Peers) -> bool>(&mut self, condition: F) {
+            let start = Instant::now();
+            loop {
+                self.host_events.extend(self.host.poll());
+                self.peer_events.extend(self.peer.poll());
+                if condition(self) {
+                    return;
+                }
+                assert!(
+                    start.elapsed() < Duration::from_secs(5),
+                    "Timed out waiting for the peers"
+                );
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+
+        fn has_desync(events: &[NetworkEvent]) -> bool {
+            events
+                .iter()
+                .any(|event| matches!(event, NetworkEvent::Desync { .. }))
+        }
+    }
+
+    fn game_setup() -> GameSetup {
+        GameSetup {
+            seed: 7,
+            rules: Rules::default(),
+            diplomacy: Diplomacy::new(),
+            profiles: PlayerProfile::defaults(2),
+        }
+    }
+
+    fn move_entry(player: usize) -> Entry {
+        Entry::new(
+            player,
+            Command::Move(
+                Hexagon::zero(),
+                vec![Hexagon::new_axial(1, 0), Hexagon::new_axial(2, -1)],
+            ),
+        )
+    }
+
+    #[test]
+    fn messages_survive_formatting() {
+        let messages = vec![
+            Message::Hello(vec![1, 3]),
+            Message::Setup(game_setup()),
+            Message::Command(4, move_entry(1)),
+            Message::Command(
+                5,
+                Entry::new(
+                    0,
+                    Command::Attack(Hexagon::zero(), Hexagon::new_axial(0, 2)),
+                ),
+            ),
+            Message::Hash(6, u64::MAX),
+            Message::Snapshot(0, Snapshot::default()),
+            Message::Snapshot(
+                8,
+                Snapshot {
+                    current_player: Some(1),
+                    ..Snapshot::default()
+                },
+            ),
+            Message::Resync(Vec::new()),
+            Message::Resync(vec![move_entry(0), Entry::new(0, Command::EndTurn)]),
+            Message::Resync(vec![
+                Entry::new(
+                    1,
+                    Command::UseAbility(Hexagon::zero(), 2, Hexagon::new_axial(1, 1)),
+                ),
+                Entry::new(1, Command::SelectWeapon(Hexagon::zero(), None)),
+                Entry::new(1, Command::SelectWeapon(Hexagon::zero(), Some(1))),
+                Entry::new(
+                    1,
+                    Command::Disembark(Hexagon::zero(), Hexagon::new_axial(0, -1)),
+                ),
+                Entry::new(1, Command::Recruit(Hexagon::new_axial(3, 0), 4)),
+            ]),
+        ];
+        for message in messages {
+            assert_eq!(message.to_string().parse::<Message>(), Ok(message));
+        }
+        assert_eq!(
+            "jump 1".parse::<Message>(),
+            Err(ParseMessageError::UnknownKind("jump".to_owned()))
+        );
+        assert_eq!(
+            "hash 1".parse::<Message>(),
+            Err(ParseMessageError::MissingField)
+        );
+        assert!(matches!(
+            "command 1 0 move 1,1,1".parse::<Message>(),
+            Err(ParseMessageError::InvalidHexagon(_))
+        ));
+    }
+
+    #[test]
+    fn peers_receive_the_setup_of_the_host() {
+        let mut peers = Peers::connect();
+        assert_eq!(peers.peer_events, vec![NetworkEvent::Setup(game_setup())]);
+        assert!(peers.host.submit(move_entry(0)));
+
+        let address = peers.host.local_addr().unwrap();
+        peers.peer.reconnect(address).unwrap();
+        peers.poll_until(|peers| peers.peer.has_pending());
+        assert_eq!(peers.peer_events.len(), 1);
+    }
+
+    #[test]
+    fn peers_only_get_open_players() {
+        let mut peers = Peers::connect();
+        let address = peers.host.local_addr().unwrap();
+        let mut second = Lockstep::join(address, vec![1]).unwrap();
+        let mut third = Lockstep::join(address, vec![0]).unwrap();
+        let mut fourth = Lockstep::join(address, vec![2]).unwrap();
+
+        peers.poll_until(|peers| peers.host.connections.len() == 2);
+        for _ in 0..10 {
+            peers.host_events.extend(peers.host.poll());
+            second.poll();
+            third.poll();
+            fourth.poll();
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(peers.host.connections.len(), 2);
+        assert!(!second.is_ready());
+        assert!(!third.is_ready());
+        assert!(!fourth.is_ready());
+        assert_eq!(
+            peers
+                .host_events
+                .iter()
+                .filter(|event| matches!(event, NetworkEvent::Joined(_)))
+                .count(),
+            1
+        );
+
+        // The player is open again once its peer left
+        drop(peers.peer);
+        let start = Instant::now();
+        while !second.is_ready() {
+            peers.host.poll();
+            second.poll();
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn peers_exchange_commands_and_hashes() {
+        let mut peers = Peers::connect();
+        assert!(!peers.host.submit(Entry::new(1, Command::EndTurn)));
+        assert!(peers.host.submit(move_entry(0)));
+        assert!(peers.host.submit(Entry::new(0, Command::EndTurn)));
+        assert!(!peers.host.submit(move_entry(0)));
+        peers.host.record_hash(7);
+
+        peers.poll_until(|peers| peers.peer.incoming.len() == 2);
+        assert!(!peers.peer.submit(move_entry(1)));
+        assert_eq!(peers.peer.next_command(|_| true), Some(Ok(move_entry(0))));
+        assert!(peers.peer.next_command(|_| true).unwrap().is_ok());
+        assert!(peers.peer.needs_hash());
+        peers.peer.record_hash(7);
+        assert!(peers.peer.submit(move_entry(1)));
+
+        peers.poll_until(|peers| peers.host.has_pending());
+        assert_eq!(peers.host.next_command(|_| true), Some(Ok(move_entry(1))));
+        assert_eq!(peers.host.get_log(), peers.peer.get_log());
+        assert!(!Peers::has_desync(&peers.host_events));
+        assert!(!Peers::has_desync(&peers.peer_events));
+    }
+
+    #[test]
+    fn differing_hashes_restart_the_peer() {
+        let mut peers = Peers::connect();
+        let ended = Entry::new(0, Command::EndTurn);
+        assert!(peers.host.submit(ended.clone()));
+        peers.host.record_hash(1);
+        peers.poll_until(|peers| peers.peer.has_pending());
+        assert!(peers.peer.next_command(|_| true).is_some());
+        peers.peer.record_hash(2);
+
+        peers.poll_until(|peers| peers.peer_events.contains(&NetworkEvent::Restart(None)));
+        assert!(peers.host_events.contains(&NetworkEvent::Desync {
+            sequence: 1,
+            local: 1,
+            remote: 2
+        }));
+        assert!(peers.peer_events.contains(&NetworkEvent::Desync {
+            sequence: 1,
+            local: 2,
+            remote: 1
+        }));
+        assert!(peers.peer.get_log().is_empty());
+        assert_eq!(peers.peer.next_command(|_| true), Some(Ok(ended)));
+    }
+
+    #[test]
+    fn differing_hashes_restart_the_peer_from_the_snapshot() {
+        let mut peers = Peers::connect();
+        let snapshot = Snapshot {
+            current_player: Some(1),
+            ..Snapshot::default()
+        };
+        assert!(peers.host.submit(move_entry(0)));
+        assert!(peers.host.submit(Entry::new(0, Command::EndTurn)));
+        peers.host.record_hash(1);
+        peers.host.record_snapshot(snapshot.clone());
+        assert!(peers.host.submit(move_entry(0)));
+        peers.poll_until(|peers| peers.peer.incoming.len() == 3);
+        assert!(peers.peer.next_command(|_| true).is_some());
+        assert!(peers.peer.next_command(|_| true).is_some());
+        peers.peer.record_hash(2);
+
+        peers.poll_until(|peers| {
+            peers
+                .peer_events
+                .contains(&NetworkEvent::Restart(Some(snapshot.clone())))
+        });
+        assert!(Peers::has_desync(&peers.host_events));
+        assert_eq!(peers.peer.get_log(), &peers.host.get_log()[..2]);
+        assert!(peers.peer.needs_hash());
+        assert_eq!(peers.peer.next_command(|_| true), Some(Ok(move_entry(0))));
+        assert!(!peers.peer.has_pending());
+    }
+
+    #[test]
+    fn invalid_commands_restart_the_sender() {
+        let mut peers = Peers::connect();
+        assert!(peers.peer.submit(move_entry(1)));
+        peers.poll_until(|peers| peers.host.has_pending());
+        assert_eq!(peers.host.next_command(|_| false), Some(Err(move_entry(1))));
+
+        peers.poll_until(|peers| peers.peer_events.contains(&NetworkEvent::Restart(None)));
+        assert!(peers.host.get_log().is_empty());
+        assert!(peers.peer.get_log().is_empty());
+        assert!(!peers.peer.has_pending());
+    }
+
+    #[test]
+    fn peers_that_stop_reading_are_disconnected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut connection = Connection::new(0, listener.accept().unwrap().0).unwrap();
+        let message = Message::Resync(vec![move_entry(0); 1000]);
+        let start = Instant::now();
+        while connection.is_open {
+            connection.send(&message);
+            assert!(start.elapsed() < Duration::from_secs(30));
+        }
+    }
+
+    #[test]
+    fn reconnecting_peers_receive_missed_commands() {
+        let mut peers = Peers::connect();
+        assert!(peers.host.submit(move_entry(0)));
+        peers.poll_until(|peers| peers.peer.has_pending());
+        assert!(peers.peer.next_command(|_| true).is_some());
+        assert!(peers.host.submit(Entry::new(0, Command::EndTurn)));
+
+        let address = peers.host.local_addr().unwrap();
+        peers.peer.reconnect(address).unwrap();
+        peers.poll_until(|peers| {
+            peers.host_events.contains(&NetworkEvent::Disconnected(1)) && peers.peer.has_pending()
+        });
+        assert_eq!(
+            peers
+                .host_events
+                .iter()
+                .filter(|event| **event == NetworkEvent::Joined(1))
+                .count(),
+            2
+        );
+        assert_eq!(
+            peers.peer.next_command(|_| true),
+            Some(Ok(Entry::new(0, Command::EndTurn)))
+        );
+        assert!(!peers.peer.has_pending());
+        assert!(!peers.peer_events.contains(&NetworkEvent::Restart(None)));
+    }
+}
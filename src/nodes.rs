@@ -1,3 +1,5 @@
 pub mod gameworld;
 pub mod hexgrid;
+pub mod objectives;
+pub mod structures;
 pub mod units;
@@ -1,4 +1,10 @@
 use crate::components::node_component::NodeComponent;
+use crate::diplomacy::Relation;
+use crate::economy::Currency;
+use crate::layout::{Layout, Orientation};
+use crate::player::{Controller, PlayerProfile};
+use crate::search::SearchSettings;
+use crate::systems::ai::Difficulty;
 use crate::systems::{with_world, UpdateNodes};
 use crossbeam::channel::Receiver;
 use crossbeam::crossbeam_channel;
@@ -7,6 +13,7 @@ use gdnative::prelude::*;
 use legion::world::Event;
 use legion::{component, Entity};
 use std::collections::HashMap;
+use std::time::Duration;
 
 #[derive(NativeClass)]
 #[inherit(Node2D)]
@@ -19,6 +26,12 @@ pub struct GameWorld {
     ui_node: Option<NodePath>,
     #[property]
     camera_node: Option<NodePath>,
+    #[property(default = false, after_set = "Self::update_layout")]
+    hex_flat_topped: bool,
+    #[property(after_set = "Self::update_layout")]
+    hex_size: Vector2,
+    #[property(after_set = "Self::update_layout")]
+    hex_origin: Vector2,
 }
 
 #[methods]
@@ -28,15 +41,37 @@ impl GameWorld {
         with_world(|world| {
             world.subscribe(sender.clone(), component::<NodeComponent>());
         });
+        let hex_size = Vector2::new(40f32, 40f32);
+        let hex_origin = Vector2::zero();
         Self {
-            process: UpdateNodes::new(owner.claim(), 40f32),
+            process: UpdateNodes::new(
+                owner.claim(),
+                Layout::new(Orientation::Pointy, hex_size, hex_origin),
+            ),
             event_receiver: receiver,
             node_entity: HashMap::new(),
             ui_node: None,
             camera_node: None,
+            hex_flat_topped: false,
+            hex_size,
+            hex_origin,
         }
     }
 
+    fn get_layout(&self) -> Layout {
+        let orientation = if self.hex_flat_topped {
+            Orientation::Flat
+        } else {
+            Orientation::Pointy
+        };
+        Layout::new(orientation, self.hex_size, self.hex_origin)
+    }
+
+    fn update_layout(&mut self, _owner: TRef<'_, Node2D>) {
+        let layout = self.get_layout();
+        self.process.set_layout(layout);
+    }
+
     fn register_signals(builder: &ClassBuilder<Self>) {
         builder.add_signal(Signal {
             name: "hex_left_clicked",
@@ -68,15 +103,28 @@ impl GameWorld {
             }
         }
         for entity in added_entities {
+            // Entities can be gone again within the frame, like cargo that is destroyed by
+            // overwatch while it disembarks, and not every entity has a node
             with_world(|world| {
-                let entry = world.entry(entity).unwrap();
-                let node = entry.get_component::<NodeComponent>().unwrap();
-                self.node_entity.insert(entity, node.node);
+                if let Some(entry) = world.entry(entity) {
+                    if let Ok(node) = entry.get_component::<NodeComponent>() {
+                        self.node_entity.insert(entity, node.node);
+                    }
+                }
             });
         }
 
         for entity in removed_entities {
-            unsafe { self.node_entity[&entity].assume_safe() }.queue_free();
+            // Adding or removing components moves the entity to another archetype, which
+            // also sends a removed event. Only free the node if the entity is really gone.
+            let mut exists = false;
+            with_world(|world| exists = world.contains(entity));
+            if exists {
+                continue;
+            }
+            if let Some(node) = self.node_entity.remove(&entity) {
+                unsafe { node.assume_safe() }.queue_free();
+            }
         }
 
         let ui_node = match &self.ui_node {
@@ -135,6 +183,139 @@ impl GameWorld {
         self.process.new_round();
     }
 
+    /// Returns the balance of the player in the currency with the name
+    #[export]
+    pub fn get_balance(&self, _owner: TRef<'_, Node2D>, player: i64, currency: String) -> i64 {
+        match (
+            self.process.get_ledger(player as usize),
+            Currency::from_name(&currency),
+        ) {
+            (Some(ledger), Some(currency)) => ledger.get_balance(currency) as i64,
+            _ => 0,
+        }
+    }
+
+    /// Returns the transactions of the player as dictionaries with the currency, the amount
+    /// and the reason
+    #[export]
+    pub fn get_transactions(&self, _owner: TRef<'_, Node2D>, player: i64) -> VariantArray {
+        let transactions = VariantArray::new();
+        if let Some(ledger) = self.process.get_ledger(player as usize) {
+            for transaction in ledger.get_transactions() {
+                let dictionary = Dictionary::new();
+                dictionary.insert("currency", transaction.currency.get_name());
+                dictionary.insert("amount", transaction.amount);
+                dictionary.insert("reason", transaction.reason.clone());
+                transactions.push(dictionary.into_shared());
+            }
+        }
+        transactions.into_shared()
+    }
+
+    /// Puts the player into the team. Players of a team are allied.
+    #[export]
+    pub fn set_team(&mut self, _owner: TRef<'_, Node2D>, player: i64, team: i64) {
+        self.process.set_team(player as usize, team as usize);
+    }
+
+    /// Sets the relation with the name, Allied, Neutral or Hostile, between two players
+    #[export]
+    pub fn set_relation(
+        &mut self,
+        _owner: TRef<'_, Node2D>,
+        player: i64,
+        other: i64,
+        relation: String,
+    ) {
+        match Relation::from_name(&relation) {
+            None => godot_error!("Unknown relation {}", relation),
+            Some(relation) => self
+                .process
+                .set_relation(player as usize, other as usize, relation),
+        }
+    }
+
+    /// Sets up the player. The controller is Human or AI, a negative team leaves the
+    /// player without allies.
+    #[export]
+    pub fn set_player(
+        &mut self,
+        _owner: TRef<'_, Node2D>,
+        player: i64,
+        name: String,
+        colour: Color,
+        controller: String,
+        team: i64,
+    ) {
+        let controller = match Controller::from_name(&controller) {
+            None => {
+                godot_error!("Unknown controller {}", controller);
+                return;
+            }
+            Some(controller) => controller,
+        };
+        let mut profile = PlayerProfile::new(name, colour);
+        profile.controller = controller;
+        profile.team = if team < 0 { None } else { Some(team as usize) };
+        self.process.configure_player(player as usize, &profile);
+    }
+
+    /// Lets the AI play for the player on the difficulty with the name, Easy, Normal or Hard
+    #[export]
+    pub fn set_ai(&mut self, _owner: TRef<'_, Node2D>, player: i64, difficulty: String) {
+        match Difficulty::from_name(&difficulty) {
+            None => godot_error!("Unknown difficulty {}", difficulty),
+            Some(difficulty) => self
+                .process
+                .set_controller(player as usize, Controller::Ai(difficulty)),
+        }
+    }
+
+    /// Lets a search play for the player, taking up to the time budget in milliseconds for
+    /// every action
+    #[export]
+    pub fn set_search(&mut self, _owner: TRef<'_, Node2D>, player: i64, time_budget: i64) {
+        let settings = SearchSettings::new(Duration::from_millis(time_budget.max(0) as u64));
+        self.process
+            .set_controller(player as usize, Controller::Search(settings));
+    }
+
+    /// Hosts a networked game on the port, playing the player here
+    #[export]
+    pub fn host(&mut self, _owner: TRef<'_, Node2D>, port: i64, player: i64) -> bool {
+        match self.process.host(port as u16, player as usize) {
+            Ok(()) => true,
+            Err(error) => {
+                godot_error!("Could not host on port {}: {}", port, error);
+                false
+            }
+        }
+    }
+
+    /// Joins the networked game at the address, playing the player here
+    #[export]
+    pub fn join(&mut self, _owner: TRef<'_, Node2D>, address: String, player: i64) -> bool {
+        match self.process.join(&address, player as usize) {
+            Ok(()) => true,
+            Err(error) => {
+                godot_error!("Could not join {}: {}", address, error);
+                false
+            }
+        }
+    }
+
+    /// Connects to the host at the address again after the connection was lost
+    #[export]
+    pub fn reconnect(&mut self, _owner: TRef<'_, Node2D>, address: String) -> bool {
+        match self.process.reconnect(&address) {
+            Ok(()) => true,
+            Err(error) => {
+                godot_error!("Could not reconnect to {}: {}", address, error);
+                false
+            }
+        }
+    }
+
     #[export]
     pub fn _draw(&mut self, _owner: TRef<'_, Node2D>) {
         self.process.execute_draw();
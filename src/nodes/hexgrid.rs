@@ -1,9 +1,7 @@
 use crate::{
     components::{
-        hexagon::Hexagon,
-        node_component::NodeComponent,
-        player::Player,
-        unit::{CanMove, Unit},
+        hexagon::Hexagon, mobility::CanMove, node_component::NodeComponent, player::Player,
+        unit::Unit,
     },
     game_state::{GameState, State},
     systems::{
@@ -0,0 +1,43 @@
+use crate::components::node_component::NodeComponent;
+use crate::components::objective::Objective;
+use crate::components::player::Player;
+use crate::game_state::GameState;
+use gdnative::prelude::*;
+use legion::system;
+
+#[system(par_for_each)]
+pub fn update_objectives(
+    node: &NodeComponent,
+    objective: &Objective,
+    player: Option<&Player>,
+    #[resource] state: &GameState,
+) {
+    let node = match node.get_node() {
+        Some(node) => node,
+        None => return,
+    };
+    let name_label = node
+        .get_node("Name")
+        .and_then(|node| unsafe { node.assume_safe_if_sane() })
+        .and_then(|node| node.cast::<Label>());
+    if let Some(name_label) = name_label {
+        name_label.set_text(objective.kind.get_name());
+    }
+
+    let model = node
+        .get_node("Model")
+        .and_then(|node| unsafe { node.assume_safe_if_sane() })
+        .and_then(|node| node.cast::<CanvasItem>());
+    let model = match model {
+        None => {
+            godot_error!("Node has no Model CanvasItem node");
+            return;
+        }
+        Some(model) => model,
+    };
+    let colour = match player.and_then(|player| state.players.get(player.0)) {
+        None => Color::rgb(1f32, 1f32, 1f32),
+        Some(player) => player.get_colour(),
+    };
+    model.set_modulate(colour);
+}
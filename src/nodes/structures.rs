@@ -0,0 +1,32 @@
+use crate::components::health::Health;
+use crate::components::node_component::NodeComponent;
+use crate::components::structure::Structure;
+use gdnative::prelude::*;
+use legion::system;
+
+#[system(par_for_each)]
+pub fn update_structures(node: &NodeComponent, structure: &Structure, health: Option<&Health>) {
+    let node = match node.get_node() {
+        Some(node) => node,
+        None => return,
+    };
+    let integrity_label = node
+        .get_node("Integrity")
+        .and_then(|node| unsafe { node.assume_safe_if_sane() })
+        .and_then(|node| node.cast::<Label>());
+    if let Some(integrity_label) = integrity_label {
+        let text = match health {
+            None => String::new(),
+            Some(health) => format!("{}", health.integrity),
+        };
+        integrity_label.set_text(text);
+    }
+
+    let structure_model = node
+        .get_node("Structure")
+        .and_then(|node| unsafe { node.assume_safe_if_sane() })
+        .and_then(|node| node.cast::<CanvasItem>());
+    if let Some(structure_model) = structure_model {
+        structure_model.set_modulate(structure.kind.get_colour());
+    }
+}
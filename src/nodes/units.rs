@@ -1,19 +1,45 @@
+use crate::components::experience::Experience;
+use crate::components::health::Health;
+use crate::components::hexagon::Hexagon;
+use crate::components::morale::Morale;
 use crate::components::node_component::NodeComponent;
 use crate::components::player::Player;
+use crate::components::status_effects::StatusEffects;
+use crate::components::transport::Carried;
 use crate::components::unit::Unit;
 use crate::game_state::GameState;
 use crate::game_state::State::Selected;
 use gdnative::prelude::*;
-use legion::{system, Entity};
+use legion::{component, system, Entity};
 
 pub mod dummy_unit;
 
+/// Hides the units aboard a transport and the units the current player can not see
 #[system(par_for_each)]
+#[filter(component::<Unit>())]
+pub fn update_unit_visibility(
+    node: &NodeComponent,
+    hexagon: Option<&Hexagon>,
+    carried: Option<&Carried>,
+    #[resource] state: &GameState,
+) {
+    if let Some(node) = node.get_node() {
+        let is_seen = matches!(hexagon, Some(hexagon) if state.is_hexagon_visible(hexagon));
+        node.set_visible(carried.is_none() && is_seen);
+    }
+}
+
+#[system(par_for_each)]
+#[filter(component::<Unit>())]
+#[allow(clippy::too_many_arguments)]
 pub fn update_units(
     entity: &Entity,
     node: &NodeComponent,
-    unit: &Unit,
+    health: &Health,
     player: &Player,
+    status_effects: Option<&StatusEffects>,
+    experience: Option<&Experience>,
+    morale: Option<&Morale>,
     #[resource] state: &GameState,
 ) {
     let node = match node.get_node() {
@@ -32,7 +58,38 @@ pub fn update_units(
         Some(label) => label,
     };
 
-    integrity_label.set_text(format!("{}", unit.integrity));
+    integrity_label.set_text(format!("{}", health.integrity));
+
+    let rank_label = node
+        .get_node("Rank")
+        .and_then(|node| unsafe { node.assume_safe_if_sane() })
+        .and_then(|node| node.cast::<Label>());
+    if let Some(rank_label) = rank_label {
+        let text = match experience {
+            None => "",
+            Some(experience) => experience.get_badge(),
+        };
+        rank_label.set_text(text);
+    }
+
+    let status_label = node
+        .get_node("StatusEffects")
+        .and_then(|node| unsafe { node.assume_safe_if_sane() })
+        .and_then(|node| node.cast::<Label>());
+    if let Some(status_label) = status_label {
+        let morale_icon = morale.map_or("", |morale| morale.get_state().get_icon());
+        let effect_icons = match status_effects {
+            None => String::new(),
+            Some(status_effects) => status_effects.get_icon_text(),
+        };
+        let text = [morale_icon, effect_icons.as_str()]
+            .iter()
+            .filter(|icons| !icons.is_empty())
+            .copied()
+            .collect::<Vec<&str>>()
+            .join(" ");
+        status_label.set_text(text);
+    }
 
     let visible = if let Selected(selected) = state.state {
         *entity == selected
@@ -1,21 +1,170 @@
+use crate::economy::Ledger;
+use crate::search::SearchSettings;
+use crate::systems::ai::Difficulty;
 use gdnative::prelude::*;
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Controller {
+    Human,
+    Ai(Difficulty),
+    /// Searches the best action within the time budget of the settings
+    Search(SearchSettings),
+    /// Plays on another peer of a networked game
+    Remote,
+}
+
+impl Controller {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Controller::Human => "Human",
+            Controller::Ai(_) => "AI",
+            Controller::Search(_) => "Search",
+            Controller::Remote => "Remote",
+        }
+    }
+
+    /// Parses the name of the controller. AI controllers play on normal difficulty and
+    /// searches use the default settings.
+    pub fn from_name(name: &str) -> Option<Controller> {
+        [
+            Controller::Human,
+            Controller::Ai(Difficulty::Normal),
+            Controller::Search(SearchSettings::default()),
+            Controller::Remote,
+        ]
+        .iter()
+        .copied()
+        .find(|controller| controller.get_name() == name)
+    }
+}
+
+/// The setup of a player before the game starts
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerProfile {
+    pub name: String,
+    pub colour: Color,
+    pub controller: Controller,
+    /// The team of the player, or None to play without allies
+    pub team: Option<usize>,
+}
+
+impl PlayerProfile {
+    pub fn new(name: String, colour: Color) -> Self {
+        PlayerProfile {
+            name,
+            colour,
+            controller: Controller::Human,
+            team: None,
+        }
+    }
+
+    /// Human players without teams, named and coloured by their index
+    pub fn defaults(count: usize) -> Vec<PlayerProfile> {
+        let colours = [
+            Color::rgb(0f32, 0f32, 1f32),
+            Color::rgb(1f32, 0f32, 0f32),
+            Color::rgb(0f32, 0.8f32, 0f32),
+            Color::rgb(1f32, 1f32, 0f32),
+        ];
+        (0..count)
+            .map(|index| {
+                PlayerProfile::new(
+                    format!("Player {}", index + 1),
+                    colours[index % colours.len()],
+                )
+            })
+            .collect()
+    }
+}
+
 #[derive(Clone)]
 pub struct Player {
     name: String,
     colour: Color,
+    controller: Controller,
+    ledger: Ledger,
 }
 
 impl Player {
     pub fn new(name: String, colour: Color) -> Self {
-        Player { name, colour }
+        Player {
+            name,
+            colour,
+            controller: Controller::Human,
+            ledger: Ledger::new(),
+        }
+    }
+
+    pub fn from_profile(profile: &PlayerProfile) -> Self {
+        let mut player = Player::new(profile.name.clone(), profile.colour);
+        player.controller = profile.controller;
+        player
     }
 
     pub fn get_name(&self) -> String {
         self.name.clone()
     }
 
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
     pub fn get_colour(&self) -> Color {
         self.colour
     }
+
+    pub fn set_colour(&mut self, colour: Color) {
+        self.colour = colour;
+    }
+
+    pub fn get_controller(&self) -> Controller {
+        self.controller
+    }
+
+    pub fn set_controller(&mut self, controller: Controller) {
+        self.controller = controller;
+    }
+
+    pub fn is_human(&self) -> bool {
+        self.controller == Controller::Human
+    }
+
+    pub fn get_ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    pub fn get_ledger_mut(&mut self) -> &mut Ledger {
+        &mut self.ledger
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_distinct_humans() {
+        let profiles = PlayerProfile::defaults(3);
+        assert_eq!(profiles.len(), 3);
+        assert_eq!(profiles[2].name, "Player 3");
+        assert_ne!(profiles[0].colour, profiles[1].colour);
+        assert!(profiles
+            .iter()
+            .all(|profile| profile.controller == Controller::Human && profile.team.is_none()));
+    }
+
+    #[test]
+    fn from_profile_keeps_the_controller() {
+        let mut profile = PlayerProfile::new("Bot".to_owned(), Color::rgb(1f32, 1f32, 1f32));
+        profile.controller = Controller::Ai(Difficulty::Hard);
+        let player = Player::from_profile(&profile);
+        assert_eq!(player.get_name(), "Bot");
+        assert!(!player.is_human());
+        assert_eq!(player.get_controller(), Controller::Ai(Difficulty::Hard));
+        assert_eq!(
+            Controller::from_name("AI"),
+            Some(Controller::Ai(Difficulty::Normal))
+        );
+        assert_eq!(Controller::from_name("Robot"), None);
+    }
 }
@@ -0,0 +1,74 @@
+use crate::components::field::Field;
+use crate::components::hexagon::Hexagon;
+use crate::components::node_template::NodeTemplate;
+use crate::components::objective::{Objective, ObjectiveKind};
+use crate::components::structure::{Structure, StructureKind};
+use crate::player::PlayerProfile;
+use crate::systems::hexgrid::TerrainMap;
+use legion::World;
+
+/// The layout of a map: its fields and the hexagons each player may start on.
+#[derive(Clone)]
+pub struct Scenario {
+    pub fields: Vec<Field>,
+    /// Start zones by player index. The first hexagon of a zone is its center.
+    pub start_zones: Vec<Vec<Hexagon>>,
+    /// Neutral objectives placed on the map at the start
+    pub objectives: Vec<(Hexagon, ObjectiveKind)>,
+    /// Neutral structures placed on the map at the start
+    pub structures: Vec<(Hexagon, StructureKind)>,
+    /// Profiles by player index, or empty to use the default profiles
+    pub players: Vec<PlayerProfile>,
+}
+
+impl Scenario {
+    pub fn new(fields: Vec<Field>, start_zones: Vec<Vec<Hexagon>>) -> Self {
+        Scenario {
+            fields,
+            start_zones,
+            objectives: Vec::new(),
+            structures: Vec::new(),
+            players: Vec::new(),
+        }
+    }
+
+    pub fn get_terrain_map(&self) -> TerrainMap {
+        TerrainMap::from_fields(&self.fields)
+    }
+
+    pub fn spawn_fields(&self, world: &mut World) {
+        world.extend(self.fields.iter().map(|field| (*field,)));
+    }
+
+    pub fn spawn_objectives(&self, world: &mut World) {
+        world.extend(self.objectives.iter().map(|(hexagon, kind)| {
+            (
+                *hexagon,
+                Objective::new(*kind),
+                kind.get_income(),
+                NodeTemplate {
+                    scene_file: "res://Objective.tscn".to_owned(),
+                    scale_x: 1.0,
+                    scale_y: 1.0,
+                    z_index: 1,
+                },
+            )
+        }));
+    }
+
+    pub fn spawn_structures(&self, world: &mut World) {
+        world.extend(self.structures.iter().map(|(hexagon, kind)| {
+            (
+                *hexagon,
+                Structure::new(*kind),
+                kind.get_health(),
+                NodeTemplate {
+                    scene_file: "res://Structure.tscn".to_owned(),
+                    scale_x: 1.0,
+                    scale_y: 1.0,
+                    z_index: 0,
+                },
+            )
+        }));
+    }
+}
@@ -0,0 +1,700 @@
+use crate::components::hexagon::Hexagon;
+use crate::components::weapon::DamageType;
+use crate::diplomacy::Diplomacy;
+use crate::systems::hexgrid::get_neighbours;
+use crossbeam::channel::{Receiver, TryRecvError};
+use crossbeam::crossbeam_channel;
+use legion::Entity;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Destinations considered per unit, to keep the search tree narrow
+const MOVES_PER_UNIT: usize = 3;
+/// Weight of exploring rarely visited actions against exploiting good ones
+const EXPLORATION: f64 = 1.4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SearchSettings {
+    /// How long the search for a single action may take
+    pub time_budget: Duration,
+    /// Actions simulated after leaving the search tree, before the position is evaluated
+    pub rollout_depth: usize,
+}
+
+impl SearchSettings {
+    pub fn new(time_budget: Duration) -> Self {
+        SearchSettings {
+            time_budget,
+            rollout_depth: 24,
+        }
+    }
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        SearchSettings::new(Duration::from_millis(500))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotWeapon {
+    pub damage: i32,
+    pub damage_type: DamageType,
+    pub min_range: i32,
+    pub max_range: i32,
+    pub ammo: Option<i32>,
+    pub attack_cost: i32,
+}
+
+impl SnapshotWeapon {
+    fn can_fire(&self, distance: i32, remaining_attacks: i32) -> bool {
+        distance >= self.min_range
+            && distance <= self.max_range
+            && remaining_attacks > 0
+            && remaining_attacks >= self.attack_cost
+            && !matches!(self.ammo, Some(ammo) if ammo <= 0)
+    }
+}
+
+/// A unit or a structure as the search sees it, with its modifiers applied to the stats
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotUnit {
+    pub entity: Entity,
+    pub player: usize,
+    pub hexagon: Hexagon,
+    pub integrity: i32,
+    pub max_integrity: i32,
+    pub armor: i32,
+    /// The range the unit gets at the start of its turn
+    pub mobility: i32,
+    pub remaining_range: i32,
+    pub attacks: i32,
+    pub remaining_attacks: i32,
+    pub weapons: Vec<SnapshotWeapon>,
+    /// Structures can be attacked, but neither act nor block movement
+    pub is_structure: bool,
+}
+
+impl SnapshotUnit {
+    /// Creates an unarmed unit that can not move
+    pub fn new(entity: Entity, player: usize, hexagon: Hexagon, integrity: i32) -> Self {
+        SnapshotUnit {
+            entity,
+            player,
+            hexagon,
+            integrity,
+            max_integrity: integrity,
+            armor: 0,
+            mobility: 0,
+            remaining_range: 0,
+            attacks: 0,
+            remaining_attacks: 0,
+            weapons: Vec::new(),
+            is_structure: false,
+        }
+    }
+
+    pub fn is_destroyed(&self) -> bool {
+        self.integrity <= 0
+    }
+
+    fn is_active(&self) -> bool {
+        !self.is_destroyed() && !self.is_structure
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SearchAction {
+    Attack(Entity, Entity),
+    /// Moves the unit to the hexagon, using up the number of steps of its range
+    Move(Entity, Hexagon, i32),
+    EndTurn,
+}
+
+/// The state of the units and the map, separate from the world so that it can be cloned
+/// cheaply and searched on another thread. Attacks only hit their target and status
+/// effects, morale and supply are left out.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub units: Vec<SnapshotUnit>,
+    pub current_player: usize,
+    player_count: usize,
+    passable: Arc<HashSet<Hexagon>>,
+    sight_blocking: Arc<HashSet<Hexagon>>,
+    diplomacy: Arc<Diplomacy>,
+}
+
+impl Snapshot {
+    pub fn new(
+        units: Vec<SnapshotUnit>,
+        current_player: usize,
+        player_count: usize,
+        passable: HashSet<Hexagon>,
+        sight_blocking: HashSet<Hexagon>,
+        diplomacy: Diplomacy,
+    ) -> Self {
+        Snapshot {
+            units,
+            current_player,
+            player_count,
+            passable: Arc::new(passable),
+            sight_blocking: Arc::new(sight_blocking),
+            diplomacy: Arc::new(diplomacy),
+        }
+    }
+
+    fn get_index(&self, entity: Entity) -> Option<usize> {
+        self.units.iter().position(|unit| unit.entity == entity)
+    }
+
+    /// Whether all players with units left are allied with each other
+    pub fn is_over(&self) -> bool {
+        let mut active = self.units.iter().filter(|unit| unit.is_active());
+        match active.next() {
+            None => true,
+            Some(first) => active.all(|unit| self.diplomacy.is_allied(first.player, unit.player)),
+        }
+    }
+
+    fn is_sight_blocked(&self, from: &Hexagon, to: &Hexagon) -> bool {
+        let line = from.line_to(to);
+        line.iter()
+            .skip(1)
+            .take(line.len().saturating_sub(2))
+            .any(|hexagon| self.sight_blocking.contains(hexagon))
+    }
+
+    /// Returns the index and the damage of the weapon that deals the most damage to the
+    /// target, if any can damage it
+    fn get_best_weapon(
+        &self,
+        attacker: &SnapshotUnit,
+        target: &SnapshotUnit,
+    ) -> Option<(usize, i32)> {
+        if attacker.is_destroyed()
+            || target.is_destroyed()
+            || !self.diplomacy.is_hostile(attacker.player, target.player)
+            || self.is_sight_blocked(&attacker.hexagon, &target.hexagon)
+        {
+            return None;
+        }
+        let distance = attacker.hexagon.distance_to(&target.hexagon);
+        attacker
+            .weapons
+            .iter()
+            .enumerate()
+            .filter(|(_, weapon)| weapon.can_fire(distance, attacker.remaining_attacks))
+            .map(|(index, weapon)| {
+                (
+                    index,
+                    weapon.damage_type.apply_armor(weapon.damage, target.armor),
+                )
+            })
+            .filter(|(_, damage)| *damage > 0)
+            .max_by_key(|(_, damage)| *damage)
+    }
+
+    /// Returns the hexagons the unit can reach with its remaining range, with the steps
+    /// to each
+    fn get_reachable(&self, unit: &SnapshotUnit) -> HashMap<Hexagon, i32> {
+        let occupied: HashSet<Hexagon> = self
+            .units
+            .iter()
+            .filter(|other| other.is_active())
+            .map(|other| other.hexagon)
+            .collect();
+        let mut reachable = HashMap::new();
+        reachable.insert(unit.hexagon, 0);
+        let mut frontier = VecDeque::new();
+        frontier.push_back(unit.hexagon);
+        while let Some(current) = frontier.pop_front() {
+            let steps = reachable[&current] + 1;
+            if steps > unit.remaining_range {
+                continue;
+            }
+            for next in get_neighbours(&current) {
+                if reachable.contains_key(&next)
+                    || occupied.contains(&next)
+                    || !self.passable.contains(&next)
+                {
+                    continue;
+                }
+                reachable.insert(next, steps);
+                frontier.push_back(next);
+            }
+        }
+        reachable
+    }
+
+    /// Returns the destinations considered for the unit: closest to the enemies, furthest
+    /// away from them and the nearest one from which an enemy can be attacked
+    fn get_moves(&self, unit: &SnapshotUnit, enemies: &[Hexagon]) -> Vec<SearchAction> {
+        let reachable: Vec<(Hexagon, i32)> = self
+            .get_reachable(unit)
+            .into_iter()
+            .filter(|(hexagon, _)| *hexagon != unit.hexagon)
+            .collect();
+        let nearest = |hexagon: &Hexagon| {
+            enemies
+                .iter()
+                .map(|enemy| enemy.distance_to(hexagon))
+                .min()
+                .unwrap_or(0)
+        };
+        let can_fire = |hexagon: &Hexagon| {
+            enemies.iter().any(|enemy| {
+                let distance = enemy.distance_to(hexagon);
+                unit.weapons
+                    .iter()
+                    .any(|weapon| distance >= weapon.min_range && distance <= weapon.max_range)
+            })
+        };
+        let candidates = [
+            reachable
+                .iter()
+                .min_by_key(|(hexagon, steps)| {
+                    (nearest(hexagon), *steps, hexagon.get_q(), hexagon.get_r())
+                })
+                .copied(),
+            reachable
+                .iter()
+                .max_by_key(|(hexagon, steps)| {
+                    (nearest(hexagon), -*steps, hexagon.get_q(), hexagon.get_r())
+                })
+                .copied(),
+            reachable
+                .iter()
+                .filter(|(hexagon, _)| can_fire(hexagon))
+                .min_by_key(|(hexagon, steps)| (*steps, hexagon.get_q(), hexagon.get_r()))
+                .copied(),
+        ];
+        let mut moves = Vec::new();
+        for (hexagon, steps) in candidates.iter().flatten() {
+            let action = SearchAction::Move(unit.entity, *hexagon, *steps);
+            if !moves.contains(&action) && moves.len() < MOVES_PER_UNIT {
+                moves.push(action);
+            }
+        }
+        moves
+    }
+
+    /// Returns the actions of the current player. Ending the turn is always possible.
+    pub fn get_actions(&self) -> Vec<SearchAction> {
+        let mut actions = vec![SearchAction::EndTurn];
+        if self.is_over() {
+            return actions;
+        }
+        for unit in self
+            .units
+            .iter()
+            .filter(|unit| unit.player == self.current_player && unit.is_active())
+        {
+            let enemies: Vec<&SnapshotUnit> = self
+                .units
+                .iter()
+                .filter(|other| {
+                    !other.is_destroyed() && self.diplomacy.is_hostile(unit.player, other.player)
+                })
+                .collect();
+            for enemy in &enemies {
+                if self.get_best_weapon(unit, enemy).is_some() {
+                    actions.push(SearchAction::Attack(unit.entity, enemy.entity));
+                }
+            }
+            if unit.remaining_range > 0 && !enemies.is_empty() {
+                let enemies: Vec<Hexagon> = enemies.iter().map(|enemy| enemy.hexagon).collect();
+                actions.extend(self.get_moves(unit, &enemies));
+            }
+        }
+        actions
+    }
+
+    pub fn apply(&mut self, action: &SearchAction) {
+        match action {
+            SearchAction::Attack(attacker, target) => {
+                let (attacker, target) = match (self.get_index(*attacker), self.get_index(*target))
+                {
+                    (Some(attacker), Some(target)) => (attacker, target),
+                    _ => return,
+                };
+                let (weapon, damage) =
+                    match self.get_best_weapon(&self.units[attacker], &self.units[target]) {
+                        None => return,
+                        Some(weapon) => weapon,
+                    };
+                self.units[target].integrity -= damage;
+                let attacker = &mut self.units[attacker];
+                attacker.remaining_attacks -= attacker.weapons[weapon].attack_cost;
+                let weapon = &mut attacker.weapons[weapon];
+                weapon.ammo = weapon.ammo.map(|ammo| ammo - 1);
+            }
+            SearchAction::Move(entity, hexagon, steps) => {
+                if let Some(index) = self.get_index(*entity) {
+                    let unit = &mut self.units[index];
+                    unit.hexagon = *hexagon;
+                    unit.remaining_range -= steps;
+                }
+            }
+            SearchAction::EndTurn => self.end_turn(),
+        }
+    }
+
+    /// Passes the turn to the next player with units left and restores their range and
+    /// attacks
+    fn end_turn(&mut self) {
+        for _ in 0..self.player_count {
+            self.current_player = (self.current_player + 1) % self.player_count.max(1);
+            let player = self.current_player;
+            if self
+                .units
+                .iter()
+                .any(|unit| unit.player == player && unit.is_active())
+            {
+                break;
+            }
+        }
+        let player = self.current_player;
+        for unit in self.units.iter_mut().filter(|unit| unit.player == player) {
+            unit.remaining_range = unit.mobility;
+            unit.remaining_attacks = unit.attacks;
+        }
+    }
+
+    /// Rates the position for the player between 0 and 1, by the share of integrity and of
+    /// units that their side and the hostile side have left
+    pub fn evaluate(&self, player: usize) -> f64 {
+        let side = |is_member: &dyn Fn(usize) -> bool| {
+            let members: Vec<&SnapshotUnit> = self
+                .units
+                .iter()
+                .filter(|unit| is_member(unit.player))
+                .collect();
+            if members.is_empty() {
+                return 0.0;
+            }
+            let integrity: i32 = members.iter().map(|unit| unit.integrity.max(0)).sum();
+            let max_integrity: i32 = members.iter().map(|unit| unit.max_integrity).sum();
+            let remaining = members.iter().filter(|unit| !unit.is_destroyed()).count();
+            let integrity = f64::from(integrity) / f64::from(max_integrity.max(1));
+            let remaining = remaining as f64 / members.len() as f64;
+            (integrity + remaining) / 2.0
+        };
+        let own = side(&|other| self.diplomacy.is_allied(player, other));
+        let hostile = side(&|other| self.diplomacy.is_hostile(player, other));
+        0.5 + 0.5 * (own - hostile)
+    }
+}
+
+/// A small xorshift generator, good enough to pick actions during rollouts
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn pick(&mut self, length: usize) -> usize {
+        (self.next() % length as u64) as usize
+    }
+}
+
+struct Node {
+    action: Option<SearchAction>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<SearchAction>,
+    /// The player choosing between the children
+    player: usize,
+    visits: u32,
+    reward: f64,
+}
+
+impl Node {
+    fn new(action: Option<SearchAction>, parent: Option<usize>, snapshot: &Snapshot) -> Self {
+        Node {
+            action,
+            parent,
+            children: Vec::new(),
+            untried: snapshot.get_actions(),
+            player: snapshot.current_player,
+            visits: 0,
+            reward: 0.0,
+        }
+    }
+}
+
+/// Returns the child with the highest upper confidence bound. Players that are not allied
+/// with the searching player pick the children that are worst for them.
+fn select_child(nodes: &[Node], index: usize, player: usize, diplomacy: &Diplomacy) -> usize {
+    let parent = &nodes[index];
+    let is_allied = diplomacy.is_allied(player, parent.player);
+    let log_visits = f64::from(parent.visits).ln();
+    let bound = |child: usize| {
+        let child = &nodes[child];
+        let value = child.reward / f64::from(child.visits);
+        let value = if is_allied { value } else { 1.0 - value };
+        value + EXPLORATION * (log_visits / f64::from(child.visits)).sqrt()
+    };
+    *parent
+        .children
+        .iter()
+        .max_by(|a, b| {
+            bound(**a)
+                .partial_cmp(&bound(**b))
+                .unwrap_or(Ordering::Equal)
+        })
+        .unwrap()
+}
+
+/// Picks an attack most of the time, to make rollouts play like an aggressive opponent
+fn get_rollout_action(snapshot: &Snapshot, random: &mut Random) -> SearchAction {
+    let mut actions = snapshot.get_actions();
+    let attacks: Vec<usize> = (0..actions.len())
+        .filter(|index| matches!(actions[*index], SearchAction::Attack(_, _)))
+        .collect();
+    let index = if !attacks.is_empty() && random.pick(4) != 0 {
+        attacks[random.pick(attacks.len())]
+    } else {
+        random.pick(actions.len())
+    };
+    actions.swap_remove(index)
+}
+
+/// Searches the best action for the current player of the snapshot with a Monte Carlo tree
+/// search, until the time budget is used up
+pub fn search(snapshot: &Snapshot, settings: &SearchSettings) -> SearchAction {
+    let deadline = Instant::now() + settings.time_budget;
+    let player = snapshot.current_player;
+    let mut random = Random(0x9e37_79b9_7f4a_7c15);
+    let mut nodes = vec![Node::new(None, None, snapshot)];
+    if nodes[0].untried.len() <= 1 {
+        return SearchAction::EndTurn;
+    }
+
+    loop {
+        let mut state = snapshot.clone();
+        let mut index = 0;
+        while nodes[index].untried.is_empty() && !nodes[index].children.is_empty() {
+            index = select_child(&nodes, index, player, &snapshot.diplomacy);
+            if let Some(action) = &nodes[index].action {
+                state.apply(action);
+            }
+        }
+
+        if !nodes[index].untried.is_empty() {
+            let untried = &mut nodes[index].untried;
+            let action = untried.swap_remove(random.pick(untried.len()));
+            state.apply(&action);
+            let child = nodes.len();
+            nodes.push(Node::new(Some(action), Some(index), &state));
+            nodes[index].children.push(child);
+            index = child;
+        }
+
+        for _ in 0..settings.rollout_depth {
+            if state.is_over() {
+                break;
+            }
+            let action = get_rollout_action(&state, &mut random);
+            state.apply(&action);
+        }
+
+        let reward = state.evaluate(player);
+        let mut current = Some(index);
+        while let Some(node) = current {
+            nodes[node].visits += 1;
+            nodes[node].reward += reward;
+            current = nodes[node].parent;
+        }
+
+        if Instant::now() >= deadline {
+            break;
+        }
+    }
+
+    nodes[0]
+        .children
+        .iter()
+        .max_by_key(|child| nodes[**child].visits)
+        .and_then(|child| nodes[*child].action.clone())
+        .unwrap_or(SearchAction::EndTurn)
+}
+
+/// A search running on a background thread, so that the game keeps running meanwhile
+pub struct SearchTask {
+    player: usize,
+    receiver: Receiver<SearchAction>,
+}
+
+impl SearchTask {
+    pub fn spawn(snapshot: Snapshot, settings: SearchSettings) -> Self {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        let player = snapshot.current_player;
+        thread::spawn(move || {
+            // The task may have been dropped in the meantime
+            let _ = sender.send(search(&snapshot, &settings));
+        });
+        SearchTask { player, receiver }
+    }
+
+    /// The player the search is for
+    pub fn get_player(&self) -> usize {
+        self.player
+    }
+
+    /// Returns the action once the search finished. A search that failed ends the turn.
+    pub fn poll(&self) -> Option<SearchAction> {
+        match self.receiver.try_recv() {
+            Ok(action) => Some(action),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(SearchAction::EndTurn),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::hexagon::Hexagon;
+    use crate::systems::hexgrid::create_grid;
+    use legion::{World, WorldOptions};
+
+    fn create_entities(count: usize) -> Vec<Entity> {
+        let mut world = World::new(WorldOptions::default());
+        world
+            .extend((0..count).map(|_| (Hexagon::zero(),)))
+            .to_vec()
+    }
+
+    fn cannon() -> SnapshotWeapon {
+        SnapshotWeapon {
+            damage: 5,
+            damage_type: DamageType::Kinetic,
+            min_range: 1,
+            max_range: 2,
+            ammo: None,
+            attack_cost: 1,
+        }
+    }
+
+    fn tank(entity: Entity, player: usize, hexagon: Hexagon, integrity: i32) -> SnapshotUnit {
+        SnapshotUnit {
+            mobility: 3,
+            remaining_range: 3,
+            attacks: 1,
+            remaining_attacks: 1,
+            weapons: vec![cannon()],
+            ..SnapshotUnit::new(entity, player, hexagon, integrity)
+        }
+    }
+
+    fn create_snapshot(units: Vec<SnapshotUnit>) -> Snapshot {
+        Snapshot::new(
+            units,
+            0,
+            2,
+            create_grid(6).into_iter().collect(),
+            HashSet::new(),
+            Diplomacy::new(),
+        )
+    }
+
+    #[test]
+    fn apply_attack_uses_up_attacks_and_ammo() {
+        let entities = create_entities(2);
+        let (attacker, target) = (entities[0], entities[1]);
+        let mut unit = tank(attacker, 0, Hexagon::zero(), 10);
+        unit.weapons[0].ammo = Some(2);
+        let enemy = SnapshotUnit {
+            armor: 1,
+            ..SnapshotUnit::new(target, 1, Hexagon::new_axial(2, 0), 10)
+        };
+        let mut snapshot = create_snapshot(vec![unit, enemy]);
+
+        let actions = snapshot.get_actions();
+        assert!(actions.contains(&SearchAction::Attack(attacker, target)));
+        assert!(actions.contains(&SearchAction::EndTurn));
+        assert!(
+            actions
+                .iter()
+                .filter(|action| matches!(action, SearchAction::Move(_, _, _)))
+                .count()
+                <= MOVES_PER_UNIT
+        );
+
+        snapshot.apply(&SearchAction::Attack(attacker, target));
+        assert_eq!(snapshot.units[1].integrity, 6);
+        assert_eq!(snapshot.units[0].remaining_attacks, 0);
+        assert_eq!(snapshot.units[0].weapons[0].ammo, Some(1));
+        assert!(!snapshot
+            .get_actions()
+            .contains(&SearchAction::Attack(attacker, target)));
+    }
+
+    #[test]
+    fn end_turn_restores_the_next_player() {
+        let entities = create_entities(2);
+        let (own, enemy) = (entities[0], entities[1]);
+        let mut snapshot = create_snapshot(vec![
+            tank(own, 0, Hexagon::zero(), 10),
+            SnapshotUnit {
+                remaining_range: 0,
+                remaining_attacks: 0,
+                ..tank(enemy, 1, Hexagon::new_axial(4, 0), 10)
+            },
+        ]);
+        snapshot.apply(&SearchAction::Move(own, Hexagon::new_axial(2, 0), 2));
+        assert_eq!(snapshot.units[0].remaining_range, 1);
+
+        snapshot.apply(&SearchAction::EndTurn);
+        assert_eq!(snapshot.current_player, 1);
+        assert_eq!(snapshot.units[1].remaining_range, 3);
+        assert_eq!(snapshot.units[1].remaining_attacks, 1);
+        assert!((snapshot.evaluate(0) - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn search_finishes_off_the_weak_enemy() {
+        let entities = create_entities(3);
+        let (own, strong, weak) = (entities[0], entities[1], entities[2]);
+        // Only the weak enemy is armed, so finishing it off is clearly better than retreating
+        let snapshot = create_snapshot(vec![
+            tank(own, 0, Hexagon::zero(), 10),
+            SnapshotUnit {
+                weapons: Vec::new(),
+                ..tank(strong, 1, Hexagon::new_axial(2, 0), 20)
+            },
+            tank(weak, 1, Hexagon::new_axial(0, 2), 5),
+        ]);
+
+        let settings = SearchSettings::new(Duration::from_millis(200));
+        assert_eq!(
+            search(&snapshot, &settings),
+            SearchAction::Attack(own, weak)
+        );
+    }
+
+    #[test]
+    fn search_task_runs_in_the_background() {
+        let own = create_entities(1)[0];
+        let snapshot = create_snapshot(vec![tank(own, 0, Hexagon::zero(), 10)]);
+
+        let task = SearchTask::spawn(snapshot, SearchSettings::new(Duration::from_millis(10)));
+        assert_eq!(task.get_player(), 0);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let action = loop {
+            if let Some(action) = task.poll() {
+                break action;
+            }
+            assert!(Instant::now() < deadline, "Search did not finish");
+            thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(action, SearchAction::EndTurn);
+    }
+}
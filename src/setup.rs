@@ -0,0 +1,272 @@
+use crate::components::action_points::{ActionCosts, ActionPoints};
+use crate::diplomacy::{Diplomacy, Relation};
+use crate::game_state::Rules;
+use crate::player::PlayerProfile;
+use gdnative::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+
+/// Everything the peers of a game have to agree on before the first command: the seed of
+/// the map, the rules, the teams and relations, and the players
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameSetup {
+    pub seed: u64,
+    pub rules: Rules,
+    pub diplomacy: Diplomacy,
+    /// The names and colours of the players. Controllers differ between the peers, and
+    /// teams are part of the diplomacy.
+    pub profiles: Vec<PlayerProfile>,
+}
+
+/// Formats the setup as "key=value" fields separated by spaces, with one "player" field per
+/// profile, e.g. "seed=3 fog_of_war=false player=Player%201:0,0,1,1"
+impl fmt::Display for GameSetup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "seed={}", self.seed)?;
+        write!(
+            f,
+            " attacking_ends_movement={}",
+            self.rules.attacking_ends_movement
+        )?;
+        write!(
+            f,
+            " ability_ends_movement={}",
+            self.rules.ability_ends_movement
+        )?;
+        write!(f, " fog_of_war={}", self.rules.fog_of_war)?;
+        if let Some(points) = self.rules.action_points {
+            write!(
+                f,
+                " action_points={}:{}:{}:{}",
+                points.points, points.costs.move_hexagon, points.costs.attack, points.costs.ability
+            )?;
+        }
+        for (player, team) in self.diplomacy.get_teams() {
+            write!(f, " team={}:{}", player, team)?;
+        }
+        for (player, other, relation) in self.diplomacy.get_relations() {
+            write!(f, " relation={}:{}:{}", player, other, relation.get_name())?;
+        }
+        for profile in &self.profiles {
+            let colour = profile.colour;
+            write!(
+                f,
+                " player={}:{},{},{},{}",
+                escape(&profile.name),
+                colour.r,
+                colour.g,
+                colour.b,
+                colour.a
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for GameSetup {
+    type Err = ParseSetupError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut setup = GameSetup {
+            seed: 0,
+            rules: Rules::default(),
+            diplomacy: Diplomacy::new(),
+            profiles: Vec::new(),
+        };
+        let mut teams = Vec::new();
+        for field in value.split_whitespace() {
+            let invalid = || ParseSetupError::InvalidValue(field.to_owned());
+            let mut parts = field.splitn(2, '=');
+            let key = parts.next().unwrap_or_default();
+            let value = parts.next().ok_or_else(invalid)?;
+            let values: Vec<&str> = value.split(':').collect();
+            match (key, values.as_slice()) {
+                ("seed", [seed]) => setup.seed = seed.parse().map_err(|_| invalid())?,
+                ("attacking_ends_movement", [value]) => {
+                    setup.rules.attacking_ends_movement = value.parse().map_err(|_| invalid())?
+                }
+                ("ability_ends_movement", [value]) => {
+                    setup.rules.ability_ends_movement = value.parse().map_err(|_| invalid())?
+                }
+                ("fog_of_war", [value]) => {
+                    setup.rules.fog_of_war = value.parse().map_err(|_| invalid())?
+                }
+                ("action_points", values) => {
+                    let numbers = values
+                        .iter()
+                        .map(|number| number.parse())
+                        .collect::<Result<Vec<i32>, _>>()
+                        .map_err(|_| invalid())?;
+                    let (points, move_hexagon, attack, ability) = match numbers.as_slice() {
+                        [points, move_hexagon, attack, ability] => {
+                            (*points, *move_hexagon, *attack, *ability)
+                        }
+                        _ => return Err(invalid()),
+                    };
+                    let costs = ActionCosts {
+                        move_hexagon,
+                        attack,
+                        ability,
+                    };
+                    setup.rules.action_points = Some(ActionPoints::new(points, costs));
+                }
+                ("team", [player, team]) => {
+                    let player: usize = player.parse().map_err(|_| invalid())?;
+                    let team: usize = team.parse().map_err(|_| invalid())?;
+                    setup.diplomacy.set_team(player, team);
+                    teams.push((player, team));
+                }
+                ("relation", [player, other, relation]) => setup.diplomacy.set_relation(
+                    player.parse().map_err(|_| invalid())?,
+                    other.parse().map_err(|_| invalid())?,
+                    Relation::from_name(relation).ok_or_else(invalid)?,
+                ),
+                ("player", [name, colour]) => {
+                    let channels = colour
+                        .split(',')
+                        .map(|channel| channel.parse())
+                        .collect::<Result<Vec<f32>, _>>()
+                        .map_err(|_| invalid())?;
+                    let colour = match channels.as_slice() {
+                        [r, g, b, a] => Color::rgba(*r, *g, *b, *a),
+                        _ => return Err(invalid()),
+                    };
+                    let name = unescape(name).ok_or_else(invalid)?;
+                    setup.profiles.push(PlayerProfile::new(name, colour));
+                }
+                ("seed", _)
+                | ("attacking_ends_movement", _)
+                | ("ability_ends_movement", _)
+                | ("fog_of_war", _)
+                | ("team", _)
+                | ("relation", _)
+                | ("player", _) => return Err(invalid()),
+                (key, _) => return Err(ParseSetupError::UnknownKey(key.to_owned())),
+            }
+        }
+        for (player, team) in teams {
+            if let Some(profile) = setup.profiles.get_mut(player) {
+                profile.team = Some(team);
+            }
+        }
+        Ok(setup)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseSetupError {
+    UnknownKey(String),
+    InvalidValue(String),
+}
+
+impl fmt::Display for ParseSetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseSetupError::UnknownKey(key) => write!(f, "unknown setup key {}", key),
+            ParseSetupError::InvalidValue(field) => write!(f, "invalid setup field {}", field),
+        }
+    }
+}
+
+/// Percent-encodes the characters that separate the fields of the setup
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for character in value.chars() {
+        if character == '%' || character == ':' || character.is_whitespace() {
+            let mut bytes = [0; 4];
+            for byte in character.encode_utf8(&mut bytes).bytes() {
+                escaped.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            escaped.push(character);
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut rest = value.as_bytes();
+    while let Some((byte, tail)) = rest.split_first() {
+        if *byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(*byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_setup() -> GameSetup {
+        let mut diplomacy = Diplomacy::new();
+        diplomacy.set_team(0, 1);
+        diplomacy.set_team(2, 1);
+        diplomacy.set_relation(1, 3, Relation::Neutral);
+        let mut profiles = PlayerProfile::defaults(4);
+        profiles[1].name = "Ann: 100% sure".to_owned();
+        profiles[0].team = Some(1);
+        profiles[2].team = Some(1);
+        GameSetup {
+            seed: 42,
+            rules: Rules {
+                action_points: Some(ActionPoints::new(
+                    6,
+                    ActionCosts {
+                        move_hexagon: 1,
+                        attack: 3,
+                        ability: 2,
+                    },
+                )),
+                attacking_ends_movement: false,
+                ability_ends_movement: true,
+                fog_of_war: true,
+            },
+            diplomacy,
+            profiles,
+        }
+    }
+
+    #[test]
+    fn setups_survive_formatting() {
+        let setup = game_setup();
+        assert_eq!(setup.to_string().parse::<GameSetup>(), Ok(setup));
+        let setup = GameSetup {
+            rules: Rules::default(),
+            ..game_setup()
+        };
+        assert_eq!(setup.to_string().parse::<GameSetup>(), Ok(setup));
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        assert_eq!(escape("Ann: 100% sure"), "Ann%3A%20100%25%20sure");
+        assert_eq!(
+            unescape("Ann%3A%20100%25%20sure").unwrap(),
+            "Ann: 100% sure"
+        );
+        assert_eq!(unescape("%2"), None);
+    }
+
+    #[test]
+    fn invalid_fields_are_rejected() {
+        assert_eq!(
+            "size=3".parse::<GameSetup>(),
+            Err(ParseSetupError::UnknownKey("size".to_owned()))
+        );
+        assert_eq!(
+            "seed=-1".parse::<GameSetup>(),
+            Err(ParseSetupError::InvalidValue("seed=-1".to_owned()))
+        );
+        assert_eq!(
+            "player=Ann:1,0,0".parse::<GameSetup>(),
+            Err(ParseSetupError::InvalidValue("player=Ann:1,0,0".to_owned()))
+        );
+    }
+}
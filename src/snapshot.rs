@@ -0,0 +1,385 @@
+use crate::catalogue::UnitKind;
+use crate::components::hexagon::Hexagon;
+use crate::components::objective::ObjectiveKind;
+use crate::components::production::Order;
+use crate::components::status_effects::{StatusEffect, StatusEffectKind};
+use crate::components::structure::StructureKind;
+use crate::economy::Currency;
+use std::fmt;
+use std::str::FromStr;
+
+/// Everything commands change about a game, so that a peer can catch up without replaying
+/// the commands since the start: the current player, the balances, the units and the state
+/// of the structures and objectives
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub current_player: Option<usize>,
+    /// The balances as player, currency and amount
+    pub balances: Vec<(usize, Currency, i32)>,
+    pub units: Vec<UnitSnapshot>,
+    pub sites: Vec<SiteSnapshot>,
+}
+
+/// A unit, spawned again from its kind with the values that changed since
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnitSnapshot {
+    pub kind: UnitKind,
+    pub player: usize,
+    /// None for cargo, which is on the hexagon of its transport
+    pub hexagon: Option<Hexagon>,
+    pub integrity: i32,
+    pub remaining_range: i32,
+    pub remaining_attacks: i32,
+    pub remaining_points: Option<i32>,
+    pub morale: i32,
+    pub experience: i32,
+    pub selected_weapon: Option<usize>,
+    /// The ammo by weapon index
+    pub ammo: Vec<Option<i32>>,
+    /// The remaining cooldowns by ability index
+    pub cooldowns: Vec<i32>,
+    pub effects: Vec<StatusEffect>,
+    pub cargo: Vec<UnitSnapshot>,
+}
+
+/// A structure or objective, which the map spawns again. Sites are told apart by their
+/// hexagon and kinds, sites missing from the snapshot were destroyed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SiteSnapshot {
+    pub hexagon: Hexagon,
+    pub structure: Option<StructureKind>,
+    pub objective: Option<ObjectiveKind>,
+    pub owner: Option<usize>,
+    pub integrity: Option<i32>,
+    pub queue: Vec<Order>,
+}
+
+/// Formats the snapshot as "key=value" fields separated by spaces like the setup, with "-"
+/// for missing values. Every "unit" field is followed by a "cargo" field per carried unit,
+/// e.g. "current_player=1 balance=1:Credits:4 unit=Artillery:1:1,0,-1:10:2:1:-:10:0:-:-:0,0:
+/// site=0,0,0:Building:Depot:1:25:Tank/2"
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "current_player={}",
+            format_optional(&self.current_player)
+        )?;
+        for (player, currency, amount) in &self.balances {
+            write!(f, " balance={}:{}:{}", player, currency.get_name(), amount)?;
+        }
+        for unit in &self.units {
+            write!(f, " unit=")?;
+            write_unit(f, unit)?;
+            for cargo in &unit.cargo {
+                write!(f, " cargo=")?;
+                write_unit(f, cargo)?;
+            }
+        }
+        for site in &self.sites {
+            let queue: Vec<String> = site
+                .queue
+                .iter()
+                .map(|order| format!("{}/{}", order.kind.get_name(), order.remaining_turns))
+                .collect();
+            write!(
+                f,
+                " site={}:{}:{}:{}:{}:{}",
+                site.hexagon,
+                format_optional(&site.structure.map(|kind| kind.get_name())),
+                format_optional(&site.objective.map(|kind| kind.get_name())),
+                format_optional(&site.owner),
+                format_optional(&site.integrity),
+                queue.join(",")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = ParseSnapshotError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut snapshot = Snapshot::default();
+        for field in value.split_whitespace() {
+            let invalid = || ParseSnapshotError::InvalidValue(field.to_owned());
+            let mut parts = field.splitn(2, '=');
+            let key = parts.next().unwrap_or_default();
+            let value = parts.next().ok_or_else(invalid)?;
+            let values: Vec<&str> = value.split(':').collect();
+            match (key, values.as_slice()) {
+                ("current_player", [player]) => {
+                    snapshot.current_player = parse_optional(player).ok_or_else(invalid)?
+                }
+                ("balance", [player, currency, amount]) => snapshot.balances.push((
+                    player.parse().map_err(|_| invalid())?,
+                    Currency::from_name(currency).ok_or_else(invalid)?,
+                    amount.parse().map_err(|_| invalid())?,
+                )),
+                ("unit", values) => snapshot.units.push(parse_unit(values).ok_or_else(invalid)?),
+                ("cargo", values) => {
+                    let cargo = parse_unit(values).ok_or_else(invalid)?;
+                    match snapshot.units.last_mut() {
+                        None => return Err(invalid()),
+                        Some(unit) => unit.cargo.push(cargo),
+                    }
+                }
+                ("site", [hexagon, structure, objective, owner, integrity, queue]) => {
+                    snapshot.sites.push(SiteSnapshot {
+                        hexagon: hexagon.parse().map_err(|_| invalid())?,
+                        structure: parse_name(structure, StructureKind::from_name)
+                            .ok_or_else(invalid)?,
+                        objective: parse_name(objective, ObjectiveKind::from_name)
+                            .ok_or_else(invalid)?,
+                        owner: parse_optional(owner).ok_or_else(invalid)?,
+                        integrity: parse_optional(integrity).ok_or_else(invalid)?,
+                        queue: parse_list(queue, |order| {
+                            let parts: Vec<&str> = order.split('/').collect();
+                            match parts.as_slice() {
+                                [kind, turns] => Some(Order {
+                                    kind: UnitKind::from_name(kind)?,
+                                    remaining_turns: turns.parse().ok()?,
+                                }),
+                                _ => None,
+                            }
+                        })
+                        .ok_or_else(invalid)?,
+                    })
+                }
+                ("current_player", _) | ("balance", _) | ("site", _) => return Err(invalid()),
+                (key, _) => return Err(ParseSnapshotError::UnknownKey(key.to_owned())),
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseSnapshotError {
+    UnknownKey(String),
+    InvalidValue(String),
+}
+
+impl fmt::Display for ParseSnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseSnapshotError::UnknownKey(key) => write!(f, "unknown snapshot key {}", key),
+            ParseSnapshotError::InvalidValue(field) => {
+                write!(f, "invalid snapshot field {}", field)
+            }
+        }
+    }
+}
+
+/// Writes the values of the unit without its cargo, separated by colons
+fn write_unit(f: &mut fmt::Formatter<'_>, unit: &UnitSnapshot) -> fmt::Result {
+    let ammo: Vec<String> = unit.ammo.iter().map(format_optional).collect();
+    let cooldowns: Vec<String> = unit
+        .cooldowns
+        .iter()
+        .map(|turns| turns.to_string())
+        .collect();
+    let effects: Vec<String> = unit
+        .effects
+        .iter()
+        .map(|effect| {
+            format!(
+                "{}/{}/{}",
+                effect.kind.get_name(),
+                effect.remaining_turns,
+                effect.stacks
+            )
+        })
+        .collect();
+    write!(
+        f,
+        "{}:{}:{}:{}:{}:{}:{}:{}:{}:{}:{}:{}:{}",
+        unit.kind.get_name(),
+        unit.player,
+        format_optional(&unit.hexagon),
+        unit.integrity,
+        unit.remaining_range,
+        unit.remaining_attacks,
+        format_optional(&unit.remaining_points),
+        unit.morale,
+        unit.experience,
+        format_optional(&unit.selected_weapon),
+        ammo.join(","),
+        cooldowns.join(","),
+        effects.join(",")
+    )
+}
+
+/// Parses the values of a unit in the order they are written, without its cargo
+fn parse_unit(values: &[&str]) -> Option<UnitSnapshot> {
+    if values.len() != 13 {
+        return None;
+    }
+    Some(UnitSnapshot {
+        kind: UnitKind::from_name(values[0])?,
+        player: values[1].parse().ok()?,
+        hexagon: parse_optional(values[2])?,
+        integrity: values[3].parse().ok()?,
+        remaining_range: values[4].parse().ok()?,
+        remaining_attacks: values[5].parse().ok()?,
+        remaining_points: parse_optional(values[6])?,
+        morale: values[7].parse().ok()?,
+        experience: values[8].parse().ok()?,
+        selected_weapon: parse_optional(values[9])?,
+        ammo: parse_list(values[10], parse_optional)?,
+        cooldowns: parse_list(values[11], |turns| turns.parse().ok())?,
+        effects: parse_list(values[12], |effect| {
+            let parts: Vec<&str> = effect.split('/').collect();
+            match parts.as_slice() {
+                [kind, turns, stacks] => Some(StatusEffect {
+                    kind: StatusEffectKind::from_name(kind)?,
+                    remaining_turns: turns.parse().ok()?,
+                    stacks: stacks.parse().ok()?,
+                }),
+                _ => None,
+            }
+        })?,
+        cargo: Vec::new(),
+    })
+}
+
+fn format_optional<T: fmt::Display>(value: &Option<T>) -> String {
+    match value {
+        None => "-".to_owned(),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// Parses "-" as None, or returns None if the value is invalid
+fn parse_optional<T: FromStr>(value: &str) -> Option<Option<T>> {
+    match value {
+        "-" => Some(None),
+        value => value.parse().ok().map(Some),
+    }
+}
+
+fn parse_name<T, F: Fn(&str) -> Option<T>>(value: &str, from_name: F) -> Option<Option<T>> {
+    match value {
+        "-" => Some(None),
+        value => from_name(value).map(Some),
+    }
+}
+
+/// Parses the comma separated items, or returns None if any of them is invalid
+fn parse_list<T, F: Fn(&str) -> Option<T>>(value: &str, parse: F) -> Option<Vec<T>> {
+    value
+        .split(',')
+        .filter(|item| !item.is_empty())
+        .map(parse)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(kind: UnitKind, hexagon: Option<Hexagon>) -> UnitSnapshot {
+        UnitSnapshot {
+            kind,
+            player: 1,
+            hexagon,
+            integrity: 7,
+            remaining_range: 2,
+            remaining_attacks: 0,
+            remaining_points: None,
+            morale: 4,
+            experience: 12,
+            selected_weapon: None,
+            ammo: vec![None],
+            cooldowns: Vec::new(),
+            effects: Vec::new(),
+            cargo: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn snapshots_survive_formatting() {
+        let tank = UnitSnapshot {
+            remaining_points: Some(3),
+            selected_weapon: Some(1),
+            ammo: vec![None, Some(2)],
+            cooldowns: vec![0, 2, 1],
+            effects: vec![
+                StatusEffect {
+                    kind: StatusEffectKind::Slowed,
+                    remaining_turns: 2,
+                    stacks: 3,
+                },
+                StatusEffect {
+                    kind: StatusEffectKind::Overwatch,
+                    remaining_turns: 1,
+                    stacks: 1,
+                },
+            ],
+            cargo: vec![unit(UnitKind::Artillery, None)],
+            ..unit(UnitKind::Tank, Some(Hexagon::new_axial(1, -2)))
+        };
+        let snapshot = Snapshot {
+            current_player: Some(1),
+            balances: vec![(0, Currency::Credits, -3), (1, Currency::Fuel, 8)],
+            units: vec![tank, unit(UnitKind::Artillery, Some(Hexagon::zero()))],
+            sites: vec![
+                SiteSnapshot {
+                    hexagon: Hexagon::zero(),
+                    structure: Some(StructureKind::Building),
+                    objective: Some(ObjectiveKind::Depot),
+                    owner: Some(0),
+                    integrity: Some(20),
+                    queue: vec![
+                        Order {
+                            kind: UnitKind::Tank,
+                            remaining_turns: 2,
+                        },
+                        Order {
+                            kind: UnitKind::Artillery,
+                            remaining_turns: 1,
+                        },
+                    ],
+                },
+                SiteSnapshot {
+                    hexagon: Hexagon::new_axial(3, 0),
+                    structure: None,
+                    objective: Some(ObjectiveKind::Village),
+                    owner: None,
+                    integrity: None,
+                    queue: Vec::new(),
+                },
+            ],
+        };
+        assert_eq!(snapshot.to_string().parse::<Snapshot>(), Ok(snapshot));
+        let snapshot = Snapshot::default();
+        assert_eq!(snapshot.to_string().parse::<Snapshot>(), Ok(snapshot));
+    }
+
+    #[test]
+    fn invalid_fields_are_rejected() {
+        assert_eq!(
+            "turn=3".parse::<Snapshot>(),
+            Err(ParseSnapshotError::UnknownKey("turn".to_owned()))
+        );
+        assert_eq!(
+            "cargo=Tank:0:-:1:1:1:-:1:0:-:::".parse::<Snapshot>(),
+            Err(ParseSnapshotError::InvalidValue(
+                "cargo=Tank:0:-:1:1:1:-:1:0:-:::".to_owned()
+            ))
+        );
+        assert_eq!(
+            "unit=Jeep:0:-:1:1:1:-:1:0:-:::".parse::<Snapshot>(),
+            Err(ParseSnapshotError::InvalidValue(
+                "unit=Jeep:0:-:1:1:1:-:1:0:-:::".to_owned()
+            ))
+        );
+        assert_eq!(
+            "site=0,0,0:Tower:-:-:-:".parse::<Snapshot>(),
+            Err(ParseSnapshotError::InvalidValue(
+                "site=0,0,0:Tower:-:-:-:".to_owned()
+            ))
+        );
+    }
+}
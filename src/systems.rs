@@ -1,16 +1,65 @@
+use crate::catalogue::UnitKind;
+use crate::components::abilities::{Abilities, Ability, AbilityTarget};
+use crate::components::action_budget::ActionBudget;
+use crate::components::action_points::ActionPoints;
+use crate::components::attack_profile::{AttackProfile, AttackShape};
+use crate::components::economy::{Income, Upkeep};
+use crate::components::experience::Experience;
 use crate::components::field::Field;
+use crate::components::health::Health;
 use crate::components::hexagon::Hexagon;
+use crate::components::mobility::{CanMove, Mobility};
+use crate::components::morale::{Leader, Morale};
 use crate::components::node_component::NodeComponent;
 use crate::components::node_template::NodeTemplate;
+use crate::components::objective::{Objective, ObjectiveKind};
 use crate::components::player::Player as PlayerComponent;
-use crate::components::unit::{AttackError, AttackResult, CanMove, Unit};
-use crate::game_state::{GameState, State};
-use crate::nodes::units::update_units_system;
-use crate::player::Player;
+use crate::components::production::Production;
+use crate::components::regeneration::Regeneration;
+use crate::components::status_effects::{StatusEffectKind, StatusEffects};
+use crate::components::structure::{Structure, StructureKind};
+use crate::components::supply::Supply;
+use crate::components::transport::{Carried, Transport};
+use crate::components::unit::{AttackError, AttackResult, Unit};
+use crate::components::vision::Vision;
+use crate::components::weapon::{DamageType, Weapon, Weapons};
+use crate::diplomacy::{Diplomacy, Relation};
+use crate::economy::{Currency, Ledger, Transaction};
+use crate::game_state::{GameState, Rules, State};
+use crate::layout::Layout;
+use crate::legion::entity_has_component;
+use crate::map_generator::{generate_map, MapSettings};
+use crate::network::{Command, Lockstep, NetworkEvent};
+use crate::nodes::objectives::update_objectives_system;
+use crate::nodes::structures::update_structures_system;
+use crate::nodes::units::{update_unit_visibility_system, update_units_system};
+use crate::player::{Controller, Player, PlayerProfile};
+use crate::scenario::Scenario;
+use crate::setup::GameSetup;
+use crate::systems::abilities::{
+    get_ability_targets, get_usable_ability, is_valid_ability_target, use_ability,
+};
+use crate::systems::ai::{plan_action, AiAction};
+use crate::systems::combat::{combine_modifiers, get_attacker, get_defender, get_modifiers};
+use crate::systems::economy::get_turn_transactions;
 use crate::systems::hexgrid::{
     calculate_hexagon_points, create_grid, find_path, get_2d_position_from_hex,
-    get_entities_at_hexagon, is_hexagon_visible_for_attack,
+    get_entities_at_hexagon, get_entities_in_attack_area, is_hexagon_visible_for_attack,
+    TerrainMap,
+};
+use crate::systems::influence::{get_danger_zone, get_hostile_threat_map};
+use crate::systems::morale::{apply_attack_morale, rally_units, route_units};
+use crate::systems::network::{apply_snapshot, NetworkGame};
+use crate::systems::objectives::capture_objectives;
+use crate::systems::production::{advance_production, recruit};
+use crate::systems::search::{poll_search, PendingSearch};
+use crate::systems::structures::is_attackable_structure;
+use crate::systems::supply::{regenerate_units, supply_units};
+use crate::systems::transport::{
+    can_disembark, can_embark, disembark, embark_at_hexagon, get_next_cargo, release_cargo,
 };
+use crate::systems::victory::{get_winners, is_defeated};
+use crate::systems::vision::get_visible_hexagons;
 use dynamic_nodes::create_node_system;
 use gdnative::api::input_event_mouse::InputEventMouse;
 use gdnative::api::input_event_mouse_button::InputEventMouseButton;
@@ -27,14 +76,29 @@ use legion::{
 };
 use std::borrow::Borrow;
 use std::collections::vec_deque::VecDeque;
+use std::io;
 use std::sync::Mutex;
+pub mod abilities;
+pub mod ai;
+pub mod combat;
 pub mod dynamic_nodes;
+pub mod economy;
 pub mod hexgrid;
+pub mod influence;
+pub mod morale;
+pub mod network;
+pub mod objectives;
+pub mod production;
+pub mod search;
+pub mod structures;
+pub mod supply;
+pub mod transport;
+pub mod victory;
+pub mod vision;
 
 pub struct WorldNode(Ref<Node2D>);
 pub struct MainCamera(TRef<'static, Camera2D>);
 pub struct UINode(TRef<'static, Control>);
-pub struct HexfieldSize(pub f32);
 pub struct Delta(pub f64);
 
 const SECONDS_PER_MOVEMENT: f64 = 0.1f64;
@@ -77,19 +141,33 @@ pub fn set_state(state: &mut GameState, game_state: State) {
     match game_state {
         State::NewRound => {}
         State::Startup => {}
+        State::Handover => {}
         State::Waiting => {}
         State::Selected(_) => {
             state.update_fields = true;
         }
         State::Attacking(_, _) => {}
         State::Moving(_, _, _) => {}
+        State::Targeting(_, _) => {
+            state.update_fields = true;
+        }
+        State::UsingAbility(_, _, _) => {}
+        State::Unloading(_) => {
+            state.update_fields = true;
+        }
+        State::Disembarking(_, _) => {}
+        State::Recruiting(_, _) => {}
+        State::SelectingWeapon(_, _) => {}
     }
     state.state = game_state;
     state.current_path = Vec::new();
+    state.splash_area = Vec::new();
     state.redraw_grid = true;
+    state.update_threats = true;
 }
 
 fn move_entity_to_hexagon(entity: Entity, hexagon: &Hexagon, world: &mut World) {
+    let modifiers = get_modifiers(world, entity);
     let mut entry = match world.entry(entity) {
         None => {
             godot_error!("Entity not found in world");
@@ -97,25 +175,35 @@ fn move_entity_to_hexagon(entity: Entity, hexagon: &Hexagon, world: &mut World)
         }
         Some(e) => e,
     };
-    let selected_unit = *entry.get_component::<Unit>().unwrap();
+    let selected_mobility = match entry.get_component::<Mobility>() {
+        Err(_) => {
+            godot_error!("Entity has no mobility component");
+            return;
+        }
+        Ok(mobility) => *mobility,
+    };
     let selected_hexagon = *entry.get_component::<Hexagon>().unwrap();
     let distance = selected_hexagon.distance_to(&hexagon);
-    let can_move = selected_unit.is_in_movement_range(distance);
+    let can_move = selected_mobility.is_in_movement_range(distance, &modifiers);
     match can_move {
         CanMove::Yes(remaining_range) => {
-            let updated_hexagon = Hexagon::new_axial(hexagon.get_q(), hexagon.get_r());
-            let updated_selected_unit = Unit::new(
-                selected_unit.integrity,
-                selected_unit.damage,
-                selected_unit.max_attack_range,
-                selected_unit.min_attack_range,
-                selected_unit.armor,
-                selected_unit.mobility,
-                remaining_range,
-                selected_unit.remaining_attacks,
-            );
-            entry.add_component(updated_selected_unit);
-            entry.add_component(updated_hexagon);
+            if let Ok(mobility) = entry.get_component_mut::<Mobility>() {
+                mobility.remaining_range = remaining_range;
+            }
+            if let Ok(points) = entry.get_component_mut::<ActionPoints>() {
+                points.spend_movement(distance);
+                let points = *points;
+                if let Ok(mobility) = entry.get_component_mut::<Mobility>() {
+                    points.limit_movement(mobility);
+                }
+                if let Ok(budget) = entry.get_component_mut::<ActionBudget>() {
+                    points.limit_attacks(budget);
+                }
+            }
+            if let Ok(position) = entry.get_component_mut::<Hexagon>() {
+                *position = *hexagon;
+            }
+            embark_at_hexagon(world, entity);
         }
         CanMove::No => {}
     }
@@ -133,11 +221,36 @@ fn handle_attack_result(
     attacker: Entity,
     defender: Entity,
     result: AttackResult,
+    rules: &Rules,
 ) {
+    apply_attack_morale(world, attacker, defender, &result);
+
     match world.entry(attacker) {
         None => {}
         Some(mut e) => {
             e.add_component(result.attacker);
+            if let Some(points) = result.points {
+                e.add_component(points);
+            }
+            if let Ok(mobility) = e.get_component_mut::<Mobility>() {
+                if rules.attacking_ends_movement {
+                    mobility.remaining_range = 0;
+                } else if let Some(points) = result.points {
+                    points.limit_movement(mobility);
+                }
+            }
+            if let Ok(weapons) = e.get_component_mut::<Weapons>() {
+                if let Some(weapon) = weapons.weapons.get_mut(result.weapon_index) {
+                    *weapon = result.weapon;
+                }
+            }
+            if let Ok(experience) = e.get_component_mut::<Experience>() {
+                if experience.gain(result.actual_damage, result.defender.integrity <= 0) {
+                    if let Some(rank) = experience.get_rank() {
+                        godot_print!("Unit was promoted to {}", rank.name);
+                    }
+                }
+            }
         }
     }
 
@@ -145,6 +258,7 @@ fn handle_attack_result(
         None => {}
         Some(mut e) => {
             if result.defender.integrity <= 0 {
+                release_cargo(world, defender);
                 world.remove(defender);
             } else {
                 e.add_component(result.defender);
@@ -153,6 +267,69 @@ fn handle_attack_result(
     }
 }
 
+/// Lets hostile units on overwatch attack the entity, if it is in their attack range.
+/// Each unit on overwatch attacks only once.
+fn resolve_overwatch(world: &mut World, entity: Entity, rules: &Rules, diplomacy: &Diplomacy) {
+    let (hexagon, player) = match world.entry_ref(entity) {
+        Err(_) => return,
+        Ok(entry) => match entry.get_component::<Hexagon>() {
+            Err(_) => return,
+            Ok(hexagon) => (*hexagon, get_player_of_entity(&entry)),
+        },
+    };
+    let watchers: Vec<Entity> =
+        <(Entity, &Hexagon, &Weapons, &StatusEffects, &PlayerComponent)>::query()
+            .iter(world)
+            .filter(
+                |(watcher, watcher_hexagon, weapons, effects, watcher_player)| {
+                    matches!(player, Some(player) if diplomacy.is_hostile(player, watcher_player.0))
+                        && effects.has(StatusEffectKind::Overwatch)
+                        && weapons.is_in_attack_range(
+                            watcher_hexagon.distance_to(&hexagon),
+                            &get_modifiers(world, **watcher),
+                        )
+                },
+            )
+            .map(|(watcher, _, _, _, _)| *watcher)
+            .collect();
+
+    for watcher in watchers {
+        let attacker = match get_attacker(world, watcher, entity) {
+            None => continue,
+            Some(attacker) => attacker,
+        };
+        if let Some(mut entry) = world.entry(watcher) {
+            if let Ok(effects) = entry.get_component_mut::<StatusEffects>() {
+                effects.remove(StatusEffectKind::Overwatch);
+            }
+        }
+        let defender = match get_defender(world, entity) {
+            None => return,
+            Some(defender) => defender,
+        };
+        if let Ok(result) = attacker.attack(&defender) {
+            godot_print!("Overwatch damage dealt: {}", result.actual_damage);
+            handle_attack_result(world, watcher, entity, result, rules);
+        }
+    }
+}
+
+/// Disembarks the next cargo of the transport to the hexagon, where hostile units on
+/// overwatch can attack it. The cargo can be destroyed before it is ever drawn again.
+fn disembark_to_hexagon(
+    world: &mut World,
+    transport: Entity,
+    hexagon: &Hexagon,
+    terrain_map: &TerrainMap,
+    rules: &Rules,
+    diplomacy: &Diplomacy,
+) -> Option<Entity> {
+    let cargo = disembark(world, transport, hexagon, terrain_map)?;
+    move_entity_to_hexagon(cargo, hexagon, world);
+    resolve_overwatch(world, cargo, rules, diplomacy);
+    Some(cargo)
+}
+
 #[system]
 pub fn finalize(#[resource] state: &mut GameState) {
     state.update_fields = false;
@@ -161,18 +338,32 @@ pub fn finalize(#[resource] state: &mut GameState) {
 #[system(par_for_each)]
 #[read_component(Hexagon)]
 #[read_component(Unit)]
+#[read_component(Mobility)]
+#[read_component(Weapons)]
+#[read_component(ActionBudget)]
+#[read_component(ActionPoints)]
+#[read_component(StatusEffects)]
+#[read_component(Experience)]
+#[read_component(Morale)]
 #[read_component(PlayerComponent)]
+#[read_component(Abilities)]
+#[read_component(Transport)]
+#[read_component(Structure)]
 pub fn update_field(
     world: &SubWorld<'_>,
     field: &mut Field,
     #[resource] state: &GameState,
-    #[resource] hexfield_size: &HexfieldSize,
+    #[resource] layout: &Layout,
+    #[resource] terrain_map: &TerrainMap,
     #[resource] physic_state: &Ref<Physics2DDirectSpaceState>,
 ) {
+    field.danger = state.danger_zone.contains(&field.location);
     if let State::Selected(entity) = state.state.clone() {
+        field.splash = state.splash_area.contains(&field.location);
         if !state.update_fields {
             return;
         }
+        field.targetable = false;
         let entry = match world.entry_ref(entity) {
             Err(_) => return,
             Ok(entity) => entity,
@@ -183,38 +374,67 @@ pub fn update_field(
             Ok(hexagon) => *hexagon,
         };
 
-        let unit = match entry.get_component::<Unit>() {
-            Err(_) => return,
-            Ok(unit) => *unit,
-        };
+        if entry.get_component::<Unit>().is_err() {
+            return;
+        }
+        let mobility = entry.get_component::<Mobility>().ok().copied();
+        let budget = entry.get_component::<ActionBudget>().ok().copied();
+        let modifiers = get_modifiers(world, entity);
 
-        let selected_data = Some((entity, unit, hexagon));
+        let selected_data = Some((entity, hexagon));
 
         if let Some(data) = selected_data {
-            let (selected_entity, selected_unit, selected_hexagon) = (data.0, data.1, data.2);
-            let can_move = selected_hexagon.distance_to(&field.location)
-                <= selected_unit.remaining_range
-                && match selected_unit.is_in_movement_range(
-                    find_path(&selected_hexagon, &field.location, world).len() as i32,
-                ) {
-                    CanMove::Yes(_) => true,
-                    CanMove::No => false,
-                };
+            let (selected_entity, selected_hexagon) = (data.0, data.1);
+            let can_move = match mobility {
+                None => false,
+                Some(mobility) => {
+                    selected_hexagon.distance_to(&field.location) <= mobility.remaining_range
+                        && match mobility.is_in_movement_range(
+                            find_path(&selected_hexagon, &field.location, world, terrain_map).len()
+                                as i32,
+                            &modifiers,
+                        ) {
+                            CanMove::Yes(_) => true,
+                            CanMove::No => false,
+                        }
+                }
+            };
 
-            let can_attack = selected_unit.remaining_attacks > 0
+            let can_attack = matches!(budget, Some(budget) if budget.can_attack(&modifiers))
+                && state.is_hexagon_visible(&field.location)
                 && is_hexagon_visible_for_attack(
                     physic_state,
                     world,
-                    hexfield_size.0,
+                    layout,
+                    &state.diplomacy,
                     selected_entity,
                     field.location,
                 );
             field.moveable = can_move;
             field.attackable = can_attack;
         };
+    } else if let State::Targeting(entity, ability) = state.state {
+        field.splash = state.splash_area.contains(&field.location);
+        if !state.update_fields {
+            return;
+        }
+        field.attackable = false;
+        field.moveable = false;
+        field.targetable =
+            is_valid_ability_target(world, entity, ability, &field.location, &state.diplomacy);
+    } else if let State::Unloading(transport) = state.state {
+        field.splash = false;
+        if !state.update_fields {
+            return;
+        }
+        field.attackable = false;
+        field.moveable = false;
+        field.targetable = can_disembark(world, transport, &field.location, terrain_map);
     } else {
         field.attackable = false;
         field.moveable = false;
+        field.splash = false;
+        field.targetable = false;
     }
 }
 
@@ -223,24 +443,23 @@ pub fn update_field(
 pub fn draw_grid(
     world: &mut SubWorld<'_>,
     #[resource] state: &mut GameState,
-    #[resource] hexfield_size: &HexfieldSize,
+    #[resource] layout: &Layout,
     #[resource] node: &WorldNode,
 ) {
     let mut query = <&Field>::query();
-    let hexfield_size = hexfield_size.0;
-    let field_polygon: Vec<Vector2> = calculate_hexagon_points(hexfield_size);
+    let field_polygon: Vec<Vector2> = calculate_hexagon_points(layout);
     let node = unsafe { node.0.assume_safe() };
 
     let viewport: Rect2 = node.get_viewport_rect();
     let viewport = viewport.scale(1.1_f32, 1.1_f32);
     let global_transf: Transform2D = node.get_global_transform_with_canvas();
 
-    let width = 3.0_f32.sqrt() * hexfield_size;
-    let height = 2.0 * hexfield_size;
-    let mut rect = Rect2::new(Point2::zero(), Size2::new(width, height));
+    let extents = layout.get_hexagon_extents();
+    let mut rect = Rect2::new(Point2::zero(), Size2::new(extents.x, extents.y));
+    let max_threat = state.threat_map.values().copied().max().unwrap_or(0).max(1) as f32;
 
     for field in query.iter(world) {
-        let pos = get_2d_position_from_hex(&field.location, hexfield_size);
+        let pos = get_2d_position_from_hex(&field.location, layout);
         rect.origin = Point2::new(pos.x + global_transf.m31, pos.y + global_transf.m32);
 
         if !viewport.intersects(&rect) {
@@ -278,7 +497,53 @@ pub fn draw_grid(
         } else {
             node.draw_colored_polygon(
                 Vector2Array::from_vec(adjusted_polygon.clone()),
-                Color::rgba(0.5, 0.5, 0.5, 1.0),
+                field.terrain.get_colour(),
+                Vector2Array::new(),
+                Texture::null(),
+                Texture::null(),
+                false,
+            );
+        }
+
+        if field.targetable {
+            node.draw_colored_polygon(
+                Vector2Array::from_vec(adjusted_polygon.clone()),
+                Color::rgba(1.0, 1.0, 0.0, 0.35),
+                Vector2Array::new(),
+                Texture::null(),
+                Texture::null(),
+                false,
+            );
+        }
+
+        if state.threat_layer {
+            if let Some(threat) = state.threat_map.get(&field.location) {
+                node.draw_colored_polygon(
+                    Vector2Array::from_vec(adjusted_polygon.clone()),
+                    Color::rgba(1.0, 0.3, 0.0, 0.15 + 0.45 * *threat as f32 / max_threat),
+                    Vector2Array::new(),
+                    Texture::null(),
+                    Texture::null(),
+                    false,
+                );
+            }
+        }
+
+        if field.danger {
+            node.draw_colored_polygon(
+                Vector2Array::from_vec(adjusted_polygon.clone()),
+                Color::rgba(0.0, 1.0, 0.0, 0.3),
+                Vector2Array::new(),
+                Texture::null(),
+                Texture::null(),
+                false,
+            );
+        }
+
+        if field.splash && state.red_layer {
+            node.draw_colored_polygon(
+                Vector2Array::from_vec(adjusted_polygon.clone()),
+                Color::rgba(1.0, 0.5, 0.0, 0.5),
                 Vector2Array::new(),
                 Texture::null(),
                 Texture::null(),
@@ -313,7 +578,7 @@ pub fn draw_grid(
 fn draw_path(
     world: &SubWorld<'_>,
     #[resource] state: &GameState,
-    #[resource] hexfield_size: &HexfieldSize,
+    #[resource] layout: &Layout,
     #[resource] node: &WorldNode,
 ) {
     let node = unsafe { node.0.assume_safe() };
@@ -330,10 +595,10 @@ fn draw_path(
             Err(_) => {
                 return;
             }
-            Ok(hexagon) => get_2d_position_from_hex(hexagon, hexfield_size.0),
+            Ok(hexagon) => get_2d_position_from_hex(hexagon, layout),
         };
         for hexagon in &state.current_path {
-            let current_point = get_2d_position_from_hex(&hexagon, hexfield_size.0);
+            let current_point = get_2d_position_from_hex(&hexagon, layout);
 
             node.draw_line(
                 last_point,
@@ -349,87 +614,297 @@ fn draw_path(
 }
 
 #[system]
-#[write_component(Unit)]
-#[read_component(Hexagon)]
+#[write_component(Health)]
+#[write_component(Mobility)]
+#[write_component(ActionBudget)]
+#[write_component(ActionPoints)]
+#[write_component(StatusEffects)]
+#[write_component(Experience)]
+#[write_component(Abilities)]
+#[write_component(Weapons)]
+#[write_component(Hexagon)]
+#[write_component(Morale)]
+#[read_component(PlayerComponent)]
+#[read_component(AttackProfile)]
+#[read_component(Unit)]
+#[read_component(Regeneration)]
+#[read_component(Supply)]
+#[read_component(Leader)]
+#[read_component(Income)]
+#[read_component(Upkeep)]
+#[read_component(Objective)]
+#[read_component(Carried)]
+#[read_component(Structure)]
+#[read_component(Vision)]
+#[read_component(Transport)]
+#[write_component(Production)]
 pub fn update_state(
     cmd: &mut CommandBuffer,
     world: &mut SubWorld<'_>,
     #[resource] state: &mut GameState,
     #[resource] delta: &Delta,
+    #[resource] terrain_map: &TerrainMap,
+    #[resource] pending_search: &mut PendingSearch,
+    #[resource] network: &mut NetworkGame,
 ) {
     let delta = delta.0;
+    if state.rules.fog_of_war {
+        state.visible_hexagons = state
+            .current_player
+            .map(|player| get_visible_hexagons(world, player, &state.diplomacy));
+    }
+    let pinned_count = state.pinned_units.len();
+    state
+        .pinned_units
+        .retain(|entity| world.entry_ref(*entity).is_ok());
+    if state.pinned_units.len() != pinned_count {
+        state.update_threats = true;
+    }
+    // The effects of the state handled last frame were applied to the world in between
+    if state.update_threats {
+        state.update_threats = false;
+        state.threat_map = state
+            .current_player
+            .filter(|_| state.threat_layer)
+            .map(|player| get_hostile_threat_map(world, state, player, terrain_map))
+            .unwrap_or_default();
+        state.danger_zone = state
+            .current_player
+            .filter(|_| state.green_layer)
+            .map(|player| get_danger_zone(world, state, player, terrain_map))
+            .unwrap_or_default();
+    }
+    network.record_hash(world, state);
+    if state.winners.is_none() && state.state.is_idle() {
+        if let Some(player) = state.current_player {
+            let controller = state.players[player].get_controller();
+            // Received commands are applied first, also those of local players after a resync
+            let is_received = network.has_pending() || controller == Controller::Remote;
+            let next = if is_received {
+                network.next_state(world, state, terrain_map)
+            } else {
+                let plan = match controller {
+                    Controller::Human | Controller::Remote => None,
+                    Controller::Ai(difficulty) => {
+                        Some(plan_action(world, state, player, difficulty, terrain_map))
+                    }
+                    Controller::Search(settings) => {
+                        poll_search(world, state, player, settings, terrain_map, pending_search)
+                    }
+                };
+                plan.map(|action| match action {
+                    None => State::NewRound,
+                    Some(AiAction::Attack(attacker, target)) => State::Attacking(attacker, target),
+                    Some(AiAction::Move(entity, path)) => {
+                        State::Moving(entity, VecDeque::from(path), 0f64)
+                    }
+                })
+            };
+            if let Some(next) = next {
+                if is_received || network.submit(world, player, &next) {
+                    set_state(state, next);
+                }
+            }
+        }
+    }
     match state.state.clone() {
         State::Startup => {
-            state.state = State::Waiting;
+            state.state = state.get_turn_start();
         }
         State::NewRound => {
-            for mut unit in <&mut Unit>::query().iter_mut(world) {
-                unit.remaining_attacks = 1;
-                unit.remaining_range = unit.mobility;
+            if state.winners.is_some() {
+                set_state(state, State::Waiting);
+                return;
+            }
+            if let Some(winners) = get_winners(world, &state.diplomacy, state.players.len()) {
+                let names: Vec<String> = winners
+                    .iter()
+                    .map(|player| state.players[*player].get_name())
+                    .collect();
+                godot_print!("Victory for {}", names.join(", "));
+                state.winners = Some(winners);
+                set_state(state, State::Waiting);
+                return;
             }
             let next_player = match state.current_player {
                 None => 0,
                 Some(mut player) => {
-                    player += 1;
-                    if player >= state.players.len() {
-                        player = 0;
+                    // Defeated players are skipped
+                    for _ in 0..state.players.len() {
+                        player += 1;
+                        if player >= state.players.len() {
+                            player = 0;
+                        }
+                        if !is_defeated(world, player) {
+                            break;
+                        }
                     }
                     player
                 }
             };
+            // Durations count the rounds of the owner, and a round for unowned entities
+            let is_new_round =
+                !matches!(state.current_player, Some(player) if next_player > player);
+            let is_ticking = |owner: Option<&PlayerComponent>| match owner {
+                None => is_new_round,
+                Some(owner) => owner.0 == next_player,
+            };
+            for (entity, health, effects, owner) in <(
+                Entity,
+                &mut Health,
+                &mut StatusEffects,
+                Option<&PlayerComponent>,
+            )>::query()
+            .iter_mut(world)
+            {
+                if !is_ticking(owner) {
+                    continue;
+                }
+                health.integrity -= effects.tick();
+                if health.integrity <= 0 {
+                    let entity = *entity;
+                    cmd.exec_mut(move |world| release_cargo(world, entity));
+                    cmd.remove(entity);
+                }
+            }
+            if let Some(player) = state.current_player {
+                regenerate_units(world, player);
+                let name = state.players[player].get_name();
+                let diplomacy = state.diplomacy.clone();
+                cmd.exec_mut(move |world| {
+                    for _ in capture_objectives(world, player, &diplomacy) {
+                        godot_print!("Objective captured by {}", name);
+                    }
+                });
+            }
+            for (mobility, effects, experience, morale) in <(
+                &mut Mobility,
+                Option<&StatusEffects>,
+                Option<&Experience>,
+                Option<&Morale>,
+            )>::query()
+            .iter_mut(world)
+            {
+                let modifiers = combine_modifiers(effects, experience, morale);
+                mobility.remaining_range = mobility.get_mobility(&modifiers);
+            }
+            for budget in <&mut ActionBudget>::query().iter_mut(world) {
+                budget.reset();
+            }
+            for (points, mobility, budget, effects, experience, morale) in <(
+                &mut ActionPoints,
+                &mut Mobility,
+                &mut ActionBudget,
+                Option<&StatusEffects>,
+                Option<&Experience>,
+                Option<&Morale>,
+            )>::query()
+            .iter_mut(world)
+            {
+                let modifiers = combine_modifiers(effects, experience, morale);
+                points.reset(mobility, budget, &modifiers);
+            }
+            for (abilities, owner) in
+                <(&mut Abilities, Option<&PlayerComponent>)>::query().iter_mut(world)
+            {
+                if is_ticking(owner) {
+                    abilities.tick();
+                }
+            }
+            for transaction in get_turn_transactions(world, next_player) {
+                state.players[next_player]
+                    .get_ledger_mut()
+                    .record(transaction);
+            }
+            let rules = state.rules;
+            let production_map = terrain_map.clone();
+            cmd.exec_mut(move |world| {
+                for _ in advance_production(world, next_player, &production_map, &rules) {
+                    godot_print!("Recruited unit is ready");
+                }
+            });
+            supply_units(world, next_player);
+            rally_units(world, next_player, &state.diplomacy);
+            route_units(world, next_player, terrain_map, &state.diplomacy);
             state.current_player = Some(next_player);
-            set_state(state, State::Waiting);
+            let turn_start = state.get_turn_start();
+            set_state(state, turn_start);
         }
         State::Attacking(attacker_entity, defender_entity) => {
-            let attacking_unit = {
-                let attacker_entry = match world.entry_mut(attacker_entity) {
-                    Err(_) => {
-                        godot_error!("ATTACKING: Attacking entity not in world.");
-                        set_state(state, State::Waiting);
-                        return;
-                    }
-                    Ok(entry) => entry,
-                };
-                let attacking_unit = attacker_entry.get_component::<Unit>();
-                match attacking_unit {
-                    Err(_error) => {
-                        godot_error!("ATTACKING: Attacking entity had no unit component.",);
-                        set_state(state, State::Waiting);
-                        return;
-                    }
-                    Ok(unit) => *unit,
+            if world.entry_ref(attacker_entity).is_err() {
+                godot_error!("ATTACKING: Attacking entity not in world.");
+                set_state(state, State::Waiting);
+                return;
+            }
+            let attacker = match get_attacker(world, attacker_entity, defender_entity) {
+                None => {
+                    godot_error!(
+                        "ATTACKING: Attacking entity had no weapon in range or no action budget.",
+                    );
+                    set_state(state, State::Waiting);
+                    return;
                 }
+                Some(attacker) => attacker,
             };
-            let defending_unit = {
-                let defender_entry = match world.entry_mut(defender_entity) {
-                    Err(_) => {
-                        godot_error!("ATTACKING: Defending entity not in world.");
-                        set_state(state, State::Waiting);
-                        return;
-                    }
-                    Ok(entry) => entry,
-                };
-                match defender_entry.get_component::<Unit>() {
-                    Err(_) => {
-                        godot_error!("ATTACKING: Defending entity had no unit component.");
-                        set_state(state, State::Waiting);
-                        return;
-                    }
-                    Ok(unit) => *unit,
+            if world.entry_ref(defender_entity).is_err() {
+                godot_error!("ATTACKING: Defending entity not in world.");
+                set_state(state, State::Waiting);
+                return;
+            }
+            let defender = match get_defender(world, defender_entity) {
+                None => {
+                    godot_error!("ATTACKING: Defending entity had no health component.");
+                    set_state(state, State::Waiting);
+                    return;
                 }
+                Some(defender) => defender,
             };
-            let result = { attacking_unit.attack(defending_unit.borrow()) };
+            let result = { attacker.attack(defender.borrow()) };
 
             match result {
-                Ok(result) => {
-                    godot_print!("Damage dealt: {}", result.actual_damage);
-                    godot_print!("Remaining integrity: {}", result.defender.integrity);
-                    cmd.exec_mut(move |world| {
-                        handle_attack_result(world, attacker_entity, defender_entity, result);
-                    });
+                Ok(_) => {
+                    let target_hexagon = match world
+                        .entry_ref(defender_entity)
+                        .ok()
+                        .and_then(|entry| entry.get_component::<Hexagon>().ok().copied())
+                    {
+                        None => {
+                            godot_error!("ATTACKING: Defending entity had no hexagon component.");
+                            set_state(state, State::Waiting);
+                            return;
+                        }
+                        Some(hexagon) => hexagon,
+                    };
+                    for target in get_entities_in_attack_area(
+                        world,
+                        attacker_entity,
+                        &target_hexagon,
+                        &state.diplomacy,
+                    ) {
+                        let target_defender = match get_defender(world, target) {
+                            None => continue,
+                            Some(defender) => defender,
+                        };
+                        if let Ok(result) = attacker.attack(&target_defender) {
+                            godot_print!("Damage dealt: {}", result.actual_damage);
+                            godot_print!("Remaining integrity: {}", result.defender.integrity);
+                            let rules = state.rules;
+                            cmd.exec_mut(move |world| {
+                                handle_attack_result(
+                                    world,
+                                    attacker_entity,
+                                    target,
+                                    result.clone(),
+                                    &rules,
+                                );
+                            });
+                        }
+                    }
                 }
                 Err(error) => match error {
                     AttackError::NoAttacksLeft => godot_print!("Attacker has no attacks left"),
+                    AttackError::NoAmmo => godot_print!("Weapon has no ammo left"),
+                    AttackError::Disabled => godot_print!("Attacker can not attack"),
                 },
             }
             set_state(state, State::Waiting);
@@ -437,7 +912,8 @@ pub fn update_state(
         State::Moving(entity, path, mut total_time) => {
             let mut path = path.clone();
             total_time += delta;
-            while total_time > SECONDS_PER_MOVEMENT {
+            // One step per frame, since the step is only applied once the frame is over
+            if total_time > SECONDS_PER_MOVEMENT {
                 let entry = match world.entry_mut(entity) {
                     Err(_) => {
                         godot_error!("MOVING: Entity to move does not exist in world.");
@@ -447,19 +923,19 @@ pub fn update_state(
                     Ok(e) => e,
                 };
 
-                let unit = {
-                    let unit = entry.get_component::<Unit>();
-                    match unit {
+                let mobility = {
+                    let mobility = entry.get_component::<Mobility>();
+                    match mobility {
                         Err(_) => {
-                            godot_error!("MOVING: Entity to move has no unit component");
+                            godot_error!("MOVING: Entity to move has no mobility component");
                             set_state(state, State::Waiting);
                             return;
                         }
-                        Ok(unit) => *unit,
+                        Ok(mobility) => *mobility,
                     }
                 };
 
-                if unit.remaining_range <= 0 {
+                if mobility.remaining_range <= 0 {
                     {
                         set_state(state, State::Selected(entity));
                     }
@@ -490,8 +966,11 @@ pub fn update_state(
                     return;
                 }
 
+                let rules = state.rules;
+                let diplomacy = state.diplomacy.clone();
                 cmd.exec_mut(move |world| {
                     move_entity_to_hexagon(entity, &next_hexagon, world);
+                    resolve_overwatch(world, entity, &rules, &diplomacy);
                 });
 
                 total_time -= SECONDS_PER_MOVEMENT;
@@ -502,12 +981,75 @@ pub fn update_state(
                 set_state(state, State::Selected(entity));
             }
         }
+        State::UsingAbility(entity, ability, hexagon) => {
+            let rules = state.rules;
+            let diplomacy = state.diplomacy.clone();
+            cmd.exec_mut(move |world| {
+                if let Err(error) =
+                    use_ability(world, entity, ability, &hexagon, &rules, &diplomacy)
+                {
+                    godot_print!("{}", error);
+                }
+            });
+            set_state(state, State::Selected(entity));
+        }
+        State::Selected(entity) => {
+            // Units that boarded a transport are no longer on the map
+            if let Some(carried) = world
+                .entry_ref(entity)
+                .ok()
+                .and_then(|entry| entry.get_component::<Carried>().ok().copied())
+            {
+                set_state(state, State::Selected(carried.transport));
+            }
+        }
+        State::Disembarking(transport, hexagon) => {
+            let rules = state.rules;
+            let diplomacy = state.diplomacy.clone();
+            let terrain_map = terrain_map.clone();
+            cmd.exec_mut(move |world| {
+                disembark_to_hexagon(world, transport, &hexagon, &terrain_map, &rules, &diplomacy);
+            });
+            set_state(state, State::Selected(transport));
+        }
+        State::Recruiting(building, index) => {
+            if let Some(player) = state.current_player {
+                let ledger = state.players[player].get_ledger_mut();
+                match recruit(world, ledger, building, index) {
+                    Err(error) => godot_print!("{}", error),
+                    Ok(kind) => godot_print!(
+                        "Recruited {}, ready in {} turns",
+                        kind.get_name(),
+                        kind.get_build_turns()
+                    ),
+                }
+            }
+            set_state(state, State::Selected(building));
+        }
+        State::SelectingWeapon(entity, weapon) => {
+            if let Ok(mut entry) = world.entry_mut(entity) {
+                if let Ok(weapons) = entry.get_component_mut::<Weapons>() {
+                    weapons.selected = weapon;
+                    match weapon.and_then(|index| weapons.get(index)) {
+                        None => godot_print!("Weapon: automatic"),
+                        Some(weapon) => godot_print!("Weapon: {}", weapon.name),
+                    }
+                }
+            }
+            set_state(state, State::Selected(entity));
+        }
         _ => {}
     }
 }
 #[system]
-fn update_ui(#[resource] state: &GameState, #[resource] ui_node: &UINode) {
+fn update_ui(
+    #[resource] state: &GameState,
+    #[resource] ui_node: &UINode,
+    #[resource] world_node: &WorldNode,
+) {
     let ui_node = &ui_node.0;
+    let is_handover = matches!(state.state, State::Handover);
+    unsafe { world_node.0.assume_safe() }.set_visible(!is_handover);
     let player_name = match state.current_player {
         None => "None".to_owned(),
         Some(index) => state.players[index].get_name(),
@@ -531,8 +1073,32 @@ fn update_ui(#[resource] state: &GameState, #[resource] ui_node: &UINode) {
         Some(label) => label,
     };
 
-    player_name_label.set_text(format!("Current player: {}", player_name));
+    match &state.winners {
+        None if is_handover => {
+            player_name_label.set_text(format!("Pass to {}, click to continue", player_name))
+        }
+        None => player_name_label.set_text(format!("Current player: {}", player_name)),
+        Some(winners) => {
+            let names: Vec<String> = winners
+                .iter()
+                .map(|player| state.players[*player].get_name())
+                .collect();
+            player_name_label.set_text(format!("Victory: {}", names.join(", ")))
+        }
+    }
     player_name_label.add_color_override("font_color", player_colour);
+
+    let resources_label = ui_node
+        .get_node("Top/Resources")
+        .and_then(|node| unsafe { node.assume_safe_if_sane() })
+        .and_then(|node| node.cast::<Label>());
+    if let Some(resources_label) = resources_label {
+        let summary = match state.current_player {
+            None => String::new(),
+            Some(index) => state.players[index].get_ledger().get_summary(),
+        };
+        resources_label.set_text(summary);
+    }
 }
 
 pub struct UpdateNodes {
@@ -543,77 +1109,40 @@ pub struct UpdateNodes {
 }
 
 impl UpdateNodes {
-    pub fn new(world_node: Ref<Node2D>, hexfield_size: f32) -> Self {
+    pub fn new(world_node: Ref<Node2D>, layout: Layout) -> Self {
         let mut resources = Resources::default();
 
         let mut state = GameState::new();
 
-        state.players.push(Player::new(
-            "Player 1".to_owned(),
-            Color::rgb(0f32, 0f32, 1f32),
-        ));
-        state.players.push(Player::new(
-            "Player 2".to_owned(),
-            Color::rgb(1f32, 0f32, 0f32),
-        ));
-
-        with_world(|world| {
-            world.extend(vec![(
-                PlayerComponent(0),
-                Hexagon::new_axial(2, 0),
-                NodeTemplate {
-                    scene_file: "res://DummyUnit.tscn".to_owned(),
-                    scale_x: 1.0,
-                    scale_y: 1.0,
-                    z_index: 1,
-                },
-                Unit::new(20, 5, 2, 1, 3, 5, 5, 1),
-            )]);
-            world.extend(vec![(
-                PlayerComponent(0),
-                Hexagon::new_axial(2, 1),
-                NodeTemplate {
-                    scene_file: "res://DummyUnit.tscn".to_owned(),
-                    scale_x: 1.0,
-                    scale_y: 1.0,
-                    z_index: 1,
-                },
-                Unit::new(10, 10, 4, 2, 1, 2, 2, 1),
-            )]);
-
-            world.extend(vec![(
-                PlayerComponent(1),
-                Hexagon::new_axial(-2, 0),
-                NodeTemplate {
-                    scene_file: "res://DummyUnit.tscn".to_owned(),
-                    scale_x: 1.0,
-                    scale_y: 1.0,
-                    z_index: 1,
-                },
-                Unit::new(20, 5, 2, 1, 3, 5, 5, 1),
-            )]);
-            world.extend(vec![(
-                PlayerComponent(1),
-                Hexagon::new_axial(-2, -1),
-                NodeTemplate {
-                    scene_file: "res://DummyUnit.tscn".to_owned(),
-                    scale_x: 1.0,
-                    scale_y: 1.0,
-                    z_index: 1,
-                },
-                Unit::new(10, 10, 4, 2, 1, 2, 2, 1),
-            )]);
+        let scenario = generate_map(&MapSettings {
+            seed: state.seed,
+            ..MapSettings::default()
+        });
 
-            for field in create_grid(128) {
-                world.extend(vec![(Field::new(field),)]);
+        let profiles = if scenario.players.is_empty() {
+            PlayerProfile::defaults(scenario.start_zones.len())
+        } else {
+            scenario.players.clone()
+        };
+        for (index, profile) in profiles.iter().enumerate() {
+            let mut player = Player::from_profile(profile);
+            UpdateNodes::record_starting_funds(&mut player);
+            state.players.push(player);
+            if let Some(team) = profile.team {
+                state.diplomacy.set_team(index, team);
             }
-        });
+        }
+
+        with_world(|world| UpdateNodes::spawn_scenario(world, &scenario, &state.rules));
 
         state.current_player = Some(0);
         resources.insert(WorldNode(world_node));
-        resources.insert(HexfieldSize(hexfield_size));
+        resources.insert(layout);
+        resources.insert(scenario.get_terrain_map());
         resources.insert(state);
         resources.insert(Delta(0f64));
+        resources.insert(PendingSearch::default());
+        resources.insert(NetworkGame::default());
 
         let process_schedule = Schedule::builder()
             .add_thread_local(update_state_system())
@@ -621,17 +1150,20 @@ impl UpdateNodes {
             .add_system(
                 SystemBuilder::new("process")
                     .with_query(<(&mut NodeComponent, &Hexagon)>::query())
-                    .read_resource::<HexfieldSize>()
-                    .build(|_, world, hexfield_size, query| {
+                    .read_resource::<Layout>()
+                    .build(|_, world, layout, query| {
                         for (node, position) in query.iter_mut(world) {
                             unsafe {
-                                let position = get_2d_position_from_hex(&position, hexfield_size.0);
+                                let position = get_2d_position_from_hex(&position, layout);
                                 node.node.assume_safe().set_position(position);
                             }
                         }
                     }),
             )
             .add_thread_local(update_units_system())
+            .add_thread_local(update_unit_visibility_system())
+            .add_thread_local(update_objectives_system())
+            .add_thread_local(update_structures_system())
             .add_system(update_field_system())
             .add_thread_local(create_node_system(world_node))
             .add_thread_local(update_ui_system())
@@ -652,6 +1184,77 @@ impl UpdateNodes {
         }
     }
 
+    fn record_starting_funds(player: &mut Player) {
+        player
+            .get_ledger_mut()
+            .record(Transaction::new(Currency::Credits, 10, "Starting funds"));
+    }
+
+    /// Spawns the map of the scenario and the starting units and depots of the players
+    fn spawn_scenario(world: &mut World, scenario: &Scenario, rules: &Rules) {
+        for (player, start_zone) in scenario.start_zones.iter().enumerate() {
+            UnitKind::Tank.spawn(world, player, start_zone[0], rules);
+            UnitKind::Artillery.spawn(world, player, start_zone[1], rules);
+            let depot = world.push((
+                PlayerComponent(player),
+                start_zone[0],
+                Supply::new(3, 1, 0),
+                Objective::new(ObjectiveKind::Depot),
+                ObjectiveKind::Depot.get_income(),
+                Production::new(UnitKind::iter().collect()),
+                NodeTemplate {
+                    scene_file: "res://Objective.tscn".to_owned(),
+                    scale_x: 1.0,
+                    scale_y: 1.0,
+                    z_index: 1,
+                },
+            ));
+            if let Some(mut entry) = world.entry(depot) {
+                entry.add_component(Structure::new(StructureKind::Building));
+                entry.add_component(StructureKind::Building.get_health());
+                entry.add_component(Vision::new(2));
+            }
+        }
+
+        scenario.spawn_fields(world);
+        scenario.spawn_objectives(world);
+        scenario.spawn_structures(world);
+    }
+
+    /// Starts the game over on a fresh map, keeping the setup of the players
+    fn restart(&mut self, world: &mut World) {
+        let terrain_map = {
+            let mut state = self.resources.get_mut::<GameState>().unwrap();
+            let scenario = generate_map(&MapSettings {
+                seed: state.seed,
+                player_count: state.players.len(),
+                ..MapSettings::default()
+            });
+            for player in state.players.iter_mut() {
+                *player.get_ledger_mut() = Ledger::new();
+                UpdateNodes::record_starting_funds(player);
+            }
+            state.current_player = Some(0);
+            state.winners = None;
+            state.pinned_units.clear();
+            set_state(&mut state, State::Startup);
+            world.clear();
+            UpdateNodes::spawn_scenario(world, &scenario, &state.rules);
+            scenario.get_terrain_map()
+        };
+        self.resources.insert(terrain_map);
+        if let Some(mut pending_search) = self.resources.get_mut::<PendingSearch>() {
+            pending_search.0 = None;
+        }
+    }
+
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.resources.insert(layout);
+        if let Some(mut state) = self.resources.get_mut::<GameState>() {
+            state.redraw_grid = true;
+        }
+    }
+
     pub fn new_round(&mut self) {
         let mut state = match self.resources.get_mut::<GameState>() {
             None => {
@@ -661,6 +1264,20 @@ impl UpdateNodes {
             Some(state) => state,
         };
 
+        if let State::Handover = state.state {
+            return;
+        }
+        if state.is_ai_turn() {
+            return;
+        }
+        if let (Some(player), Some(mut network)) = (
+            state.current_player,
+            self.resources.get_mut::<NetworkGame>(),
+        ) {
+            if network.is_busy(&state) || !network.submit_command(player, Command::EndTurn) {
+                return;
+            }
+        }
         state.state = State::NewRound;
     }
 
@@ -680,6 +1297,7 @@ impl UpdateNodes {
             }
             self.resources.insert(UINode(ui_node));
             self.resources.insert(MainCamera(camera_node));
+            self.poll_network(world);
 
             self.process_schedule
                 .execute(&mut world, &mut self.resources);
@@ -709,6 +1327,16 @@ impl UpdateNodes {
                     let event: TRef<'_, InputEventKey> = unsafe { event.assume_safe() };
                     if !event.is_echo() && event.is_pressed() {
                         let scancode = event.scancode();
+                        if let State::Handover = state.state {
+                            if scancode == GlobalConstants::KEY_ENTER
+                                || scancode == GlobalConstants::KEY_SPACE
+                            {
+                                set_state(state, State::Waiting);
+                            }
+                            continue;
+                        }
+                        let mut network = self.resources.get_mut::<NetworkGame>().unwrap();
+                        let is_input = !state.is_ai_turn() && !network.is_busy(state);
                         match scancode {
                             GlobalConstants::KEY_R => {
                                 state.red_layer = !state.red_layer;
@@ -717,11 +1345,40 @@ impl UpdateNodes {
                             GlobalConstants::KEY_G => {
                                 state.green_layer = !state.green_layer;
                                 state.redraw_grid = true;
+                                state.update_threats = true;
                             }
                             GlobalConstants::KEY_B => {
                                 state.blue_layer = !state.blue_layer;
                                 state.redraw_grid = true;
                             }
+                            GlobalConstants::KEY_T => {
+                                state.threat_layer = !state.threat_layer;
+                                state.redraw_grid = true;
+                                state.update_threats = true;
+                            }
+                            GlobalConstants::KEY_1
+                            | GlobalConstants::KEY_2
+                            | GlobalConstants::KEY_3
+                            | GlobalConstants::KEY_4
+                            | GlobalConstants::KEY_5
+                                if is_input =>
+                            {
+                                let index = (scancode - GlobalConstants::KEY_1) as usize;
+                                match UpdateNodes::recruit(world, state, index) {
+                                    None => UpdateNodes::start_targeting(world, state, index),
+                                    Some(next) => {
+                                        UpdateNodes::submit_state(world, state, &mut network, next)
+                                    }
+                                }
+                            }
+                            GlobalConstants::KEY_W if is_input => {
+                                if let Some(next) = UpdateNodes::select_next_weapon(world, state) {
+                                    UpdateNodes::submit_state(world, state, &mut network, next);
+                                }
+                            }
+                            GlobalConstants::KEY_E if is_input => {
+                                UpdateNodes::start_unloading(world, state);
+                            }
                             GlobalConstants::KEY_H => match self.resources.get::<MainCamera>() {
                                 None => {}
                                 Some(camera) => {
@@ -758,9 +1415,18 @@ impl UpdateNodes {
         };
         let mut mouse_pos = UpdateNodes::to_view_pos(&camera, event.global_position());
         let mut state: &mut GameState = &mut *self.resources.get_mut::<GameState>().unwrap();
-        let hexfield_size = self.resources.get::<HexfieldSize>().unwrap().0;
-        let hex = Hexagon::from_vector2(mouse_pos, hexfield_size);
-        let value_dict = Dictionary::new();
+        let mut network = self.resources.get_mut::<NetworkGame>().unwrap();
+        if state.winners.is_some() || state.is_ai_turn() || network.is_busy(state) {
+            return;
+        }
+        if let State::Handover = state.state {
+            set_state(state, State::Waiting);
+            return;
+        }
+        let layout = *self.resources.get::<Layout>().unwrap();
+        let terrain_map = self.resources.get::<TerrainMap>().unwrap();
+        let hex = Hexagon::from_vector2(mouse_pos, &layout);
+        let value_dict = Dictionary::new();
         value_dict.insert("q", hex.get_q());
         value_dict.insert("r", hex.get_r());
         let value_dict = value_dict.owned_to_variant();
@@ -768,7 +1434,24 @@ impl UpdateNodes {
 
         let entities_at_hexagon = get_entities_at_hexagon(&hex, world);
 
-        if entities_at_hexagon.is_empty() {
+        if event.shift() {
+            UpdateNodes::toggle_pinned_units(world, state, &entities_at_hexagon);
+            return;
+        }
+
+        if let State::Targeting(selected_entity, ability) = state.state {
+            if is_valid_ability_target(world, selected_entity, ability, &hex, &state.diplomacy) {
+                possible_states.push(State::UsingAbility(selected_entity, ability, hex));
+            } else {
+                possible_states.push(State::Selected(selected_entity));
+            }
+        } else if let State::Unloading(transport) = state.state {
+            if can_disembark(world, transport, &hex, &terrain_map) {
+                possible_states.push(State::Disembarking(transport, hex));
+            } else {
+                possible_states.push(State::Selected(transport));
+            }
+        } else if entities_at_hexagon.is_empty() {
             if let State::Selected(selected_entity) = state.state {
                 let selected_hexagon = {
                     let selected_entry = world.entry_ref(selected_entity).unwrap();
@@ -801,11 +1484,11 @@ impl UpdateNodes {
                         Ok(hexagon) => *hexagon,
                     }
                 };
-                let path = find_path(&selected_hexagon, &hex, world);
+                let path = find_path(&selected_hexagon, &hex, world, &terrain_map);
 
                 if path.is_empty() {
                     godot_warn!("Path from entity to target not found.",);
-                } else {
+                } else if entity_has_component::<Mobility, World>(world, &selected_entity) {
                     possible_states.push(State::Moving(
                         selected_entity,
                         VecDeque::from(path),
@@ -814,11 +1497,51 @@ impl UpdateNodes {
                 }
             }
         } else {
+            // Units of other sides are only selectable where they are visible
+            let is_visible = state.is_hexagon_visible(&hex);
+            let current_player = state.current_player;
+            let diplomacy = &state.diplomacy;
+            let is_selectable = |entity: &Entity| {
+                let is_allied = match (world.entry_ref(*entity), current_player) {
+                    (Ok(entry), Some(player)) => matches!(
+                        get_player_of_entity(&entry),
+                        Some(owner) if diplomacy.is_allied(player, owner)
+                    ),
+                    _ => false,
+                };
+                (entity_has_component::<Unit, World>(world, entity)
+                    || entity_has_component::<Production, World>(world, entity))
+                    && (is_visible || is_allied)
+            };
+            let mut entities_at_hexagon = entities_at_hexagon;
+            // Units are selected before the buildings they stand on
+            entities_at_hexagon
+                .sort_by_key(|entity| entity_has_component::<Unit, World>(world, entity));
+            let selectable: Vec<Entity> = entities_at_hexagon
+                .iter()
+                .copied()
+                .filter(|entity| is_selectable(entity))
+                .collect();
+            // Clicking the hexagon of the selection again selects the next entity on it
+            let next_selection = match state.state {
+                State::Selected(selected_entity) => selectable
+                    .iter()
+                    .position(|entity| *entity == selected_entity)
+                    .map(|index| selectable[(index + 1) % selectable.len()]),
+                _ => None,
+            };
+            if let Some(next_selection) = next_selection {
+                possible_states.push(State::Selected(next_selection));
+                entities_at_hexagon.clear();
+            }
             for entity in entities_at_hexagon {
-                possible_states.push(State::Selected(entity));
+                if selectable.contains(&entity) {
+                    possible_states.push(State::Selected(entity));
+                }
                 match state.state {
                     State::NewRound => {}
                     State::Startup => {}
+                    State::Handover => {}
                     State::Waiting => {}
                     State::Selected(selected_entity) => {
                         if world.contains(selected_entity) {
@@ -851,7 +1574,36 @@ impl UpdateNodes {
                                 };
 
                                 match clicked_unit {
-                                    Some(_) => {
+                                    Some(_) if can_embark(world, selected_entity, entity) => {
+                                        if current_player_id != selected_player_id {
+                                            return;
+                                        }
+                                        let selected_hexagon =
+                                            match selected_entry.get_component::<Hexagon>() {
+                                                Err(_) => return,
+                                                Ok(hexagon) => *hexagon,
+                                            };
+                                        let path =
+                                            find_path(&selected_hexagon, &hex, world, &terrain_map);
+
+                                        if path.is_empty() {
+                                            godot_warn!("Path from entity to transport not found.",);
+                                        } else {
+                                            possible_states.push(State::Moving(
+                                                selected_entity,
+                                                VecDeque::from(path),
+                                                0f64,
+                                            ));
+                                        }
+                                    }
+                                    _ if clicked_unit.is_some()
+                                        || is_attackable_structure(
+                                            world,
+                                            entity,
+                                            selected_player_id,
+                                            &state.diplomacy,
+                                        ) =>
+                                    {
                                         if current_player_id != selected_player_id {
                                             return;
                                         }
@@ -866,7 +1618,8 @@ impl UpdateNodes {
                                                         is_hexagon_visible_for_attack(
                                                             &physic_state,
                                                             world,
-                                                            hexfield_size,
+                                                            &layout,
+                                                            &state.diplomacy,
                                                             selected_entity,
                                                             hex,
                                                         )
@@ -875,12 +1628,12 @@ impl UpdateNodes {
                                             }
                                         };
 
-                                        if is_visible {
+                                        if is_visible && state.is_hexagon_visible(&hex) {
                                             possible_states
                                                 .push(State::Attacking(selected_entity, entity));
                                         }
                                     }
-                                    None => {
+                                    _ => {
                                         let selected_hexagon = {
                                             if current_player_id != selected_player_id {
                                                 return;
@@ -895,11 +1648,15 @@ impl UpdateNodes {
                                                 Ok(hexagon) => *hexagon,
                                             }
                                         };
-                                        let path = find_path(&selected_hexagon, &hex, world);
+                                        let path =
+                                            find_path(&selected_hexagon, &hex, world, &terrain_map);
 
                                         if path.is_empty() {
                                             godot_warn!("Path from entity to target not found.",);
-                                        } else {
+                                        } else if entity_has_component::<Mobility, World>(
+                                            world,
+                                            &selected_entity,
+                                        ) {
                                             possible_states.push(State::Moving(
                                                 selected_entity,
                                                 VecDeque::from(path),
@@ -914,18 +1671,29 @@ impl UpdateNodes {
                     }
                     State::Attacking(_, _) => {}
                     State::Moving(_, _, _) => {}
+                    State::Targeting(_, _) => {}
+                    State::UsingAbility(_, _, _) => {}
+                    State::Unloading(_) => {}
+                    State::Disembarking(_, _) => {}
+                    State::Recruiting(_, _) => {}
+                    State::SelectingWeapon(_, _) => {}
                 }
             }
         }
 
-        match possible_states.last() {
-            Some(last_state) => {
-                set_state(state, last_state.clone());
-            }
-            None => {
-                set_state(state, State::Waiting);
-            }
-        }
+        let next_state = possible_states.last().cloned().unwrap_or(State::Waiting);
+        let is_submitted = match state.current_player {
+            None => true,
+            Some(player) => network.submit(world, player, &next_state),
+        };
+        set_state(
+            state,
+            if is_submitted {
+                next_state
+            } else {
+                State::Waiting
+            },
+        );
 
         unsafe {
             root.call_deferred(
@@ -946,13 +1714,16 @@ impl UpdateNodes {
             Some(camera) => camera.0,
         };
         let mut mouse_pos = UpdateNodes::to_view_pos(&camera, event.global_position());
-        let hexfield_size = self.resources.get::<HexfieldSize>().unwrap().0;
-        let hex = Hexagon::from_vector2(mouse_pos, hexfield_size);
+        let layout = *self.resources.get::<Layout>().unwrap();
+        let hex = Hexagon::from_vector2(mouse_pos, &layout);
         let value_dict = Dictionary::new();
         value_dict.insert("q", hex.get_q());
         value_dict.insert("r", hex.get_r());
         let value_dict = value_dict.owned_to_variant();
         let mut state: &mut GameState = &mut *self.resources.get_mut::<GameState>().unwrap();
+        if self.is_networked() && !state.state.is_idle() {
+            return;
+        }
         set_state(state, State::Waiting);
         unsafe {
             root.call_deferred(
@@ -988,8 +1759,8 @@ impl UpdateNodes {
                 camera.move_local_y((-pos.y).into(), false);
             }
             _ => {
-                let hexfield_size = self.resources.get::<HexfieldSize>().unwrap().0;
-                let hex = Hexagon::from_vector2(mouse_pos, hexfield_size);
+                let layout = *self.resources.get::<Layout>().unwrap();
+                let hex = Hexagon::from_vector2(mouse_pos, &layout);
                 if match state.hovered_hexagon {
                     Some(hovered_hexagon) => {
                         if hex != hovered_hexagon {
@@ -1014,7 +1785,9 @@ impl UpdateNodes {
                     }
                     None => true,
                 } {
-                    UpdateNodes::update_path(world, state, &hex);
+                    let terrain_map = self.resources.get::<TerrainMap>().unwrap();
+                    UpdateNodes::update_path(world, state, &terrain_map, &hex);
+                    UpdateNodes::update_splash_area(world, state, &hex);
 
                     let value_dict = Dictionary::new();
                     value_dict.insert("q", hex.get_q());
@@ -1022,6 +1795,7 @@ impl UpdateNodes {
                     let value_dict = value_dict.owned_to_variant();
                     state.hovered_hexagon = Some(hex);
                     state.redraw_grid = true;
+                    state.update_threats = true;
                     unsafe {
                         root.call_deferred(
                             "emit_signal",
@@ -1043,7 +1817,12 @@ impl UpdateNodes {
         camera.to_global(mouse_pos)
     }
 
-    fn update_path<S: EntityStore>(world: &S, mut state: &mut GameState, hex: &Hexagon) {
+    fn update_path<S: EntityStore>(
+        world: &S,
+        mut state: &mut GameState,
+        terrain_map: &TerrainMap,
+        hex: &Hexagon,
+    ) {
         let selected_entity = match state.state {
             State::Selected(index) => index,
             _ => {
@@ -1087,7 +1866,412 @@ impl UpdateNodes {
             Ok(hexagon) => *hexagon,
         };
 
-        state.current_path = find_path(&selected_hexagon, &hex, world);
+        state.current_path = find_path(&selected_hexagon, &hex, world, terrain_map);
+    }
+
+    /// Pins the visible enemy units on the hexagon so their danger zone stays shown, or
+    /// unpins them if they are pinned already
+    fn toggle_pinned_units<S: EntityStore>(world: &S, state: &mut GameState, entities: &[Entity]) {
+        let player = match state.current_player {
+            None => return,
+            Some(player) => player,
+        };
+        for entity in entities {
+            let entry = match world.entry_ref(*entity) {
+                Err(_) => continue,
+                Ok(entry) => entry,
+            };
+            let is_enemy = entry.get_component::<Unit>().is_ok()
+                && match (
+                    get_player_of_entity(&entry),
+                    entry.get_component::<Hexagon>(),
+                ) {
+                    (Some(owner), Ok(hexagon)) => {
+                        state.diplomacy.is_hostile(player, owner)
+                            && state.is_hexagon_visible(hexagon)
+                    }
+                    _ => false,
+                };
+            if !is_enemy {
+                continue;
+            }
+            match state
+                .pinned_units
+                .iter()
+                .position(|pinned| pinned == entity)
+            {
+                None => state.pinned_units.push(*entity),
+                Some(index) => {
+                    state.pinned_units.remove(index);
+                }
+            }
+        }
+        state.redraw_grid = true;
+        state.update_threats = true;
+    }
+
+    fn update_splash_area<S: EntityStore>(world: &S, state: &mut GameState, hex: &Hexagon) {
+        state.splash_area = Vec::new();
+        if let State::Targeting(selected_entity, index) = state.state {
+            if let Ok(ability) = get_usable_ability(world, selected_entity, index) {
+                if let AbilityTarget::Area(radius) = ability.target {
+                    if get_ability_targets(world, selected_entity, &ability, hex, &state.diplomacy)
+                        .is_ok()
+                    {
+                        state.splash_area = create_grid(radius.max(0) as u32)
+                            .iter()
+                            .map(|offset| *offset + *hex)
+                            .collect();
+                    }
+                }
+            }
+            return;
+        }
+        let selected_entity = match state.state {
+            State::Selected(index) => index,
+            _ => return,
+        };
+
+        let selected_entry = match world.entry_ref(selected_entity) {
+            Err(_) => return,
+            Ok(entity) => entity,
+        };
+
+        if state.current_player != get_player_of_entity(&selected_entry) {
+            return;
+        }
+
+        let target = match get_entities_at_hexagon(hex, world)
+            .into_iter()
+            .find(|entity| {
+                *entity != selected_entity && entity_has_component::<Unit, S>(world, entity)
+            }) {
+            None => return,
+            Some(target) => target,
+        };
+
+        let (selected_hexagon, selected_attacker) = match (
+            selected_entry.get_component::<Hexagon>(),
+            get_attacker(world, selected_entity, target),
+        ) {
+            (Ok(hexagon), Some(attacker)) => (*hexagon, attacker),
+            _ => return,
+        };
+
+        if !selected_attacker
+            .weapon
+            .can_fire(&selected_attacker.budget, &selected_attacker.modifiers)
+            || !selected_attacker.weapon.is_in_attack_range(
+                selected_hexagon.distance_to(hex),
+                &selected_attacker.modifiers,
+            )
+        {
+            return;
+        }
+
+        let profile = selected_entry
+            .get_component::<AttackProfile>()
+            .ok()
+            .copied()
+            .unwrap_or_default();
+        state.splash_area = profile.get_affected_hexagons(&selected_hexagon, hex);
+    }
+
+    fn start_targeting<S: EntityStore>(world: &S, state: &mut GameState, ability: usize) {
+        let selected_entity = match state.state {
+            State::Selected(entity) | State::Targeting(entity, _) => entity,
+            _ => return,
+        };
+
+        let selected_entry = match world.entry_ref(selected_entity) {
+            Err(_) => return,
+            Ok(entity) => entity,
+        };
+
+        if state.current_player != get_player_of_entity(&selected_entry) {
+            return;
+        }
+
+        match get_usable_ability(world, selected_entity, ability) {
+            Err(error) => godot_print!("{}", error),
+            Ok(_) => set_state(state, State::Targeting(selected_entity, ability)),
+        }
+    }
+
+    /// Returns the state that recruits the unit with the index if a building of the current
+    /// player is selected, or None if the selection can not recruit.
+    fn recruit<S: EntityStore>(world: &S, state: &GameState, index: usize) -> Option<State> {
+        let selected_entity = match state.state {
+            State::Selected(entity) => entity,
+            _ => return None,
+        };
+
+        let entry = world.entry_ref(selected_entity).ok()?;
+        if entry.get_component::<Production>().is_err()
+            || state.current_player != get_player_of_entity(&entry)
+        {
+            return None;
+        }
+        Some(State::Recruiting(selected_entity, index))
+    }
+
+    /// Sets the state once its command is sent to the peers
+    fn submit_state<S: EntityStore>(
+        world: &S,
+        state: &mut GameState,
+        network: &mut NetworkGame,
+        next: State,
+    ) {
+        let is_submitted = match state.current_player {
+            None => true,
+            Some(player) => network.submit(world, player, &next),
+        };
+        if is_submitted {
+            set_state(state, next);
+        }
+    }
+
+    fn start_unloading<S: EntityStore>(world: &S, state: &mut GameState) {
+        let selected_entity = match state.state {
+            State::Selected(entity) => entity,
+            _ => return,
+        };
+
+        let selected_entry = match world.entry_ref(selected_entity) {
+            Err(_) => return,
+            Ok(entity) => entity,
+        };
+
+        if state.current_player != get_player_of_entity(&selected_entry) {
+            return;
+        }
+
+        match get_next_cargo(world, selected_entity) {
+            None => godot_print!("No cargo can disembark"),
+            Some(_) => set_state(state, State::Unloading(selected_entity)),
+        }
+    }
+
+    /// Returns the state that selects the next weapon of the selected unit of the current
+    /// player, or None if there is no such unit
+    fn select_next_weapon<S: EntityStore>(world: &S, state: &GameState) -> Option<State> {
+        let selected_entity = match state.state {
+            State::Selected(entity) => entity,
+            _ => return None,
+        };
+
+        let entry = world.entry_ref(selected_entity).ok()?;
+        if state.current_player != get_player_of_entity(&entry) {
+            return None;
+        }
+        let mut weapons = entry.get_component::<Weapons>().ok()?.clone();
+        weapons.select_next();
+        Some(State::SelectingWeapon(selected_entity, weapons.selected))
+    }
+
+    pub fn get_ledger(&self, player: usize) -> Option<Ledger> {
+        let state = self.resources.get::<GameState>()?;
+        state
+            .players
+            .get(player)
+            .map(|player| player.get_ledger().clone())
+    }
+
+    pub fn set_team(&mut self, player: usize, team: usize) {
+        if let Some(mut state) = self.resources.get_mut::<GameState>() {
+            state.diplomacy.set_team(player, team);
+        }
+    }
+
+    pub fn set_relation(&mut self, player: usize, other: usize, relation: Relation) {
+        if let Some(mut state) = self.resources.get_mut::<GameState>() {
+            state.diplomacy.set_relation(player, other, relation);
+        }
+    }
+
+    fn is_networked(&self) -> bool {
+        matches!(self.resources.get::<NetworkGame>(), Some(network) if network.is_active())
+    }
+
+    /// Hosts a networked game with the current setup on the port, starting it over. The
+    /// player and the AI players are played here, all other players by the peers that join.
+    pub fn host(&mut self, port: u16, player: usize) -> io::Result<()> {
+        let setup = self.get_setup();
+        let players = {
+            let state = self.resources.get::<GameState>().unwrap();
+            (0..state.players.len())
+                .filter(|index| {
+                    *index == player
+                        || matches!(
+                            state.players[*index].get_controller(),
+                            Controller::Ai(_) | Controller::Search(_)
+                        )
+                })
+                .collect()
+        };
+        let lockstep = Lockstep::host(("0.0.0.0", port), players, setup)?;
+        self.resources.insert(NetworkGame(Some(lockstep)));
+        self.assign_controllers();
+        with_world(|world| self.restart(world));
+        Ok(())
+    }
+
+    /// Joins the networked game hosted on the address as the player. The game starts over
+    /// with the setup of the host once it arrives, and catches up with its commands.
+    pub fn join(&mut self, address: &str, player: usize) -> io::Result<()> {
+        let lockstep = Lockstep::join(address, vec![player])?;
+        self.resources.insert(NetworkGame(Some(lockstep)));
+        self.assign_controllers();
+        Ok(())
+    }
+
+    /// Connects to the host of the networked game again, catching up with the commands that
+    /// were missed
+    pub fn reconnect(&mut self, address: &str) -> io::Result<()> {
+        match self.resources.get_mut::<NetworkGame>() {
+            Some(mut network) => match &mut network.0 {
+                Some(lockstep) if !lockstep.is_host() => lockstep.reconnect(address),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "Not a peer of a networked game",
+                )),
+            },
+            None => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "Not a peer of a networked game",
+            )),
+        }
+    }
+
+    /// The setup of the current game, which the peers of a networked game share
+    fn get_setup(&self) -> GameSetup {
+        let state = self.resources.get::<GameState>().unwrap();
+        let teams = state.diplomacy.get_teams();
+        let profiles = state
+            .players
+            .iter()
+            .enumerate()
+            .map(|(index, player)| {
+                let mut profile = PlayerProfile::new(player.get_name(), player.get_colour());
+                profile.team = teams
+                    .iter()
+                    .find(|(member, _)| *member == index)
+                    .map(|(_, team)| *team);
+                profile
+            })
+            .collect();
+        GameSetup {
+            seed: state.seed,
+            rules: state.rules,
+            diplomacy: state.diplomacy.clone(),
+            profiles,
+        }
+    }
+
+    /// Starts the game over with the setup of the host
+    fn apply_setup(&mut self, world: &mut World, setup: &GameSetup) {
+        if let Some(mut state) = self.resources.get_mut::<GameState>() {
+            state.seed = setup.seed;
+            state.rules = setup.rules;
+            state.diplomacy = setup.diplomacy.clone();
+            state.players = setup.profiles.iter().map(Player::from_profile).collect();
+        }
+        self.assign_controllers();
+        self.restart(world);
+    }
+
+    /// Lets the peers of the networked game play the players that are not played here
+    fn assign_controllers(&mut self) {
+        let network = self.resources.get::<NetworkGame>();
+        let lockstep = match network.as_ref().and_then(|network| network.0.as_ref()) {
+            None => return,
+            Some(lockstep) => lockstep,
+        };
+        if let Some(mut state) = self.resources.get_mut::<GameState>() {
+            for index in 0..state.players.len() {
+                let controller = match state.players[index].get_controller() {
+                    _ if !lockstep.is_local(index) => Controller::Remote,
+                    Controller::Remote => Controller::Human,
+                    controller => controller,
+                };
+                state.set_controller(index, controller);
+            }
+        }
+    }
+
+    /// Receives the messages of the peers, and starts the game over if it went out of sync
+    fn poll_network(&mut self, world: &mut World) {
+        let events = match self.resources.get_mut::<NetworkGame>() {
+            Some(mut network) => match &mut network.0 {
+                None => return,
+                Some(lockstep) => lockstep.poll(),
+            },
+            None => return,
+        };
+        for event in events {
+            match event {
+                NetworkEvent::Joined(player) => godot_print!("Player {} joined", player + 1),
+                NetworkEvent::Disconnected(player) => {
+                    godot_warn!("Player {} disconnected", player + 1)
+                }
+                NetworkEvent::Desync {
+                    sequence,
+                    local,
+                    remote,
+                } => godot_error!(
+                    "Out of sync after {} commands, hash {} differs from {}",
+                    sequence,
+                    local,
+                    remote
+                ),
+                NetworkEvent::Setup(setup) => {
+                    godot_print!("Starting the game of the host");
+                    self.apply_setup(world, &setup);
+                }
+                NetworkEvent::Restart(None) => {
+                    godot_print!("Replaying the game of the host");
+                    self.restart(world);
+                }
+                NetworkEvent::Restart(Some(snapshot)) => {
+                    godot_print!("Resuming the game of the host from its snapshot");
+                    self.restart(world);
+                    if let Some(mut state) = self.resources.get_mut::<GameState>() {
+                        apply_snapshot(world, &mut state, &snapshot);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn set_controller(&mut self, player: usize, controller: Controller) {
+        if let Some(mut state) = self.resources.get_mut::<GameState>() {
+            state.set_controller(player, controller);
+        }
+    }
+
+    /// Applies the profile to the player, keeping their ledger
+    pub fn configure_player(&mut self, index: usize, profile: &PlayerProfile) {
+        let mut state = match self.resources.get_mut::<GameState>() {
+            None => {
+                godot_error!("configure_player: No GameState");
+                return;
+            }
+            Some(state) => state,
+        };
+        let player = match state.players.get_mut(index) {
+            None => {
+                godot_error!("configure_player: No player {}", index);
+                return;
+            }
+            Some(player) => player,
+        };
+        player.set_name(profile.name.clone());
+        player.set_colour(profile.colour);
+        player.set_controller(profile.controller);
+        match profile.team {
+            None => state.diplomacy.clear_team(index),
+            Some(team) => state.diplomacy.set_team(index, team),
+        }
     }
 
     pub fn execute_draw(&mut self) {
@@ -1103,8 +2287,18 @@ impl UpdateNodes {
 
 #[cfg(test)]
 mod tests {
+    use crate::components::action_budget::ActionBudget;
+    use crate::components::action_points::ActionCosts;
+    use crate::components::experience::KILL_EXPERIENCE;
+    use crate::components::field::Terrain;
+    use crate::components::health::Health;
     use crate::components::hexagon::Hexagon;
-    use crate::components::unit::{AttackResult, Unit};
+    use crate::components::mobility::Mobility;
+    use crate::components::transport::CargoFate;
+    use crate::components::unit::AttackResult;
+    use crate::legion::add_unit;
+    use crate::systems::hexgrid::create_grid;
+    use crate::systems::transport::embark;
     use crate::systems::*;
     use legion::{World, WorldOptions};
 
@@ -1112,27 +2306,27 @@ mod tests {
     fn handle_attack_result_updates_components() {
         let mut world = World::new(WorldOptions::default());
         let attacker = *world
-            .extend(vec![(Unit::new(1, 1, 0, 0, 0, 0, 0, 1),)])
-            .first()
-            .unwrap();
-        let defender = *world
-            .extend(vec![(Unit::new(2, 1, 0, 0, 0, 0, 0, 0),)])
+            .extend(vec![(ActionBudget::new(1, 1),)])
             .first()
             .unwrap();
+        let defender = *world.extend(vec![(Health::new(2, 0),)]).first().unwrap();
         let result = AttackResult {
-            attacker: Unit::new(1, 1, 0, 0, 0, 0, 0, 0),
-            defender: Unit::new(1, 1, 0, 0, 0, 0, 0, 0),
+            attacker: ActionBudget::new(1, 0),
+            defender: Health::new(1, 0),
             actual_damage: 1,
+            points: None,
+            weapon_index: 0,
+            weapon: Weapon::new("Test", 1, 1, 1),
         };
 
-        handle_attack_result(&mut world, attacker, defender, result);
+        handle_attack_result(&mut world, attacker, defender, result, &Rules::default());
 
         let entry = world.entry(attacker).unwrap();
-        let changed_attacker = entry.get_component::<Unit>().unwrap();
+        let changed_attacker = entry.get_component::<ActionBudget>().unwrap();
         assert_eq!(changed_attacker.remaining_attacks, 0);
 
         let entry = world.entry(defender).unwrap();
-        let changed_defender = entry.get_component::<Unit>().unwrap();
+        let changed_defender = entry.get_component::<Health>().unwrap();
         assert_eq!(changed_defender.integrity, 1);
     }
 
@@ -1140,76 +2334,242 @@ mod tests {
     fn handle_attack_result_removes_defender_when_integrity_lower_or_eq_0() {
         let mut world = World::new(WorldOptions::default());
         let attacker = *world
-            .extend(vec![(Unit::new(1, 2, 0, 0, 0, 0, 0, 1),)])
+            .extend(vec![(ActionBudget::new(1, 1),)])
             .first()
             .unwrap();
-        let defender = *world
-            .extend(vec![(Unit::new(2, 1, 0, 0, 0, 0, 0, 0),)])
+        let defender = *world.extend(vec![(Health::new(2, 0),)]).first().unwrap();
+        let result = AttackResult {
+            attacker: ActionBudget::new(1, 0),
+            defender: Health::new(0, 0),
+            actual_damage: 1,
+            points: None,
+            weapon_index: 0,
+            weapon: Weapon::new("Test", 1, 1, 1),
+        };
+
+        handle_attack_result(&mut world, attacker, defender, result, &Rules::default());
+
+        assert!(!world.contains(defender));
+    }
+
+    #[test]
+    fn handle_attack_result_sets_attacker_remaining_range_to_0() {
+        let mut world = World::new(WorldOptions::default());
+        let attacker = *world
+            .extend(vec![(ActionBudget::new(1, 1), Mobility::new(5, 5))])
             .first()
             .unwrap();
+        let defender = *world.extend(vec![(Health::new(2, 0),)]).first().unwrap();
         let result = AttackResult {
-            attacker: Unit::new(1, 1, 0, 0, 0, 0, 0, 0),
-            defender: Unit::new(0, 1, 0, 0, 0, 0, 0, 0),
+            attacker: ActionBudget::new(1, 0),
+            defender: Health::new(1, 0),
             actual_damage: 1,
+            points: None,
+            weapon_index: 0,
+            weapon: Weapon::new("Test", 1, 1, 1),
         };
 
-        handle_attack_result(&mut world, attacker, defender, result);
+        handle_attack_result(&mut world, attacker, defender, result, &Rules::default());
 
-        assert!(!world.contains(defender));
+        let entry = world.entry(attacker).unwrap();
+        let mobility = entry.get_component::<Mobility>().unwrap();
+        assert_eq!(mobility.remaining_range, 0);
+        assert_eq!(mobility.mobility, 5);
+    }
+
+    fn action_points(points: i32) -> ActionPoints {
+        ActionPoints::new(
+            points,
+            ActionCosts {
+                move_hexagon: 1,
+                attack: 2,
+                ability: 1,
+            },
+        )
     }
 
     #[test]
-    fn handle_attack_results_only_changes_affected_fields() {
+    fn handle_attack_result_keeps_movement_if_rules_allow_it() {
         let mut world = World::new(WorldOptions::default());
-        let attacking_unit = Unit::new(1, 1, 2, 4, 5, 3, 0, 1);
-        let attacker = *world.extend(vec![(attacking_unit,)]).first().unwrap();
-        let defending_unit = Unit::new(2, 4, 5, 3, 2, 4, 0, 0);
-        let defender = *world.extend(vec![(defending_unit,)]).first().unwrap();
+        let attacker = *world
+            .extend(vec![(
+                ActionBudget::new(1, 2),
+                Mobility::new(5, 5),
+                action_points(5),
+            )])
+            .first()
+            .unwrap();
+        let defender = *world.extend(vec![(Health::new(2, 0),)]).first().unwrap();
+        let mut points = action_points(5);
+        points.spend_attacks(1);
         let result = AttackResult {
-            attacker: attacking_unit,
-            defender: defending_unit,
+            attacker: ActionBudget::new(1, 1),
+            defender: Health::new(1, 0),
             actual_damage: 1,
+            points: Some(points),
+            weapon_index: 0,
+            weapon: Weapon::new("Test", 1, 1, 1),
+        };
+        let rules = Rules {
+            attacking_ends_movement: false,
+            ..Rules::default()
         };
 
-        handle_attack_result(&mut world, attacker, defender, result);
+        handle_attack_result(&mut world, attacker, defender, result, &rules);
 
         let entry = world.entry(attacker).unwrap();
-        let changed_attacker = entry.get_component::<Unit>().unwrap();
-        assert_eq!(changed_attacker.damage, attacking_unit.damage);
         assert_eq!(
-            changed_attacker.max_attack_range,
-            attacking_unit.max_attack_range
+            entry.get_component::<Mobility>().unwrap().remaining_range,
+            3
+        );
+        assert_eq!(
+            entry
+                .get_component::<ActionPoints>()
+                .unwrap()
+                .remaining_points,
+            3
         );
+    }
+
+    #[test]
+    fn handle_attack_result_grants_experience_for_damage_and_kills() {
+        let mut world = World::new(WorldOptions::default());
+        let attacker = *world
+            .extend(vec![(ActionBudget::new(2, 2), Experience::default())])
+            .first()
+            .unwrap();
+        let first = *world.extend(vec![(Health::new(5, 0),)]).first().unwrap();
+        let second = *world.extend(vec![(Health::new(3, 0),)]).first().unwrap();
+        let result = |defender: Health, actual_damage: i32| AttackResult {
+            attacker: ActionBudget::new(2, 1),
+            defender,
+            actual_damage,
+            points: None,
+            weapon_index: 0,
+            weapon: Weapon::new("Test", 3, 1, 1),
+        };
+
+        handle_attack_result(
+            &mut world,
+            attacker,
+            first,
+            result(Health::new(2, 0), 3),
+            &Rules::default(),
+        );
+        handle_attack_result(
+            &mut world,
+            attacker,
+            second,
+            result(Health::new(0, 0), 3),
+            &Rules::default(),
+        );
+
+        let entry = world.entry(attacker).unwrap();
+        assert_eq!(
+            entry.get_component::<Experience>().unwrap().points,
+            6 + KILL_EXPERIENCE
+        );
+    }
+
+    #[test]
+    fn handle_attack_result_updates_used_weapon() {
+        let mut world = World::new(WorldOptions::default());
+        let weapon = Weapon {
+            ammo: Some(2),
+            ..Weapon::new("Test", 1, 1, 1)
+        };
+        let attacker = *world
+            .extend(vec![(
+                ActionBudget::new(1, 1),
+                Weapons::new(vec![Weapon::new("Other", 1, 1, 1), weapon.clone()]),
+            )])
+            .first()
+            .unwrap();
+        let defender = *world.extend(vec![(Health::new(2, 0),)]).first().unwrap();
+        let result = AttackResult {
+            attacker: ActionBudget::new(1, 0),
+            defender: Health::new(1, 0),
+            actual_damage: 1,
+            points: None,
+            weapon_index: 1,
+            weapon: Weapon {
+                ammo: Some(1),
+                ..weapon
+            },
+        };
+
+        handle_attack_result(&mut world, attacker, defender, result, &Rules::default());
+
+        let entry = world.entry(attacker).unwrap();
+        let weapons = entry.get_component::<Weapons>().unwrap();
+        assert_eq!(weapons.weapons[0].ammo, None);
+        assert_eq!(weapons.weapons[1].ammo, Some(1));
+    }
+
+    #[test]
+    fn handle_attack_results_only_changes_affected_fields() {
+        let mut world = World::new(WorldOptions::default());
+        let attacking_weapons = Weapons::new(vec![Weapon::new("Test", 1, 4, 2)]);
+        let attacking_health = Health::new(1, 5);
+        let attacker = *world
+            .extend(vec![(
+                attacking_weapons.clone(),
+                attacking_health,
+                ActionBudget::new(1, 1),
+            )])
+            .first()
+            .unwrap();
+        let defending_weapons = Weapons::new(vec![Weapon::new("Test", 4, 3, 5)]);
+        let defending_health = Health::new(2, 2);
+        let defender = *world
+            .extend(vec![(
+                defending_weapons.clone(),
+                defending_health,
+                ActionBudget::new(1, 0),
+            )])
+            .first()
+            .unwrap();
+        let result = AttackResult {
+            attacker: ActionBudget::new(1, 0),
+            defender: Health::new(1, 2),
+            actual_damage: 1,
+            points: None,
+            weapon_index: 0,
+            weapon: attacking_weapons.weapons[0].clone(),
+        };
+
+        handle_attack_result(&mut world, attacker, defender, result, &Rules::default());
+
+        let entry = world.entry(attacker).unwrap();
         assert_eq!(
-            changed_attacker.min_attack_range,
-            attacking_unit.min_attack_range
+            *entry.get_component::<Weapons>().unwrap(),
+            attacking_weapons
         );
-        assert_eq!(changed_attacker.armor, attacking_unit.armor);
-        assert_eq!(changed_attacker.mobility, attacking_unit.mobility);
+        assert_eq!(*entry.get_component::<Health>().unwrap(), attacking_health);
 
         let entry = world.entry(defender).unwrap();
-        let changed_defender = entry.get_component::<Unit>().unwrap();
-        assert_eq!(changed_defender.damage, defending_unit.damage);
         assert_eq!(
-            changed_defender.max_attack_range,
-            defending_unit.max_attack_range
+            *entry.get_component::<Weapons>().unwrap(),
+            defending_weapons
+        );
+        assert_eq!(
+            entry.get_component::<Health>().unwrap().armor,
+            defending_health.armor
         );
         assert_eq!(
-            changed_defender.min_attack_range,
-            defending_unit.min_attack_range
+            entry
+                .get_component::<ActionBudget>()
+                .unwrap()
+                .remaining_attacks,
+            0
         );
-        assert_eq!(changed_defender.armor, defending_unit.armor);
-        assert_eq!(changed_defender.mobility, defending_unit.mobility);
     }
 
     #[test]
     fn move_entity_to_hexagon_updates_entity() {
         let mut world = World::new(WorldOptions::default());
         let entity = *world
-            .extend(vec![(
-                Hexagon::new_axial(0, 0),
-                Unit::new(0, 0, 0, 0, 0, 0, 2, 0),
-            )])
+            .extend(vec![(Hexagon::new_axial(0, 0), Mobility::new(0, 2))])
             .first()
             .unwrap();
 
@@ -1219,16 +2579,17 @@ mod tests {
         let hexagon = entry.get_component::<Hexagon>().unwrap();
         assert_eq!(hexagon.get_q(), 1);
         assert_eq!(hexagon.get_r(), 1);
+        assert_eq!(
+            entry.get_component::<Mobility>().unwrap().remaining_range,
+            0
+        );
     }
 
     #[test]
     fn move_entity_to_hexagon_does_nothing_if_entity_cannot_move() {
         let mut world = World::default();
         let entity = *world
-            .extend(vec![(
-                Hexagon::new_axial(5, 5),
-                Unit::new(0, 0, 0, 0, 0, 0, 1, 0),
-            )])
+            .extend(vec![(Hexagon::new_axial(5, 5), Mobility::new(0, 1))])
             .first()
             .unwrap();
 
@@ -1239,4 +2600,108 @@ mod tests {
         assert_eq!(hexagon.get_q(), 5);
         assert_eq!(hexagon.get_r(), 5);
     }
+
+    #[test]
+    fn move_entity_to_hexagon_spends_action_points() {
+        let mut world = World::default();
+        let entity = *world
+            .extend(vec![(
+                Hexagon::new_axial(0, 0),
+                Mobility::new(4, 4),
+                ActionBudget::new(1, 2),
+                action_points(4),
+            )])
+            .first()
+            .unwrap();
+
+        move_entity_to_hexagon(entity, &Hexagon::new_axial(1, 0), &mut world);
+
+        let entry = world.entry(entity).unwrap();
+        assert_eq!(
+            entry
+                .get_component::<ActionPoints>()
+                .unwrap()
+                .remaining_points,
+            3
+        );
+        assert_eq!(
+            entry.get_component::<Mobility>().unwrap().remaining_range,
+            3
+        );
+        assert_eq!(
+            entry
+                .get_component::<ActionBudget>()
+                .unwrap()
+                .remaining_attacks,
+            1
+        );
+    }
+
+    #[test]
+    fn disembark_to_hexagon_next_to_overwatch_can_destroy_the_cargo() {
+        let mut world = World::default();
+        let mut terrain_map = TerrainMap::default();
+        for hexagon in create_grid(3) {
+            terrain_map.0.insert(hexagon, Terrain::Plains);
+        }
+        let transport = add_unit(
+            &mut world,
+            0,
+            Hexagon::zero(),
+            (Mobility::new(3, 3), Transport::new(1, CargoFate::Destroyed)),
+        );
+        let cargo = add_unit(
+            &mut world,
+            0,
+            Hexagon::new_axial(0, 1),
+            (Health::new(1, 0), Mobility::new(3, 3)),
+        );
+        assert!(embark(&mut world, cargo, transport));
+        let mut effects = StatusEffects::new();
+        effects.add(StatusEffectKind::Overwatch, 1);
+        add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(2, 0),
+            (
+                Weapons::new(vec![Weapon::new("Rifle", 5, 1, 1)]),
+                ActionBudget::new(1, 1),
+                effects,
+            ),
+        );
+        let (sender, receiver) = crossbeam::crossbeam_channel::unbounded();
+        world.subscribe(sender, legion::component::<Unit>());
+
+        let disembarked = disembark_to_hexagon(
+            &mut world,
+            transport,
+            &Hexagon::new_axial(1, 0),
+            &terrain_map,
+            &Rules::default(),
+            &Diplomacy::new(),
+        );
+
+        // The cargo was inserted into a new archetype, but is gone once the events are read
+        assert_eq!(disembarked, Some(cargo));
+        assert!(!world.contains(cargo));
+        assert!(receiver.try_iter().any(|event| matches!(event,
+            legion::world::Event::EntityInserted(entity, _) if entity == cargo)));
+    }
+
+    #[test]
+    fn move_entity_to_hexagon_does_nothing_without_mobility() {
+        let mut world = World::default();
+        let entity = *world
+            .extend(vec![(Hexagon::new_axial(0, 0), Unit)])
+            .first()
+            .unwrap();
+
+        move_entity_to_hexagon(entity, &Hexagon::new_axial(1, 0), &mut world);
+
+        let entry = world.entry(entity).unwrap();
+        assert_eq!(
+            *entry.get_component::<Hexagon>().unwrap(),
+            Hexagon::new_axial(0, 0)
+        );
+    }
 }
@@ -0,0 +1,471 @@
+use crate::components::abilities::{
+    Abilities, Ability, AbilityEffect, AbilityError, AbilityTarget,
+};
+use crate::components::action_budget::ActionBudget;
+use crate::components::action_points::ActionPoints;
+use crate::components::health::Health;
+use crate::components::hexagon::Hexagon;
+use crate::components::mobility::Mobility;
+use crate::components::player::Player;
+use crate::components::status_effects::StatusEffects;
+use crate::components::unit::Unit;
+use crate::diplomacy::Diplomacy;
+use crate::game_state::Rules;
+use crate::systems::combat::get_modifiers;
+use crate::systems::hexgrid::{create_grid, get_entities_at_hexagon};
+use legion::{Entity, EntityStore, World};
+
+/// Returns the ability of the user, if the user can use it right now
+pub fn get_usable_ability<S: EntityStore>(
+    world: &S,
+    user: Entity,
+    index: usize,
+) -> Result<Ability, AbilityError> {
+    let entry = match world.entry_ref(user) {
+        Err(_) => return Err(AbilityError::NotFound),
+        Ok(entry) => entry,
+    };
+    let abilities = match entry.get_component::<Abilities>() {
+        Err(_) => return Err(AbilityError::NotFound),
+        Ok(abilities) => abilities,
+    };
+    let ability = match abilities.get(index) {
+        None => return Err(AbilityError::NotFound),
+        Some(ability) => ability.clone(),
+    };
+    let budget = match entry.get_component::<ActionBudget>() {
+        Err(_) => return Err(AbilityError::NotFound),
+        Ok(budget) => *budget,
+    };
+    let can_afford = match entry.get_component::<ActionPoints>() {
+        Ok(points) => points.can_afford_ability(ability.actions),
+        Err(_) => budget.remaining_attacks >= ability.actions,
+    };
+
+    if !get_modifiers(world, user).can_attack {
+        Err(AbilityError::Disabled)
+    } else if !can_afford {
+        Err(AbilityError::NoActionsLeft)
+    } else if !abilities.is_ready(index) {
+        Err(AbilityError::OnCooldown)
+    } else {
+        Ok(ability)
+    }
+}
+
+/// Returns the units affected when the user uses the ability on the target hexagon.
+/// Allies are the units of allied players, enemies the units of hostile players.
+pub fn get_ability_targets<S: EntityStore>(
+    world: &S,
+    user: Entity,
+    ability: &Ability,
+    target: &Hexagon,
+    diplomacy: &Diplomacy,
+) -> Result<Vec<Entity>, AbilityError> {
+    let (user_hexagon, user_player) = match world.entry_ref(user) {
+        Err(_) => return Err(AbilityError::NotFound),
+        Ok(entry) => match entry.get_component::<Hexagon>() {
+            Err(_) => return Err(AbilityError::NotFound),
+            Ok(hexagon) => (*hexagon, entry.get_component::<Player>().ok().copied()),
+        },
+    };
+    if !ability.is_in_range(user_hexagon.distance_to(target)) {
+        return Err(AbilityError::InvalidTarget);
+    }
+
+    let units_at = |hexagon: &Hexagon| -> Vec<(Entity, Option<Player>)> {
+        get_entities_at_hexagon(hexagon, world)
+            .into_iter()
+            .filter_map(|entity| {
+                let entry = world.entry_ref(entity).ok()?;
+                entry.get_component::<Unit>().ok()?;
+                Some((entity, entry.get_component::<Player>().ok().copied()))
+            })
+            .collect()
+    };
+
+    let targets: Vec<Entity> = match ability.target {
+        AbilityTarget::Own => {
+            if *target == user_hexagon {
+                vec![user]
+            } else {
+                Vec::new()
+            }
+        }
+        AbilityTarget::Ally => units_at(target)
+            .into_iter()
+            .filter(|(_, player)| {
+                matches!((user_player, player), (Some(user), Some(other))
+                    if diplomacy.is_allied(user.0, other.0))
+            })
+            .map(|(entity, _)| entity)
+            .collect(),
+        AbilityTarget::Enemy => units_at(target)
+            .into_iter()
+            .filter(|(_, player)| {
+                matches!((user_player, player), (Some(user), Some(other))
+                    if diplomacy.is_hostile(user.0, other.0))
+            })
+            .map(|(entity, _)| entity)
+            .collect(),
+        AbilityTarget::Hexagon => {
+            return Ok(units_at(target)
+                .into_iter()
+                .map(|(entity, _)| entity)
+                .collect())
+        }
+        AbilityTarget::Area(radius) => {
+            return Ok(create_grid(radius.max(0) as u32)
+                .iter()
+                .flat_map(|offset| units_at(&(*offset + *target)))
+                .map(|(entity, _)| entity)
+                .collect())
+        }
+    };
+
+    if targets.is_empty() {
+        Err(AbilityError::InvalidTarget)
+    } else {
+        Ok(targets)
+    }
+}
+
+pub fn is_valid_ability_target<S: EntityStore>(
+    world: &S,
+    user: Entity,
+    index: usize,
+    target: &Hexagon,
+    diplomacy: &Diplomacy,
+) -> bool {
+    get_usable_ability(world, user, index)
+        .and_then(|ability| get_ability_targets(world, user, &ability, target, diplomacy))
+        .is_ok()
+}
+
+pub fn use_ability(
+    world: &mut World,
+    user: Entity,
+    index: usize,
+    target: &Hexagon,
+    rules: &Rules,
+    diplomacy: &Diplomacy,
+) -> Result<(), AbilityError> {
+    let ability = get_usable_ability(world, user, index)?;
+    let targets = get_ability_targets(world, user, &ability, target, diplomacy)?;
+
+    for target in targets {
+        let mut entry = match world.entry(target) {
+            None => continue,
+            Some(entry) => entry,
+        };
+        match ability.effect {
+            AbilityEffect::RestoreIntegrity(amount) => {
+                if let Ok(health) = entry.get_component_mut::<Health>() {
+                    health.heal(amount);
+                }
+            }
+            AbilityEffect::ApplyStatus(kind, turns) => {
+                match entry.get_component_mut::<StatusEffects>() {
+                    Ok(effects) => effects.add(kind, turns),
+                    Err(_) => {
+                        let mut effects = StatusEffects::new();
+                        effects.add(kind, turns);
+                        entry.add_component(effects);
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(mut entry) = world.entry(user) {
+        let points = entry
+            .get_component_mut::<ActionPoints>()
+            .ok()
+            .map(|points| {
+                points.spend_ability(ability.actions);
+                *points
+            });
+        if let Ok(budget) = entry.get_component_mut::<ActionBudget>() {
+            match points {
+                Some(points) => points.limit_attacks(budget),
+                None => budget.remaining_attacks -= ability.actions,
+            }
+        }
+        if let Ok(mobility) = entry.get_component_mut::<Mobility>() {
+            if rules.ability_ends_movement {
+                mobility.remaining_range = 0;
+            } else if let Some(points) = points {
+                points.limit_movement(mobility);
+            }
+        }
+        if let Ok(abilities) = entry.get_component_mut::<Abilities>() {
+            abilities.start_cooldown(index);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::action_points::ActionCosts;
+    use crate::components::status_effects::StatusEffectKind;
+    use crate::diplomacy::Relation;
+    use crate::legion::add_unit;
+    use legion::WorldOptions;
+
+    /// The components of a damaged unit with the abilities
+    fn with_abilities(abilities: &[&str]) -> (Health, ActionBudget, Abilities) {
+        (
+            Health {
+                integrity: 5,
+                ..Health::new(10, 0)
+            },
+            ActionBudget::new(1, 1),
+            Abilities::from_names(abilities),
+        )
+    }
+
+    #[test]
+    fn use_ability_applies_effect_and_costs() {
+        let mut world = World::new(WorldOptions::default());
+        let user = add_unit(&mut world, 0, Hexagon::zero(), with_abilities(&["Heal"]));
+        let ally = add_unit(&mut world, 0, Hexagon::new_axial(1, 0), with_abilities(&[]));
+
+        assert!(use_ability(
+            &mut world,
+            user,
+            0,
+            &Hexagon::new_axial(1, 0),
+            &Rules::default(),
+            &Diplomacy::new()
+        )
+        .is_ok());
+
+        let entry = world.entry(ally).unwrap();
+        assert_eq!(entry.get_component::<Health>().unwrap().integrity, 10);
+        let entry = world.entry(user).unwrap();
+        assert_eq!(
+            entry
+                .get_component::<ActionBudget>()
+                .unwrap()
+                .remaining_attacks,
+            0
+        );
+        assert!(!entry.get_component::<Abilities>().unwrap().is_ready(0));
+    }
+
+    #[test]
+    fn ally_ability_can_not_target_enemies() {
+        let mut world = World::new(WorldOptions::default());
+        let user = add_unit(&mut world, 0, Hexagon::zero(), with_abilities(&["Heal"]));
+        add_unit(&mut world, 1, Hexagon::new_axial(1, 0), with_abilities(&[]));
+        add_unit(&mut world, 2, Hexagon::new_axial(0, 1), with_abilities(&[]));
+        let mut diplomacy = Diplomacy::new();
+        diplomacy.set_team(2, 0);
+
+        assert!(!is_valid_ability_target(
+            &world,
+            user,
+            0,
+            &Hexagon::new_axial(1, 0),
+            &diplomacy
+        ));
+        assert!(is_valid_ability_target(
+            &world,
+            user,
+            0,
+            &Hexagon::zero(),
+            &diplomacy
+        ));
+        assert!(is_valid_ability_target(
+            &world,
+            user,
+            0,
+            &Hexagon::new_axial(0, 1),
+            &diplomacy
+        ));
+    }
+
+    #[test]
+    fn enemy_ability_only_targets_hostile_players() {
+        let mut world = World::new(WorldOptions::default());
+        let ability: Ability =
+            "Mark: actions=1 cooldown=1 target=enemy range=1:1 status=Suppressed:1"
+                .parse()
+                .unwrap();
+        let user = add_unit(
+            &mut world,
+            0,
+            Hexagon::zero(),
+            (ActionBudget::new(1, 1), Abilities::new(vec![ability])),
+        );
+        add_unit(&mut world, 1, Hexagon::new_axial(1, 0), with_abilities(&[]));
+        add_unit(&mut world, 2, Hexagon::new_axial(0, 1), with_abilities(&[]));
+        add_unit(
+            &mut world,
+            3,
+            Hexagon::new_axial(-1, 0),
+            with_abilities(&[]),
+        );
+        let mut diplomacy = Diplomacy::new();
+        diplomacy.set_team(2, 0);
+        diplomacy.set_relation(0, 3, Relation::Neutral);
+
+        let is_valid =
+            |target: Hexagon| is_valid_ability_target(&world, user, 0, &target, &diplomacy);
+        assert!(is_valid(Hexagon::new_axial(1, 0)));
+        assert!(!is_valid(Hexagon::new_axial(0, 1)));
+        assert!(!is_valid(Hexagon::new_axial(-1, 0)));
+    }
+
+    #[test]
+    fn own_ability_only_targets_the_user() {
+        let mut world = World::new(WorldOptions::default());
+        let user = add_unit(&mut world, 0, Hexagon::zero(), with_abilities(&["Dig in"]));
+
+        assert!(!is_valid_ability_target(
+            &world,
+            user,
+            0,
+            &Hexagon::new_axial(0, 1),
+            &Diplomacy::new()
+        ));
+        assert!(use_ability(
+            &mut world,
+            user,
+            0,
+            &Hexagon::zero(),
+            &Rules::default(),
+            &Diplomacy::new()
+        )
+        .is_ok());
+        let entry = world.entry(user).unwrap();
+        assert!(entry
+            .get_component::<StatusEffects>()
+            .unwrap()
+            .has(StatusEffectKind::Entrenched));
+    }
+
+    #[test]
+    fn area_ability_affects_all_units_in_radius() {
+        let mut world = World::new(WorldOptions::default());
+        let user = add_unit(
+            &mut world,
+            0,
+            Hexagon::zero(),
+            with_abilities(&["Smoke screen"]),
+        );
+        let first = add_unit(&mut world, 1, Hexagon::new_axial(3, 0), with_abilities(&[]));
+        let second = add_unit(&mut world, 0, Hexagon::new_axial(2, 0), with_abilities(&[]));
+        let outside = add_unit(&mut world, 1, Hexagon::new_axial(5, 0), with_abilities(&[]));
+
+        let ability = Ability::from_name("Smoke screen").unwrap();
+        let targets = get_ability_targets(
+            &world,
+            user,
+            &ability,
+            &Hexagon::new_axial(3, 0),
+            &Diplomacy::new(),
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(targets.len(), 2);
+        assert!(targets.contains(&first));
+        assert!(targets.contains(&second));
+        assert!(!targets.contains(&outside));
+    }
+
+    #[test]
+    fn use_ability_spends_action_points() {
+        let mut world = World::new(WorldOptions::default());
+        let user = add_unit(&mut world, 0, Hexagon::zero(), with_abilities(&["Dig in"]));
+        let mut entry = world.entry(user).unwrap();
+        entry.add_component(Mobility::new(4, 4));
+        entry.add_component(ActionPoints::new(
+            4,
+            ActionCosts {
+                move_hexagon: 1,
+                attack: 2,
+                ability: 1,
+            },
+        ));
+
+        assert!(use_ability(
+            &mut world,
+            user,
+            0,
+            &Hexagon::zero(),
+            &Rules::default(),
+            &Diplomacy::new()
+        )
+        .is_ok());
+
+        let entry = world.entry(user).unwrap();
+        assert_eq!(
+            entry
+                .get_component::<ActionPoints>()
+                .unwrap()
+                .remaining_points,
+            3
+        );
+        assert_eq!(
+            entry.get_component::<Mobility>().unwrap().remaining_range,
+            3
+        );
+        assert_eq!(
+            entry
+                .get_component::<ActionBudget>()
+                .unwrap()
+                .remaining_attacks,
+            1
+        );
+    }
+
+    #[test]
+    fn use_ability_ends_movement_if_the_rules_say_so() {
+        let mut world = World::new(WorldOptions::default());
+        let user = add_unit(&mut world, 0, Hexagon::zero(), with_abilities(&["Repair"]));
+        world
+            .entry(user)
+            .unwrap()
+            .add_component(Mobility::new(4, 4));
+        let rules = Rules {
+            ability_ends_movement: true,
+            ..Rules::default()
+        };
+
+        assert!(use_ability(
+            &mut world,
+            user,
+            0,
+            &Hexagon::zero(),
+            &rules,
+            &Diplomacy::new()
+        )
+        .is_ok());
+
+        let entry = world.entry(user).unwrap();
+        assert_eq!(
+            entry.get_component::<Mobility>().unwrap().remaining_range,
+            0
+        );
+    }
+
+    #[test]
+    fn get_usable_ability_fails_without_actions() {
+        let mut world = World::new(WorldOptions::default());
+        let user = add_unit(&mut world, 0, Hexagon::zero(), with_abilities(&["Dig in"]));
+        world
+            .entry(user)
+            .unwrap()
+            .get_component_mut::<ActionBudget>()
+            .unwrap()
+            .remaining_attacks = 0;
+
+        assert!(matches!(
+            get_usable_ability(&world, user, 0),
+            Err(AbilityError::NoActionsLeft)
+        ));
+    }
+}
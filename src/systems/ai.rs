@@ -0,0 +1,505 @@
+use crate::components::health::Health;
+use crate::components::hexagon::Hexagon;
+use crate::components::mobility::{CanMove, Mobility};
+use crate::components::objective::Objective;
+use crate::components::player::Player;
+use crate::components::structure::Structure;
+use crate::components::unit::Unit;
+use crate::components::weapon::Weapons;
+use crate::game_state::GameState;
+use crate::systems::combat::{get_attacker, get_defender, get_modifiers};
+use crate::systems::hexgrid::{find_path, get_reachable_hexagons, TerrainMap};
+use crate::systems::influence::{get_hostile_threat_map, get_influence_map, ThreatMap};
+use crate::systems::structures::is_sight_blocked;
+use legion::{Entity, EntityStore, IntoQuery};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard]
+            .iter()
+            .copied()
+            .find(|difficulty| difficulty.get_name() == name)
+    }
+
+    /// Units retreat once their integrity drops below this percentage of the maximum
+    pub fn get_retreat_threshold(&self) -> i32 {
+        match self {
+            Difficulty::Easy => 0,
+            Difficulty::Normal => 30,
+            Difficulty::Hard => 50,
+        }
+    }
+
+    /// Whether targets are chosen by the forecast damage instead of by distance
+    pub fn uses_forecast(&self) -> bool {
+        !matches!(self, Difficulty::Easy)
+    }
+
+    /// Whether targets that the attack destroys are preferred
+    pub fn prefers_kills(&self) -> bool {
+        matches!(self, Difficulty::Hard)
+    }
+}
+
+/// An action of the AI, the same a human player takes by clicking
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AiAction {
+    Attack(Entity, Entity),
+    Move(Entity, Vec<Hexagon>),
+}
+
+/// Returns the next action of the player, or None once their turn is over. Units attack
+/// the best target in range, then retreat if they are damaged or else close in on the
+/// nearest visible enemy, or on an objective if no enemy is visible. Retreats prefer
+/// hexagons the visible enemies threaten less, approaches hexagons the side of the player
+/// controls.
+pub fn plan_action<S: EntityStore>(
+    world: &S,
+    state: &GameState,
+    player: usize,
+    difficulty: Difficulty,
+    terrain_map: &TerrainMap,
+) -> Option<AiAction> {
+    let units: Vec<(Entity, Hexagon)> = <(Entity, &Unit, &Player, &Hexagon)>::query()
+        .iter(world)
+        .filter(|(_, _, owner, _)| owner.0 == player)
+        .map(|(entity, _, _, hexagon)| (*entity, *hexagon))
+        .collect();
+    let threat = get_hostile_threat_map(world, state, player, terrain_map);
+    let influence = get_influence_map(world, state, player, &threat, terrain_map);
+
+    for (entity, hexagon) in units {
+        if let Some(target) = get_best_target(world, state, player, difficulty, entity, &hexagon) {
+            return Some(AiAction::Attack(entity, target));
+        }
+        let range = match get_movement_range(world, entity) {
+            None => continue,
+            Some(range) => range,
+        };
+        let destination = if is_damaged(world, entity, difficulty) {
+            get_retreat(world, state, player, &hexagon, range, &threat, terrain_map)
+        } else {
+            get_approach(
+                world,
+                state,
+                player,
+                entity,
+                &hexagon,
+                range,
+                &influence,
+                terrain_map,
+            )
+        };
+        if let Some(destination) = destination {
+            let path = find_path(&hexagon, &destination, world, terrain_map);
+            if !path.is_empty() {
+                return Some(AiAction::Move(entity, path));
+            }
+        }
+    }
+    None
+}
+
+/// Returns the target the unit attacks, if it can damage any
+fn get_best_target<S: EntityStore>(
+    world: &S,
+    state: &GameState,
+    player: usize,
+    difficulty: Difficulty,
+    entity: Entity,
+    hexagon: &Hexagon,
+) -> Option<Entity> {
+    get_targets(world, state, player)
+        .into_iter()
+        .filter(|(_, target_hexagon)| {
+            state.is_hexagon_visible(target_hexagon)
+                && !is_sight_blocked(world, hexagon, target_hexagon)
+        })
+        .filter_map(|(target, target_hexagon)| {
+            let attacker = get_attacker(world, entity, target)?;
+            let defender = get_defender(world, target)?;
+            let distance = hexagon.distance_to(&target_hexagon);
+            if !attacker
+                .weapon
+                .is_in_attack_range(distance, &attacker.modifiers)
+                || attacker.attack(&defender).is_err()
+            {
+                return None;
+            }
+            let damage = attacker.forecast(&defender);
+            if damage <= 0 {
+                return None;
+            }
+            let kills = difficulty.prefers_kills() && damage >= defender.health.integrity;
+            let score = if difficulty.uses_forecast() {
+                damage
+            } else {
+                -distance
+            };
+            Some((target, (kills, score)))
+        })
+        .max_by_key(|(_, score)| *score)
+        .map(|(target, _)| target)
+}
+
+/// Returns the hostile units and the structures of hostile players with their hexagons
+fn get_targets<S: EntityStore>(
+    world: &S,
+    state: &GameState,
+    player: usize,
+) -> Vec<(Entity, Hexagon)> {
+    <(
+        Entity,
+        &Player,
+        &Hexagon,
+        &Health,
+        Option<&Unit>,
+        Option<&Structure>,
+    )>::query()
+    .iter(world)
+    .filter(|(_, owner, _, _, unit, structure)| {
+        (unit.is_some() || structure.is_some()) && state.diplomacy.is_hostile(player, owner.0)
+    })
+    .map(|(entity, _, hexagon, _, _, _)| (*entity, *hexagon))
+    .collect()
+}
+
+/// Returns the range the unit can still move, if it can move at all
+fn get_movement_range<S: EntityStore>(world: &S, entity: Entity) -> Option<i32> {
+    let mobility = *world
+        .entry_ref(entity)
+        .ok()?
+        .get_component::<Mobility>()
+        .ok()?;
+    match mobility.is_in_movement_range(1, &get_modifiers(world, entity)) {
+        CanMove::Yes(_) => Some(mobility.remaining_range),
+        CanMove::No => None,
+    }
+}
+
+fn is_damaged<S: EntityStore>(world: &S, entity: Entity, difficulty: Difficulty) -> bool {
+    match world
+        .entry_ref(entity)
+        .ok()
+        .and_then(|entry| entry.get_component::<Health>().ok().copied())
+    {
+        None => false,
+        Some(health) => {
+            health.integrity * 100 < health.max_integrity * difficulty.get_retreat_threshold()
+        }
+    }
+}
+
+/// Returns the reachable hexagon least threatened by the visible enemies, and furthest
+/// away from them among those, if it is safer than the current one
+fn get_retreat<S: EntityStore>(
+    world: &S,
+    state: &GameState,
+    player: usize,
+    hexagon: &Hexagon,
+    range: i32,
+    threat: &ThreatMap,
+    terrain_map: &TerrainMap,
+) -> Option<Hexagon> {
+    let threats: Vec<Hexagon> = get_targets(world, state, player)
+        .into_iter()
+        .map(|(_, hexagon)| hexagon)
+        .filter(|hexagon| state.is_hexagon_visible(hexagon))
+        .collect();
+    let danger = |hexagon: &Hexagon| {
+        let distance = threats
+            .iter()
+            .map(|threat| threat.distance_to(hexagon))
+            .min()?;
+        Some((threat.get(hexagon).copied().unwrap_or(0), -distance))
+    };
+    let current = danger(hexagon)?;
+    get_reachable_hexagons(hexagon, range, world, terrain_map)
+        .into_iter()
+        .filter_map(|(reachable, steps)| Some((reachable, (danger(&reachable)?, steps))))
+        .filter(|(_, (danger, _))| *danger < current)
+        .min_by_key(|(_, score)| *score)
+        .map(|(reachable, _)| reachable)
+}
+
+/// Returns the reachable hexagon from which the unit gets closest to attacking the nearest
+/// visible enemy, or to the nearest objective not held by an ally, if it gets closer than
+/// from the current one. Among those, the one with the most influence of the side of the
+/// player is preferred.
+#[allow(clippy::too_many_arguments)]
+fn get_approach<S: EntityStore>(
+    world: &S,
+    state: &GameState,
+    player: usize,
+    entity: Entity,
+    hexagon: &Hexagon,
+    range: i32,
+    influence: &ThreatMap,
+    terrain_map: &TerrainMap,
+) -> Option<Hexagon> {
+    let weapons = world
+        .entry_ref(entity)
+        .ok()?
+        .get_component::<Weapons>()
+        .ok()?
+        .clone();
+    let modifiers = get_modifiers(world, entity);
+    let min_range = weapons
+        .weapons
+        .iter()
+        .map(|weapon| weapon.min_attack_range)
+        .min()?;
+    let max_range = weapons
+        .weapons
+        .iter()
+        .map(|weapon| weapon.get_max_attack_range(&modifiers))
+        .max()?;
+
+    let mut goals: Vec<Hexagon> = get_targets(world, state, player)
+        .into_iter()
+        .map(|(_, hexagon)| hexagon)
+        .filter(|hexagon| state.is_hexagon_visible(hexagon))
+        .collect();
+    let (min_range, max_range) = if goals.is_empty() {
+        goals = <(&Objective, &Hexagon, Option<&Player>)>::query()
+            .iter(world)
+            .filter(|(_, _, owner)| match owner {
+                None => true,
+                Some(owner) => !state.diplomacy.is_allied(player, owner.0),
+            })
+            .map(|(_, hexagon, _)| *hexagon)
+            .collect();
+        (0, 0)
+    } else {
+        (min_range, max_range)
+    };
+    // How many steps the hexagon is away from a position to attack or capture a goal from
+    let shortfall = |hexagon: &Hexagon| {
+        goals
+            .iter()
+            .map(|goal| {
+                let distance = goal.distance_to(hexagon);
+                (min_range - distance).max(distance - max_range).max(0)
+            })
+            .min()
+    };
+    let current = shortfall(hexagon)?;
+    get_reachable_hexagons(hexagon, range, world, terrain_map)
+        .into_iter()
+        .filter_map(|(reachable, steps)| {
+            let exposure = -influence.get(&reachable).copied().unwrap_or(0);
+            Some((reachable, (shortfall(&reachable)?, exposure, steps)))
+        })
+        .filter(|(_, (shortfall, _, _))| *shortfall < current)
+        .min_by_key(|(_, score)| *score)
+        .map(|(reachable, _)| reachable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::action_budget::ActionBudget;
+    use crate::components::field::Terrain;
+    use crate::components::objective::ObjectiveKind;
+    use crate::components::weapon::Weapon;
+    use crate::legion::add_unit;
+    use crate::systems::hexgrid::create_grid;
+    use legion::{World, WorldOptions};
+
+    fn create_terrain_map() -> TerrainMap {
+        let mut terrain_map = TerrainMap::default();
+        for hexagon in create_grid(6) {
+            terrain_map.0.insert(hexagon, Terrain::Plains);
+        }
+        terrain_map
+    }
+
+    /// The components of a unit with a cannon and the health
+    fn armed(health: Health) -> (Health, Mobility, Weapons, ActionBudget) {
+        (
+            health,
+            Mobility::new(3, 3),
+            Weapons::new(vec![Weapon::new("Cannon", 5, 2, 1)]),
+            ActionBudget::new(1, 1),
+        )
+    }
+
+    #[test]
+    fn plan_action_attacks_the_best_target_in_range() {
+        let mut world = World::new(WorldOptions::default());
+        let terrain_map = create_terrain_map();
+        let state = GameState::new();
+        let unit = add_unit(&mut world, 0, Hexagon::zero(), armed(Health::new(10, 0)));
+        let near = add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(1, 0),
+            armed(Health::new(10, 3)),
+        );
+        let far = add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(2, 0),
+            armed(Health::new(10, 1)),
+        );
+        let weak = add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(0, 2),
+            armed(Health::new(3, 2)),
+        );
+
+        let plan = |difficulty| plan_action(&world, &state, 0, difficulty, &terrain_map);
+        assert_eq!(plan(Difficulty::Easy), Some(AiAction::Attack(unit, near)));
+        assert_eq!(plan(Difficulty::Normal), Some(AiAction::Attack(unit, far)));
+        assert_eq!(plan(Difficulty::Hard), Some(AiAction::Attack(unit, weak)));
+    }
+
+    #[test]
+    fn plan_action_moves_into_attack_range() {
+        let mut world = World::new(WorldOptions::default());
+        let terrain_map = create_terrain_map();
+        let state = GameState::new();
+        let unit = add_unit(&mut world, 0, Hexagon::zero(), armed(Health::new(10, 0)));
+        let enemy = Hexagon::new_axial(5, 0);
+        add_unit(&mut world, 1, enemy, armed(Health::new(10, 0)));
+
+        match plan_action(&world, &state, 0, Difficulty::Normal, &terrain_map) {
+            Some(AiAction::Move(entity, path)) => {
+                assert_eq!(entity, unit);
+                assert_eq!(path.len(), 3);
+                assert_eq!(path.last().unwrap().distance_to(&enemy), 2);
+            }
+            action => panic!("Expected a move, got {:?}", action),
+        }
+    }
+
+    #[test]
+    fn plan_action_approaches_under_the_cover_of_allies() {
+        let mut world = World::new(WorldOptions::default());
+        let terrain_map = create_terrain_map();
+        let mut state = GameState::new();
+        let unit = add_unit(&mut world, 0, Hexagon::zero(), armed(Health::new(10, 0)));
+        add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(4, 0),
+            armed(Health::new(10, 0)),
+        );
+        let plan =
+            |state: &GameState| plan_action(&world, state, 0, Difficulty::Normal, &terrain_map);
+        assert_eq!(
+            plan(&state),
+            Some(AiAction::Move(
+                unit,
+                vec![Hexagon::new_axial(1, 0), Hexagon::new_axial(2, 0)]
+            ))
+        );
+
+        // The ally covers another hexagon in attack range of the enemy
+        add_unit(
+            &mut world,
+            2,
+            Hexagon::new_axial(3, -3),
+            (
+                Mobility::new(0, 0),
+                Weapons::new(vec![Weapon::new("Cannon", 5, 2, 1)]),
+            ),
+        );
+        state.diplomacy.set_team(2, 0);
+        let plan =
+            |state: &GameState| plan_action(&world, state, 0, Difficulty::Normal, &terrain_map);
+        assert!(matches!(
+            plan(&state),
+            Some(AiAction::Move(_, path)) if path.last() == Some(&Hexagon::new_axial(3, -1))
+        ));
+    }
+
+    #[test]
+    fn plan_action_retreats_damaged_units() {
+        let mut world = World::new(WorldOptions::default());
+        let terrain_map = create_terrain_map();
+        let state = GameState::new();
+        let unit = add_unit(&mut world, 0, Hexagon::zero(), armed(Health::new(10, 0)));
+        world.entry(unit).unwrap().add_component(Health {
+            integrity: 2,
+            ..Health::new(10, 0)
+        });
+        world
+            .entry(unit)
+            .unwrap()
+            .add_component(ActionBudget::new(1, 0));
+        let enemy = Hexagon::new_axial(3, 0);
+        add_unit(&mut world, 1, enemy, armed(Health::new(10, 0)));
+
+        match plan_action(&world, &state, 0, Difficulty::Normal, &terrain_map) {
+            Some(AiAction::Move(_, path)) => {
+                assert_eq!(path.last().unwrap().distance_to(&enemy), 6)
+            }
+            action => panic!("Expected a retreat, got {:?}", action),
+        }
+        assert!(matches!(
+            plan_action(&world, &state, 0, Difficulty::Easy, &terrain_map),
+            Some(AiAction::Move(_, path)) if path.last().unwrap().distance_to(&enemy) == 2
+        ));
+    }
+
+    #[test]
+    fn plan_action_heads_for_objectives_and_ends_the_turn() {
+        let mut world = World::new(WorldOptions::default());
+        let terrain_map = create_terrain_map();
+        let mut state = GameState::new();
+        let unit = add_unit(&mut world, 0, Hexagon::zero(), armed(Health::new(10, 0)));
+        let objective = Hexagon::new_axial(0, 2);
+        world.push((Objective::new(ObjectiveKind::Village), objective));
+
+        assert_eq!(
+            plan_action(&world, &state, 0, Difficulty::Normal, &terrain_map),
+            Some(AiAction::Move(
+                unit,
+                vec![Hexagon::new_axial(0, 1), objective]
+            ))
+        );
+
+        world
+            .entry(unit)
+            .unwrap()
+            .add_component(Mobility::new(3, 0));
+        let enemy = add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(3, 0),
+            armed(Health::new(10, 0)),
+        );
+        state.visible_hexagons = Some(vec![Hexagon::zero()].into_iter().collect());
+        assert_eq!(
+            plan_action(&world, &state, 0, Difficulty::Normal, &terrain_map),
+            None
+        );
+
+        world
+            .entry(unit)
+            .unwrap()
+            .add_component(Hexagon::new_axial(1, 0));
+        state.visible_hexagons = None;
+        assert_eq!(
+            plan_action(&world, &state, 0, Difficulty::Normal, &terrain_map),
+            Some(AiAction::Attack(unit, enemy))
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use crate::components::action_budget::ActionBudget;
+use crate::components::action_points::ActionPoints;
+use crate::components::experience::Experience;
+use crate::components::health::Health;
+use crate::components::hexagon::Hexagon;
+use crate::components::morale::Morale;
+use crate::components::status_effects::{StatModifiers, StatusEffects};
+use crate::components::unit::{Attacker, Defender};
+use crate::components::weapon::Weapons;
+use crate::systems::structures::get_structure_defense;
+use legion::{Entity, EntityStore};
+
+/// Returns the modifiers of the status effects, the rank and the morale of the entity
+pub fn get_modifiers<S: EntityStore>(world: &S, entity: Entity) -> StatModifiers {
+    match world.entry_ref(entity) {
+        Err(_) => StatModifiers::default(),
+        Ok(entry) => combine_modifiers(
+            entry.get_component::<StatusEffects>().ok(),
+            entry.get_component::<Experience>().ok(),
+            entry.get_component::<Morale>().ok(),
+        ),
+    }
+}
+
+/// Returns the modifiers of the components, for queries that already fetched them
+pub fn combine_modifiers(
+    effects: Option<&StatusEffects>,
+    experience: Option<&Experience>,
+    morale: Option<&Morale>,
+) -> StatModifiers {
+    let mut modifiers = effects
+        .map(|effects| effects.get_modifiers())
+        .unwrap_or_default();
+    if let Some(experience) = experience {
+        modifiers = modifiers.combine(&experience.get_modifiers());
+    }
+    if let Some(morale) = morale {
+        modifiers = modifiers.combine(&morale.get_modifiers());
+    }
+    modifiers
+}
+
+/// Returns the attacking side of the entity against the target, if it is armed.
+/// Uses the selected weapon if it reaches the target or, without a selection, the usable
+/// weapon with the highest forecast damage against the target.
+pub fn get_attacker<S: EntityStore>(world: &S, entity: Entity, target: Entity) -> Option<Attacker> {
+    let entry = world.entry_ref(entity).ok()?;
+    let weapons = entry.get_component::<Weapons>().ok()?;
+    let budget = *entry.get_component::<ActionBudget>().ok()?;
+    let points = entry.get_component::<ActionPoints>().ok().copied();
+    let modifiers = get_modifiers(world, entity);
+    let attacker = |index: usize| {
+        weapons.get(index).map(|weapon| Attacker {
+            points,
+            ..Attacker::new(weapon.clone(), index, budget, modifiers)
+        })
+    };
+
+    let distance = match (
+        entry.get_component::<Hexagon>(),
+        world
+            .entry_ref(target)
+            .ok()
+            .and_then(|target| target.get_component::<Hexagon>().ok().copied()),
+    ) {
+        (Ok(hexagon), Some(target_hexagon)) => Some(hexagon.distance_to(&target_hexagon)),
+        _ => None,
+    };
+
+    if let Some(index) = weapons.selected {
+        if matches!((distance, weapons.get(index)), (Some(distance), Some(weapon))
+            if !weapon.is_in_attack_range(distance, &modifiers))
+        {
+            return None;
+        }
+        return attacker(index);
+    }
+
+    let distance = match distance {
+        None => return attacker(0),
+        Some(distance) => distance,
+    };
+    let defender = get_defender(world, target);
+    weapons
+        .get_usable(distance, &budget, &modifiers)
+        .into_iter()
+        .filter_map(attacker)
+        .max_by_key(|attacker| match &defender {
+            Some(defender) => attacker.forecast(defender),
+            None => attacker.weapon.get_damage(&modifiers),
+        })
+        .or_else(|| attacker(0))
+}
+
+/// Returns the defending side of the entity, if it can be damaged.
+/// Structures on the hexagon of the entity add to its armor.
+pub fn get_defender<S: EntityStore>(world: &S, entity: Entity) -> Option<Defender> {
+    let entry = world.entry_ref(entity).ok()?;
+    let health = *entry.get_component::<Health>().ok()?;
+    let mut modifiers = get_modifiers(world, entity);
+    modifiers.armor += get_structure_defense(world, entity);
+    Some(Defender::new(health, modifiers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::status_effects::StatusEffectKind;
+    use crate::components::structure::{Structure, StructureKind};
+    use crate::components::weapon::{DamageType, Weapon};
+    use legion::{World, WorldOptions};
+
+    #[test]
+    fn get_attacker_requires_weapon_and_action_budget() {
+        let mut world = World::new(WorldOptions::default());
+        let armed = *world
+            .extend(vec![(
+                Weapons::new(vec![Weapon::new("Test", 1, 1, 1)]),
+                ActionBudget::new(1, 1),
+            )])
+            .first()
+            .unwrap();
+        let unarmed = *world
+            .extend(vec![(ActionBudget::new(1, 1),)])
+            .first()
+            .unwrap();
+
+        assert!(get_attacker(&world, armed, unarmed).is_some());
+        assert!(get_attacker(&world, unarmed, armed).is_none());
+    }
+
+    fn add_armed_unit(world: &mut World) -> (Entity, Entity) {
+        let weapons = Weapons::new(vec![
+            Weapon::new("Cannon", 5, 2, 1),
+            Weapon {
+                damage_type: DamageType::Piercing,
+                ..Weapon::new("Rail gun", 4, 3, 1)
+            },
+            Weapon::new("Mortar", 9, 4, 3),
+        ]);
+        let attacker = *world
+            .extend(vec![(Hexagon::zero(), weapons, ActionBudget::new(1, 1))])
+            .first()
+            .unwrap();
+        let defender = *world
+            .extend(vec![(Hexagon::new_axial(2, 0), Health::new(10, 4))])
+            .first()
+            .unwrap();
+        (attacker, defender)
+    }
+
+    #[test]
+    fn get_attacker_picks_weapon_with_best_forecast_in_range() {
+        let mut world = World::new(WorldOptions::default());
+        let (attacker, defender) = add_armed_unit(&mut world);
+
+        let attacker = get_attacker(&world, attacker, defender).unwrap();
+        assert_eq!(attacker.weapon_index, 1);
+    }
+
+    #[test]
+    fn get_attacker_uses_selected_weapon() {
+        let mut world = World::new(WorldOptions::default());
+        let (attacker_entity, defender) = add_armed_unit(&mut world);
+        world
+            .entry(attacker_entity)
+            .unwrap()
+            .get_component_mut::<Weapons>()
+            .unwrap()
+            .selected = Some(0);
+
+        let attacker = get_attacker(&world, attacker_entity, defender).unwrap();
+        assert_eq!(attacker.weapon_index, 0);
+    }
+
+    #[test]
+    fn get_attacker_does_not_fire_selected_weapon_out_of_range() {
+        let mut world = World::new(WorldOptions::default());
+        let (attacker, defender) = add_armed_unit(&mut world);
+        world
+            .entry(attacker)
+            .unwrap()
+            .get_component_mut::<Weapons>()
+            .unwrap()
+            .selected = Some(2);
+
+        assert!(get_attacker(&world, attacker, defender).is_none());
+    }
+
+    #[test]
+    fn get_modifiers_includes_rank_bonus() {
+        let mut world = World::new(WorldOptions::default());
+        let mut effects = StatusEffects::new();
+        effects.add(StatusEffectKind::Suppressed, 1);
+        let experience = Experience {
+            points: 10,
+            ..Experience::default()
+        };
+        let entity = *world.extend(vec![(effects, experience)]).first().unwrap();
+
+        assert_eq!(get_modifiers(&world, entity).damage, -1);
+    }
+
+    #[test]
+    fn get_defender_includes_status_modifiers() {
+        let mut world = World::new(WorldOptions::default());
+        let mut effects = StatusEffects::new();
+        effects.add(StatusEffectKind::Entrenched, 1);
+        let entity = *world
+            .extend(vec![(Health::new(5, 1), effects)])
+            .first()
+            .unwrap();
+
+        let defender = get_defender(&world, entity).unwrap();
+        assert_eq!(defender.health.get_armor(&defender.modifiers), 3);
+    }
+
+    #[test]
+    fn get_defender_includes_structure_defense() {
+        let mut world = World::new(WorldOptions::default());
+        world.push((Hexagon::zero(), Structure::new(StructureKind::Bunker)));
+        let entity = world.push((Hexagon::zero(), Health::new(5, 1)));
+
+        let defender = get_defender(&world, entity).unwrap();
+        assert_eq!(defender.health.get_armor(&defender.modifiers), 3);
+    }
+}
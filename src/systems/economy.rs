@@ -0,0 +1,68 @@
+use crate::components::economy::{Income, Upkeep};
+use crate::components::objective::Objective;
+use crate::components::player::Player;
+use crate::economy::{Currency, Transaction};
+use legion::{EntityStore, IntoQuery};
+
+/// Returns the income of the entities of the player and the upkeep of their units,
+/// which are booked at the start of the turn of the player
+pub fn get_turn_transactions<S: EntityStore>(world: &S, player: usize) -> Vec<Transaction> {
+    let mut transactions: Vec<Transaction> = <(&Income, &Player, Option<&Objective>)>::query()
+        .iter(world)
+        .filter(|(_, owner, _)| owner.0 == player)
+        .map(|(income, _, objective)| {
+            let reason = match objective {
+                None => "Income",
+                Some(objective) => objective.kind.get_name(),
+            };
+            Transaction::new(income.currency, income.amount, reason)
+        })
+        .collect();
+
+    let upkeep: Vec<Upkeep> = <(&Upkeep, &Player)>::query()
+        .iter(world)
+        .filter(|(_, owner)| owner.0 == player)
+        .map(|(upkeep, _)| *upkeep)
+        .collect();
+    for currency in Currency::iter() {
+        let amount: i32 = upkeep
+            .iter()
+            .filter(|upkeep| upkeep.currency == currency)
+            .map(|upkeep| upkeep.amount)
+            .sum();
+        if amount != 0 {
+            transactions.push(Transaction::new(currency, -amount, "Upkeep"));
+        }
+    }
+    transactions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::objective::ObjectiveKind;
+    use legion::{World, WorldOptions};
+
+    #[test]
+    fn get_turn_transactions_books_income_and_upkeep_of_the_player() {
+        let mut world = World::new(WorldOptions::default());
+        world.push((
+            Income::new(Currency::Credits, 3),
+            Player(0),
+            Objective::new(ObjectiveKind::Village),
+        ));
+        world.push((Income::new(Currency::Fuel, 2), Player(0)));
+        world.push((Income::new(Currency::Credits, 5), Player(1)));
+        world.push((Income::new(Currency::Credits, 5),));
+        world.push((Upkeep::new(Currency::Credits, 1), Player(0)));
+        world.push((Upkeep::new(Currency::Credits, 2), Player(0)));
+        world.push((Upkeep::new(Currency::Fuel, 4), Player(1)));
+
+        let transactions = get_turn_transactions(&world, 0);
+
+        assert_eq!(transactions.len(), 3);
+        assert!(transactions.contains(&Transaction::new(Currency::Credits, 3, "Village")));
+        assert!(transactions.contains(&Transaction::new(Currency::Fuel, 2, "Income")));
+        assert!(transactions.contains(&Transaction::new(Currency::Credits, -3, "Upkeep")));
+    }
+}
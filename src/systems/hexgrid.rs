@@ -1,19 +1,56 @@
+use crate::components::attack_profile::AttackProfile;
+use crate::components::field::{Field, Terrain};
+use crate::components::health::Health;
 use crate::components::hexagon::Direction;
 use crate::components::hexagon::Hexagon;
 use crate::components::node_component::NodeComponent;
 use crate::components::player::Player;
+use crate::components::structure::Structure;
 use crate::components::unit::Unit;
+use crate::components::weapon::Weapons;
+use crate::diplomacy::Diplomacy;
+use crate::layout::Layout;
 use crate::legion::entity_has_component;
+use crate::systems::combat::get_modifiers;
+use crate::systems::structures::{is_passable_hexagon, is_sight_blocked};
+use crate::systems::transport::can_embark;
 use core::cmp::Reverse;
 use gdnative::api::Physics2DDirectSpaceState;
 use gdnative::prelude::*;
 use legion::{Entity, EntityStore, IntoQuery};
 use priority_queue::PriorityQueue;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 const GROUND_BIT: i64 = 0;
 const UNIT_BIT: i64 = 1;
 
+/// Lookup of the terrain of every hexagon that is part of the map.
+/// Hexagons that are not in the map are treated as impassable.
+#[derive(Clone, Debug, Default)]
+pub struct TerrainMap(pub HashMap<Hexagon, Terrain>);
+
+impl TerrainMap {
+    pub fn from_fields(fields: &[Field]) -> Self {
+        TerrainMap(
+            fields
+                .iter()
+                .map(|field| (field.location, field.terrain))
+                .collect(),
+        )
+    }
+
+    pub fn get_terrain(&self, hexagon: &Hexagon) -> Option<Terrain> {
+        self.0.get(hexagon).copied()
+    }
+
+    pub fn is_passable(&self, hexagon: &Hexagon) -> bool {
+        match self.get_terrain(hexagon) {
+            None => false,
+            Some(terrain) => terrain.is_passable(),
+        }
+    }
+}
+
 pub fn create_grid(radius: u32) -> Vec<Hexagon> {
     let radius = radius as i32;
     let mut field = Vec::new();
@@ -31,24 +68,18 @@ pub fn create_grid(radius: u32) -> Vec<Hexagon> {
     field
 }
 
-pub fn get_2d_position_from_hex(hex: &Hexagon, hexfield_size: f32) -> Vector2 {
-    let x = hexfield_size
-        * (3.0_f32.sqrt() * (hex.get_q() as f32) + 3.0_f32.sqrt() / 2.0 * (hex.get_r() as f32));
-    let y = hexfield_size * (3.0 / 2.0 * (hex.get_r() as f32));
-    Vector2::new(x, y)
+pub fn get_2d_position_from_hex(hex: &Hexagon, layout: &Layout) -> Vector2 {
+    layout.hex_to_pixel(hex)
 }
 
 pub fn get_neighbours(hexagon: &Hexagon) -> Vec<Hexagon> {
-    vec![
-        hexagon.get_neighbour(Direction::East),
-        hexagon.get_neighbour(Direction::NorthEast),
-        hexagon.get_neighbour(Direction::NorthWest),
-        hexagon.get_neighbour(Direction::West),
-        hexagon.get_neighbour(Direction::SouthWest),
-        hexagon.get_neighbour(Direction::SouthEast),
-    ]
+    Direction::iter()
+        .map(|direction| hexagon.get_neighbour(direction))
+        .collect()
 }
 
+/// Returns the entities on the hexagon. Units aboard a transport have no hexagon and are
+/// not included.
 pub fn get_entities_at_hexagon<S: EntityStore>(hexagon: &Hexagon, world: &S) -> Vec<Entity> {
     <&Hexagon>::query()
         .iter_chunks(world)
@@ -64,13 +95,90 @@ pub fn get_entities_at_hexagon<S: EntityStore>(hexagon: &Hexagon, world: &S) ->
         .collect()
 }
 
-pub fn find_path<S: EntityStore>(start: &Hexagon, target: &Hexagon, world: &S) -> Vec<Hexagon> {
-    match get_entities_at_hexagon(target, world)
+/// Whether a unit could be placed on the hexagon
+pub fn is_free_hexagon<S: EntityStore>(
+    world: &S,
+    hexagon: &Hexagon,
+    terrain_map: &TerrainMap,
+) -> bool {
+    is_passable_hexagon(world, hexagon, terrain_map)
+        && !get_entities_at_hexagon(hexagon, world)
+            .iter()
+            .any(|entity| entity_has_component::<Unit, S>(world, entity))
+}
+
+/// Returns the units and destructible structures that are hit when the attacker attacks
+/// the target hexagon, according to the attack profile of the attacker. Without friendly
+/// fire only entities of hostile players and neutral structures are hit.
+pub fn get_entities_in_attack_area<S: EntityStore>(
+    world: &S,
+    attacker: Entity,
+    target: &Hexagon,
+    diplomacy: &Diplomacy,
+) -> Vec<Entity> {
+    let (attacker_hexagon, attacker_player, profile) = match world.entry_ref(attacker) {
+        Err(_) => return Vec::new(),
+        Ok(entry) => {
+            let hexagon = match entry.get_component::<Hexagon>() {
+                Err(_) => return Vec::new(),
+                Ok(hexagon) => *hexagon,
+            };
+            let player = entry.get_component::<Player>().ok().copied();
+            let profile = entry
+                .get_component::<AttackProfile>()
+                .ok()
+                .copied()
+                .unwrap_or_default();
+            (hexagon, player, profile)
+        }
+    };
+
+    profile
+        .get_affected_hexagons(&attacker_hexagon, target)
         .iter()
-        .find(|entity| entity_has_component::<Unit, S>(world, entity))
-    {
-        None => {}
-        Some(_) => return Vec::new(),
+        .flat_map(|hexagon| get_entities_at_hexagon(hexagon, world))
+        .filter(|entity| *entity != attacker)
+        .filter(|entity| {
+            let entry = match world.entry_ref(*entity) {
+                Err(_) => return false,
+                Ok(entry) => entry,
+            };
+            let is_structure = entry.get_component::<Structure>().is_ok()
+                && entry.get_component::<Health>().is_ok();
+            if entry.get_component::<Unit>().is_err() && !is_structure {
+                return false;
+            }
+            profile.friendly_fire
+                || match (entry.get_component::<Player>(), attacker_player) {
+                    (Ok(player), Some(attacker_player)) => {
+                        diplomacy.is_hostile(attacker_player.0, player.0)
+                    }
+                    _ => true,
+                }
+        })
+        .collect()
+}
+
+/// Returns the path from the start to the target, without the start. Units and impassable
+/// hexagons block the path, except for a friendly transport with free space as the target
+/// of the unit at the start.
+pub fn find_path<S: EntityStore>(
+    start: &Hexagon,
+    target: &Hexagon,
+    world: &S,
+    terrain_map: &TerrainMap,
+) -> Vec<Hexagon> {
+    if !is_passable_hexagon(world, target, terrain_map) {
+        return Vec::new();
+    }
+    let mover = get_entities_at_hexagon(start, world)
+        .into_iter()
+        .find(|entity| entity_has_component::<Unit, S>(world, entity));
+    if get_entities_at_hexagon(target, world).iter().any(|entity| {
+        entity_has_component::<Unit, S>(world, entity)
+            && !matches!(mover, Some(mover) if can_embark(world, mover, *entity))
+    }) {
+        return Vec::new();
     }
     let mut frontier = PriorityQueue::new();
     frontier.push(*start, Reverse(0));
@@ -84,9 +192,13 @@ pub fn find_path<S: EntityStore>(start: &Hexagon, target: &Hexagon, world: &S) -
             break;
         }
         for next in get_neighbours(&current) {
-            if get_entities_at_hexagon(&next, world)
-                .iter()
-                .any(|entity| entity_has_component::<Unit, S>(world, entity))
+            if !is_passable_hexagon(world, &next, terrain_map) {
+                continue;
+            }
+            if next != *target
+                && get_entities_at_hexagon(&next, world)
+                    .iter()
+                    .any(|entity| entity_has_component::<Unit, S>(world, entity))
             {
                 continue;
             }
@@ -103,7 +215,7 @@ pub fn find_path<S: EntityStore>(start: &Hexagon, target: &Hexagon, world: &S) -
 
     let mut path = Vec::new();
 
-    let mut current = match came_from[target] {
+    let mut current = match came_from.get(target).copied().flatten() {
         None => return Vec::new(),
         Some(hexagon) => hexagon,
     };
@@ -117,14 +229,46 @@ pub fn find_path<S: EntityStore>(start: &Hexagon, target: &Hexagon, world: &S) -
     path
 }
 
+/// Returns the hexagons that can be reached from the start within the range, with the
+/// number of steps to each, including the start itself. Units and impassable hexagons
+/// block the way like they do for `find_path`.
+pub fn get_reachable_hexagons<S: EntityStore>(
+    start: &Hexagon,
+    range: i32,
+    world: &S,
+    terrain_map: &TerrainMap,
+) -> HashMap<Hexagon, i32> {
+    let mut reachable = HashMap::new();
+    reachable.insert(*start, 0);
+    let mut frontier = VecDeque::new();
+    frontier.push_back(*start);
+    while let Some(current) = frontier.pop_front() {
+        let steps = reachable[&current] + 1;
+        if steps > range {
+            continue;
+        }
+        for next in get_neighbours(&current) {
+            if reachable.contains_key(&next) || !is_free_hexagon(world, &next, terrain_map) {
+                continue;
+            }
+            reachable.insert(next, steps);
+            frontier.push_back(next);
+        }
+    }
+    reachable
+}
+
+/// Whether the selected entity can attack the target hexagon. Units of players that are
+/// not hostile can not be attacked.
 pub fn is_hexagon_visible_for_attack<S: EntityStore>(
     physic_state: &Ref<Physics2DDirectSpaceState>,
     legion_world: &S,
-    hexfield_size: f32,
+    layout: &Layout,
+    diplomacy: &Diplomacy,
     selected_entity: Entity,
     target_hexagon: Hexagon,
 ) -> bool {
-    let (selected_unit, selected_hexagon, select_unit_player) = {
+    let (selected_weapons, selected_hexagon, select_unit_player) = {
         let entry = legion_world.entry_ref(selected_entity).unwrap();
         let hexagon = match entry.get_component::<Hexagon>() {
             Err(_) => {
@@ -133,11 +277,11 @@ pub fn is_hexagon_visible_for_attack<S: EntityStore>(
             Ok(hexagon) => *hexagon,
         };
 
-        let unit = match entry.get_component::<Unit>() {
+        let weapons = match entry.get_component::<Weapons>() {
             Err(_) => {
                 return false;
             }
-            Ok(unit) => *unit,
+            Ok(weapons) => weapons.clone(),
         };
 
         let player = match entry.get_component::<Player>() {
@@ -147,9 +291,13 @@ pub fn is_hexagon_visible_for_attack<S: EntityStore>(
             Ok(player) => *player,
         };
 
-        (unit, hexagon, player)
+        (weapons, hexagon, player)
     };
-    if selected_unit.is_in_attack_range(selected_hexagon.distance_to(&target_hexagon)) {
+    let modifiers = get_modifiers(legion_world, selected_entity);
+    if selected_weapons
+        .is_in_attack_range(selected_hexagon.distance_to(&target_hexagon), &modifiers)
+        && !is_sight_blocked(legion_world, &selected_hexagon, &target_hexagon)
+    {
         let entities_at_target = get_entities_at_hexagon(&target_hexagon, legion_world);
         let mut target_entity = None;
         for entity in &entities_at_target {
@@ -168,21 +316,21 @@ pub fn is_hexagon_visible_for_attack<S: EntityStore>(
             }
         }
 
-        let same_player = match target_entity {
+        let is_friendly = match target_entity {
             None => false,
             Some(e) => match legion_world.entry_ref(*e) {
                 Err(_) => false,
                 Ok(e) => match e.get_component::<Player>() {
                     Err(_) => false,
-                    Ok(player) => *player == select_unit_player,
+                    Ok(player) => !diplomacy.is_hostile(select_unit_player.0, player.0),
                 },
             },
         };
 
-        if !same_player {
+        if !is_friendly {
             let physic_state = unsafe { physic_state.assume_safe() };
-            let self_position = get_2d_position_from_hex(&target_hexagon, hexfield_size);
-            let selected_position = get_2d_position_from_hex(&selected_hexagon, hexfield_size);
+            let self_position = get_2d_position_from_hex(&target_hexagon, layout);
+            let selected_position = get_2d_position_from_hex(&selected_hexagon, layout);
 
             let exclude = VariantArray::new();
 
@@ -222,7 +370,7 @@ pub fn is_hexagon_visible_for_attack<S: EntityStore>(
                 };
             }
 
-            let adjustment_vector = Vector2::new(hexfield_size / 8.0, hexfield_size / 8.0);
+            let adjustment_vector = Vector2::new(layout.size.x / 8.0, layout.size.y / 8.0);
             let result = physic_state.intersect_ray(
                 self_position + adjustment_vector,
                 selected_position,
@@ -253,23 +401,9 @@ pub fn is_hexagon_visible_for_attack<S: EntityStore>(
     }
 }
 
-pub fn calculate_hexagon_points(hexfield_size: f32) -> Vec<Vector2> {
-    let mut field_polygon = Vec::new();
-
-    let width = 3.0_f32.sqrt() * hexfield_size;
-    let height = 2.0 * hexfield_size;
-    let half_height = height / 2.0;
-    let quarter_height = height / 4.0;
-
-    let half_width = width / 2.0;
-
-    field_polygon.push(Vector2::new(-half_width, -quarter_height));
-    field_polygon.push(Vector2::new(0.0, -half_height));
-    field_polygon.push(Vector2::new(half_width, -quarter_height));
-    field_polygon.push(Vector2::new(half_width, quarter_height));
-    field_polygon.push(Vector2::new(0.0, half_height));
-    field_polygon.push(Vector2::new(-half_width, quarter_height));
-    field_polygon.push(Vector2::new(-half_width, -quarter_height));
+pub fn calculate_hexagon_points(layout: &Layout) -> Vec<Vector2> {
+    let mut field_polygon = layout.get_corner_offsets();
+    field_polygon.push(field_polygon[0]);
 
     field_polygon
 }
@@ -277,6 +411,10 @@ pub fn calculate_hexagon_points(hexfield_size: f32) -> Vec<Vector2> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::components::attack_profile::AttackShape;
+    use crate::components::structure::StructureKind;
+    use crate::components::transport::{CargoFate, Transport};
+    use crate::diplomacy::Relation;
     use legion::{World, WorldOptions};
 
     //noinspection DuplicatedCode
@@ -329,4 +467,242 @@ mod tests {
         }));
         assert_eq!(result.len(), 4);
     }
+
+    #[test]
+    fn get_entities_in_attack_area_ignores_friendly_units_without_friendly_fire() {
+        let mut world = World::new(WorldOptions::default());
+        let attacker = *world
+            .extend(vec![(
+                Hexagon::new_axial(0, 0),
+                Player(0),
+                Unit,
+                AttackProfile::new(AttackShape::Burst(1), false),
+            )])
+            .first()
+            .unwrap();
+        let enemy = *world
+            .extend(vec![(Hexagon::new_axial(3, 0), Player(1), Unit)])
+            .first()
+            .unwrap();
+        let second_enemy = *world
+            .extend(vec![(Hexagon::new_axial(2, 0), Player(1), Unit)])
+            .first()
+            .unwrap();
+        let friend = *world
+            .extend(vec![(Hexagon::new_axial(3, -1), Player(0), Unit)])
+            .first()
+            .unwrap();
+        world.extend(vec![(Hexagon::new_axial(5, 0), Player(1), Unit)]);
+
+        let result = get_entities_in_attack_area(
+            &world,
+            attacker,
+            &Hexagon::new_axial(3, 0),
+            &Diplomacy::new(),
+        );
+        assert_eq!(result.len(), 2);
+        assert!(result.contains(&enemy));
+        assert!(result.contains(&second_enemy));
+
+        world
+            .entry(attacker)
+            .unwrap()
+            .add_component(AttackProfile::new(AttackShape::Burst(1), true));
+        let result = get_entities_in_attack_area(
+            &world,
+            attacker,
+            &Hexagon::new_axial(3, 0),
+            &Diplomacy::new(),
+        );
+        assert_eq!(result.len(), 3);
+        assert!(result.contains(&friend));
+    }
+
+    #[test]
+    fn get_entities_in_attack_area_defaults_to_single_target() {
+        let mut world = World::new(WorldOptions::default());
+        let attacker = *world
+            .extend(vec![(Hexagon::new_axial(0, 0), Player(0), Unit)])
+            .first()
+            .unwrap();
+        let enemy = *world
+            .extend(vec![(Hexagon::new_axial(1, 0), Player(1), Unit)])
+            .first()
+            .unwrap();
+        world.extend(vec![(Hexagon::new_axial(2, 0), Player(1), Unit)]);
+
+        let result = get_entities_in_attack_area(
+            &world,
+            attacker,
+            &Hexagon::new_axial(1, 0),
+            &Diplomacy::new(),
+        );
+        assert_eq!(result, vec![enemy]);
+    }
+
+    #[test]
+    fn find_path_does_not_cross_impassable_terrain() {
+        let world = World::new(WorldOptions::default());
+        let mut terrain_map = TerrainMap::default();
+        for hexagon in create_grid(3) {
+            terrain_map.0.insert(hexagon, Terrain::Plains);
+        }
+        for r in -3..3 {
+            terrain_map
+                .0
+                .insert(Hexagon::new_axial(0, r), Terrain::Mountains);
+        }
+
+        let path = find_path(
+            &Hexagon::new_axial(-1, 0),
+            &Hexagon::new_axial(1, 0),
+            &world,
+            &terrain_map,
+        );
+        assert!(!path.is_empty());
+        assert!(path.iter().all(|hexagon| terrain_map.is_passable(hexagon)));
+
+        terrain_map
+            .0
+            .insert(Hexagon::new_axial(0, 3), Terrain::Water);
+        let path = find_path(
+            &Hexagon::new_axial(-1, 0),
+            &Hexagon::new_axial(1, 0),
+            &world,
+            &terrain_map,
+        );
+        assert!(path.is_empty());
+    }
+
+    #[test]
+    fn find_path_ends_on_friendly_transport_with_space() {
+        let mut world = World::new(WorldOptions::default());
+        let mut terrain_map = TerrainMap::default();
+        for hexagon in create_grid(3) {
+            terrain_map.0.insert(hexagon, Terrain::Plains);
+        }
+        let transport = world.push((Hexagon::new_axial(2, 0), Player(0), Unit));
+        world
+            .entry(transport)
+            .unwrap()
+            .add_component(Transport::new(1, CargoFate::Destroyed));
+        world.push((Hexagon::zero(), Player(0), Unit));
+        world.push((Hexagon::new_axial(0, 1), Player(1), Unit));
+
+        let path = find_path(
+            &Hexagon::zero(),
+            &Hexagon::new_axial(2, 0),
+            &world,
+            &terrain_map,
+        );
+        assert_eq!(path.last(), Some(&Hexagon::new_axial(2, 0)));
+
+        let path = find_path(
+            &Hexagon::new_axial(0, 1),
+            &Hexagon::new_axial(2, 0),
+            &world,
+            &terrain_map,
+        );
+        assert!(path.is_empty());
+    }
+
+    #[test]
+    fn find_path_crosses_bridges_and_avoids_walls() {
+        let mut world = World::new(WorldOptions::default());
+        let mut terrain_map = TerrainMap::default();
+        for hexagon in create_grid(3) {
+            terrain_map.0.insert(hexagon, Terrain::Plains);
+        }
+        for r in -3..4 {
+            terrain_map
+                .0
+                .insert(Hexagon::new_axial(0, r), Terrain::Water);
+        }
+        let start = Hexagon::new_axial(-1, 0);
+        let target = Hexagon::new_axial(1, 0);
+        assert!(find_path(&start, &target, &world, &terrain_map).is_empty());
+
+        world.push((
+            Hexagon::new_axial(0, 2),
+            Structure::new(StructureKind::Bridge),
+        ));
+        let path = find_path(&start, &target, &world, &terrain_map);
+        assert!(path.contains(&Hexagon::new_axial(0, 2)));
+
+        world.push((
+            Hexagon::new_axial(1, 1),
+            Structure::new(StructureKind::Wall),
+        ));
+        let path = find_path(&start, &target, &world, &terrain_map);
+        assert!(!path.is_empty());
+        assert!(!path.contains(&Hexagon::new_axial(1, 1)));
+        assert!(find_path(&start, &Hexagon::new_axial(1, 1), &world, &terrain_map).is_empty());
+    }
+
+    #[test]
+    fn get_reachable_hexagons_counts_steps_around_obstacles() {
+        let mut world = World::new(WorldOptions::default());
+        let mut terrain_map = TerrainMap::default();
+        for hexagon in create_grid(3) {
+            terrain_map.0.insert(hexagon, Terrain::Plains);
+        }
+        terrain_map
+            .0
+            .insert(Hexagon::new_axial(1, 0), Terrain::Mountains);
+        world.push((Hexagon::new_axial(0, 1), Player(1), Unit));
+
+        let reachable = get_reachable_hexagons(&Hexagon::zero(), 2, &world, &terrain_map);
+        assert_eq!(reachable[&Hexagon::zero()], 0);
+        assert_eq!(reachable[&Hexagon::new_axial(1, -1)], 1);
+        assert_eq!(reachable[&Hexagon::new_axial(2, -1)], 2);
+        assert!(!reachable.contains_key(&Hexagon::new_axial(1, 0)));
+        assert!(!reachable.contains_key(&Hexagon::new_axial(0, 1)));
+        assert!(!reachable.contains_key(&Hexagon::new_axial(2, 0)));
+        assert!(reachable.values().all(|steps| *steps <= 2));
+    }
+
+    #[test]
+    fn get_entities_in_attack_area_hits_destructible_structures() {
+        let mut world = World::new(WorldOptions::default());
+        let attacker = world.push((Hexagon::zero(), Player(0), Unit));
+        let target = Hexagon::new_axial(2, 0);
+        let bunker = world.push((
+            target,
+            Structure::new(StructureKind::Bunker),
+            StructureKind::Bunker.get_health(),
+        ));
+        world.push((target, Structure::new(StructureKind::Bridge)));
+
+        assert_eq!(
+            get_entities_in_attack_area(&world, attacker, &target, &Diplomacy::new()),
+            vec![bunker]
+        );
+    }
+
+    #[test]
+    fn get_entities_in_attack_area_spares_allies() {
+        let mut world = World::new(WorldOptions::default());
+        let attacker = world.push((
+            Hexagon::zero(),
+            Player(0),
+            Unit,
+            AttackProfile::new(AttackShape::Burst(1), false),
+        ));
+        let enemy = world.push((Hexagon::new_axial(3, 0), Player(1), Unit));
+        world.push((Hexagon::new_axial(2, 0), Player(2), Unit));
+        let neutral = world.push((Hexagon::new_axial(3, -1), Player(3), Unit));
+        let mut diplomacy = Diplomacy::new();
+        diplomacy.set_team(2, 0);
+        diplomacy.set_relation(0, 3, Relation::Neutral);
+
+        let result =
+            get_entities_in_attack_area(&world, attacker, &Hexagon::new_axial(3, 0), &diplomacy);
+        assert_eq!(result, vec![enemy]);
+
+        diplomacy.set_relation(0, 3, Relation::Hostile);
+        let result =
+            get_entities_in_attack_area(&world, attacker, &Hexagon::new_axial(3, 0), &diplomacy);
+        assert_eq!(result.len(), 2);
+        assert!(result.contains(&neutral));
+    }
 }
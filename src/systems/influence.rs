@@ -0,0 +1,292 @@
+use crate::components::hexagon::Hexagon;
+use crate::components::mobility::Mobility;
+use crate::components::player::Player;
+use crate::components::unit::Unit;
+use crate::components::weapon::Weapons;
+use crate::game_state::GameState;
+use crate::systems::combat::get_modifiers;
+use crate::systems::hexgrid::{create_grid, get_reachable_hexagons, TerrainMap};
+use legion::{Entity, EntityStore, IntoQuery};
+use std::collections::{HashMap, HashSet};
+
+/// Damage that can be dealt to each hexagon. Hexagons that can not be hit are left out.
+pub type ThreatMap = HashMap<Hexagon, i32>;
+
+/// Returns the most damage the unit can deal to each hexagon during its next turn, from
+/// any hexagon it can reach with its full movement range
+pub fn get_unit_threat<S: EntityStore>(
+    world: &S,
+    entity: Entity,
+    terrain_map: &TerrainMap,
+) -> ThreatMap {
+    let mut threat = ThreatMap::new();
+    let entry = match world.entry_ref(entity) {
+        Err(_) => return threat,
+        Ok(entry) => entry,
+    };
+    let (hexagon, weapons) = match (
+        entry.get_component::<Hexagon>(),
+        entry.get_component::<Weapons>(),
+    ) {
+        (Ok(hexagon), Ok(weapons)) => (*hexagon, weapons.clone()),
+        _ => return threat,
+    };
+    let modifiers = get_modifiers(world, entity);
+    let range = match entry.get_component::<Mobility>() {
+        Ok(mobility) if modifiers.can_move => mobility.get_mobility(&modifiers),
+        _ => 0,
+    };
+
+    let reachable = get_reachable_hexagons(&hexagon, range, world, terrain_map);
+    for weapon in weapons.weapons.iter().filter(|weapon| weapon.has_ammo()) {
+        let damage = weapon.get_damage(&modifiers);
+        let max_range = weapon.get_max_attack_range(&modifiers);
+        if damage <= 0 || max_range < weapon.min_attack_range {
+            continue;
+        }
+        let offsets: Vec<Hexagon> = create_grid(max_range.max(0) as u32)
+            .into_iter()
+            .filter(|offset| offset.distance_to(&Hexagon::zero()) >= weapon.min_attack_range)
+            .collect();
+        for position in reachable.keys() {
+            for offset in &offsets {
+                let target = *position + *offset;
+                if terrain_map.get_terrain(&target).is_none() {
+                    continue;
+                }
+                let entry = threat.entry(target).or_insert(0);
+                *entry = (*entry).max(damage);
+            }
+        }
+    }
+    threat
+}
+
+/// Returns the damage the units of the players that match the filter can deal to each
+/// hexagon during their next turn, summed over the units
+fn get_combined_threat<S: EntityStore, F: Fn(usize, &Hexagon) -> bool>(
+    world: &S,
+    terrain_map: &TerrainMap,
+    filter: F,
+) -> ThreatMap {
+    let units: Vec<Entity> = <(Entity, &Unit, &Player, &Hexagon)>::query()
+        .iter(world)
+        .filter(|(_, _, owner, hexagon)| filter(owner.0, hexagon))
+        .map(|(entity, _, _, _)| *entity)
+        .collect();
+    let mut threat = ThreatMap::new();
+    for unit in units {
+        for (hexagon, damage) in get_unit_threat(world, unit, terrain_map) {
+            *threat.entry(hexagon).or_insert(0) += damage;
+        }
+    }
+    threat
+}
+
+/// Returns the damage the hostile units the player can see can deal to each hexagon next
+/// turn, i.e. where the units of the player are in danger
+pub fn get_hostile_threat_map<S: EntityStore>(
+    world: &S,
+    state: &GameState,
+    player: usize,
+    terrain_map: &TerrainMap,
+) -> ThreatMap {
+    get_combined_threat(world, terrain_map, |owner, hexagon| {
+        state.diplomacy.is_hostile(player, owner) && state.is_hexagon_visible(hexagon)
+    })
+}
+
+/// Returns the threat of the player and their allies minus the hostile threat, as returned
+/// by `get_hostile_threat_map`, for each hexagon. Positive values mark hexagons the side of
+/// the player controls.
+pub fn get_influence_map<S: EntityStore>(
+    world: &S,
+    state: &GameState,
+    player: usize,
+    hostile_threat: &ThreatMap,
+    terrain_map: &TerrainMap,
+) -> ThreatMap {
+    let mut influence = get_combined_threat(world, terrain_map, |owner, _| {
+        state.diplomacy.is_allied(player, owner)
+    });
+    for (hexagon, damage) in hostile_threat {
+        *influence.entry(*hexagon).or_insert(0) -= damage;
+    }
+    influence
+}
+
+/// Returns the hexagons the enemy units on the hovered hexagon and the pinned enemy units
+/// can attack next turn. Units that are not hostile to the player or not visible are left
+/// out.
+pub fn get_danger_zone<S: EntityStore>(
+    world: &S,
+    state: &GameState,
+    player: usize,
+    terrain_map: &TerrainMap,
+) -> HashSet<Hexagon> {
+    <(Entity, &Unit, &Player, &Hexagon)>::query()
+        .iter(world)
+        .filter(|(entity, _, owner, hexagon)| {
+            (state.hovered_hexagon == Some(**hexagon) || state.pinned_units.contains(entity))
+                && state.diplomacy.is_hostile(player, owner.0)
+                && state.is_hexagon_visible(hexagon)
+        })
+        .map(|(entity, _, _, _)| *entity)
+        .collect::<Vec<Entity>>()
+        .into_iter()
+        .flat_map(|entity| get_unit_threat(world, entity, terrain_map).into_iter())
+        .map(|(hexagon, _)| hexagon)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::field::Terrain;
+    use crate::components::weapon::Weapon;
+    use crate::legion::add_unit;
+    use legion::{World, WorldOptions};
+
+    fn create_terrain_map() -> TerrainMap {
+        let mut terrain_map = TerrainMap::default();
+        for hexagon in create_grid(8) {
+            terrain_map.0.insert(hexagon, Terrain::Plains);
+        }
+        terrain_map
+    }
+
+    #[test]
+    fn get_unit_threat_covers_reach_and_attack_range() {
+        let mut world = World::new(WorldOptions::default());
+        let terrain_map = create_terrain_map();
+        let unit = add_unit(
+            &mut world,
+            0,
+            Hexagon::zero(),
+            (
+                Mobility::new(1, 0),
+                Weapons::new(vec![Weapon::new("Mortar", 4, 3, 2)]),
+            ),
+        );
+
+        let threat = get_unit_threat(&world, unit, &terrain_map);
+        assert_eq!(threat.get(&Hexagon::new_axial(4, 0)), Some(&4));
+        assert_eq!(threat.get(&Hexagon::new_axial(1, 0)), Some(&4));
+        assert_eq!(threat.get(&Hexagon::new_axial(5, 0)), None);
+        assert!(threat
+            .keys()
+            .all(|hexagon| hexagon.distance_to(&Hexagon::zero()) <= 4));
+
+        world.push((Unit, Player(1), Hexagon::new_axial(1, 0)));
+        let threat = get_unit_threat(&world, unit, &terrain_map);
+        assert_eq!(threat.get(&Hexagon::new_axial(4, 0)), None);
+        assert_eq!(threat.get(&Hexagon::new_axial(-4, 0)), Some(&4));
+    }
+
+    #[test]
+    fn get_influence_map_weighs_allies_against_visible_enemies() {
+        let mut world = World::new(WorldOptions::default());
+        let terrain_map = create_terrain_map();
+        add_unit(
+            &mut world,
+            0,
+            Hexagon::zero(),
+            (
+                Mobility::new(1, 0),
+                Weapons::new(vec![Weapon::new("Cannon", 5, 1, 1)]),
+            ),
+        );
+        add_unit(
+            &mut world,
+            2,
+            Hexagon::new_axial(0, 1),
+            (
+                Mobility::new(1, 0),
+                Weapons::new(vec![Weapon::new("Cannon", 2, 1, 1)]),
+            ),
+        );
+        add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(3, 0),
+            (
+                Mobility::new(1, 0),
+                Weapons::new(vec![Weapon::new("Cannon", 3, 1, 1)]),
+            ),
+        );
+        add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(-6, 0),
+            (
+                Mobility::new(1, 0),
+                Weapons::new(vec![Weapon::new("Cannon", 9, 1, 1)]),
+            ),
+        );
+
+        let mut state = GameState::new();
+        state.diplomacy.set_team(0, 0);
+        state.diplomacy.set_team(2, 0);
+        state.visible_hexagons = Some(create_grid(4).into_iter().collect());
+
+        let hostile = get_hostile_threat_map(&world, &state, 0, &terrain_map);
+        assert_eq!(hostile.get(&Hexagon::new_axial(1, 0)), Some(&3));
+        assert_eq!(hostile.get(&Hexagon::new_axial(-4, 0)), None);
+
+        let influence = get_influence_map(&world, &state, 0, &hostile, &terrain_map);
+        assert_eq!(influence.get(&Hexagon::new_axial(-1, 0)), Some(&7));
+        assert_eq!(influence.get(&Hexagon::new_axial(1, 0)), Some(&4));
+        assert_eq!(influence.get(&Hexagon::new_axial(5, 0)), Some(&-3));
+    }
+
+    #[test]
+    fn get_danger_zone_covers_hovered_and_pinned_enemies() {
+        let mut world = World::new(WorldOptions::default());
+        let terrain_map = create_terrain_map();
+        add_unit(
+            &mut world,
+            0,
+            Hexagon::zero(),
+            (
+                Mobility::new(1, 0),
+                Weapons::new(vec![Weapon::new("Cannon", 5, 1, 1)]),
+            ),
+        );
+        let pinned = add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(5, 0),
+            (
+                Mobility::new(1, 0),
+                Weapons::new(vec![Weapon::new("Cannon", 3, 1, 1)]),
+            ),
+        );
+        add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(-5, 0),
+            (
+                Mobility::new(1, 0),
+                Weapons::new(vec![Weapon::new("Cannon", 3, 1, 1)]),
+            ),
+        );
+
+        let mut state = GameState::new();
+        state.hovered_hexagon = Some(Hexagon::zero());
+        assert!(get_danger_zone(&world, &state, 0, &terrain_map).is_empty());
+
+        state.hovered_hexagon = Some(Hexagon::new_axial(-5, 0));
+        let hovered = get_danger_zone(&world, &state, 0, &terrain_map);
+        assert!(hovered.contains(&Hexagon::new_axial(-3, 0)));
+        assert!(!hovered.contains(&Hexagon::new_axial(3, 0)));
+
+        state.pinned_units.push(pinned);
+        let danger = get_danger_zone(&world, &state, 0, &terrain_map);
+        assert!(danger.contains(&Hexagon::new_axial(-3, 0)));
+        assert!(danger.contains(&Hexagon::new_axial(3, 0)));
+        assert!(!danger.contains(&Hexagon::zero()));
+
+        state.visible_hexagons = Some(create_grid(4).into_iter().collect());
+        assert!(get_danger_zone(&world, &state, 0, &terrain_map).is_empty());
+    }
+}
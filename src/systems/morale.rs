@@ -0,0 +1,383 @@
+use crate::components::hexagon::Hexagon;
+use crate::components::mobility::Mobility;
+use crate::components::morale::{
+    get_damage_morale_loss, Leader, Morale, MoraleState, FLANKING_MORALE, FRIENDLY_DEATH_MORALE,
+    FRIENDLY_DEATH_RANGE, RALLY_MORALE,
+};
+use crate::components::player::Player;
+use crate::components::status_effects::StatModifiers;
+use crate::components::unit::{AttackResult, Unit};
+use crate::diplomacy::Diplomacy;
+use crate::systems::hexgrid::{create_grid, find_path, TerrainMap};
+use legion::{Entity, EntityStore, IntoQuery};
+
+/// Returns the hexagons and players of all units
+fn get_unit_positions<S: EntityStore>(world: &S) -> Vec<(Entity, Hexagon, Player)> {
+    <(Entity, &Hexagon, &Player, &Unit)>::query()
+        .iter(world)
+        .map(|(entity, hexagon, player, _)| (*entity, *hexagon, *player))
+        .collect()
+}
+
+/// Lowers the morale of the defender for the damage it took. If it was destroyed, the
+/// friendly units around it lose morale instead. Has to be called before the destroyed
+/// defender is removed.
+pub fn apply_attack_morale<S: EntityStore>(
+    world: &mut S,
+    attacker: Entity,
+    defender: Entity,
+    result: &AttackResult,
+) {
+    let (defender_hexagon, defender_player) = match world.entry_ref(defender) {
+        Err(_) => return,
+        Ok(entry) => match (
+            entry.get_component::<Hexagon>(),
+            entry.get_component::<Player>(),
+        ) {
+            (Ok(hexagon), Ok(player)) => (*hexagon, *player),
+            _ => return,
+        },
+    };
+    let attacker_player = world
+        .entry_ref(attacker)
+        .ok()
+        .and_then(|entry| entry.get_component::<Player>().ok().copied());
+    let units = get_unit_positions(world);
+
+    if result.defender.integrity <= 0 {
+        let nearby_friends: Vec<Entity> = units
+            .iter()
+            .filter(|(entity, hexagon, player)| {
+                *entity != defender
+                    && *player == defender_player
+                    && hexagon.distance_to(&defender_hexagon) <= FRIENDLY_DEATH_RANGE
+            })
+            .map(|(entity, _, _)| *entity)
+            .collect();
+        for friend in nearby_friends {
+            if let Ok(mut entry) = world.entry_mut(friend) {
+                if let Ok(morale) = entry.get_component_mut::<Morale>() {
+                    morale.lose(FRIENDLY_DEATH_MORALE);
+                }
+            }
+        }
+        return;
+    }
+
+    let adjacent_enemies = units
+        .iter()
+        .filter(|(_, hexagon, player)| {
+            Some(*player) == attacker_player && hexagon.is_neighbour(&defender_hexagon)
+        })
+        .count();
+    let mut loss = get_damage_morale_loss(result.actual_damage, result.defender.max_integrity);
+    if adjacent_enemies >= 2 {
+        loss += FLANKING_MORALE;
+    }
+    if let Ok(mut entry) = world.entry_mut(defender) {
+        if let Ok(morale) = entry.get_component_mut::<Morale>() {
+            morale.lose(loss);
+        }
+    }
+}
+
+/// Lets the units of the player that are not next to a unit of a hostile player recover
+/// morale
+pub fn rally_units<S: EntityStore>(world: &mut S, player: usize, diplomacy: &Diplomacy) {
+    let units = get_unit_positions(world);
+    let leaders: Vec<(Hexagon, Leader)> = <(&Hexagon, &Player, &Leader)>::query()
+        .iter(world)
+        .filter(|(_, owner, _)| owner.0 == player)
+        .map(|(hexagon, _, leader)| (*hexagon, *leader))
+        .collect();
+
+    for (hexagon, owner, morale) in <(&Hexagon, &Player, &mut Morale)>::query().iter_mut(world) {
+        if owner.0 != player {
+            continue;
+        }
+        let is_engaged = units.iter().any(|(_, enemy_hexagon, enemy)| {
+            diplomacy.is_hostile(player, enemy.0) && enemy_hexagon.is_neighbour(hexagon)
+        });
+        if is_engaged {
+            continue;
+        }
+        let leader_rally: i32 = leaders
+            .iter()
+            .filter(|(leader_hexagon, leader)| leader_hexagon.distance_to(hexagon) <= leader.range)
+            .map(|(_, leader)| leader.rally)
+            .max()
+            .unwrap_or(0);
+        morale.recover(RALLY_MORALE + leader_rally);
+    }
+}
+
+/// Returns the path within the mobility of the unit that leads furthest away from the
+/// closest unit of a hostile player, or an empty path if the unit can not get further away.
+pub fn find_retreat_path<S: EntityStore>(
+    world: &S,
+    entity: Entity,
+    terrain_map: &TerrainMap,
+    diplomacy: &Diplomacy,
+) -> Vec<Hexagon> {
+    let (start, player, range) = match world.entry_ref(entity) {
+        Err(_) => return Vec::new(),
+        Ok(entry) => match (
+            entry.get_component::<Hexagon>(),
+            entry.get_component::<Player>(),
+            entry.get_component::<Mobility>(),
+        ) {
+            (Ok(hexagon), Ok(player), Ok(mobility)) => (
+                *hexagon,
+                *player,
+                mobility.get_mobility(&StatModifiers::default()),
+            ),
+            _ => return Vec::new(),
+        },
+    };
+    let enemies: Vec<Hexagon> = get_unit_positions(world)
+        .into_iter()
+        .filter(|(_, _, enemy)| diplomacy.is_hostile(player.0, enemy.0))
+        .map(|(_, hexagon, _)| hexagon)
+        .collect();
+    let get_safety = |hexagon: &Hexagon| {
+        enemies
+            .iter()
+            .map(|enemy| enemy.distance_to(hexagon))
+            .min()
+            .unwrap_or(i32::MAX)
+    };
+    if enemies.is_empty() || range <= 0 {
+        return Vec::new();
+    }
+
+    let current_safety = get_safety(&start);
+    let mut candidates: Vec<(i32, Hexagon)> = create_grid(range as u32)
+        .into_iter()
+        .map(|offset| offset + start)
+        .map(|hexagon| (get_safety(&hexagon), hexagon))
+        .filter(|(safety, _)| *safety > current_safety)
+        .collect();
+    candidates.sort_by(|(first, _), (second, _)| second.cmp(first));
+
+    for (_, candidate) in candidates {
+        let path = find_path(&start, &candidate, world, terrain_map);
+        if !path.is_empty() && path.len() as i32 <= range {
+            return path;
+        }
+    }
+    Vec::new()
+}
+
+/// Moves the routing units of the player away from the enemies
+pub fn route_units<S: EntityStore>(
+    world: &mut S,
+    player: usize,
+    terrain_map: &TerrainMap,
+    diplomacy: &Diplomacy,
+) {
+    let routing: Vec<Entity> = <(Entity, &Player, &Morale)>::query()
+        .iter(world)
+        .filter(|(_, owner, morale)| {
+            owner.0 == player && morale.get_state() == MoraleState::Routing
+        })
+        .map(|(entity, _, _)| *entity)
+        .collect();
+
+    for entity in routing {
+        let destination = match find_retreat_path(world, entity, terrain_map, diplomacy).last() {
+            None => continue,
+            Some(hexagon) => *hexagon,
+        };
+        if let Ok(mut entry) = world.entry_mut(entity) {
+            if let Ok(hexagon) = entry.get_component_mut::<Hexagon>() {
+                *hexagon = destination;
+            }
+            if let Ok(mobility) = entry.get_component_mut::<Mobility>() {
+                mobility.remaining_range = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::action_budget::ActionBudget;
+    use crate::components::field::Terrain;
+    use crate::components::health::Health;
+    use crate::components::weapon::Weapon;
+    use crate::legion::add_unit;
+    use legion::{World, WorldOptions};
+
+    fn get_morale(world: &World, entity: Entity) -> i32 {
+        world
+            .entry_ref(entity)
+            .unwrap()
+            .get_component::<Morale>()
+            .unwrap()
+            .morale
+    }
+
+    fn result(integrity: i32, actual_damage: i32) -> AttackResult {
+        AttackResult {
+            actual_damage,
+            attacker: ActionBudget::new(1, 0),
+            points: None,
+            weapon_index: 0,
+            weapon: Weapon::new("Test", 1, 1, 1),
+            defender: Health {
+                integrity,
+                ..Health::new(10, 0)
+            },
+        }
+    }
+
+    #[test]
+    fn apply_attack_morale_lowers_morale_of_flanked_defender() {
+        let mut world = World::new(WorldOptions::default());
+        let defender = add_unit(
+            &mut world,
+            0,
+            Hexagon::zero(),
+            (Morale::new(10), Mobility::new(2, 2)),
+        );
+        let attacker = add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(1, 0),
+            (Morale::new(10), Mobility::new(2, 2)),
+        );
+        add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(-1, 0),
+            (Morale::new(10), Mobility::new(2, 2)),
+        );
+
+        apply_attack_morale(&mut world, attacker, defender, &result(7, 3));
+
+        assert_eq!(get_morale(&world, defender), 10 - 3 - FLANKING_MORALE);
+    }
+
+    #[test]
+    fn apply_attack_morale_lowers_morale_of_nearby_friends_on_kill() {
+        let mut world = World::new(WorldOptions::default());
+        let defender = add_unit(
+            &mut world,
+            0,
+            Hexagon::zero(),
+            (Morale::new(10), Mobility::new(2, 2)),
+        );
+        let friend = add_unit(
+            &mut world,
+            0,
+            Hexagon::new_axial(2, 0),
+            (Morale::new(10), Mobility::new(2, 2)),
+        );
+        let far_friend = add_unit(
+            &mut world,
+            0,
+            Hexagon::new_axial(4, 0),
+            (Morale::new(10), Mobility::new(2, 2)),
+        );
+        let attacker = add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(-1, 0),
+            (Morale::new(10), Mobility::new(2, 2)),
+        );
+
+        apply_attack_morale(&mut world, attacker, defender, &result(0, 10));
+
+        assert_eq!(get_morale(&world, friend), 10 - FRIENDLY_DEATH_MORALE);
+        assert_eq!(get_morale(&world, far_friend), 10);
+        assert_eq!(get_morale(&world, attacker), 10);
+    }
+
+    #[test]
+    fn rally_units_skips_units_engaged_by_enemies_and_uses_leaders() {
+        let mut world = World::new(WorldOptions::default());
+        let engaged = add_unit(
+            &mut world,
+            0,
+            Hexagon::zero(),
+            (Morale::new(10), Mobility::new(2, 2)),
+        );
+        add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(1, 0),
+            (Morale::new(10), Mobility::new(2, 2)),
+        );
+        let led = add_unit(
+            &mut world,
+            0,
+            Hexagon::new_axial(-3, 0),
+            (Morale::new(10), Mobility::new(2, 2)),
+        );
+        world.entry(led).unwrap().add_component(Leader::new(1, 2));
+        add_unit(
+            &mut world,
+            2,
+            Hexagon::new_axial(-4, 0),
+            (Morale::new(10), Mobility::new(2, 2)),
+        );
+        let mut diplomacy = Diplomacy::new();
+        diplomacy.set_team(2, 0);
+        for entity in &[engaged, led] {
+            world
+                .entry(*entity)
+                .unwrap()
+                .get_component_mut::<Morale>()
+                .unwrap()
+                .morale = 0;
+        }
+
+        rally_units(&mut world, 0, &diplomacy);
+
+        assert_eq!(get_morale(&world, engaged), 0);
+        assert_eq!(get_morale(&world, led), RALLY_MORALE + 2);
+    }
+
+    #[test]
+    fn route_units_moves_routing_units_away_from_enemies_but_not_allies() {
+        let mut world = World::new(WorldOptions::default());
+        let mut terrain_map = TerrainMap::default();
+        for hexagon in create_grid(4) {
+            terrain_map.0.insert(hexagon, Terrain::Plains);
+        }
+        let routing = add_unit(
+            &mut world,
+            0,
+            Hexagon::zero(),
+            (Morale::new(10), Mobility::new(2, 2)),
+        );
+        add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(1, 0),
+            (Morale::new(10), Mobility::new(2, 2)),
+        );
+        world
+            .entry(routing)
+            .unwrap()
+            .get_component_mut::<Morale>()
+            .unwrap()
+            .morale = 0;
+        add_unit(
+            &mut world,
+            2,
+            Hexagon::new_axial(-1, 0),
+            (Morale::new(10), Mobility::new(2, 2)),
+        );
+        let mut diplomacy = Diplomacy::new();
+        diplomacy.set_team(2, 0);
+
+        route_units(&mut world, 0, &terrain_map, &diplomacy);
+
+        let entry = world.entry_ref(routing).unwrap();
+        let hexagon = *entry.get_component::<Hexagon>().unwrap();
+        assert_eq!(hexagon.distance_to(&Hexagon::new_axial(1, 0)), 3);
+        assert_eq!(hexagon.distance_to(&Hexagon::zero()), 2);
+    }
+}
@@ -0,0 +1,866 @@
+use crate::catalogue::UnitKind;
+use crate::components::abilities::Abilities;
+use crate::components::action_budget::ActionBudget;
+use crate::components::action_points::ActionPoints;
+use crate::components::experience::Experience;
+use crate::components::health::Health;
+use crate::components::hexagon::Hexagon;
+use crate::components::mobility::Mobility;
+use crate::components::morale::Morale;
+use crate::components::objective::Objective;
+use crate::components::player::Player;
+use crate::components::production::Production;
+use crate::components::status_effects::StatusEffects;
+use crate::components::structure::Structure;
+use crate::components::supply::Supply;
+use crate::components::transport::{Carried, Transport};
+use crate::components::unit::Unit;
+use crate::components::weapon::Weapons;
+use crate::economy::{Currency, Transaction};
+use crate::game_state::{GameState, Rules, State};
+use crate::network::{Command, Entry, Lockstep};
+use crate::snapshot::{SiteSnapshot, Snapshot, UnitSnapshot};
+use crate::systems::abilities::is_valid_ability_target;
+use crate::systems::combat::get_modifiers;
+use crate::systems::hexgrid::{find_path, get_entities_at_hexagon, is_free_hexagon, TerrainMap};
+use crate::systems::structures::{is_attackable_structure, is_sight_blocked};
+use crate::systems::transport::can_disembark;
+use legion::storage::Component;
+use legion::world::EntryRef;
+use legion::{Entity, EntityStore, IntoQuery, World};
+use std::fmt::{self, Write};
+use std::iter::once;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// The lockstep session of a networked game, if any
+#[derive(Default)]
+pub struct NetworkGame(pub Option<Lockstep>);
+
+impl NetworkGame {
+    pub fn is_active(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Whether received commands wait to be applied
+    pub fn has_pending(&self) -> bool {
+        match &self.0 {
+            None => false,
+            Some(lockstep) => lockstep.has_pending(),
+        }
+    }
+
+    /// Whether local input has to wait until commands are applied. Interrupting an action
+    /// would only change the game on this peer.
+    pub fn is_busy(&self, state: &GameState) -> bool {
+        self.has_pending()
+            || matches!(&self.0, Some(lockstep) if !lockstep.is_ready())
+            || (self.is_active() && !state.state.is_idle())
+    }
+
+    /// Sends the command of the player to the peers. Returns false if the game is
+    /// networked and the command can not be sent right now.
+    pub fn submit_command(&mut self, player: usize, command: Command) -> bool {
+        match &mut self.0 {
+            None => true,
+            Some(lockstep) => lockstep.submit(Entry::new(player, command)),
+        }
+    }
+
+    /// Sends the command that leads the player to the next state. States like selections
+    /// need no command. Returns false if the game is networked and the command can not be
+    /// sent right now.
+    pub fn submit<S: EntityStore>(&mut self, world: &S, player: usize, next: &State) -> bool {
+        if !self.is_active() {
+            return true;
+        }
+        match to_command(world, next) {
+            None => false,
+            Some(None) => true,
+            Some(Some(command)) => self.submit_command(player, command),
+        }
+    }
+
+    /// Returns the state for the next received command once it is applied, or None while
+    /// no command is waiting. Invalid commands are dropped.
+    pub fn next_state<S: EntityStore>(
+        &mut self,
+        world: &S,
+        state: &GameState,
+        terrain_map: &TerrainMap,
+    ) -> Option<State> {
+        let mut next = None;
+        let result = self.0.as_mut()?.next_command(|entry| {
+            next = to_state(world, state, entry, terrain_map);
+            next.is_some()
+        })?;
+        result.ok().and(next)
+    }
+
+    /// Sends the hash of the game state to the peers once a turn ended. The host also keeps a
+    /// snapshot to resync peers from.
+    pub fn record_hash<S: EntityStore>(&mut self, world: &S, state: &GameState) {
+        if let Some(lockstep) = &mut self.0 {
+            if lockstep.needs_hash()
+                && (state.state.is_idle() || matches!(state.state, State::Handover))
+            {
+                lockstep.record_hash(get_state_hash(world, state));
+                if lockstep.is_host() {
+                    lockstep.record_snapshot(take_snapshot(world, state));
+                }
+            }
+        }
+    }
+}
+
+/// Returns a hash of everything commands change: the entities on the map with their cargo,
+/// the balances and the current player. It is the same for peers that applied the same
+/// commands.
+pub fn get_state_hash<S: EntityStore>(world: &S, state: &GameState) -> u64 {
+    let mut records: Vec<String> = <(Entity, &Hexagon)>::query()
+        .iter(world)
+        .map(|(entity, hexagon)| {
+            let mut record = hexagon.to_string();
+            write_record(world, *entity, &mut record);
+            record
+        })
+        .collect();
+    // The order of the entities differs between the worlds of the peers
+    records.sort_unstable();
+
+    let mut values = vec![state.current_player.map_or(-1, |player| player as i64)];
+    for player in &state.players {
+        values.extend(
+            Currency::iter().map(|currency| player.get_ledger().get_balance(currency) as i64),
+        );
+    }
+    values
+        .iter()
+        .flat_map(|value| value.to_le_bytes().to_vec())
+        .chain(
+            records
+                .iter()
+                .flat_map(|record| record.bytes().chain(once(b'\n'))),
+        )
+        .fold(FNV_OFFSET, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+        })
+}
+
+/// Writes the components of the entity that commands change, and the records of its cargo
+fn write_record<S: EntityStore>(world: &S, entity: Entity, record: &mut String) {
+    let entry = match world.entry_ref(entity) {
+        Err(_) => return,
+        Ok(entry) => entry,
+    };
+    write_component::<Player>(&entry, record);
+    write_component::<Health>(&entry, record);
+    write_component::<Mobility>(&entry, record);
+    write_component::<ActionBudget>(&entry, record);
+    write_component::<ActionPoints>(&entry, record);
+    write_component::<StatusEffects>(&entry, record);
+    write_component::<Morale>(&entry, record);
+    write_component::<Weapons>(&entry, record);
+    write_component::<Abilities>(&entry, record);
+    write_component::<Supply>(&entry, record);
+    write_component::<Structure>(&entry, record);
+    write_component::<Objective>(&entry, record);
+    write_component::<Production>(&entry, record);
+    if let Ok(experience) = entry.get_component::<Experience>() {
+        let _ = write!(record, " Experience({})", experience.points);
+    }
+    // Entities differ between the worlds, so the cargo is written in place
+    if let Ok(transport) = entry.get_component::<Transport>() {
+        for cargo in &transport.cargo {
+            record.push_str(" [");
+            write_record(world, *cargo, record);
+            record.push(']');
+        }
+    }
+}
+
+fn write_component<T: Component + fmt::Debug>(entry: &EntryRef<'_>, record: &mut String) {
+    if let Ok(component) = entry.get_component::<T>() {
+        let _ = write!(record, " {:?}", component);
+    }
+}
+
+/// Returns a snapshot of everything the state hash covers, for peers to resync from
+pub fn take_snapshot<S: EntityStore>(world: &S, state: &GameState) -> Snapshot {
+    let balances = state
+        .players
+        .iter()
+        .enumerate()
+        .flat_map(|(index, player)| {
+            Currency::iter()
+                .map(move |currency| (index, currency, player.get_ledger().get_balance(currency)))
+        })
+        .collect();
+    let units = <(Entity, &Unit, &Hexagon)>::query()
+        .iter(world)
+        .filter_map(|(entity, _, _)| get_unit_snapshot(world, *entity))
+        .collect();
+    let sites = <(
+        &Hexagon,
+        Option<&Structure>,
+        Option<&Objective>,
+        Option<&Player>,
+        Option<&Health>,
+        Option<&Production>,
+    )>::query()
+    .iter(world)
+    .filter(|(_, structure, objective, _, _, _)| structure.is_some() || objective.is_some())
+    .map(
+        |(hexagon, structure, objective, owner, health, production)| SiteSnapshot {
+            hexagon: *hexagon,
+            structure: structure.map(|structure| structure.kind),
+            objective: objective.map(|objective| objective.kind),
+            owner: owner.map(|owner| owner.0),
+            integrity: health.map(|health| health.integrity),
+            queue: production.map_or_else(Vec::new, |production| {
+                production.queue.iter().copied().collect()
+            }),
+        },
+    )
+    .collect();
+    Snapshot {
+        current_player: state.current_player,
+        balances,
+        units,
+        sites,
+    }
+}
+
+/// Returns the snapshot of the unit with its cargo, or None if it was not spawned from a kind
+fn get_unit_snapshot<S: EntityStore>(world: &S, entity: Entity) -> Option<UnitSnapshot> {
+    let entry = world.entry_ref(entity).ok()?;
+    let weapons = entry.get_component::<Weapons>().ok();
+    let cargo = match entry.get_component::<Transport>() {
+        Err(_) => Vec::new(),
+        Ok(transport) => transport
+            .cargo
+            .iter()
+            .filter_map(|cargo| get_unit_snapshot(world, *cargo))
+            .collect(),
+    };
+    Some(UnitSnapshot {
+        kind: *entry.get_component::<UnitKind>().ok()?,
+        player: entry.get_component::<Player>().ok()?.0,
+        hexagon: entry.get_component::<Hexagon>().ok().copied(),
+        integrity: entry
+            .get_component::<Health>()
+            .map_or(0, |health| health.integrity),
+        remaining_range: entry
+            .get_component::<Mobility>()
+            .map_or(0, |mobility| mobility.remaining_range),
+        remaining_attacks: entry
+            .get_component::<ActionBudget>()
+            .map_or(0, |budget| budget.remaining_attacks),
+        remaining_points: entry
+            .get_component::<ActionPoints>()
+            .ok()
+            .map(|points| points.remaining_points),
+        morale: entry
+            .get_component::<Morale>()
+            .map_or(0, |morale| morale.morale),
+        experience: entry
+            .get_component::<Experience>()
+            .map_or(0, |experience| experience.points),
+        selected_weapon: weapons.and_then(|weapons| weapons.selected),
+        ammo: weapons.map_or_else(Vec::new, |weapons| {
+            weapons.weapons.iter().map(|weapon| weapon.ammo).collect()
+        }),
+        cooldowns: entry.get_component::<Abilities>().map_or_else(
+            |_| Vec::new(),
+            |abilities| {
+                abilities
+                    .slots
+                    .iter()
+                    .map(|slot| slot.remaining_cooldown)
+                    .collect()
+            },
+        ),
+        effects: entry
+            .get_component::<StatusEffects>()
+            .map_or_else(|_| Vec::new(), |effects| effects.effects.clone()),
+        cargo,
+    })
+}
+
+/// Brings a game that just started over to the state of the snapshot. The units are spawned
+/// again, the structures and objectives of the map are updated or removed if they are gone.
+pub fn apply_snapshot(world: &mut World, state: &mut GameState, snapshot: &Snapshot) {
+    state.current_player = snapshot.current_player;
+    for (index, currency, amount) in &snapshot.balances {
+        if let Some(player) = state.players.get_mut(*index) {
+            let ledger = player.get_ledger_mut();
+            let difference = amount - ledger.get_balance(*currency);
+            if difference != 0 {
+                ledger.record(Transaction::new(*currency, difference, "Resync"));
+            }
+        }
+    }
+
+    let sites: Vec<_> = <(Entity, &Hexagon, Option<&Structure>, Option<&Objective>)>::query()
+        .iter(world)
+        .filter(|(_, _, structure, objective)| structure.is_some() || objective.is_some())
+        .map(|(entity, hexagon, structure, objective)| {
+            (
+                *entity,
+                *hexagon,
+                structure.map(|structure| structure.kind),
+                objective.map(|objective| objective.kind),
+            )
+        })
+        .collect();
+    for (entity, hexagon, structure, objective) in sites {
+        let site = snapshot.sites.iter().find(|site| {
+            site.hexagon == hexagon && site.structure == structure && site.objective == objective
+        });
+        let site = match site {
+            None => {
+                world.remove(entity);
+                continue;
+            }
+            Some(site) => site,
+        };
+        let mut entry = match world.entry(entity) {
+            None => continue,
+            Some(entry) => entry,
+        };
+        match site.owner {
+            None => entry.remove_component::<Player>(),
+            Some(owner) => entry.add_component(Player(owner)),
+        }
+        if let (Some(integrity), Ok(health)) = (site.integrity, entry.get_component_mut::<Health>())
+        {
+            health.integrity = integrity;
+        }
+        if let Ok(production) = entry.get_component_mut::<Production>() {
+            production.queue = site.queue.iter().copied().collect();
+        }
+    }
+
+    let units: Vec<Entity> = <(Entity, &Unit)>::query()
+        .iter(world)
+        .map(|(entity, _)| *entity)
+        .collect();
+    for unit in units {
+        world.remove(unit);
+    }
+    for unit in &snapshot.units {
+        let hexagon = match unit.hexagon {
+            None => continue,
+            Some(hexagon) => hexagon,
+        };
+        let transport = spawn_unit(world, unit, hexagon, &state.rules);
+        for cargo in &unit.cargo {
+            let entity = spawn_unit(world, cargo, hexagon, &state.rules);
+            if let Some(mut entry) = world.entry(transport) {
+                if let Ok(transport) = entry.get_component_mut::<Transport>() {
+                    transport.embark(entity);
+                }
+            }
+            if let Some(mut entry) = world.entry(entity) {
+                entry.remove_component::<Hexagon>();
+                entry.add_component(Carried::new(transport));
+            }
+        }
+    }
+}
+
+/// Spawns the unit of the snapshot on the hexagon, without its cargo
+fn spawn_unit(world: &mut World, unit: &UnitSnapshot, hexagon: Hexagon, rules: &Rules) -> Entity {
+    let entity = unit.kind.spawn(world, unit.player, hexagon, rules);
+    let mut entry = match world.entry(entity) {
+        None => return entity,
+        Some(entry) => entry,
+    };
+    if let Ok(health) = entry.get_component_mut::<Health>() {
+        health.integrity = unit.integrity;
+    }
+    if let Ok(mobility) = entry.get_component_mut::<Mobility>() {
+        mobility.remaining_range = unit.remaining_range;
+    }
+    if let Ok(budget) = entry.get_component_mut::<ActionBudget>() {
+        budget.remaining_attacks = unit.remaining_attacks;
+    }
+    if let (Some(remaining), Ok(points)) = (
+        unit.remaining_points,
+        entry.get_component_mut::<ActionPoints>(),
+    ) {
+        points.remaining_points = remaining;
+    }
+    if let Ok(morale) = entry.get_component_mut::<Morale>() {
+        morale.morale = unit.morale;
+    }
+    if let Ok(experience) = entry.get_component_mut::<Experience>() {
+        experience.points = unit.experience;
+    }
+    if let Ok(effects) = entry.get_component_mut::<StatusEffects>() {
+        effects.effects = unit.effects.clone();
+    }
+    if let Ok(weapons) = entry.get_component_mut::<Weapons>() {
+        weapons.selected = unit.selected_weapon;
+        for (weapon, ammo) in weapons.weapons.iter_mut().zip(&unit.ammo) {
+            weapon.ammo = *ammo;
+        }
+    }
+    if let Ok(abilities) = entry.get_component_mut::<Abilities>() {
+        for (slot, cooldown) in abilities.slots.iter_mut().zip(&unit.cooldowns) {
+            slot.remaining_cooldown = *cooldown;
+        }
+    }
+    entity
+}
+
+/// Returns the command that leads to the state, None if the entities of the state are not on
+/// the map and Some(None) if the state needs no command
+fn to_command<S: EntityStore>(world: &S, next: &State) -> Option<Option<Command>> {
+    let get_hexagon = |entity: Entity| {
+        world
+            .entry_ref(entity)
+            .ok()?
+            .get_component::<Hexagon>()
+            .ok()
+            .copied()
+    };
+    match next {
+        State::Moving(entity, path, _) => Some(Some(Command::Move(
+            get_hexagon(*entity)?,
+            path.iter().copied().collect(),
+        ))),
+        State::Attacking(attacker, target) => Some(Some(Command::Attack(
+            get_hexagon(*attacker)?,
+            get_hexagon(*target)?,
+        ))),
+        State::UsingAbility(user, ability, target) => Some(Some(Command::UseAbility(
+            get_hexagon(*user)?,
+            *ability,
+            *target,
+        ))),
+        State::SelectingWeapon(entity, weapon) => {
+            Some(Some(Command::SelectWeapon(get_hexagon(*entity)?, *weapon)))
+        }
+        State::Disembarking(transport, target) => {
+            Some(Some(Command::Disembark(get_hexagon(*transport)?, *target)))
+        }
+        State::Recruiting(building, index) => {
+            Some(Some(Command::Recruit(get_hexagon(*building)?, *index)))
+        }
+        State::NewRound => Some(Some(Command::EndTurn)),
+        _ => Some(None),
+    }
+}
+
+/// Returns the state for the command, or None if the command is not valid for the current
+/// player. The rules are checked when the state is applied, the same way on every peer.
+fn to_state<S: EntityStore>(
+    world: &S,
+    state: &GameState,
+    entry: &Entry,
+    terrain_map: &TerrainMap,
+) -> Option<State> {
+    if state.current_player != Some(entry.player) {
+        return None;
+    }
+    let get_unit = |hexagon: &Hexagon| {
+        <(Entity, &Unit, &Player, &Hexagon)>::query()
+            .iter(world)
+            .find(|(_, _, owner, position)| owner.0 == entry.player && *position == hexagon)
+            .map(|(entity, _, _, _)| *entity)
+    };
+    match &entry.command {
+        Command::Move(from, path) => {
+            let unit = get_unit(from)?;
+            let (last, steps) = path.split_last()?;
+            let mut previous = from;
+            for hexagon in path {
+                if !previous.is_neighbour(hexagon) {
+                    return None;
+                }
+                previous = hexagon;
+            }
+            if steps
+                .iter()
+                .any(|hexagon| !is_free_hexagon(world, hexagon, terrain_map))
+                || find_path(from, last, world, terrain_map).is_empty()
+            {
+                return None;
+            }
+            Some(State::Moving(unit, path.iter().copied().collect(), 0f64))
+        }
+        Command::Attack(from, target) => {
+            let attacker = get_unit(from)?;
+            let is_in_range = world
+                .entry_ref(attacker)
+                .ok()?
+                .get_component::<Weapons>()
+                .ok()?
+                .is_in_attack_range(from.distance_to(target), &get_modifiers(world, attacker));
+            if !is_in_range || is_sight_blocked(world, from, target) {
+                return None;
+            }
+            let defender = get_entities_at_hexagon(target, world)
+                .into_iter()
+                .find(|entity| {
+                    let is_hostile_unit = match world.entry_ref(*entity) {
+                        Err(_) => false,
+                        Ok(target) => {
+                            target.get_component::<Unit>().is_ok()
+                                && matches!(target.get_component::<Player>(),
+                                    Ok(owner) if state.diplomacy.is_hostile(entry.player, owner.0))
+                        }
+                    };
+                    is_hostile_unit
+                        || is_attackable_structure(world, *entity, entry.player, &state.diplomacy)
+                })?;
+            Some(State::Attacking(attacker, defender))
+        }
+        Command::UseAbility(from, ability, target) => {
+            let user = get_unit(from)?;
+            if !is_valid_ability_target(world, user, *ability, target, &state.diplomacy) {
+                return None;
+            }
+            Some(State::UsingAbility(user, *ability, *target))
+        }
+        Command::SelectWeapon(from, weapon) => {
+            let unit = get_unit(from)?;
+            let entry = world.entry_ref(unit).ok()?;
+            let weapons = entry.get_component::<Weapons>().ok()?;
+            if matches!(weapon, Some(index) if weapons.get(*index).is_none()) {
+                return None;
+            }
+            Some(State::SelectingWeapon(unit, *weapon))
+        }
+        Command::Disembark(from, target) => {
+            let transport = get_unit(from)?;
+            if !can_disembark(world, transport, target, terrain_map) {
+                return None;
+            }
+            Some(State::Disembarking(transport, *target))
+        }
+        Command::Recruit(from, index) => {
+            let building = <(Entity, &Production, &Player, &Hexagon)>::query()
+                .iter(world)
+                .find(|(_, production, owner, position)| {
+                    owner.0 == entry.player
+                        && *position == from
+                        && production.get_recruit(*index).is_some()
+                })
+                .map(|(entity, _, _, _)| *entity)?;
+            Some(State::Recruiting(building, *index))
+        }
+        Command::EndTurn => Some(State::NewRound),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::field::Terrain;
+    use crate::components::objective::ObjectiveKind;
+    use crate::components::status_effects::StatusEffectKind;
+    use crate::components::structure::StructureKind;
+    use crate::components::transport::CargoFate;
+    use crate::components::weapon::Weapon;
+    use crate::legion::add_unit;
+    use crate::player::PlayerProfile;
+    use crate::systems::hexgrid::create_grid;
+    use crate::systems::transport::embark;
+    use legion::WorldOptions;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn commands_turn_into_states_of_the_current_player() {
+        let mut world = World::new(WorldOptions::default());
+        let mut terrain_map = TerrainMap::default();
+        for hexagon in create_grid(3) {
+            terrain_map.0.insert(hexagon, Terrain::Plains);
+        }
+        let unit = add_unit(
+            &mut world,
+            0,
+            Hexagon::zero(),
+            (
+                Health::new(10, 0),
+                Mobility::new(3, 3),
+                Weapons::new(vec![Weapon::new("Rifle", 3, 2, 1)]),
+            ),
+        );
+        let enemy = add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(2, 0),
+            (Health::new(10, 0), Mobility::new(3, 3)),
+        );
+        let mut state = GameState::new();
+        state.current_player = Some(0);
+        let next = |entry: Entry| to_state(&world, &state, &entry, &terrain_map);
+
+        let path = vec![Hexagon::new_axial(0, 1), Hexagon::new_axial(0, 2)];
+        let moving = State::Moving(unit, VecDeque::from(path.clone()), 0f64);
+        let command = to_command(&world, &moving).unwrap().unwrap();
+        assert_eq!(command, Command::Move(Hexagon::zero(), path));
+        assert_eq!(next(Entry::new(0, command.clone())), Some(moving));
+        assert_eq!(next(Entry::new(1, command)), None);
+        assert_eq!(
+            next(Entry::new(
+                0,
+                Command::Move(Hexagon::zero(), vec![Hexagon::new_axial(0, 2)])
+            )),
+            None
+        );
+
+        let attacking = State::Attacking(unit, enemy);
+        let command = to_command(&world, &attacking).unwrap().unwrap();
+        assert_eq!(next(Entry::new(0, command)), Some(attacking));
+        assert_eq!(next(Entry::new(0, Command::EndTurn)), Some(State::NewRound));
+        assert_eq!(to_command(&world, &State::Selected(unit)), Some(None));
+        assert_eq!(to_command(&world, &State::Targeting(unit, 0)), Some(None));
+    }
+
+    #[test]
+    fn commands_of_abilities_weapons_transports_and_buildings_are_checked() {
+        let mut world = World::new(WorldOptions::default());
+        let mut terrain_map = TerrainMap::default();
+        for hexagon in create_grid(3) {
+            terrain_map.0.insert(hexagon, Terrain::Plains);
+        }
+        let unit = add_unit(
+            &mut world,
+            0,
+            Hexagon::zero(),
+            (Health::new(10, 0), Mobility::new(3, 3)),
+        );
+        let mut entry = world.entry(unit).unwrap();
+        entry.add_component(ActionBudget::new(1, 1));
+        entry.add_component(Abilities::from_names(&["Heal"]));
+        entry.add_component(Weapons::new(vec![Weapon::new("Rifle", 3, 2, 1)]));
+        entry.add_component(Transport::new(1, CargoFate::Destroyed));
+        let cargo = add_unit(
+            &mut world,
+            0,
+            Hexagon::new_axial(0, 1),
+            (Health::new(10, 0), Mobility::new(3, 3)),
+        );
+        embark(&mut world, cargo, unit);
+        let enemy = add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(2, 0),
+            (Health::new(10, 0), Mobility::new(3, 3)),
+        );
+        for hexagon in [Hexagon::new_axial(0, -3), Hexagon::new_axial(0, -2)].iter() {
+            add_unit(
+                &mut world,
+                1,
+                *hexagon,
+                (Health::new(10, 0), Mobility::new(3, 3)),
+            );
+        }
+        world.push((
+            Hexagon::new_axial(0, -1),
+            Structure::new(StructureKind::Wall),
+        ));
+        add_unit(
+            &mut world,
+            2,
+            Hexagon::new_axial(1, -1),
+            (Health::new(10, 0), Mobility::new(3, 3)),
+        );
+        let building = world.push((
+            Player(0),
+            Hexagon::new_axial(-2, 0),
+            Production::new(vec![UnitKind::Tank]),
+        ));
+        let mut state = GameState::new();
+        state.current_player = Some(0);
+        state.diplomacy.set_team(2, 0);
+        let next = |entry: Entry| to_state(&world, &state, &entry, &terrain_map);
+        let states = vec![
+            State::Attacking(unit, enemy),
+            State::UsingAbility(unit, 0, Hexagon::zero()),
+            State::SelectingWeapon(unit, Some(0)),
+            State::SelectingWeapon(unit, None),
+            State::Disembarking(unit, Hexagon::new_axial(1, 0)),
+            State::Recruiting(building, 0),
+        ];
+        for expected in states {
+            let command = to_command(&world, &expected).unwrap().unwrap();
+            assert_eq!(next(Entry::new(1, command.clone())), None);
+            assert_eq!(next(Entry::new(0, command)), Some(expected));
+        }
+
+        let invalid = vec![
+            // Out of range, behind a wall and an ally
+            Command::Attack(Hexagon::zero(), Hexagon::new_axial(0, -3)),
+            Command::Attack(Hexagon::zero(), Hexagon::new_axial(0, -2)),
+            Command::Attack(Hexagon::zero(), Hexagon::new_axial(1, -1)),
+            Command::UseAbility(Hexagon::zero(), 1, Hexagon::zero()),
+            Command::UseAbility(Hexagon::zero(), 0, Hexagon::new_axial(2, 0)),
+            Command::UseAbility(Hexagon::new_axial(2, 0), 0, Hexagon::new_axial(2, 0)),
+            Command::SelectWeapon(Hexagon::zero(), Some(1)),
+            Command::Disembark(Hexagon::zero(), Hexagon::new_axial(3, 0)),
+            Command::Disembark(Hexagon::new_axial(2, 0), Hexagon::new_axial(1, 0)),
+            Command::Recruit(Hexagon::new_axial(-2, 0), 1),
+            Command::Recruit(Hexagon::zero(), 0),
+        ];
+        for command in invalid {
+            assert_eq!(next(Entry::new(0, command)), None);
+        }
+    }
+
+    #[test]
+    fn state_hashes_ignore_the_order_of_entities() {
+        let mut world = World::new(WorldOptions::default());
+        add_unit(
+            &mut world,
+            0,
+            Hexagon::zero(),
+            (Health::new(10, 0), Mobility::new(3, 3)),
+        );
+        add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(2, 0),
+            (Health::new(10, 0), Mobility::new(3, 3)),
+        );
+        let mut other = World::new(WorldOptions::default());
+        let enemy = add_unit(
+            &mut other,
+            1,
+            Hexagon::new_axial(2, 0),
+            (Health::new(10, 0), Mobility::new(3, 3)),
+        );
+        add_unit(
+            &mut other,
+            0,
+            Hexagon::zero(),
+            (Health::new(10, 0), Mobility::new(3, 3)),
+        );
+        let state = GameState::new();
+
+        assert_eq!(
+            get_state_hash(&world, &state),
+            get_state_hash(&other, &state)
+        );
+        other.entry(enemy).unwrap().add_component(Health {
+            integrity: 9,
+            ..Health::new(10, 0)
+        });
+        assert_ne!(
+            get_state_hash(&world, &state),
+            get_state_hash(&other, &state)
+        );
+    }
+
+    #[test]
+    fn applied_snapshots_have_the_state_hash_of_their_game() {
+        let new_game = || {
+            let mut world = World::new(WorldOptions::default());
+            let mut state = GameState::new();
+            state.players = PlayerProfile::defaults(2)
+                .iter()
+                .map(crate::player::Player::from_profile)
+                .collect();
+            state.current_player = Some(0);
+            let depot = world.push((
+                Hexagon::zero(),
+                Player(0),
+                Structure::new(StructureKind::Building),
+                StructureKind::Building.get_health(),
+                Objective::new(ObjectiveKind::Depot),
+                Production::new(UnitKind::iter().collect()),
+            ));
+            let wall = world.push((
+                Hexagon::new_axial(1, 0),
+                Structure::new(StructureKind::Wall),
+                StructureKind::Wall.get_health(),
+            ));
+            let village = world.push((
+                Hexagon::new_axial(2, 0),
+                Objective::new(ObjectiveKind::Village),
+            ));
+            let tank = UnitKind::Tank.spawn(&mut world, 0, Hexagon::zero(), &state.rules);
+            let cargo = UnitKind::Artillery.spawn(&mut world, 0, Hexagon::zero(), &state.rules);
+            assert!(embark(&mut world, cargo, tank));
+            UnitKind::Artillery.spawn(&mut world, 1, Hexagon::new_axial(3, 0), &state.rules);
+            (world, state, [depot, wall, village, tank, cargo])
+        };
+        let (mut world, mut state, [depot, wall, village, tank, cargo]) = new_game();
+        state.current_player = Some(1);
+        state.players[0]
+            .get_ledger_mut()
+            .record(Transaction::new(Currency::Credits, 5, "Test"));
+        let mut entry = world.entry(depot).unwrap();
+        entry.get_component_mut::<Health>().unwrap().integrity = 3;
+        entry
+            .get_component_mut::<Production>()
+            .unwrap()
+            .order(UnitKind::Tank);
+        world.remove(wall);
+        world.entry(village).unwrap().add_component(Player(1));
+        let mut entry = world.entry(tank).unwrap();
+        entry
+            .get_component_mut::<Mobility>()
+            .unwrap()
+            .remaining_range = 2;
+        entry.get_component_mut::<Morale>().unwrap().morale = 6;
+        entry.get_component_mut::<Experience>().unwrap().points = 7;
+        entry
+            .get_component_mut::<StatusEffects>()
+            .unwrap()
+            .add(StatusEffectKind::Slowed, 2);
+        let weapons = entry.get_component_mut::<Weapons>().unwrap();
+        weapons.selected = Some(1);
+        weapons.weapons[1].ammo = Some(1);
+        entry.get_component_mut::<Abilities>().unwrap().slots[0].remaining_cooldown = 2;
+        let mut entry = world.entry(cargo).unwrap();
+        entry.get_component_mut::<Health>().unwrap().integrity = 4;
+        let hash = get_state_hash(&world, &state);
+        let snapshot = take_snapshot(&world, &state);
+        assert_eq!(snapshot.to_string().parse(), Ok(snapshot.clone()));
+
+        let (mut other, mut other_state, _) = new_game();
+        assert_ne!(get_state_hash(&other, &other_state), hash);
+        apply_snapshot(&mut other, &mut other_state, &snapshot);
+        assert_eq!(get_state_hash(&other, &other_state), hash);
+        assert_eq!(take_snapshot(&other, &other_state), snapshot);
+    }
+
+    #[test]
+    fn state_hashes_cover_effects_experience_and_cargo() {
+        let new_world = || {
+            let mut world = World::new(WorldOptions::default());
+            let transport = add_unit(
+                &mut world,
+                0,
+                Hexagon::zero(),
+                (Health::new(10, 0), Mobility::new(3, 3)),
+            );
+            let cargo = world.push((Unit, Player(0), Experience::default()));
+            let mut carrier = Transport::new(1, CargoFate::Destroyed);
+            carrier.cargo.push(cargo);
+            world.entry(transport).unwrap().add_component(carrier);
+            (world, transport, cargo)
+        };
+        let state = GameState::new();
+        let (world, _, _) = new_world();
+        let hash = get_state_hash(&world, &state);
+
+        let (mut other, transport, _) = new_world();
+        assert_eq!(get_state_hash(&other, &state), hash);
+        let mut effects = StatusEffects::new();
+        effects.add(StatusEffectKind::Slowed, 2);
+        other.entry(transport).unwrap().add_component(effects);
+        assert_ne!(get_state_hash(&other, &state), hash);
+
+        let (mut other, _, cargo) = new_world();
+        let mut experience = Experience::default();
+        experience.gain(3, false);
+        other.entry(cargo).unwrap().add_component(experience);
+        assert_ne!(get_state_hash(&other, &state), hash);
+    }
+}
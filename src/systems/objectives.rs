@@ -0,0 +1,105 @@
+use crate::components::hexagon::Hexagon;
+use crate::components::objective::Objective;
+use crate::components::player::Player;
+use crate::components::unit::Unit;
+use crate::diplomacy::Diplomacy;
+use legion::{Entity, EntityStore, IntoQuery, World};
+
+/// Gives the objectives the units of the player stand on to the player, at the end of
+/// their turn. Only objectives without owner or of hostile players are captured.
+/// Returns the objectives that changed owner.
+pub fn capture_objectives(world: &mut World, player: usize, diplomacy: &Diplomacy) -> Vec<Entity> {
+    let occupied: Vec<Hexagon> = <(&Hexagon, &Player, &Unit)>::query()
+        .iter(world)
+        .filter(|(_, owner, _)| owner.0 == player)
+        .map(|(hexagon, _, _)| *hexagon)
+        .collect();
+    let captured: Vec<Entity> = <(Entity, &Hexagon, &Objective, Option<&Player>)>::query()
+        .iter(world)
+        .filter(|(_, hexagon, _, owner)| {
+            occupied.contains(hexagon)
+                && match owner {
+                    None => true,
+                    Some(owner) => diplomacy.is_hostile(player, owner.0),
+                }
+        })
+        .map(|(entity, _, _, _)| *entity)
+        .collect();
+
+    for objective in &captured {
+        if let Some(mut entry) = world.entry(*objective) {
+            entry.add_component(Player(player));
+        }
+    }
+    captured
+}
+
+/// Returns how many objectives the player owns
+pub fn count_objectives<S: EntityStore>(world: &S, player: usize) -> usize {
+    <(&Objective, &Player)>::query()
+        .iter(world)
+        .filter(|(_, owner)| owner.0 == player)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::objective::ObjectiveKind;
+    use legion::WorldOptions;
+
+    #[test]
+    fn capture_objectives_takes_objectives_under_units_of_the_player() {
+        let mut world = World::new(WorldOptions::default());
+        let neutral = world.push((Hexagon::zero(), Objective::new(ObjectiveKind::Flag)));
+        let enemy = world.push((
+            Hexagon::new_axial(1, 0),
+            Objective::new(ObjectiveKind::Village),
+            Player(1),
+        ));
+        let empty = world.push((
+            Hexagon::new_axial(2, 0),
+            Objective::new(ObjectiveKind::Depot),
+        ));
+        world.push((Hexagon::zero(), Player(0), Unit));
+        world.push((Hexagon::new_axial(1, 0), Player(0), Unit));
+
+        let captured = capture_objectives(&mut world, 0, &Diplomacy::new());
+
+        assert_eq!(captured.len(), 2);
+        assert!(captured.contains(&neutral) && captured.contains(&enemy));
+        assert!(!captured.contains(&empty));
+        assert_eq!(count_objectives(&world, 0), 2);
+        assert_eq!(count_objectives(&world, 1), 0);
+    }
+
+    #[test]
+    fn capture_objectives_ignores_objectives_the_player_owns() {
+        let mut world = World::new(WorldOptions::default());
+        world.push((
+            Hexagon::zero(),
+            Objective::new(ObjectiveKind::Flag),
+            Player(0),
+        ));
+        world.push((Hexagon::zero(), Player(0), Unit));
+
+        assert!(capture_objectives(&mut world, 0, &Diplomacy::new()).is_empty());
+        assert_eq!(count_objectives(&world, 0), 1);
+    }
+
+    #[test]
+    fn capture_objectives_ignores_objectives_of_allies() {
+        let mut world = World::new(WorldOptions::default());
+        world.push((
+            Hexagon::zero(),
+            Objective::new(ObjectiveKind::Flag),
+            Player(2),
+        ));
+        world.push((Hexagon::zero(), Player(0), Unit));
+        let mut diplomacy = Diplomacy::new();
+        diplomacy.set_team(2, 0);
+
+        assert!(capture_objectives(&mut world, 0, &diplomacy).is_empty());
+        assert_eq!(count_objectives(&world, 2), 1);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::catalogue::UnitKind;
+use crate::components::hexagon::Hexagon;
+use crate::components::player::Player;
+use crate::components::production::{Production, RecruitError};
+use crate::economy::Ledger;
+use crate::game_state::Rules;
+use crate::systems::hexgrid::{get_neighbours, is_free_hexagon, TerrainMap};
+use legion::{Entity, EntityStore, IntoQuery, World};
+
+/// Pays for the unit with the index in the recruit list of the building and adds it to
+/// the build queue
+pub fn recruit<S: EntityStore>(
+    world: &mut S,
+    ledger: &mut Ledger,
+    building: Entity,
+    index: usize,
+) -> Result<UnitKind, RecruitError> {
+    let mut entry = world
+        .entry_mut(building)
+        .map_err(|_| RecruitError::NotFound)?;
+    let production = entry
+        .get_component_mut::<Production>()
+        .map_err(|_| RecruitError::NotFound)?;
+    let kind = production
+        .get_recruit(index)
+        .ok_or(RecruitError::NotFound)?;
+    if !ledger.spend(&kind.get_cost(), kind.get_name()) {
+        return Err(RecruitError::CanNotAfford);
+    }
+    production.order(kind);
+    Ok(kind)
+}
+
+/// Returns the hexagon of the building if it is free, or else a free neighbour
+pub fn get_spawn_hexagon<S: EntityStore>(
+    world: &S,
+    hexagon: &Hexagon,
+    terrain_map: &TerrainMap,
+) -> Option<Hexagon> {
+    std::iter::once(*hexagon)
+        .chain(get_neighbours(hexagon))
+        .find(|candidate| is_free_hexagon(world, candidate, terrain_map))
+}
+
+/// Advances the build queues of the buildings of the player and spawns the units that
+/// are ready. Finished units wait in the queue while there is no room around the building.
+pub fn advance_production(
+    world: &mut World,
+    player: usize,
+    terrain_map: &TerrainMap,
+    rules: &Rules,
+) -> Vec<Entity> {
+    let buildings: Vec<(Entity, Hexagon)> = <(Entity, &Hexagon, &Player, &mut Production)>::query()
+        .iter_mut(world)
+        .filter(|(_, _, owner, _)| owner.0 == player)
+        .map(|(entity, hexagon, _, production)| {
+            production.tick();
+            (*entity, *hexagon)
+        })
+        .collect();
+
+    let mut spawned = Vec::new();
+    for (building, hexagon) in buildings {
+        let kind = match world
+            .entry_ref(building)
+            .ok()
+            .and_then(|entry| entry.get_component::<Production>().ok()?.get_finished())
+        {
+            None => continue,
+            Some(kind) => kind,
+        };
+        let spawn_hexagon = match get_spawn_hexagon(world, &hexagon, terrain_map) {
+            None => continue,
+            Some(spawn_hexagon) => spawn_hexagon,
+        };
+        if let Some(mut entry) = world.entry(building) {
+            if let Ok(production) = entry.get_component_mut::<Production>() {
+                production.remove_finished();
+            }
+        }
+        spawned.push(kind.spawn(world, player, spawn_hexagon, rules));
+    }
+    spawned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::field::Terrain;
+    use crate::components::unit::Unit;
+    use crate::economy::{Currency, Transaction};
+    use crate::systems::hexgrid::create_grid;
+    use legion::WorldOptions;
+
+    fn create_terrain_map() -> TerrainMap {
+        let mut terrain_map = TerrainMap::default();
+        for hexagon in create_grid(2) {
+            terrain_map.0.insert(hexagon, Terrain::Plains);
+        }
+        terrain_map
+    }
+
+    fn add_building(world: &mut World, player: usize) -> Entity {
+        world.push((
+            Hexagon::zero(),
+            Player(player),
+            Production::new(vec![UnitKind::Artillery]),
+        ))
+    }
+
+    #[test]
+    fn recruit_charges_the_ledger() {
+        let mut world = World::new(WorldOptions::default());
+        let building = add_building(&mut world, 0);
+        let mut ledger = Ledger::new();
+
+        assert_eq!(
+            recruit(&mut world, &mut ledger, building, 0),
+            Err(RecruitError::CanNotAfford)
+        );
+        ledger.record(Transaction::new(Currency::Credits, 10, "Income"));
+        assert_eq!(
+            recruit(&mut world, &mut ledger, building, 1),
+            Err(RecruitError::NotFound)
+        );
+        assert_eq!(
+            recruit(&mut world, &mut ledger, building, 0),
+            Ok(UnitKind::Artillery)
+        );
+        assert_eq!(ledger.get_balance(Currency::Credits), 4);
+        let entry = world.entry_ref(building).unwrap();
+        assert_eq!(entry.get_component::<Production>().unwrap().queue.len(), 1);
+    }
+
+    #[test]
+    fn advance_production_spawns_ready_units_next_to_occupied_building() {
+        let mut world = World::new(WorldOptions::default());
+        let terrain_map = create_terrain_map();
+        let building = add_building(&mut world, 0);
+        let enemy_building = add_building(&mut world, 1);
+        for entity in &[building, enemy_building] {
+            world
+                .entry(*entity)
+                .unwrap()
+                .get_component_mut::<Production>()
+                .unwrap()
+                .order(UnitKind::Artillery);
+        }
+        world.push((Hexagon::zero(), Player(0), Unit));
+
+        let spawned = advance_production(&mut world, 0, &terrain_map, &Rules::default());
+
+        assert_eq!(spawned.len(), 1);
+        let entry = world.entry_ref(spawned[0]).unwrap();
+        assert!(Hexagon::zero().is_neighbour(entry.get_component::<Hexagon>().unwrap()));
+        let entry = world.entry_ref(building).unwrap();
+        assert!(entry
+            .get_component::<Production>()
+            .unwrap()
+            .queue
+            .is_empty());
+        let entry = world.entry_ref(enemy_building).unwrap();
+        assert_eq!(entry.get_component::<Production>().unwrap().queue.len(), 1);
+    }
+
+    #[test]
+    fn advance_production_waits_for_free_hexagon() {
+        let mut world = World::new(WorldOptions::default());
+        let mut terrain_map = TerrainMap::default();
+        terrain_map.0.insert(Hexagon::zero(), Terrain::Plains);
+        let building = add_building(&mut world, 0);
+        world
+            .entry(building)
+            .unwrap()
+            .get_component_mut::<Production>()
+            .unwrap()
+            .order(UnitKind::Artillery);
+        world.push((Hexagon::zero(), Player(0), Unit));
+
+        assert!(advance_production(&mut world, 0, &terrain_map, &Rules::default()).is_empty());
+        let entry = world.entry_ref(building).unwrap();
+        assert_eq!(
+            entry.get_component::<Production>().unwrap().get_finished(),
+            Some(UnitKind::Artillery)
+        );
+    }
+}
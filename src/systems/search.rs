@@ -0,0 +1,264 @@
+use crate::components::action_budget::ActionBudget;
+use crate::components::health::Health;
+use crate::components::hexagon::Hexagon;
+use crate::components::mobility::Mobility;
+use crate::components::player::Player;
+use crate::components::structure::Structure;
+use crate::components::unit::Unit;
+use crate::components::weapon::Weapons;
+use crate::game_state::GameState;
+use crate::search::{
+    SearchAction, SearchSettings, SearchTask, Snapshot, SnapshotUnit, SnapshotWeapon,
+};
+use crate::systems::ai::AiAction;
+use crate::systems::combat::{get_defender, get_modifiers};
+use crate::systems::hexgrid::{find_path, TerrainMap};
+use crate::systems::structures::is_passable_hexagon;
+use legion::{Entity, EntityStore, IntoQuery};
+
+/// The search running in the background for the current player, if any
+#[derive(Default)]
+pub struct PendingSearch(pub Option<SearchTask>);
+
+/// Copies the units, the structures and the map into a snapshot for the search of the
+/// player. Hostile entities on hexagons the player can not see are left out.
+pub fn create_snapshot<S: EntityStore>(
+    world: &S,
+    state: &GameState,
+    player: usize,
+    terrain_map: &TerrainMap,
+) -> Snapshot {
+    let passable = terrain_map
+        .0
+        .keys()
+        .filter(|hexagon| is_passable_hexagon(world, hexagon, terrain_map))
+        .copied()
+        .collect();
+    let sight_blocking = <(&Structure, &Hexagon)>::query()
+        .iter(world)
+        .filter(|(structure, _)| structure.kind.blocks_sight())
+        .map(|(_, hexagon)| *hexagon)
+        .collect();
+    let entities: Vec<(Entity, usize, Hexagon, bool)> = <(
+        Entity,
+        &Player,
+        &Hexagon,
+        &Health,
+        Option<&Unit>,
+        Option<&Structure>,
+    )>::query()
+    .iter(world)
+    .filter(|(_, owner, hexagon, _, unit, structure)| {
+        (unit.is_some() || structure.is_some())
+            && (!state.diplomacy.is_hostile(player, owner.0) || state.is_hexagon_visible(hexagon))
+    })
+    .map(|(entity, owner, hexagon, _, _, structure)| {
+        (*entity, owner.0, *hexagon, structure.is_some())
+    })
+    .collect();
+    let units = entities
+        .into_iter()
+        .filter_map(|(entity, owner, hexagon, is_structure)| {
+            create_snapshot_unit(world, entity, owner, hexagon, is_structure)
+        })
+        .collect();
+    Snapshot::new(
+        units,
+        player,
+        state.players.len(),
+        passable,
+        sight_blocking,
+        state.diplomacy.clone(),
+    )
+}
+
+fn create_snapshot_unit<S: EntityStore>(
+    world: &S,
+    entity: Entity,
+    player: usize,
+    hexagon: Hexagon,
+    is_structure: bool,
+) -> Option<SnapshotUnit> {
+    let defender = get_defender(world, entity)?;
+    let modifiers = get_modifiers(world, entity);
+    let entry = world.entry_ref(entity).ok()?;
+    let mobility = entry.get_component::<Mobility>().ok().copied();
+    let budget = entry.get_component::<ActionBudget>().ok().copied();
+    let weapons = entry
+        .get_component::<Weapons>()
+        .map(|weapons| {
+            weapons
+                .weapons
+                .iter()
+                .map(|weapon| SnapshotWeapon {
+                    damage: weapon.get_damage(&modifiers),
+                    damage_type: weapon.damage_type,
+                    min_range: weapon.min_attack_range,
+                    max_range: weapon.get_max_attack_range(&modifiers),
+                    ammo: weapon.ammo,
+                    attack_cost: weapon.attack_cost,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Some(SnapshotUnit {
+        max_integrity: defender.health.max_integrity,
+        armor: defender.health.get_armor(&defender.modifiers),
+        mobility: mobility.map_or(0, |mobility| mobility.get_mobility(&modifiers)),
+        remaining_range: match mobility {
+            Some(mobility) if modifiers.can_move => mobility.remaining_range,
+            _ => 0,
+        },
+        attacks: budget.map_or(0, |budget| budget.attacks),
+        remaining_attacks: match budget {
+            Some(budget) if modifiers.can_attack => budget.remaining_attacks,
+            _ => 0,
+        },
+        weapons,
+        is_structure,
+        ..SnapshotUnit::new(entity, player, hexagon, defender.health.integrity)
+    })
+}
+
+/// Returns the next action of the player once their search finished, and starts the search
+/// in the background if none is running for them. Returns None while the search runs and
+/// Some(None) once the player ends their turn.
+pub fn poll_search<S: EntityStore>(
+    world: &S,
+    state: &GameState,
+    player: usize,
+    settings: SearchSettings,
+    terrain_map: &TerrainMap,
+    pending: &mut PendingSearch,
+) -> Option<Option<AiAction>> {
+    match pending.0.take() {
+        Some(task) if task.get_player() == player => match task.poll() {
+            None => {
+                pending.0 = Some(task);
+                None
+            }
+            Some(action) => Some(get_ai_action(world, action, terrain_map)),
+        },
+        _ => {
+            let snapshot = create_snapshot(world, state, player, terrain_map);
+            pending.0 = Some(SearchTask::spawn(snapshot, settings));
+            None
+        }
+    }
+}
+
+/// Turns the action found by the search into the action taken in the world. Moves that
+/// are not possible in the world end the turn.
+fn get_ai_action<S: EntityStore>(
+    world: &S,
+    action: SearchAction,
+    terrain_map: &TerrainMap,
+) -> Option<AiAction> {
+    match action {
+        SearchAction::Attack(attacker, target) => Some(AiAction::Attack(attacker, target)),
+        SearchAction::Move(entity, hexagon, _) => {
+            let start = *world
+                .entry_ref(entity)
+                .ok()?
+                .get_component::<Hexagon>()
+                .ok()?;
+            let path = find_path(&start, &hexagon, world, terrain_map);
+            if path.is_empty() {
+                None
+            } else {
+                Some(AiAction::Move(entity, path))
+            }
+        }
+        SearchAction::EndTurn => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::field::Terrain;
+    use crate::components::structure::StructureKind;
+    use crate::components::weapon::Weapon;
+    use crate::systems::hexgrid::create_grid;
+    use legion::{World, WorldOptions};
+
+    #[test]
+    fn create_snapshot_copies_visible_entities() {
+        let mut world = World::new(WorldOptions::default());
+        let mut terrain_map = TerrainMap::default();
+        for hexagon in create_grid(3) {
+            terrain_map.0.insert(hexagon, Terrain::Plains);
+        }
+        let unit = world.push((
+            Unit,
+            Player(0),
+            Hexagon::zero(),
+            Health::new(10, 1),
+            Mobility::new(3, 2),
+            ActionBudget::new(1, 1),
+            Weapons::new(vec![Weapon::new("Cannon", 5, 2, 1)]),
+        ));
+        let depot = world.push((
+            Player(1),
+            Hexagon::new_axial(2, 0),
+            Structure::new(StructureKind::Bunker),
+            Health::new(20, 3),
+        ));
+        world.push((
+            Unit,
+            Player(1),
+            Hexagon::new_axial(0, 3),
+            Health::new(10, 0),
+        ));
+        world.push((
+            Hexagon::new_axial(1, 0),
+            Structure::new(StructureKind::Wall),
+        ));
+
+        let mut state = GameState::new();
+        state.visible_hexagons = Some(create_grid(2).into_iter().collect());
+        let snapshot = create_snapshot(&world, &state, 0, &terrain_map);
+
+        assert_eq!(snapshot.units.len(), 2);
+        let own = &snapshot.units[0];
+        assert_eq!(own.entity, unit);
+        assert_eq!((own.mobility, own.remaining_range), (3, 2));
+        assert_eq!(own.weapons[0].max_range, 2);
+        let structure = &snapshot.units[1];
+        assert_eq!(structure.entity, depot);
+        assert!(structure.is_structure);
+        assert_eq!(structure.armor, 3);
+    }
+
+    #[test]
+    fn get_ai_action_finds_the_path() {
+        let mut world = World::new(WorldOptions::default());
+        let mut terrain_map = TerrainMap::default();
+        for hexagon in create_grid(3) {
+            terrain_map.0.insert(hexagon, Terrain::Plains);
+        }
+        let unit = world.push((Unit, Hexagon::zero()));
+        let target = Hexagon::new_axial(2, 0);
+
+        assert_eq!(
+            get_ai_action(&world, SearchAction::Move(unit, target, 2), &terrain_map),
+            Some(AiAction::Move(unit, vec![Hexagon::new_axial(1, 0), target]))
+        );
+        world.push((
+            Hexagon::new_axial(1, 0),
+            Structure::new(StructureKind::Wall),
+        ));
+        assert_eq!(
+            get_ai_action(
+                &world,
+                SearchAction::Move(unit, Hexagon::new_axial(1, 0), 1),
+                &terrain_map
+            ),
+            None
+        );
+        assert_eq!(
+            get_ai_action(&world, SearchAction::EndTurn, &terrain_map),
+            None
+        );
+    }
+}
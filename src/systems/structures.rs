@@ -0,0 +1,199 @@
+use crate::components::health::Health;
+use crate::components::hexagon::Hexagon;
+use crate::components::player::Player;
+use crate::components::structure::Structure;
+use crate::diplomacy::Diplomacy;
+use crate::systems::hexgrid::TerrainMap;
+use legion::{Entity, EntityStore, IntoQuery};
+
+pub fn get_structures_at_hexagon<S: EntityStore>(world: &S, hexagon: &Hexagon) -> Vec<Structure> {
+    <(&Structure, &Hexagon)>::query()
+        .iter(world)
+        .filter(|(_, structure_hexagon)| *structure_hexagon == hexagon)
+        .map(|(structure, _)| *structure)
+        .collect()
+}
+
+/// Whether units can enter the hexagon. Structures that block movement make it impassable,
+/// crossings like bridges make impassable terrain of the map passable.
+pub fn is_passable_hexagon<S: EntityStore>(
+    world: &S,
+    hexagon: &Hexagon,
+    terrain_map: &TerrainMap,
+) -> bool {
+    if terrain_map.get_terrain(hexagon).is_none() {
+        return false;
+    }
+    let structures = get_structures_at_hexagon(world, hexagon);
+    if structures
+        .iter()
+        .any(|structure| structure.kind.blocks_movement())
+    {
+        return false;
+    }
+    terrain_map.is_passable(hexagon)
+        || structures
+            .iter()
+            .any(|structure| structure.kind.is_crossing())
+}
+
+/// Returns the armor the structures on the hexagon of the unit grant it.
+/// Structures do not protect themselves.
+pub fn get_structure_defense<S: EntityStore>(world: &S, entity: Entity) -> i32 {
+    let hexagon = match world.entry_ref(entity) {
+        Err(_) => return 0,
+        Ok(entry) => {
+            if entry.get_component::<Structure>().is_ok() {
+                return 0;
+            }
+            match entry.get_component::<Hexagon>() {
+                Err(_) => return 0,
+                Ok(hexagon) => *hexagon,
+            }
+        }
+    };
+    get_structures_at_hexagon(world, &hexagon)
+        .iter()
+        .map(|structure| structure.kind.get_defense())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Whether a structure between the hexagons blocks the line of sight.
+/// Structures on the hexagons themselves do not block it.
+pub fn is_sight_blocked<S: EntityStore>(world: &S, from: &Hexagon, to: &Hexagon) -> bool {
+    let blocking: Vec<Hexagon> = <(&Structure, &Hexagon)>::query()
+        .iter(world)
+        .filter(|(structure, _)| structure.kind.blocks_sight())
+        .map(|(_, hexagon)| *hexagon)
+        .collect();
+    if blocking.is_empty() {
+        return false;
+    }
+    let line = from.line_to(to);
+    line.iter()
+        .skip(1)
+        .take(line.len().saturating_sub(2))
+        .any(|hexagon| blocking.contains(hexagon))
+}
+
+/// Whether the entity is a structure that the player can attack. Structures without an
+/// owner can be attacked by everyone.
+pub fn is_attackable_structure<S: EntityStore>(
+    world: &S,
+    entity: Entity,
+    player: usize,
+    diplomacy: &Diplomacy,
+) -> bool {
+    match world.entry_ref(entity) {
+        Err(_) => false,
+        Ok(entry) => {
+            entry.get_component::<Structure>().is_ok()
+                && entry.get_component::<Health>().is_ok()
+                && match entry.get_component::<Player>() {
+                    Err(_) => true,
+                    Ok(owner) => diplomacy.is_hostile(player, owner.0),
+                }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::field::Terrain;
+    use crate::components::structure::StructureKind;
+    use crate::components::unit::Unit;
+    use legion::{World, WorldOptions};
+
+    fn add_structure(world: &mut World, hexagon: Hexagon, kind: StructureKind) -> Entity {
+        world.push((hexagon, Structure::new(kind), kind.get_health()))
+    }
+
+    #[test]
+    fn walls_block_and_bridges_cross() {
+        let mut world = World::new(WorldOptions::default());
+        let mut terrain_map = TerrainMap::default();
+        let plains = Hexagon::zero();
+        let water = Hexagon::new_axial(1, 0);
+        let mountains = Hexagon::new_axial(2, 0);
+        terrain_map.0.insert(plains, Terrain::Plains);
+        terrain_map.0.insert(water, Terrain::Water);
+        terrain_map.0.insert(mountains, Terrain::Mountains);
+
+        assert!(is_passable_hexagon(&world, &plains, &terrain_map));
+        assert!(!is_passable_hexagon(&world, &water, &terrain_map));
+
+        add_structure(&mut world, plains, StructureKind::Wall);
+        add_structure(&mut world, water, StructureKind::Bridge);
+        add_structure(&mut world, Hexagon::new_axial(3, 0), StructureKind::Bridge);
+
+        assert!(!is_passable_hexagon(&world, &plains, &terrain_map));
+        assert!(is_passable_hexagon(&world, &water, &terrain_map));
+        assert!(!is_passable_hexagon(&world, &mountains, &terrain_map));
+        assert!(!is_passable_hexagon(
+            &world,
+            &Hexagon::new_axial(3, 0),
+            &terrain_map
+        ));
+    }
+
+    #[test]
+    fn get_structure_defense_protects_units_on_the_hexagon() {
+        let mut world = World::new(WorldOptions::default());
+        let bunker = add_structure(&mut world, Hexagon::zero(), StructureKind::Bunker);
+        add_structure(&mut world, Hexagon::zero(), StructureKind::Building);
+        let inside = world.push((Hexagon::zero(), Unit));
+        let outside = world.push((Hexagon::new_axial(1, 0), Unit));
+
+        assert_eq!(get_structure_defense(&world, inside), 2);
+        assert_eq!(get_structure_defense(&world, outside), 0);
+        assert_eq!(get_structure_defense(&world, bunker), 0);
+    }
+
+    #[test]
+    fn is_sight_blocked_only_between_the_hexagons() {
+        let mut world = World::new(WorldOptions::default());
+        let target = Hexagon::new_axial(4, 0);
+        add_structure(&mut world, Hexagon::zero(), StructureKind::Bunker);
+        add_structure(&mut world, target, StructureKind::Wall);
+        add_structure(&mut world, Hexagon::new_axial(2, 0), StructureKind::Bridge);
+
+        assert!(!is_sight_blocked(&world, &Hexagon::zero(), &target));
+
+        add_structure(&mut world, Hexagon::new_axial(3, 0), StructureKind::Wall);
+        assert!(is_sight_blocked(&world, &Hexagon::zero(), &target));
+        assert!(!is_sight_blocked(
+            &world,
+            &Hexagon::zero(),
+            &Hexagon::new_axial(0, 4)
+        ));
+    }
+
+    #[test]
+    fn is_attackable_structure_excludes_structures_of_allies() {
+        let mut world = World::new(WorldOptions::default());
+        let neutral = add_structure(&mut world, Hexagon::zero(), StructureKind::Wall);
+        let own = world.push((
+            Hexagon::zero(),
+            Structure::new(StructureKind::Building),
+            Health::new(5, 0),
+            Player(0),
+        ));
+        let indestructible = world.push((Hexagon::zero(), Structure::new(StructureKind::Wall)));
+
+        let mut diplomacy = Diplomacy::new();
+        diplomacy.set_team(2, 0);
+
+        assert!(is_attackable_structure(&world, neutral, 0, &diplomacy));
+        assert!(!is_attackable_structure(&world, own, 0, &diplomacy));
+        assert!(!is_attackable_structure(&world, own, 2, &diplomacy));
+        assert!(is_attackable_structure(&world, own, 1, &diplomacy));
+        assert!(!is_attackable_structure(
+            &world,
+            indestructible,
+            0,
+            &diplomacy
+        ));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::components::action_budget::ActionBudget;
+use crate::components::action_points::ActionPoints;
+use crate::components::health::Health;
+use crate::components::hexagon::Hexagon;
+use crate::components::mobility::Mobility;
+use crate::components::player::Player;
+use crate::components::regeneration::Regeneration;
+use crate::components::supply::Supply;
+use crate::components::unit::Unit;
+use crate::components::weapon::Weapons;
+use crate::systems::combat::get_modifiers;
+use legion::{Entity, EntityStore, IntoQuery};
+
+/// Whether the unit used any of its movement, attacks or action points since the last reset
+pub fn has_acted<S: EntityStore>(world: &S, entity: Entity) -> bool {
+    let entry = match world.entry_ref(entity) {
+        Err(_) => return false,
+        Ok(entry) => entry,
+    };
+    if let Ok(points) = entry.get_component::<ActionPoints>() {
+        return points.remaining_points < points.points;
+    }
+    let modifiers = get_modifiers(world, entity);
+    let moved = matches!(
+        entry.get_component::<Mobility>(),
+        Ok(mobility) if mobility.remaining_range < mobility.get_mobility(&modifiers)
+    );
+    let attacked = matches!(
+        entry.get_component::<ActionBudget>(),
+        Ok(budget) if budget.remaining_attacks < budget.attacks
+    );
+    moved || attacked
+}
+
+/// Lets the units of the player regenerate at the end of their turn
+pub fn regenerate_units<S: EntityStore>(world: &mut S, player: usize) {
+    let regenerating: Vec<(Entity, Regeneration)> = <(Entity, &Regeneration, &Player)>::query()
+        .iter(world)
+        .filter(|(_, _, owner)| owner.0 == player)
+        .map(|(entity, regeneration, _)| (*entity, *regeneration))
+        .collect();
+
+    for (entity, regeneration) in regenerating {
+        if regeneration.requires_rest && has_acted(world, entity) {
+            continue;
+        }
+        if let Ok(mut entry) = world.entry_mut(entity) {
+            if let Ok(health) = entry.get_component_mut::<Health>() {
+                health.heal(regeneration.amount);
+            }
+        }
+    }
+}
+
+/// Restores integrity and ammo of the units of the player that are in range of a supply
+pub fn supply_units<S: EntityStore>(world: &mut S, player: usize) {
+    let suppliers: Vec<(Hexagon, Option<Player>, Supply)> =
+        <(&Hexagon, &Supply, Option<&Player>)>::query()
+            .iter(world)
+            .map(|(hexagon, supply, owner)| (*hexagon, owner.copied(), *supply))
+            .collect();
+
+    for (hexagon, owner, _, health, weapons) in <(
+        &Hexagon,
+        &Player,
+        &Unit,
+        Option<&mut Health>,
+        Option<&mut Weapons>,
+    )>::query()
+    .iter_mut(world)
+    {
+        if owner.0 != player {
+            continue;
+        }
+        let mut integrity = 0;
+        let mut ammo = 0;
+        for (supplier_hexagon, supplier_owner, supply) in &suppliers {
+            if matches!(supplier_owner, Some(supplier_owner) if supplier_owner != owner)
+                || !supply.is_in_range(supplier_hexagon.distance_to(hexagon))
+            {
+                continue;
+            }
+            integrity += supply.integrity;
+            ammo += supply.ammo;
+        }
+        if let Some(health) = health {
+            health.heal(integrity);
+        }
+        if let Some(weapons) = weapons {
+            weapons.resupply(ammo);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::weapon::Weapon;
+    use crate::legion::add_unit;
+    use legion::{World, WorldOptions};
+
+    /// The components of a unit that has almost no integrity left
+    fn damaged() -> (Health, Mobility, ActionBudget) {
+        (
+            Health {
+                integrity: 1,
+                ..Health::new(10, 0)
+            },
+            Mobility::new(3, 3),
+            ActionBudget::new(1, 1),
+        )
+    }
+
+    fn get_integrity(world: &World, entity: Entity) -> i32 {
+        world
+            .entry_ref(entity)
+            .unwrap()
+            .get_component::<Health>()
+            .unwrap()
+            .integrity
+    }
+
+    #[test]
+    fn has_acted_detects_movement_and_attacks() {
+        let mut world = World::new(WorldOptions::default());
+        let unit = add_unit(&mut world, 0, Hexagon::zero(), damaged());
+        assert!(!has_acted(&world, unit));
+
+        let mut entry = world.entry(unit).unwrap();
+        entry
+            .get_component_mut::<Mobility>()
+            .unwrap()
+            .remaining_range = 2;
+        assert!(has_acted(&world, unit));
+
+        let mut entry = world.entry(unit).unwrap();
+        entry
+            .get_component_mut::<Mobility>()
+            .unwrap()
+            .remaining_range = 3;
+        entry
+            .get_component_mut::<ActionBudget>()
+            .unwrap()
+            .remaining_attacks = 0;
+        assert!(has_acted(&world, unit));
+    }
+
+    #[test]
+    fn regenerate_units_requires_rest_if_configured() {
+        let mut world = World::new(WorldOptions::default());
+        let resting = add_unit(&mut world, 0, Hexagon::zero(), damaged());
+        let acting = add_unit(&mut world, 0, Hexagon::new_axial(1, 0), damaged());
+        let enemy = add_unit(&mut world, 1, Hexagon::new_axial(2, 0), damaged());
+        for entity in &[resting, acting, enemy] {
+            world
+                .entry(*entity)
+                .unwrap()
+                .add_component(Regeneration::new(2, true));
+        }
+        world
+            .entry(acting)
+            .unwrap()
+            .get_component_mut::<Mobility>()
+            .unwrap()
+            .remaining_range = 0;
+
+        regenerate_units(&mut world, 0);
+
+        assert_eq!(get_integrity(&world, resting), 3);
+        assert_eq!(get_integrity(&world, acting), 1);
+        assert_eq!(get_integrity(&world, enemy), 1);
+    }
+
+    #[test]
+    fn supply_units_restores_units_in_range_of_own_or_neutral_supplies() {
+        let mut world = World::new(WorldOptions::default());
+        let unit = add_unit(&mut world, 0, Hexagon::zero(), damaged());
+        world
+            .entry(unit)
+            .unwrap()
+            .add_component(Weapons::new(vec![Weapon {
+                ammo: Some(0),
+                max_ammo: Some(2),
+                ..Weapon::new("Test", 1, 1, 1)
+            }]));
+        let far = add_unit(&mut world, 0, Hexagon::new_axial(3, 0), damaged());
+        world.push((Hexagon::zero(), Supply::new(2, 1, 0)));
+        world.push((Hexagon::new_axial(1, 0), Supply::new(3, 0, 1), Player(0)));
+        world.push((Hexagon::new_axial(-1, 0), Supply::new(4, 0, 1), Player(1)));
+
+        supply_units(&mut world, 0);
+
+        assert_eq!(get_integrity(&world, unit), 6);
+        assert_eq!(get_integrity(&world, far), 1);
+        let entry = world.entry_ref(unit).unwrap();
+        assert_eq!(
+            entry.get_component::<Weapons>().unwrap().weapons[0].ammo,
+            Some(1)
+        );
+    }
+}
@@ -0,0 +1,393 @@
+use crate::components::field::Field;
+use crate::components::hexagon::Hexagon;
+use crate::components::mobility::{CanMove, Mobility};
+use crate::components::player::Player;
+use crate::components::transport::{CargoFate, Carried, Transport};
+use crate::components::unit::Unit;
+use crate::systems::combat::get_modifiers;
+use crate::systems::hexgrid::{
+    get_entities_at_hexagon, get_neighbours, is_free_hexagon, TerrainMap,
+};
+use legion::{Entity, EntityStore, IntoQuery, World};
+
+/// Whether the entity can board the transport: both have to be units of the same player
+/// and the transport needs free capacity. Transports can not be carried themselves.
+pub fn can_embark<S: EntityStore>(world: &S, entity: Entity, transport: Entity) -> bool {
+    if entity == transport {
+        return false;
+    }
+    let (entry, transport_entry) = match (world.entry_ref(entity), world.entry_ref(transport)) {
+        (Ok(entry), Ok(transport_entry)) => (entry, transport_entry),
+        _ => return false,
+    };
+    if entry.get_component::<Unit>().is_err()
+        || entry.get_component::<Transport>().is_ok()
+        || entry.get_component::<Carried>().is_ok()
+    {
+        return false;
+    }
+    let same_player = matches!(
+        (entry.get_component::<Player>(), transport_entry.get_component::<Player>()),
+        (Ok(player), Ok(transport_player)) if player == transport_player
+    );
+    same_player
+        && matches!(
+            transport_entry.get_component::<Transport>(),
+            Ok(transport) if transport.has_space()
+        )
+}
+
+/// Puts the entity aboard the transport and takes it off the map
+pub fn embark(world: &mut World, entity: Entity, transport: Entity) -> bool {
+    if !can_embark(world, entity, transport) {
+        return false;
+    }
+    if let Some(mut entry) = world.entry(transport) {
+        if let Ok(cargo) = entry.get_component_mut::<Transport>() {
+            cargo.embark(entity);
+        }
+    }
+    if let Some(mut entry) = world.entry(entity) {
+        entry.remove_component::<Hexagon>();
+        entry.add_component(Carried::new(transport));
+    }
+    true
+}
+
+/// Lets the entity board a friendly transport at its hexagon, if there is one
+pub fn embark_at_hexagon(world: &mut World, entity: Entity) -> bool {
+    let hexagon = match world
+        .entry_ref(entity)
+        .ok()
+        .and_then(|entry| entry.get_component::<Hexagon>().ok().copied())
+    {
+        None => return false,
+        Some(hexagon) => hexagon,
+    };
+    match get_entities_at_hexagon(&hexagon, world)
+        .into_iter()
+        .find(|transport| can_embark(world, entity, *transport))
+    {
+        None => false,
+        Some(transport) => embark(world, entity, transport),
+    }
+}
+
+/// Returns the first cargo of the transport that can still move
+pub fn get_next_cargo<S: EntityStore>(world: &S, transport: Entity) -> Option<Entity> {
+    let entry = world.entry_ref(transport).ok()?;
+    let cargo = entry.get_component::<Transport>().ok()?;
+    cargo.cargo.iter().copied().find(|cargo| {
+        let modifiers = get_modifiers(world, *cargo);
+        matches!(
+            world
+                .entry_ref(*cargo)
+                .ok()
+                .and_then(|entry| entry.get_component::<Mobility>().ok().copied())
+                .map(|mobility| mobility.is_in_movement_range(1, &modifiers)),
+            Some(CanMove::Yes(_))
+        )
+    })
+}
+
+/// Whether the next cargo of the transport can disembark to the hexagon
+pub fn can_disembark<S: EntityStore>(
+    world: &S,
+    transport: Entity,
+    hexagon: &Hexagon,
+    terrain_map: &TerrainMap,
+) -> bool {
+    let transport_hexagon = match world
+        .entry_ref(transport)
+        .ok()
+        .and_then(|entry| entry.get_component::<Hexagon>().ok().copied())
+    {
+        None => return false,
+        Some(transport_hexagon) => transport_hexagon,
+    };
+    transport_hexagon.is_neighbour(hexagon)
+        && is_free_hexagon(world, hexagon, terrain_map)
+        && get_next_cargo(world, transport).is_some()
+}
+
+/// Takes the next cargo off the transport and puts it back on the map at the hexagon of
+/// the transport. Returns the cargo, which still has to move to the disembark hexagon.
+pub fn disembark(
+    world: &mut World,
+    transport: Entity,
+    hexagon: &Hexagon,
+    terrain_map: &TerrainMap,
+) -> Option<Entity> {
+    if !can_disembark(world, transport, hexagon, terrain_map) {
+        return None;
+    }
+    let cargo = get_next_cargo(world, transport)?;
+    let mut entry = world.entry(transport)?;
+    let transport_hexagon = *entry.get_component::<Hexagon>().ok()?;
+    entry
+        .get_component_mut::<Transport>()
+        .ok()?
+        .disembark(cargo);
+
+    let mut entry = world.entry(cargo)?;
+    entry.remove_component::<Carried>();
+    entry.add_component(transport_hexagon);
+    Some(cargo)
+}
+
+/// Destroys or ejects the cargo of the transport, depending on the transport.
+/// Has to be called before the destroyed transport is removed.
+pub fn release_cargo(world: &mut World, transport: Entity) {
+    let (hexagon, on_destroyed, cargo) = match world.entry(transport) {
+        None => return,
+        Some(mut entry) => match entry.get_component::<Hexagon>().ok().copied() {
+            None => return,
+            Some(hexagon) => match entry.get_component_mut::<Transport>() {
+                Err(_) => return,
+                Ok(transport) => (
+                    hexagon,
+                    transport.on_destroyed,
+                    std::mem::take(&mut transport.cargo),
+                ),
+            },
+        },
+    };
+
+    let terrain_map = TerrainMap::from_fields(
+        &<&Field>::query()
+            .iter(world)
+            .copied()
+            .collect::<Vec<Field>>(),
+    );
+    for entity in cargo {
+        let free_hexagon = match on_destroyed {
+            CargoFate::Destroyed => None,
+            CargoFate::Ejected => get_neighbours(&hexagon)
+                .into_iter()
+                .find(|neighbour| is_free_hexagon(world, neighbour, &terrain_map)),
+        };
+        match free_hexagon {
+            None => {
+                world.remove(entity);
+            }
+            Some(free_hexagon) => {
+                if let Some(mut entry) = world.entry(entity) {
+                    entry.remove_component::<Carried>();
+                    entry.add_component(free_hexagon);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::field::Terrain;
+    use crate::legion::add_unit;
+    use crate::systems::hexgrid::create_grid;
+    use legion::WorldOptions;
+
+    fn add_transport(world: &mut World, hexagon: Hexagon, on_destroyed: CargoFate) -> Entity {
+        let transport = add_unit(world, 0, hexagon, (Mobility::new(2, 2),));
+        world
+            .entry(transport)
+            .unwrap()
+            .add_component(Transport::new(1, on_destroyed));
+        transport
+    }
+
+    fn create_terrain_map() -> TerrainMap {
+        let mut terrain_map = TerrainMap::default();
+        for hexagon in create_grid(3) {
+            terrain_map.0.insert(hexagon, Terrain::Plains);
+        }
+        terrain_map
+    }
+
+    fn get_cargo(world: &World, transport: Entity) -> Vec<Entity> {
+        world
+            .entry_ref(transport)
+            .unwrap()
+            .get_component::<Transport>()
+            .unwrap()
+            .cargo
+            .clone()
+    }
+
+    #[test]
+    fn can_embark_requires_friendly_transport_with_space() {
+        let mut world = World::new(WorldOptions::default());
+        let transport = add_transport(&mut world, Hexagon::zero(), CargoFate::Destroyed);
+        let friend = add_unit(
+            &mut world,
+            0,
+            Hexagon::new_axial(1, 0),
+            (Mobility::new(2, 2),),
+        );
+        let other_friend = add_unit(
+            &mut world,
+            0,
+            Hexagon::new_axial(2, 0),
+            (Mobility::new(2, 2),),
+        );
+        let enemy = add_unit(
+            &mut world,
+            1,
+            Hexagon::new_axial(-1, 0),
+            (Mobility::new(2, 2),),
+        );
+
+        assert!(can_embark(&world, friend, transport));
+        assert!(!can_embark(&world, enemy, transport));
+        assert!(!can_embark(&world, transport, transport));
+        assert!(!can_embark(&world, transport, friend));
+
+        assert!(embark(&mut world, friend, transport));
+        assert!(!can_embark(&world, other_friend, transport));
+    }
+
+    #[test]
+    fn embark_takes_cargo_off_the_map() {
+        let mut world = World::new(WorldOptions::default());
+        let transport = add_transport(&mut world, Hexagon::zero(), CargoFate::Destroyed);
+        let cargo = add_unit(&mut world, 0, Hexagon::zero(), (Mobility::new(2, 2),));
+
+        assert!(embark_at_hexagon(&mut world, cargo));
+
+        assert_eq!(get_cargo(&world, transport), vec![cargo]);
+        assert_eq!(
+            get_entities_at_hexagon(&Hexagon::zero(), &world),
+            vec![transport]
+        );
+        let entry = world.entry_ref(cargo).unwrap();
+        assert_eq!(
+            *entry.get_component::<Carried>().unwrap(),
+            Carried::new(transport)
+        );
+    }
+
+    #[test]
+    fn disembark_puts_cargo_back_next_to_transport() {
+        let mut world = World::new(WorldOptions::default());
+        let terrain_map = create_terrain_map();
+        let transport = add_transport(&mut world, Hexagon::zero(), CargoFate::Destroyed);
+        let cargo = add_unit(
+            &mut world,
+            0,
+            Hexagon::new_axial(1, 0),
+            (Mobility::new(2, 2),),
+        );
+        embark(&mut world, cargo, transport);
+
+        assert!(!can_disembark(
+            &world,
+            transport,
+            &Hexagon::new_axial(2, 0),
+            &terrain_map
+        ));
+        assert_eq!(
+            disembark(
+                &mut world,
+                transport,
+                &Hexagon::new_axial(0, 1),
+                &terrain_map
+            ),
+            Some(cargo)
+        );
+
+        assert!(get_cargo(&world, transport).is_empty());
+        let entry = world.entry_ref(cargo).unwrap();
+        assert!(entry.get_component::<Carried>().is_err());
+        assert_eq!(*entry.get_component::<Hexagon>().unwrap(), Hexagon::zero());
+    }
+
+    #[test]
+    fn disembark_requires_cargo_that_can_move() {
+        let mut world = World::new(WorldOptions::default());
+        let terrain_map = create_terrain_map();
+        let transport = add_transport(&mut world, Hexagon::zero(), CargoFate::Destroyed);
+        let cargo = add_unit(
+            &mut world,
+            0,
+            Hexagon::new_axial(1, 0),
+            (Mobility::new(2, 2),),
+        );
+        embark(&mut world, cargo, transport);
+        world
+            .entry(cargo)
+            .unwrap()
+            .get_component_mut::<Mobility>()
+            .unwrap()
+            .remaining_range = 0;
+
+        assert_eq!(
+            disembark(
+                &mut world,
+                transport,
+                &Hexagon::new_axial(1, 0),
+                &terrain_map
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn release_cargo_destroys_cargo_if_configured() {
+        let mut world = World::new(WorldOptions::default());
+        let transport = add_transport(&mut world, Hexagon::zero(), CargoFate::Destroyed);
+        let cargo = add_unit(
+            &mut world,
+            0,
+            Hexagon::new_axial(1, 0),
+            (Mobility::new(2, 2),),
+        );
+        embark(&mut world, cargo, transport);
+
+        release_cargo(&mut world, transport);
+
+        assert!(!world.contains(cargo));
+        assert!(get_cargo(&world, transport).is_empty());
+    }
+
+    #[test]
+    fn release_cargo_ejects_cargo_to_free_hexagon() {
+        let mut world = World::new(WorldOptions::default());
+        let transport = add_transport(&mut world, Hexagon::zero(), CargoFate::Ejected);
+        let cargo = add_unit(
+            &mut world,
+            0,
+            Hexagon::new_axial(1, 0),
+            (Mobility::new(2, 2),),
+        );
+        embark(&mut world, cargo, transport);
+        world.push((Field::new(Hexagon::new_axial(1, 0), Terrain::Water),));
+        world.push((Field::new(Hexagon::new_axial(0, 1), Terrain::Plains),));
+
+        release_cargo(&mut world, transport);
+
+        let entry = world.entry_ref(cargo).unwrap();
+        assert!(entry.get_component::<Carried>().is_err());
+        assert_eq!(
+            *entry.get_component::<Hexagon>().unwrap(),
+            Hexagon::new_axial(0, 1)
+        );
+    }
+
+    #[test]
+    fn release_cargo_destroys_cargo_without_free_hexagon() {
+        let mut world = World::new(WorldOptions::default());
+        let transport = add_transport(&mut world, Hexagon::zero(), CargoFate::Ejected);
+        let cargo = add_unit(
+            &mut world,
+            0,
+            Hexagon::new_axial(1, 0),
+            (Mobility::new(2, 2),),
+        );
+        embark(&mut world, cargo, transport);
+
+        release_cargo(&mut world, transport);
+
+        assert!(!world.contains(cargo));
+    }
+}
@@ -0,0 +1,65 @@
+use crate::components::player::Player;
+use crate::components::unit::Unit;
+use crate::diplomacy::Diplomacy;
+use legion::{EntityStore, IntoQuery};
+
+/// Whether the player has no units left
+pub fn is_defeated<S: EntityStore>(world: &S, player: usize) -> bool {
+    !<(&Unit, &Player)>::query()
+        .iter(world)
+        .any(|(_, owner)| owner.0 == player)
+}
+
+/// Returns the players that won the game, once all players that are not defeated are
+/// allied with each other
+pub fn get_winners<S: EntityStore>(
+    world: &S,
+    diplomacy: &Diplomacy,
+    player_count: usize,
+) -> Option<Vec<usize>> {
+    let remaining: Vec<usize> = (0..player_count)
+        .filter(|player| !is_defeated(world, *player))
+        .collect();
+    let first = *remaining.first()?;
+    if remaining
+        .iter()
+        .all(|player| diplomacy.is_allied(first, *player))
+    {
+        Some(remaining)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use legion::{World, WorldOptions};
+
+    #[test]
+    fn get_winners_waits_for_the_last_team() {
+        let mut world = World::new(WorldOptions::default());
+        let mut diplomacy = Diplomacy::new();
+        for player in 0..4 {
+            diplomacy.set_team(player, player % 2);
+        }
+        let units: Vec<_> = (0..4)
+            .map(|player| world.push((Unit, Player(player))))
+            .collect();
+
+        assert_eq!(get_winners(&world, &diplomacy, 4), None);
+
+        world.remove(units[1]);
+        assert!(is_defeated(&world, 1));
+        assert_eq!(get_winners(&world, &diplomacy, 4), None);
+
+        world.remove(units[3]);
+        assert_eq!(get_winners(&world, &diplomacy, 4), Some(vec![0, 2]));
+    }
+
+    #[test]
+    fn get_winners_is_none_without_players() {
+        let world = World::new(WorldOptions::default());
+        assert_eq!(get_winners(&world, &Diplomacy::new(), 2), None);
+    }
+}
@@ -0,0 +1,50 @@
+use crate::components::hexagon::Hexagon;
+use crate::components::player::Player;
+use crate::components::vision::Vision;
+use crate::diplomacy::Diplomacy;
+use crate::systems::hexgrid::create_grid;
+use legion::{EntityStore, IntoQuery};
+use std::collections::HashSet;
+
+/// Returns the hexagons seen by the entities of the player and of their allies
+pub fn get_visible_hexagons<S: EntityStore>(
+    world: &S,
+    player: usize,
+    diplomacy: &Diplomacy,
+) -> HashSet<Hexagon> {
+    <(&Hexagon, &Vision, &Player)>::query()
+        .iter(world)
+        .filter(|(_, _, owner)| diplomacy.is_allied(player, owner.0))
+        .flat_map(|(hexagon, vision, _)| {
+            create_grid(vision.range.max(0) as u32)
+                .into_iter()
+                .map(move |offset| *hexagon + offset)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diplomacy::Relation;
+    use legion::{World, WorldOptions};
+
+    #[test]
+    fn get_visible_hexagons_shares_vision_with_allies() {
+        let mut world = World::new(WorldOptions::default());
+        world.push((Hexagon::zero(), Vision::new(1), Player(0)));
+        world.push((Hexagon::new_axial(5, 0), Vision::new(0), Player(1)));
+        world.push((Hexagon::new_axial(-5, 0), Vision::new(2), Player(2)));
+        let mut diplomacy = Diplomacy::new();
+        diplomacy.set_team(1, 0);
+        diplomacy.set_relation(0, 2, Relation::Neutral);
+
+        let visible = get_visible_hexagons(&world, 0, &diplomacy);
+
+        assert_eq!(visible.len(), 8);
+        assert!(visible.contains(&Hexagon::new_axial(1, -1)));
+        assert!(visible.contains(&Hexagon::new_axial(5, 0)));
+        assert!(!visible.contains(&Hexagon::new_axial(-5, 0)));
+        assert_eq!(get_visible_hexagons(&world, 1, &diplomacy), visible);
+    }
+}